@@ -0,0 +1,31 @@
+//! Parse-time benchmark: a document with dozens of code blocks, highlighted
+//! serially (`--jobs 1`) and in parallel (`--jobs 0`).
+//!
+//! Run with `cargo bench --bench parse`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use mdink::{Highlighter, ParseOptions, parser};
+
+fn code_heavy_document() -> String {
+    let snippet = include_str!("../src/layout.rs");
+    (0..48)
+        .map(|i| format!("## Block {i}\n\n```rust\n{snippet}\n```\n\n"))
+        .collect()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let highlighter = Highlighter::new();
+    let source = code_heavy_document();
+    let mut group = c.benchmark_group("parse_48_code_blocks");
+    group.sample_size(10);
+    for (name, jobs) in [("serial", 1), ("parallel", 0)] {
+        let options = ParseOptions { jobs, ..ParseOptions::default() };
+        group.bench_function(name, |b| {
+            b.iter(|| parser::parse_with_options(&source, &highlighter, &options))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+//! Pipeline benchmarks: parse, layout at 80 columns, and re-layout on
+//! resize, over a small README, a large document with many code blocks,
+//! and pathological single-paragraph documents: one very long line of
+//! words, one huge word, and one huge CJK run. The last two guard the
+//! wrapping of unbreakable runs against going quadratic again.
+//!
+//! Run with `cargo bench --bench pipeline`.
+
+use std::rc::Rc;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use mdink::layout::{self, LayoutOptions};
+use mdink::{Highlighter, RenderedBlock, parse};
+
+/// Rows the viewer needs laid out after a resize (one screenful).
+const VIEWPORT_LINES: usize = 50;
+
+fn fixtures() -> Vec<(&'static str, String)> {
+    let small = include_str!("../testdata/basic.md").to_string();
+    let code = include_str!("../testdata/code-blocks.md");
+    let large = (0..200).map(|i| format!("## Section {i}\n\n{code}\n\n")).collect();
+    let words = ["lorem", "ipsum", "dolor", "sit", "amet", "consectetur"];
+    let long_line = (0..40_000).map(|i| words[i % words.len()]).collect::<Vec<_>>().join(" ");
+    let huge_word = "x".repeat(512 * 1024);
+    let cjk_run = "漢".repeat(400 * 1024);
+    vec![
+        ("small_readme", small),
+        ("large_code_heavy", large),
+        ("long_line", long_line),
+        ("huge_word", huge_word),
+        ("cjk_run", cjk_run),
+    ]
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let highlighter = Highlighter::new();
+    let fixtures = fixtures();
+
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for (name, source) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(name), source, |b, source| {
+            b.iter(|| parse(source, &highlighter))
+        });
+    }
+    group.finish();
+
+    let parsed: Vec<(&str, Rc<[RenderedBlock]>)> = fixtures
+        .iter()
+        .map(|(name, source)| (*name, parse(source, &highlighter).into()))
+        .collect();
+
+    let mut group = c.benchmark_group("layout_80");
+    group.sample_size(10);
+    for (name, blocks) in &parsed {
+        group.bench_with_input(BenchmarkId::from_parameter(name), blocks, |b, blocks| {
+            b.iter(|| layout::flatten(blocks, 80))
+        });
+    }
+    group.finish();
+
+    // What the viewer does on resize: a lazy layout at the new width,
+    // filled out to the first screenful.
+    let mut group = c.benchmark_group("relayout_resize_120");
+    group.sample_size(10);
+    let options = LayoutOptions::default();
+    for (name, blocks) in &parsed {
+        group.bench_with_input(BenchmarkId::from_parameter(name), blocks, |b, blocks| {
+            b.iter(|| {
+                let mut document = layout::flatten_lazy(Rc::clone(blocks), 120, &options);
+                document.ensure_lines(VIEWPORT_LINES);
+                document
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_pipeline);
+criterion_main!(benches);
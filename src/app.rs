@@ -8,7 +8,80 @@ use std::ops::Range;
 
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::layout::PreRenderedDocument;
+use crate::export::{CodeBackground, ColorMode};
+use crate::keymap::{self, Action};
+use crate::layout::{PreRenderedDocument, WrapMode};
+
+/// Which part of the document a clipboard copy should cover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyScope {
+    /// Only the lines currently visible in the viewport.
+    Visible,
+    /// Every line of the document.
+    Document,
+    /// The whole document with paragraphs unwrapped, built from the IR by
+    /// the event loop so wrapped lines paste as flowing text.
+    Reflowed,
+}
+
+/// Which line numbers the status bar shows as the scroll position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusLineMode {
+    /// The top visible line: `12/200` (the default).
+    #[default]
+    Top,
+    /// The bottom visible line: `34/200`.
+    Bottom,
+    /// Every visible line: `12-34/200`.
+    Range,
+}
+
+/// What key presses currently mean.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum InputMode {
+    /// Keys scroll and trigger actions (the default).
+    #[default]
+    Normal,
+    /// Typing a `/` search query into the status bar; Enter searches,
+    /// Esc cancels.
+    Search { buffer: String },
+    /// Typing a line number (started by a digit); Enter or `g` jumps to
+    /// it, Esc cancels.
+    Goto { buffer: String },
+    /// Typing a `:` command (see `run_command`); Enter runs it, Esc cancels.
+    Command { buffer: String },
+    /// The key help overlay (`?`); any key closes it.
+    Help,
+    /// The theme picker (`T`): arrows preview `themes[selected]`, Enter
+    /// keeps it, Esc goes back to `original`.
+    ThemePicker { selected: usize, original: String },
+}
+
+/// Columns taken by the outline sidebar (including its border), when it fits.
+const SIDEBAR_WIDTH: u16 = 30;
+
+/// Narrowest wrap width `:set width` and `-` allow.
+pub const MIN_WRAP_WIDTH: u16 = 20;
+
+/// Columns `+` and `-` widen or narrow the wrap width by.
+const WRAP_WIDTH_STEP: u16 = 4;
+
+/// Blank columns between side-by-side text columns (`--columns`).
+pub const COLUMN_GAP: u16 = 3;
+
+/// Returns the width of each of `columns` side-by-side text columns sharing
+/// `width` cells, `COLUMN_GAP` apart.
+pub fn column_width(width: u16, columns: u16) -> u16 {
+    let columns = columns.max(1);
+    width.saturating_sub(COLUMN_GAP * (columns - 1)) / columns
+}
+
+/// State of the outline sidebar while it is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outline {
+    /// Index into `document.headings` of the heading under the cursor.
+    pub selected: usize,
+}
 
 /// Application state for the TUI viewer.
 ///
@@ -21,13 +94,89 @@ pub struct App {
     /// Current vertical scroll offset (0 = top of document).
     pub scroll_offset: usize,
     /// Number of visible lines in the content area (excludes status bar).
+    /// With several columns, this is the height of each one.
     pub viewport_height: usize,
+    /// Terminal width in columns, kept up to date by `main.rs` like
+    /// `viewport_height`. `+` and `-` step the wrap width within it.
+    pub terminal_width: u16,
     /// Name of the file being displayed (shown in the status bar).
     pub filename: String,
+    /// With `--concat`, each file's first block (an index into the parsed
+    /// blocks) and name, in order, so the status bar can name the file at
+    /// the top of the screen instead. Empty for a single file.
+    pub files: Vec<(usize, String)>,
     /// When true, the event loop should exit.
     pub quit: bool,
+    /// When true, the event loop should open the file in `$EDITOR`.
+    /// The event loop clears this flag once the request is handled.
+    pub edit_requested: bool,
+    /// When true, the event loop should re-read the file from disk.
+    /// The event loop clears this flag once the request is handled.
+    pub reload_requested: bool,
+    /// Set by the event loop when the file changed on disk since it was
+    /// read; shown as `[modified]` in the status bar until reloaded.
+    pub stale: bool,
+    /// Pending clipboard copy, performed by the event loop (which owns the
+    /// clipboard handle). The event loop clears this once the copy is done.
+    pub copy_request: Option<CopyScope>,
+    /// Transient message shown in the status bar until the next key press.
+    pub status_message: Option<String>,
+    /// The outline sidebar, `None` while hidden. Toggling it changes the
+    /// content width, so the event loop re-flattens the document.
+    pub outline: Option<Outline>,
+    /// Blank columns to the left of the content (`--left-margin`).
+    pub left_margin: u16,
+    /// Whether prose is wrapped or clipped, toggled with `w`. The event loop
+    /// re-flattens the document when this changes.
+    pub wrap: WrapMode,
+    /// How the status bar reports the scroll position (`--status-line-mode`).
+    pub status_line: StatusLineMode,
+    /// Whether the status bar is shown, toggled with `s` (`--no-status`).
+    /// See `status_bar_visible()`.
+    pub status_bar: bool,
+    /// Side-by-side text columns the document flows through (`--columns`):
+    /// down the first, then on into the next. At least 1.
+    pub columns: u16,
+    /// Pattern tiled across the width for thematic breaks (`--rule-char`).
+    pub rule: String,
+    /// `ColorMode::None` draws without colors (`--color none`, `NO_COLOR`).
+    pub color: ColorMode,
+    /// Where code blocks get their background color (`--code-bg`).
+    pub code_bg: CodeBackground,
+    /// Blank columns before each code line, on its background (`--code-padding`).
+    pub code_padding: usize,
+    /// What key presses currently mean (see `handle_key`).
+    pub mode: InputMode,
+    /// The last query searched for with `/`, repeated by `n`.
+    pub last_search: Option<String>,
+    /// Code themes offered by the theme picker.
+    pub themes: Vec<String>,
+    /// The code theme the document is highlighted with.
+    pub theme: String,
+    /// Set when the picker changes the theme; the event loop re-highlights.
+    pub theme_request: Option<String>,
+    /// The dark and light code themes `B` switches between.
+    pub theme_pair: [String; 2],
+    /// True while the light theme of the pair is in use.
+    pub light: bool,
+    /// Most columns the document is laid out in (`:set width N`); `None`
+    /// uses the whole text column. The event loop re-flattens on change.
+    pub wrap_width: Option<u16>,
+    /// True while the markdown source is shown instead of the rendered
+    /// document, toggled with `R`. The event loop re-parses when it changes.
+    pub raw: bool,
+    /// Diagrams shown the other way from `--collapse-diagrams`, toggled with
+    /// `x` (see `LayoutOptions::flipped_diagrams`). The event loop
+    /// re-flattens when this changes.
+    pub flipped_diagrams: Vec<usize>,
+    /// Lines of context kept above the target of a search, heading or goto
+    /// jump (`--scroll-margin`), like vim's `scrolloff`.
+    pub scroll_margin: usize,
 }
 
+/// Default `App::scroll_margin`.
+pub const DEFAULT_SCROLL_MARGIN: usize = 2;
+
 impl App {
     /// Creates a new `App` with the given document and filename.
     ///
@@ -38,48 +187,643 @@ impl App {
             document,
             scroll_offset: 0,
             viewport_height: 0,
+            terminal_width: 0,
             filename,
+            files: Vec::new(),
             quit: false,
+            edit_requested: false,
+            reload_requested: false,
+            stale: false,
+            copy_request: None,
+            status_message: None,
+            outline: None,
+            left_margin: 0,
+            wrap: WrapMode::Wrap,
+            status_line: StatusLineMode::Top,
+            status_bar: true,
+            columns: 1,
+            rule: crate::layout::DEFAULT_RULE.to_string(),
+            color: ColorMode::Full,
+            code_bg: CodeBackground::Theme,
+            code_padding: crate::export::DEFAULT_CODE_PADDING,
+            mode: InputMode::Normal,
+            last_search: None,
+            themes: Vec::new(),
+            theme: crate::highlight::DEFAULT_THEME.to_string(),
+            theme_request: None,
+            theme_pair: [
+                crate::highlight::DEFAULT_THEME.to_string(),
+                crate::highlight::LIGHT_THEME.to_string(),
+            ],
+            light: false,
+            wrap_width: None,
+            raw: false,
+            flipped_diagrams: Vec::new(),
+            scroll_margin: DEFAULT_SCROLL_MARGIN,
         }
     }
 
-    /// Dispatches a key event to the appropriate scroll or quit action.
+    /// Dispatches a key event to the handler for the current `InputMode`.
     pub fn handle_key(&mut self, key: KeyEvent) {
+        // Any key press dismisses the previous status message.
+        self.status_message = None;
+        // Ctrl+C quits from any mode.
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.quit = true;
+            return;
+        }
+        match self.mode {
+            InputMode::Normal => self.handle_normal_key(key),
+            InputMode::Search { .. } => self.handle_search_key(key),
+            InputMode::Goto { .. } => self.handle_goto_key(key),
+            InputMode::Command { .. } => self.handle_command_key(key),
+            InputMode::Help => self.mode = InputMode::Normal,
+            InputMode::ThemePicker { .. } => self.handle_theme_picker_key(key),
+        }
+    }
+
+    /// Handles a key in `InputMode::Normal`. While the outline is open, its
+    /// keys come first, and `q` / Esc close it rather than quitting.
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        if self.outline.is_some() && self.handle_outline_key(key) {
+            return;
+        }
+        let Some(action) = keymap::action_for(key) else {
+            return;
+        };
+        match action {
+            Action::LineDown => self.scroll_down(1),
+            Action::LineUp => self.scroll_up(1),
+            Action::HalfPageDown => self.scroll_down((self.screen_lines() / 2).max(1)),
+            Action::HalfPageUp => self.scroll_up((self.screen_lines() / 2).max(1)),
+            // A full page, keeping one line of context
+            Action::PageDown => self.scroll_down(self.page_step()),
+            Action::PageUp => self.scroll_up(self.page_step()),
+            Action::Top => self.scroll_to_top(),
+            Action::Bottom => self.scroll_to_bottom(),
+            // The digit pressed is the first of the line number
+            Action::Goto => {
+                if let KeyCode::Char(c) = key.code {
+                    self.mode = InputMode::Goto { buffer: c.to_string() };
+                }
+            }
+            Action::NextHeading => self.next_heading(),
+            Action::PreviousHeading => self.previous_heading(),
+            Action::NextCodeBlock => self.next_code_block(),
+            Action::PreviousCodeBlock => self.previous_code_block(),
+            Action::Search => self.mode = InputMode::Search { buffer: String::new() },
+            Action::NextMatch => {
+                if let Some(query) = self.last_search.clone() {
+                    self.search_forward(&query);
+                }
+            }
+            Action::Outline => self.toggle_outline(),
+            // Width and wrap changes are re-flattened by the event loop
+            Action::Wrap => self.toggle_wrap(),
+            Action::StatusBar => self.status_bar = !self.status_bar,
+            Action::Wider => self.step_wrap_width(true),
+            Action::Narrower => self.step_wrap_width(false),
+            Action::ThemePicker => self.open_theme_picker(),
+            Action::Brightness => self.toggle_brightness(),
+            // The event loop re-parses
+            Action::Raw => self.toggle_raw(),
+            Action::Diagram => self.toggle_diagram(),
+            // Copying, editing and reloading are done by the event loop
+            Action::CopyVisible => self.copy_request = Some(CopyScope::Visible),
+            Action::CopyDocument => self.copy_request = Some(CopyScope::Document),
+            Action::CopyReflowed => self.copy_request = Some(CopyScope::Reflowed),
+            Action::Edit => self.edit_requested = true,
+            Action::Reload => self.reload_requested = true,
+            Action::Command => self.mode = InputMode::Command { buffer: String::new() },
+            Action::Help => self.mode = InputMode::Help,
+            Action::Quit => self.quit = true,
+        }
+    }
+
+    /// Handles a key while typing a search query. Every printable key,
+    /// `q` included, is part of the query: only Esc leaves without searching.
+    fn handle_search_key(&mut self, key: KeyEvent) {
+        let InputMode::Search { buffer } = &mut self.mode else {
+            return;
+        };
         match key.code {
-            // Scroll down 1 line
-            KeyCode::Char('j') | KeyCode::Down => self.scroll_down(1),
-            // Scroll up 1 line
-            KeyCode::Char('k') | KeyCode::Up => self.scroll_up(1),
-            // Scroll down half-page
-            KeyCode::Char('d') | KeyCode::PageDown => {
-                let half = self.viewport_height / 2;
-                self.scroll_down(half.max(1));
-            }
-            // Scroll up half-page
-            KeyCode::Char('u') | KeyCode::PageUp => {
-                let half = self.viewport_height / 2;
-                self.scroll_up(half.max(1));
-            }
-            // Scroll to top
-            KeyCode::Char('g') | KeyCode::Home => self.scroll_to_top(),
-            // Scroll to bottom (Shift+g = 'G')
-            KeyCode::Char('G') | KeyCode::End => self.scroll_to_bottom(),
-            // Quit
-            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
-            // Ctrl+C also quits
-            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            KeyCode::Enter => {
+                let query = std::mem::take(buffer);
+                self.mode = InputMode::Normal;
+                if !query.is_empty() {
+                    self.search_forward(&query);
+                    self.last_search = Some(query);
+                }
+            }
+            KeyCode::Backspace if !buffer.is_empty() => {
+                buffer.pop();
+            }
+            // Backspace on an empty query cancels, as in less.
+            KeyCode::Backspace | KeyCode::Esc => self.mode = InputMode::Normal,
+            KeyCode::Char(c) => buffer.push(c),
+            _ => {}
+        }
+    }
+
+    /// Handles a key while typing a line number. Other keys are ignored, so
+    /// a mistyped motion can't scroll away mid-number.
+    fn handle_goto_key(&mut self, key: KeyEvent) {
+        let InputMode::Goto { buffer } = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) if c.is_ascii_digit() => buffer.push(c),
+            KeyCode::Enter | KeyCode::Char('g' | 'G') => {
+                let line = buffer.parse().unwrap_or(usize::MAX);
+                self.mode = InputMode::Normal;
+                self.scroll_to_line(line);
+            }
+            KeyCode::Backspace if buffer.len() > 1 => {
+                buffer.pop();
+            }
+            KeyCode::Backspace | KeyCode::Esc | KeyCode::Char('q') => {
+                self.mode = InputMode::Normal;
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a key while typing a `:` command, as `handle_search_key` does
+    /// for a query.
+    fn handle_command_key(&mut self, key: KeyEvent) {
+        let InputMode::Command { buffer } = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Enter => {
+                let command = std::mem::take(buffer);
+                self.mode = InputMode::Normal;
+                self.run_command(&command);
+            }
+            KeyCode::Backspace if !buffer.is_empty() => {
+                buffer.pop();
+            }
+            KeyCode::Backspace | KeyCode::Esc => self.mode = InputMode::Normal,
+            KeyCode::Char(c) => buffer.push(c),
+            _ => {}
+        }
+    }
+
+    /// Runs a `:` command: `goto N` (or just `N`), `theme NAME`,
+    /// `set width N|auto`, `help` or `q`. Unknown commands and bad
+    /// arguments are reported in the status bar.
+    pub fn run_command(&mut self, command: &str) {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(()),
+            ["q" | "quit"] => {
                 self.quit = true;
+                Ok(())
+            }
+            ["h" | "help"] => {
+                self.mode = InputMode::Help;
+                Ok(())
+            }
+            ["goto", line] | [line] if line.chars().all(|c| c.is_ascii_digit()) => {
+                self.scroll_to_line(line.parse().unwrap_or(usize::MAX));
+                Ok(())
+            }
+            ["theme", name] if self.themes.iter().any(|t| t == name) => {
+                self.set_theme(name.to_string());
+                Ok(())
+            }
+            ["theme", name] => Err(format!("unknown theme: {name}")),
+            ["set", "width", "auto"] => {
+                self.wrap_width = None;
+                Ok(())
+            }
+            ["set", "width", width] => match width.parse::<u16>() {
+                Ok(width) => {
+                    self.wrap_width = Some(width.max(MIN_WRAP_WIDTH));
+                    Ok(())
+                }
+                Err(_) => Err(format!("not a width: {width}")),
+            },
+            _ => Err(format!("unknown command: {command}")),
+        };
+        if let Err(message) = result {
+            self.status_message = Some(message);
+        }
+    }
+
+    /// Returns true while an overlay (key help, theme picker) covers part of
+    /// the document.
+    pub fn overlay_open(&self) -> bool {
+        matches!(self.mode, InputMode::Help | InputMode::ThemePicker { .. })
+    }
+
+    /// Opens the theme picker at the current theme. Does nothing when no
+    /// themes are available.
+    pub fn open_theme_picker(&mut self) {
+        if self.themes.is_empty() {
+            return;
+        }
+        let selected = self.themes.iter().position(|t| *t == self.theme).unwrap_or(0);
+        self.mode = InputMode::ThemePicker {
+            selected,
+            original: self.theme.clone(),
+        };
+    }
+
+    /// Handles a key in the theme picker: every move previews the theme
+    /// under the cursor, Enter keeps it, Esc or `q` restores the original.
+    fn handle_theme_picker_key(&mut self, key: KeyEvent) {
+        let InputMode::ThemePicker { selected, original } = &mut self.mode else {
+            return;
+        };
+        let last = self.themes.len().saturating_sub(1);
+        let previous = *selected;
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => *selected = (*selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => *selected = selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => *selected = 0,
+            KeyCode::Char('G') | KeyCode::End => *selected = last,
+            KeyCode::Enter => {
+                self.mode = InputMode::Normal;
+                return;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                let original = std::mem::take(original);
+                self.mode = InputMode::Normal;
+                self.set_theme(original);
+                return;
             }
             _ => {}
         }
+        if *selected != previous
+            && let Some(theme) = self.themes.get(*selected).cloned()
+        {
+            self.set_theme(theme);
+        }
+    }
+
+    /// Switches between the dark and light theme of `theme_pair`, keeping
+    /// the scroll position. Always re-renders: heading colors change too.
+    pub fn toggle_brightness(&mut self) {
+        self.light = !self.light;
+        let theme = self.theme_pair[usize::from(self.light)].clone();
+        self.status_message = Some(format!("theme: {theme}"));
+        self.theme = theme.clone();
+        self.theme_request = Some(theme);
+    }
+
+    /// Switches the code theme, asking the event loop to re-highlight
+    /// unless it is already in use.
+    fn set_theme(&mut self, theme: String) {
+        if theme != self.theme {
+            self.theme = theme.clone();
+            self.theme_request = Some(theme);
+        }
+    }
+
+    /// Scrolls the first line past the jump origin containing `query`
+    /// (ignoring case) to it, or reports that there is none.
+    /// Lines not laid out yet are searched by completing a lazy layout.
+    pub fn search_forward(&mut self, query: &str) {
+        let needle = query.to_lowercase();
+        let find = |app: &Self| {
+            app.document
+                .lines
+                .iter()
+                .enumerate()
+                .skip(app.jump_origin() + 1)
+                .find(|(_, line)| line.plain_text().to_lowercase().contains(&needle))
+                .map(|(i, _)| i)
+        };
+        let mut found = find(self);
+        if found.is_none() && !self.document.is_complete() {
+            self.document.complete();
+            found = find(self);
+        }
+        match found {
+            Some(line) => self.jump_to(line),
+            None => self.status_message = Some(format!("Pattern not found: {query}")),
+        }
     }
 
-    /// Returns the range of line indices visible in the current viewport.
+    /// Handles keys that mean something else while the outline is open:
+    /// arrows move the selection, Enter jumps to it, Esc or `q` closes the
+    /// sidebar.
+    /// Returns false for keys that should fall through to normal handling.
+    fn handle_outline_key(&mut self, key: KeyEvent) -> bool {
+        let last = self.document.headings.len().saturating_sub(1);
+        let Some(outline) = self.outline.as_mut() else {
+            return false;
+        };
+        match key.code {
+            KeyCode::Down => outline.selected = (outline.selected + 1).min(last),
+            KeyCode::Up => outline.selected = outline.selected.saturating_sub(1),
+            KeyCode::Enter => {
+                if let Some(line) = self.document.headings.get(outline.selected).map(|h| h.line) {
+                    self.jump_to(line);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => self.outline = None,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Scrolls to the first heading past the jump origin.
+    ///
+    /// Clamps rather than wraps: past the last heading this does nothing.
+    /// Headings not laid out yet are found by completing a lazy layout.
+    pub fn next_heading(&mut self) {
+        let after = |app: &Self| {
+            let headings = &app.document.headings;
+            let next = headings.partition_point(|h| h.line <= app.jump_origin());
+            headings.get(next).map(|h| h.line)
+        };
+        let mut target = after(self);
+        if target.is_none() && !self.document.is_complete() {
+            self.document.complete();
+            target = after(self);
+        }
+        if let Some(line) = target {
+            self.jump_to(line);
+        }
+    }
+
+    /// Scrolls to the last heading before the jump origin.
+    /// Clamps rather than wraps: above the first heading this does nothing.
+    pub fn previous_heading(&mut self) {
+        let headings = &self.document.headings;
+        let before = headings.partition_point(|h| h.line < self.jump_origin());
+        if let Some(prev) = before.checked_sub(1) {
+            self.jump_to(headings[prev].line);
+        }
+    }
+
+    /// Scrolls to the first code block past the jump origin, completing a
+    /// lazy layout if none is laid out yet. Clamps like `next_heading()`.
+    pub fn next_code_block(&mut self) {
+        let after = |app: &Self| {
+            let starts = &app.document.code_starts;
+            starts.get(starts.partition_point(|&line| line <= app.jump_origin())).copied()
+        };
+        let mut target = after(self);
+        if target.is_none() && !self.document.is_complete() {
+            self.document.complete();
+            target = after(self);
+        }
+        if let Some(line) = target {
+            self.jump_to(line);
+        }
+    }
+
+    /// Scrolls to the last code block before the jump origin.
+    pub fn previous_code_block(&mut self) {
+        let starts = &self.document.code_starts;
+        let before = starts.partition_point(|&line| line < self.jump_origin());
+        if let Some(prev) = before.checked_sub(1) {
+            self.jump_to(starts[prev]);
+        }
+    }
+
+    /// Scrolls to display line `line` (1-based, counted after wrapping)
+    /// as to any jump target.
+    pub fn scroll_to_line(&mut self, line: usize) {
+        let offset = line.saturating_sub(1);
+        self.document.ensure_lines(offset.saturating_add(self.screen_lines()));
+        self.jump_to(offset);
+    }
+
+    /// Scrolls so that the jump target `line` is `scroll_margin` lines below
+    /// the viewport top, or as near as the document's ends allow.
+    fn jump_to(&mut self, line: usize) {
+        let offset = line.saturating_sub(self.effective_scroll_margin());
+        self.scroll_offset = offset.min(self.max_scroll());
+    }
+
+    /// Where `jump_to()` puts its target: searches and heading jumps count
+    /// from this line, so that repeating one moves on past the last target.
+    fn jump_origin(&self) -> usize {
+        self.scroll_offset + self.effective_scroll_margin()
+    }
+
+    /// `scroll_margin`, capped at half the viewport so a target stays on screen.
+    fn effective_scroll_margin(&self) -> usize {
+        self.scroll_margin.min(self.viewport_height.saturating_sub(1) / 2)
+    }
+
+    /// Scrolls `percent` of the way through the document, in the terms of
+    /// the status bar: 0 is the top, 100 the bottom.
+    pub fn scroll_to_percent(&mut self, percent: u8) {
+        self.document.complete();
+        let percent = usize::from(percent.min(100));
+        self.scroll_offset = (self.max_scroll() * percent).div_ceil(100);
+    }
+
+    /// Lines moved by a full-page scroll: a screenful less one, so the last
+    /// line of the old page stays visible as the first of the new.
+    fn page_step(&self) -> usize {
+        self.screen_lines().saturating_sub(1).max(1)
+    }
+
+    /// Returns how many document lines fit on screen: the viewport height
+    /// in each of the `columns`.
+    pub fn screen_lines(&self) -> usize {
+        self.viewport_height * usize::from(self.columns.max(1))
+    }
+
+    /// Switches between wrapped and clipped prose and says so in the status bar.
+    pub fn toggle_wrap(&mut self) {
+        let (wrap, message) = match self.wrap {
+            WrapMode::Wrap => (WrapMode::Clip, "wrap off: long lines are clipped"),
+            WrapMode::Clip => (WrapMode::Wrap, "wrap on"),
+        };
+        self.wrap = wrap;
+        self.status_message = Some(message.to_string());
+    }
+
+    /// Collapses the first diagram on screen to its placeholder line, or
+    /// expands it back to its source.
+    pub fn toggle_diagram(&mut self) {
+        let range = self.visible_range();
+        let Some(number) = self.document.diagrams.iter().position(|line| range.contains(line))
+        else {
+            self.status_message = Some("no diagram on screen".to_string());
+            return;
+        };
+        match self.flipped_diagrams.iter().position(|&flipped| flipped == number) {
+            Some(i) => {
+                self.flipped_diagrams.remove(i);
+            }
+            None => self.flipped_diagrams.push(number),
+        }
+    }
+
+    /// Returns true if the status bar is drawn: while `status_bar` is set,
+    /// and always while a search, line number or command is being typed.
+    pub fn status_bar_visible(&self) -> bool {
+        self.status_bar
+            || matches!(
+                self.mode,
+                InputMode::Search { .. } | InputMode::Goto { .. } | InputMode::Command { .. }
+            )
+    }
+
+    /// Returns the rows of a terminal `rows` tall left for the document:
+    /// all of them but the status bar's, when it is drawn.
+    pub fn content_rows(&self, rows: u16) -> u16 {
+        rows.saturating_sub(u16::from(self.status_bar_visible()))
+    }
+
+    /// Switches between the rendered document and its markdown source and
+    /// says so in the status bar.
+    pub fn toggle_raw(&mut self) {
+        self.raw = !self.raw;
+        let message = if self.raw { "raw markdown source" } else { "rendered" };
+        self.status_message = Some(message.to_string());
+    }
+
+    /// Widens or narrows the text by `WRAP_WIDTH_STEP` columns, between
+    /// `MIN_WRAP_WIDTH` and the full text column (which clears `wrap_width`),
+    /// and shows the new width in the status bar.
+    pub fn step_wrap_width(&mut self, wider: bool) {
+        let full = column_width(self.content_width(self.terminal_width), self.columns);
+        let current = self.column_width(self.terminal_width);
+        let width = if wider {
+            current.saturating_add(WRAP_WIDTH_STEP)
+        } else {
+            current.saturating_sub(WRAP_WIDTH_STEP).max(MIN_WRAP_WIDTH)
+        };
+        self.wrap_width = (width < full).then_some(width);
+        let width = width.min(full);
+        self.status_message = Some(if width == full {
+            format!("width: {width} (full)")
+        } else {
+            format!("width: {width}")
+        });
+    }
+
+    /// Opens the outline with the current heading selected, or closes it.
+    /// Opening completes a lazy layout so that every heading is listed.
+    pub fn toggle_outline(&mut self) {
+        self.outline = match self.outline {
+            Some(_) => None,
+            None => {
+                self.document.complete();
+                Some(Outline {
+                    selected: self.current_heading().unwrap_or(0),
+                })
+            }
+        };
+    }
+
+    /// Returns the name of the file the top visible line comes from:
+    /// `filename`, or with `files`, the file whose blocks it falls in.
+    pub fn current_file(&self) -> &str {
+        let block = self.document.anchor(self.scroll_offset).map_or(0, |anchor| anchor.block);
+        let after = self.files.partition_point(|(start, _)| *start <= block);
+        match after.checked_sub(1) {
+            Some(i) => &self.files[i].1,
+            None => &self.filename,
+        }
+    }
+
+    /// Returns the index of the heading nearest the top of the viewport:
+    /// the last heading starting at or above the jump origin, so a heading
+    /// just jumped to counts, else the first heading.
+    pub fn current_heading(&self) -> Option<usize> {
+        let headings = &self.document.headings;
+        if headings.is_empty() {
+            return None;
+        }
+        let after = headings.partition_point(|h| h.line <= self.jump_origin());
+        Some(after.saturating_sub(1))
+    }
+
+    /// Returns the sidebar width for a terminal `cols` wide: 0 while the
+    /// outline is hidden or doesn't fit, and at most a third of the terminal
+    /// otherwise.
+    pub fn sidebar_width(&self, cols: u16) -> u16 {
+        self.gutter_widths(cols)[0]
+    }
+
+    /// Returns the width available to document content: the terminal
+    /// width minus the sidebar and the left margin.
+    pub fn content_width(&self, cols: u16) -> u16 {
+        let [sidebar, margin] = self.gutter_widths(cols);
+        cols - sidebar - margin
+    }
+
+    /// Returns `[sidebar, margin]` widths at `cols` columns. On terminals too
+    /// narrow for both plus some content, the sidebar goes first, then the margin.
+    fn gutter_widths(&self, cols: u16) -> [u16; 2] {
+        let sidebar = match self.outline {
+            Some(_) => SIDEBAR_WIDTH.min(cols / 3),
+            None => 0,
+        };
+        crate::layout::fit_gutters(cols, [sidebar, self.left_margin])
+    }
+
+    /// Returns the status bar's position text, e.g. `12/200` or `12-34/200`,
+    /// with 1-based line numbers as chosen by `status_line`.
+    pub fn position_label(&self) -> String {
+        let total = self.document.total_height;
+        if total == 0 {
+            return "0/0".to_string();
+        }
+        let range = self.visible_range();
+        // With no room for content (viewport height 0), the range is empty
+        // and may start past the last line.
+        let top = (range.start + 1).min(total);
+        let bottom = range.end.max(top);
+        match self.status_line {
+            StatusLineMode::Top => format!("{top}/{total}"),
+            StatusLineMode::Bottom => format!("{bottom}/{total}"),
+            StatusLineMode::Range => format!("{top}-{bottom}/{total}"),
+        }
+    }
+
+    /// Returns the width of each text column: the content width split into
+    /// `columns`, less the gaps between them, and at most `wrap_width`. The
+    /// document is laid out at this width.
+    pub fn column_width(&self, cols: u16) -> u16 {
+        let width = column_width(self.content_width(cols), self.columns);
+        self.wrap_width.map_or(width, |max| max.min(width))
+    }
+
+    /// Returns whether the last line of the document is on screen, as
+    /// `(END)` in the status bar tells. A lazy layout is not at its end
+    /// until it is complete.
+    pub fn at_end(&self) -> bool {
+        self.document.is_complete() && self.visible_range().end >= self.document.total_height
+    }
+
+    /// Returns the range of line indices visible in the current viewport,
+    /// across all columns.
     pub fn visible_range(&self) -> Range<usize> {
-        let end = (self.scroll_offset + self.viewport_height).min(self.document.total_height);
+        let end = (self.scroll_offset + self.screen_lines()).min(self.document.total_height);
         self.scroll_offset..end
     }
 
+    /// Returns the plain text of the lines covered by `scope`, joined by newlines.
+    ///
+    /// `Reflowed` needs the IR, which `App` doesn't hold; here it falls back
+    /// to the laid-out document lines.
+    pub fn copy_text(&self, scope: CopyScope) -> String {
+        let range = match scope {
+            // A lazy layout may not have reached the end of the viewport yet.
+            CopyScope::Visible => {
+                let end = self.visible_range().end.min(self.document.lines.len());
+                self.scroll_offset.min(end)..end
+            }
+            CopyScope::Document | CopyScope::Reflowed => 0..self.document.lines.len(),
+        };
+        self.document.lines[range]
+            .iter()
+            .map(|line| line.plain_text())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     /// Scrolls down by `n` lines, clamped to the maximum scroll position.
     pub fn scroll_down(&mut self, n: usize) {
         let max = self.max_scroll();
@@ -98,18 +842,28 @@ impl App {
         self.scroll_offset = 0;
     }
 
-    /// Scrolls to the bottom of the document.
+    /// Scrolls to the bottom of the document, completing a lazy layout
+    /// first so the bottom is exact.
     pub fn scroll_to_bottom(&mut self) {
+        self.document.complete();
         self.scroll_offset = self.max_scroll();
     }
 
+    /// Clamps the scroll offset to the current `max_scroll()`.
+    ///
+    /// Called after the document is replaced (resize, reload) since the new
+    /// document may be shorter than the old one.
+    pub fn clamp_scroll(&mut self) {
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll());
+    }
+
     /// Returns the maximum valid scroll offset.
     ///
     /// When the document is shorter than the viewport, returns 0 (no scrolling).
     pub fn max_scroll(&self) -> usize {
         self.document
             .total_height
-            .saturating_sub(self.viewport_height)
+            .saturating_sub(self.screen_lines())
     }
 
     /// Returns the current scroll position as a percentage (0–100).
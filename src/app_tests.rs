@@ -3,10 +3,7 @@
 
     fn make_doc(line_count: usize) -> PreRenderedDocument {
         let lines = (0..line_count).map(|_| DocumentLine::Empty).collect();
-        PreRenderedDocument {
-            lines,
-            total_height: line_count,
-        }
+        PreRenderedDocument::from_lines(lines)
     }
 
     fn make_app(doc_lines: usize, viewport: usize) -> App {
@@ -64,6 +61,20 @@
         assert_eq!(app.visible_range(), 0..3);
     }
 
+    #[test]
+    fn test_app_at_end_once_last_line_is_visible() {
+        let mut app = make_app(20, 5);
+        assert!(!app.at_end());
+        app.scroll_down(14);
+        assert!(!app.at_end());
+        app.scroll_down(1);
+        assert!(app.at_end());
+        // Pressing on stays at the end.
+        app.scroll_down(1);
+        assert!(app.at_end() && app.scroll_offset == 15);
+        assert!(make_app(3, 10).at_end(), "a short document is all on screen");
+    }
+
     #[test]
     fn test_app_scroll_percent_at_top() {
         let app = make_app(20, 5);
@@ -143,3 +154,768 @@
         app.handle_key(key);
         assert_eq!(app.scroll_offset, 4);
     }
+
+    #[test]
+    fn test_app_handle_key_e_requests_edit() {
+        let mut app = make_app(20, 5);
+        let key = KeyEvent::new(KeyCode::Char('e'), KeyModifiers::empty());
+        app.handle_key(key);
+        assert!(app.edit_requested);
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_clamp_scroll_after_document_shrinks() {
+        let mut app = make_app(20, 5);
+        app.scroll_to_bottom();
+        app.document = make_doc(8);
+        app.clamp_scroll();
+        assert_eq!(app.scroll_offset, 3);
+    }
+
+    fn make_text_app(texts: &[&str], viewport: usize) -> App {
+        let lines: Vec<DocumentLine> = texts
+            .iter()
+            .map(|t| DocumentLine::Text(ratatui::text::Line::from(t.to_string())))
+            .collect();
+        let mut app = App::new(PreRenderedDocument::from_lines(lines), "test.md".to_string());
+        app.viewport_height = viewport;
+        app
+    }
+
+    #[test]
+    fn test_app_handle_key_y_requests_visible_copy() {
+        let mut app = make_app(20, 5);
+        app.handle_key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::empty()));
+        assert_eq!(app.copy_request, Some(CopyScope::Visible));
+        app.handle_key(KeyEvent::new(KeyCode::Char('Y'), KeyModifiers::SHIFT));
+        assert_eq!(app.copy_request, Some(CopyScope::Document));
+    }
+
+    #[test]
+    fn test_app_copy_text_visible_range_only() {
+        let mut app = make_text_app(&["one", "two", "three", "four"], 2);
+        app.scroll_offset = 1;
+        assert_eq!(app.copy_text(CopyScope::Visible), "two\nthree");
+        assert_eq!(app.copy_text(CopyScope::Document), "one\ntwo\nthree\nfour");
+    }
+
+    #[test]
+    fn test_app_key_press_clears_status_message() {
+        let mut app = make_app(20, 5);
+        app.status_message = Some("copied 3 lines".to_string());
+        app.handle_key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::empty()));
+        assert!(app.status_message.is_none());
+    }
+
+    fn make_outline_app() -> App {
+        let mut app = make_app(40, 10);
+        app.document.headings = [(1, 0), (2, 12), (2, 25)]
+            .into_iter()
+            .map(|(level, line)| crate::layout::HeadingEntry {
+                level,
+                text: format!("h{line}"),
+                line,
+            })
+            .collect();
+        app
+    }
+
+    #[test]
+    fn test_app_current_heading_tracks_scroll() {
+        let mut app = make_outline_app();
+        assert_eq!(app.current_heading(), Some(0));
+        app.scroll_offset = 12;
+        assert_eq!(app.current_heading(), Some(1));
+        app.scroll_offset = 20;
+        assert_eq!(app.current_heading(), Some(1));
+        app.document.headings.clear();
+        assert_eq!(app.current_heading(), None);
+    }
+
+    #[test]
+    fn test_app_o_toggles_outline_at_current_heading() {
+        let mut app = make_outline_app();
+        app.scroll_offset = 26;
+        app.handle_key(KeyEvent::new(KeyCode::Char('o'), KeyModifiers::empty()));
+        assert_eq!(app.outline, Some(Outline { selected: 2 }));
+        assert_eq!(app.content_width(120), 90);
+        app.handle_key(KeyEvent::new(KeyCode::Char('o'), KeyModifiers::empty()));
+        assert_eq!(app.outline, None);
+        assert_eq!(app.content_width(120), 120);
+    }
+
+    #[test]
+    fn test_app_outline_arrows_and_enter_scroll_content() {
+        let mut app = make_outline_app();
+        app.toggle_outline();
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 0, "arrows move the outline cursor, not the content");
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()));
+        // The heading at 12 lands below `DEFAULT_SCROLL_MARGIN` lines of context.
+        assert_eq!(app.scroll_offset, 10);
+        // Selection clamps at the last heading.
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()));
+        app.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::empty()));
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::empty()));
+        assert_eq!(app.outline, Some(Outline { selected: 2 }));
+        assert_eq!(app.scroll_offset, 23);
+        // j still scrolls the content; Esc closes the sidebar instead of quitting.
+        app.handle_key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 24);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()));
+        assert!(app.outline.is_none());
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_handle_key_c_requests_reflowed_copy_ctrl_c_quits() {
+        let mut app = make_app(20, 5);
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::empty()));
+        assert_eq!(app.copy_request, Some(CopyScope::Reflowed));
+        assert!(!app.quit);
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_app_content_width_subtracts_margin_and_sidebar() {
+        let mut app = make_app(20, 5);
+        app.left_margin = 2;
+        assert_eq!(app.content_width(80), 78);
+        app.toggle_outline();
+        assert_eq!(app.content_width(120), 88);
+        // A margin wider than the terminal is dropped (with the sidebar)
+        // rather than leaving no room for content.
+        app.left_margin = 200;
+        assert_eq!(app.content_width(80), 80);
+    }
+
+    #[test]
+    fn test_app_narrow_terminal_drops_sidebar_then_margin() {
+        let mut app = make_app(20, 5);
+        app.left_margin = 4;
+        app.toggle_outline();
+        // 30 cols: sidebar 10 + margin 4 leaves 16 — everything fits.
+        assert_eq!((app.sidebar_width(30), app.content_width(30)), (10, 16));
+        // 20 cols: sidebar 6 + margin 4 would leave 10 — still fits.
+        assert_eq!((app.sidebar_width(20), app.content_width(20)), (6, 10));
+        // 18 cols: the sidebar goes first, the margin stays.
+        assert_eq!((app.sidebar_width(18), app.content_width(18)), (0, 14));
+        // 12 cols: the margin goes too.
+        assert_eq!((app.sidebar_width(12), app.content_width(12)), (0, 12));
+    }
+
+    #[test]
+    fn test_app_widths_1_to_10_give_content_every_column() {
+        let mut app = make_app(20, 5);
+        app.left_margin = 3;
+        app.toggle_outline();
+        for cols in 1..=10 {
+            assert_eq!(app.sidebar_width(cols), 0, "cols={cols}");
+            assert_eq!(app.content_width(cols), cols, "cols={cols}");
+        }
+    }
+
+    #[test]
+    fn test_app_scroll_to_bottom_completes_lazy_layout() {
+        use crate::parser::{RenderedBlock, StyledSpan};
+        use std::rc::Rc;
+        let blocks: Rc<[RenderedBlock]> = (0..20_000)
+            .map(|_| RenderedBlock::Paragraph {
+                content: vec![StyledSpan {
+                    text: "x".to_string(),
+                    style: ratatui::style::Style::default(),
+                    reference: None,
+                }],
+            })
+            .collect();
+        let doc = crate::layout::flatten_lazy(blocks, 80, &Default::default());
+        assert!(!doc.is_complete());
+        let mut app = App::new(doc, "big.md".to_string());
+        app.viewport_height = 10;
+        app.scroll_offset = app.max_scroll();
+        assert!(!app.at_end(), "the estimated bottom is not the end");
+        app.handle_key(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT));
+        assert!(app.document.is_complete() && app.at_end());
+        // 20,000 one-line paragraphs plus 19,999 spacing lines.
+        assert_eq!(app.document.total_height, 39_999);
+        assert_eq!(app.scroll_offset, 39_989);
+    }
+
+    #[test]
+    fn test_app_w_toggles_wrap_mode() {
+        let mut app = make_app(20, 5);
+        assert_eq!(app.wrap, WrapMode::Wrap);
+        let w = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::empty());
+        app.handle_key(w);
+        assert_eq!(app.wrap, WrapMode::Clip);
+        assert!(app.status_message.is_some());
+        app.handle_key(w);
+        assert_eq!(app.wrap, WrapMode::Wrap);
+    }
+
+    #[test]
+    fn test_app_r_requests_reload() {
+        let mut app = make_app(20, 5);
+        app.handle_key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::empty()));
+        assert!(app.reload_requested);
+    }
+
+    #[test]
+    fn test_app_full_page_keys_keep_one_line_of_context() {
+        let mut app = make_app(100, 10);
+        let space = KeyEvent::new(KeyCode::Char(' '), KeyModifiers::empty());
+        let ctrl_f = KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL);
+        let ctrl_b = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::CONTROL);
+        let b = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::empty());
+
+        app.handle_key(space);
+        // Line 9 was the last visible line; it is now the first.
+        assert_eq!(app.scroll_offset, 9);
+        app.handle_key(ctrl_f);
+        assert_eq!(app.scroll_offset, 18);
+        app.handle_key(b);
+        assert_eq!(app.scroll_offset, 9);
+        app.handle_key(ctrl_b);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_app_full_page_keys_clamp_at_document_ends() {
+        let mut app = make_app(25, 10);
+        let space = KeyEvent::new(KeyCode::Char(' '), KeyModifiers::empty());
+        app.handle_key(space);
+        app.handle_key(space);
+        app.handle_key(space);
+        assert_eq!(app.scroll_offset, 15);
+        let b = KeyEvent::new(KeyCode::Char('b'), KeyModifiers::empty());
+        app.handle_key(b);
+        app.handle_key(b);
+        app.handle_key(b);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_app_full_page_tiny_viewport_still_moves() {
+        let mut app = make_app(20, 1);
+        app.handle_key(KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL));
+        assert_eq!(app.scroll_offset, 1);
+    }
+
+    #[test]
+    fn test_app_position_label_modes() {
+        let mut app = make_app(200, 23);
+        app.scroll_offset = 11;
+        assert_eq!(app.position_label(), "12/200");
+        app.status_line = StatusLineMode::Bottom;
+        assert_eq!(app.position_label(), "34/200");
+        app.status_line = StatusLineMode::Range;
+        assert_eq!(app.position_label(), "12-34/200");
+    }
+
+    #[test]
+    fn test_app_position_label_short_and_empty_documents() {
+        let mut app = make_app(5, 23);
+        app.status_line = StatusLineMode::Range;
+        assert_eq!(app.position_label(), "1-5/5");
+        let mut empty = make_app(0, 23);
+        empty.status_line = StatusLineMode::Range;
+        assert_eq!(empty.position_label(), "0/0");
+    }
+
+    #[test]
+    fn test_app_brace_keys_move_between_headings() {
+        let mut app = make_outline_app();
+        let next = KeyEvent::new(KeyCode::Char('}'), KeyModifiers::empty());
+        let prev = KeyEvent::new(KeyCode::Char('{'), KeyModifiers::empty());
+        // Each heading lands below `DEFAULT_SCROLL_MARGIN` lines of context.
+        app.handle_key(next);
+        assert_eq!(app.scroll_offset, 10);
+        app.handle_key(next);
+        assert_eq!(app.scroll_offset, 23);
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, 10);
+        // From between two headings, `{` goes to the one above.
+        app.scroll_offset = 20;
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, 10);
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_app_brace_keys_clamp_at_ends() {
+        let mut app = make_outline_app();
+        let next = KeyEvent::new(KeyCode::Char('}'), KeyModifiers::empty());
+        let prev = KeyEvent::new(KeyCode::Char('{'), KeyModifiers::empty());
+        // Nothing above the first heading: no wrap to the last one.
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, 0);
+        app.scroll_offset = 28;
+        app.handle_key(next);
+        assert_eq!(app.scroll_offset, 28);
+    }
+
+    #[test]
+    fn test_app_next_heading_clamps_to_max_scroll() {
+        let mut app = make_outline_app();
+        app.viewport_height = 20;
+        app.scroll_offset = 12;
+        app.handle_key(KeyEvent::new(KeyCode::Char('}'), KeyModifiers::empty()));
+        // The heading at line 25 can't reach the top of a 20-line viewport.
+        assert_eq!(app.scroll_offset, 20);
+    }
+
+    #[test]
+    fn test_app_next_heading_in_flattened_document() {
+        let source = "# A\n\npara\n\n## B\n\ntext\n\n## C\n\nend";
+        let highlighter = crate::highlight::Highlighter::new();
+        let blocks = crate::parser::parse(source, &highlighter);
+        let mut app = App::new(crate::layout::flatten(&blocks, 80), "t.md".to_string());
+        app.viewport_height = 2;
+        let lines: Vec<usize> = app.document.headings.iter().map(|h| h.line).collect();
+        assert_eq!(lines, [0, 4, 8]);
+        app.handle_key(KeyEvent::new(KeyCode::Char('}'), KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 4);
+        app.handle_key(KeyEvent::new(KeyCode::Char('}'), KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 8);
+    }
+
+    #[test]
+    fn test_app_bracket_keys_move_between_code_blocks() {
+        let source = "# Intro\n\nprose\n\n```sh\nmake\n```\n\nmore prose\n\n```rust\nfn main() {}\n\
+                      ```\n\n## Next\n\n```\nplain\n```\n\nend";
+        let highlighter = crate::highlight::Highlighter::new();
+        let blocks = crate::parser::parse(source, &highlighter);
+        let mut app = App::new(crate::layout::flatten(&blocks, 80), "t.md".to_string());
+        app.viewport_height = 3;
+        app.scroll_margin = 0;
+        let starts = app.document.code_starts.clone();
+        assert_eq!(starts.len(), 3);
+        let next = KeyEvent::new(KeyCode::Char(']'), KeyModifiers::empty());
+        let prev = KeyEvent::new(KeyCode::Char('['), KeyModifiers::empty());
+        for &start in &starts {
+            app.handle_key(next);
+            assert_eq!(app.scroll_offset, start);
+        }
+        // Past the last code block `]` stays put; `[` walks back.
+        app.handle_key(next);
+        assert_eq!(app.scroll_offset, starts[2]);
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, starts[1]);
+        app.handle_key(prev);
+        app.handle_key(prev);
+        assert_eq!(app.scroll_offset, starts[0]);
+    }
+
+    #[test]
+    fn test_app_scroll_to_line_is_one_based_and_clamped() {
+        let mut app = make_app(20, 5);
+        app.scroll_to_line(1);
+        assert_eq!(app.scroll_offset, 0);
+        // Line 8 is index 7, with two lines of context above.
+        app.scroll_to_line(8);
+        assert_eq!(app.scroll_offset, 5);
+        app.scroll_to_line(1000);
+        assert_eq!(app.scroll_offset, 15);
+        app.scroll_to_line(0);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_app_scroll_to_percent_matches_status_bar() {
+        let mut app = make_app(20, 5);
+        app.scroll_to_percent(50);
+        assert_eq!(app.scroll_offset, 8);
+        assert!(app.scroll_percent() >= 50);
+        app.scroll_to_percent(100);
+        assert_eq!(app.scroll_offset, 15);
+        app.scroll_to_percent(0);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn test_app_two_columns_double_the_screen() {
+        let mut app = make_app(50, 10);
+        app.columns = 2;
+        assert_eq!(app.screen_lines(), 20);
+        assert_eq!(app.visible_range(), 0..20);
+        assert_eq!(app.max_scroll(), 30);
+        app.handle_key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 19);
+        app.handle_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::empty()));
+        assert_eq!(app.scroll_offset, 29);
+        app.scroll_to_bottom();
+        assert_eq!(app.visible_range(), 30..50);
+    }
+
+    #[test]
+    fn test_app_column_width_leaves_gaps() {
+        assert_eq!(column_width(80, 1), 80);
+        assert_eq!(column_width(163, 2), 80);
+        assert_eq!(column_width(2, 2), 0);
+        let mut app = make_app(10, 5);
+        app.columns = 2;
+        app.left_margin = 3;
+        assert_eq!(app.column_width(203), 98);
+    }
+
+    #[test]
+    fn test_app_zero_height_viewport() {
+        let mut app = make_app(30, 0);
+        assert!(app.visible_range().is_empty());
+        assert_eq!(app.scroll_percent(), 0);
+        for c in ['j', ' ', 'd', 'G', '}'] {
+            app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty()));
+            assert!(app.visible_range().is_empty());
+            assert!(app.scroll_percent() <= 100);
+        }
+        assert_eq!(app.scroll_offset, 30);
+        assert_eq!(app.position_label(), "30/30");
+        assert!(app.copy_text(CopyScope::Visible).is_empty());
+        // Growing the terminal again brings the last screenful back.
+        app.viewport_height = 10;
+        app.clamp_scroll();
+        assert_eq!(app.visible_range(), 20..30);
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::empty()));
+    }
+
+    fn type_str(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_app_search_esc_cancels_without_quitting() {
+        let mut app = make_text_app(&["alpha", "beta", "gamma"], 1);
+        press(&mut app, KeyCode::Char('/'));
+        type_str(&mut app, "gam");
+        assert_eq!(app.mode, InputMode::Search { buffer: "gam".to_string() });
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert!(!app.quit);
+        assert_eq!(app.scroll_offset, 0);
+        // Back in Normal mode, Esc quits again.
+        press(&mut app, KeyCode::Esc);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_app_search_q_is_typed_not_quit() {
+        let mut app = make_text_app(&["alpha", "quux"], 1);
+        press(&mut app, KeyCode::Char('/'));
+        type_str(&mut app, "qj");
+        assert!(!app.quit);
+        assert_eq!(app.scroll_offset, 0, "j is typed, not a scroll");
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.scroll_offset, 1);
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_search_ctrl_c_still_quits() {
+        let mut app = make_text_app(&["alpha"], 1);
+        press(&mut app, KeyCode::Char('/'));
+        app.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_app_search_backspace_on_empty_query_cancels() {
+        let mut app = make_text_app(&["alpha"], 1);
+        press(&mut app, KeyCode::Char('/'));
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(app.mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn test_app_search_n_repeats_and_reports_misses() {
+        let mut app = make_text_app(&["Match", "x", "match", "y", "z"], 1);
+        press(&mut app, KeyCode::Char('/'));
+        type_str(&mut app, "MATCH");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.scroll_offset, 2, "searches below the top line, ignoring case");
+        press(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.scroll_offset, 2);
+        assert_eq!(app.status_message.as_deref(), Some("Pattern not found: MATCH"));
+    }
+
+    #[test]
+    fn test_app_scroll_margin_keeps_context_above_search_matches() {
+        let texts: Vec<String> = (0..40)
+            .map(|i| if i % 10 == 5 { "match".to_string() } else { i.to_string() })
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let mut app = make_text_app(&texts, 10);
+        app.scroll_margin = 3;
+        press(&mut app, KeyCode::Char('/'));
+        type_str(&mut app, "match");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.scroll_offset, 2);
+        // `n` moves on from the match in view rather than finding it again.
+        press(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.scroll_offset, 12);
+
+        // Half the viewport at most, so the target stays on screen.
+        app.scroll_margin = 100;
+        press(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.scroll_offset, 25 - 4);
+    }
+
+    #[test]
+    fn test_app_outline_q_closes_before_quitting() {
+        let mut app = make_outline_app();
+        app.toggle_outline();
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.outline.is_none());
+        assert!(!app.quit);
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_app_goto_jumps_to_typed_line() {
+        let mut app = make_app(100, 10);
+        type_str(&mut app, "42");
+        assert_eq!(app.mode, InputMode::Goto { buffer: "42".to_string() });
+        assert_eq!(app.scroll_offset, 0);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.scroll_offset, 41 - DEFAULT_SCROLL_MARGIN);
+        type_str(&mut app, "7g");
+        assert_eq!(app.scroll_offset, 6 - DEFAULT_SCROLL_MARGIN);
+        // Past the end clamps like any other scroll.
+        type_str(&mut app, "99999999999999999999999G");
+        assert_eq!(app.scroll_offset, 90);
+    }
+
+    #[test]
+    fn test_app_goto_ignores_motions_and_esc_cancels() {
+        let mut app = make_app(100, 10);
+        type_str(&mut app, "5jd");
+        assert_eq!(app.scroll_offset, 0, "motion keys don't fire mid-number");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.scroll_offset, 0);
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_help_any_key_closes_without_acting() {
+        let mut app = make_app(100, 10);
+        press(&mut app, KeyCode::Char('?'));
+        assert_eq!(app.mode, InputMode::Help);
+        press(&mut app, KeyCode::Char('q'));
+        assert_eq!(app.mode, InputMode::Normal);
+        assert!(!app.quit);
+        press(&mut app, KeyCode::Char('?'));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.scroll_offset, 0);
+        press(&mut app, KeyCode::Esc);
+        assert!(app.quit);
+    }
+
+    fn make_theme_app() -> App {
+        let mut app = make_app(10, 5);
+        app.themes = ["a", "b", "c"].map(String::from).to_vec();
+        app.theme = "b".to_string();
+        app
+    }
+
+    #[test]
+    fn test_app_theme_picker_previews_and_enter_keeps() {
+        let mut app = make_theme_app();
+        press(&mut app, KeyCode::Char('T'));
+        assert_eq!(app.mode, InputMode::ThemePicker { selected: 1, original: "b".to_string() });
+        assert_eq!(app.theme_request, None);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.theme_request.take().as_deref(), Some("c"));
+        // Clamped at the last theme: nothing to re-highlight.
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.theme_request, None);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.theme, "c");
+        assert_eq!(app.theme_request, None);
+    }
+
+    #[test]
+    fn test_app_theme_picker_esc_reverts() {
+        let mut app = make_theme_app();
+        press(&mut app, KeyCode::Char('T'));
+        press(&mut app, KeyCode::Char('g'));
+        assert_eq!(app.theme_request.take().as_deref(), Some("a"));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.theme, "b");
+        assert_eq!(app.theme_request.as_deref(), Some("b"));
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_theme_picker_needs_themes() {
+        let mut app = make_app(10, 5);
+        press(&mut app, KeyCode::Char('T'));
+        assert_eq!(app.mode, InputMode::Normal);
+    }
+
+    #[test]
+    fn test_app_b_toggles_theme_pair_and_keeps_scroll() {
+        let mut app = make_app(100, 10);
+        app.scroll_offset = 40;
+        press(&mut app, KeyCode::Char('B'));
+        assert!(app.light);
+        assert_eq!(app.theme, crate::highlight::LIGHT_THEME);
+        assert_eq!(app.theme_request.take().as_deref(), Some(crate::highlight::LIGHT_THEME));
+        press(&mut app, KeyCode::Char('B'));
+        assert!(!app.light);
+        assert_eq!(app.theme_request.as_deref(), Some(crate::highlight::DEFAULT_THEME));
+        assert_eq!(app.scroll_offset, 40);
+    }
+
+    #[test]
+    fn test_app_r_toggles_raw_source_view() {
+        let mut app = make_app(100, 10);
+        press(&mut app, KeyCode::Char('R'));
+        assert!(app.raw);
+        assert_eq!(app.status_message.as_deref(), Some("raw markdown source"));
+        press(&mut app, KeyCode::Char('R'));
+        assert!(!app.raw);
+        assert_eq!(app.status_message.as_deref(), Some("rendered"));
+    }
+
+    fn run(app: &mut App, command: &str) {
+        press(app, KeyCode::Char(':'));
+        type_str(app, command);
+        press(app, KeyCode::Enter);
+    }
+
+    #[test]
+    fn test_app_command_goto_and_bare_line_number() {
+        let mut app = make_app(100, 10);
+        press(&mut app, KeyCode::Char(':'));
+        type_str(&mut app, "goto 42");
+        assert_eq!(app.mode, InputMode::Command { buffer: "goto 42".to_string() });
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert_eq!(app.scroll_offset, 41 - DEFAULT_SCROLL_MARGIN);
+        run(&mut app, "7");
+        assert_eq!(app.scroll_offset, 6 - DEFAULT_SCROLL_MARGIN);
+        assert_eq!(app.status_message, None);
+    }
+
+    #[test]
+    fn test_app_command_theme_help_and_quit() {
+        let mut app = make_theme_app();
+        run(&mut app, "theme c");
+        assert_eq!(app.theme_request.take().as_deref(), Some("c"));
+        run(&mut app, "theme nope");
+        assert_eq!(app.status_message.as_deref(), Some("unknown theme: nope"));
+        assert_eq!(app.theme, "c");
+        run(&mut app, "help");
+        assert_eq!(app.mode, InputMode::Help);
+        press(&mut app, KeyCode::Esc);
+        run(&mut app, "q");
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_app_command_set_width_clamps_column_width() {
+        let mut app = make_app(10, 5);
+        run(&mut app, "set width 60");
+        assert_eq!(app.column_width(100), 60);
+        assert_eq!(app.column_width(40), 40);
+        run(&mut app, "set width 5");
+        assert_eq!(app.wrap_width, Some(MIN_WRAP_WIDTH));
+        run(&mut app, "set width wide");
+        assert_eq!(app.status_message.as_deref(), Some("not a width: wide"));
+        run(&mut app, "set width auto");
+        assert_eq!(app.column_width(100), 100);
+    }
+
+    #[test]
+    fn test_app_command_unknown_is_reported_and_esc_cancels() {
+        let mut app = make_app(10, 5);
+        run(&mut app, "frobnicate 3");
+        assert_eq!(app.status_message.as_deref(), Some("unknown command: frobnicate 3"));
+        press(&mut app, KeyCode::Char(':'));
+        type_str(&mut app, "q");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, InputMode::Normal);
+        assert!(!app.quit);
+    }
+
+    #[test]
+    fn test_app_plus_minus_step_wrap_width_within_terminal() {
+        let mut app = make_app(10, 5);
+        app.terminal_width = 30;
+        press(&mut app, KeyCode::Char('-'));
+        assert_eq!(app.column_width(30), 26);
+        assert_eq!(app.status_message.as_deref(), Some("width: 26"));
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Char('-'));
+        }
+        assert_eq!(app.wrap_width, Some(MIN_WRAP_WIDTH));
+        press(&mut app, KeyCode::Char('+'));
+        assert_eq!(app.column_width(30), 24);
+        press(&mut app, KeyCode::Char('+'));
+        press(&mut app, KeyCode::Char('+'));
+        // Back at the full width: no override left.
+        assert_eq!(app.wrap_width, None);
+        assert_eq!(app.status_message.as_deref(), Some("width: 30 (full)"));
+    }
+
+    #[test]
+    fn test_app_status_bar_toggle_gives_its_row_to_the_document() {
+        let mut app = make_app(100, 9);
+        app.scroll_to_bottom();
+        assert_eq!(app.content_rows(10), 9);
+        press(&mut app, KeyCode::Char('s'));
+        assert!(!app.status_bar);
+        assert_eq!(app.content_rows(10), 10);
+        // The bar comes back while typing a search.
+        press(&mut app, KeyCode::Char('/'));
+        assert_eq!(app.content_rows(10), 9);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.content_rows(1), 1);
+
+        // The event loop resizes the viewport; the bottom stays reachable.
+        app.viewport_height = usize::from(app.content_rows(10));
+        app.clamp_scroll();
+        app.scroll_to_bottom();
+        assert_eq!(app.scroll_offset, 90);
+        press(&mut app, KeyCode::Char('s'));
+        app.viewport_height = usize::from(app.content_rows(10));
+        app.clamp_scroll();
+        assert_eq!(app.scroll_offset, 90);
+        app.scroll_to_bottom();
+        assert_eq!(app.scroll_offset, 91);
+    }
+
+    #[test]
+    fn test_app_toggle_diagram_flips_the_first_one_on_screen() {
+        let highlighter = crate::highlight::Highlighter::new();
+        let source = "```mermaid\ngraph TD\n```\n\ntext\n\n```dot\ndigraph {}\n```\n";
+        let blocks = crate::parser::parse(source, &highlighter);
+        let mut app = App::new(crate::layout::flatten(&blocks, 80), "t.md".to_string());
+        app.viewport_height = 3;
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.flipped_diagrams, [0]);
+        app.scroll_offset = 4;
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.flipped_diagrams, [0, 1]);
+        app.scroll_offset = 0;
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.flipped_diagrams, [1]);
+        app.scroll_offset = 3;
+        app.viewport_height = 1;
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.status_message.as_deref(), Some("no diagram on screen"));
+    }
@@ -4,14 +4,400 @@
 //! the Phase 7 xtask can import it via `#[path]` for man page and
 //! shell completion generation.
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 /// Terminal markdown renderer.
 #[derive(Parser)]
 #[command(name = "mdink", version, about = "Terminal markdown renderer")]
 pub struct Cli {
-    /// Markdown file to render (use "-" for stdin).
-    pub file: String,
+    /// Markdown file or http(s) URL to render (use "-" for stdin).
+    #[arg(required_unless_present_any = ["theme_preview", "print_keys", "concat", "demo"])]
+    pub file: Option<String>,
 
-    // Later phases will add: --style, --width, --pager, --no-images, --list-themes
+    /// Render these files (after FILE, if given) as one document, with a
+    /// rule between each and the next, e.g. a book's chapters. The status
+    /// bar names the file at the top of the screen. Relative image paths
+    /// resolve against the first file's directory.
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    pub concat: Vec<String>,
+
+    /// Show a built-in sample document instead of a file: a tour of what
+    /// mdink renders and the keys to try.
+    #[arg(long, conflicts_with_all = ["file", "concat"])]
+    pub demo: bool,
+
+    /// Show only the newest entry of a changelog: from the first level-2
+    /// heading to the next heading of level 1 or 2. Documents without a
+    /// level-2 heading are shown whole.
+    #[arg(long)]
+    pub latest: bool,
+
+    /// Largest file, download or stdin input accepted, in bytes (default 100 MB).
+    #[arg(long, value_name = "BYTES")]
+    pub max_file_bytes: Option<u64>,
+
+    /// Hide images entirely: no terminal graphics and no alt text.
+    #[arg(long)]
+    pub no_images: bool,
+
+    /// Shrink a paragraph of nothing but images and links (like the badge
+    /// row atop many READMEs) to one dim line of the images' alt texts.
+    #[arg(long)]
+    pub collapse_badges: bool,
+
+    /// Show diagram code blocks (mermaid, plantuml, dot), which a terminal
+    /// can't draw, as one "[mermaid diagram — N lines]" line. `x` in the
+    /// viewer expands or collapses the first one on screen.
+    #[arg(long)]
+    pub collapse_diagrams: bool,
+
+    /// Render curly quotes, en/em dashes and ellipses (code is left verbatim).
+    #[arg(long)]
+    pub smart_punctuation: bool,
+
+    /// Expand tabs in code blocks and paragraph text to spaces, with tab
+    /// stops every N columns, instead of leaving their width to the terminal.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub tab_to_spaces: Option<u16>,
+
+    /// Warn on stderr about code blocks indented with both tabs and
+    /// spaces, which misalign once tabs are expanded. Rendering is the same.
+    #[arg(long)]
+    pub warn_mixed_indent: bool,
+
+    /// Keep runs of blank lines between blocks (up to 3) instead of one.
+    #[arg(long)]
+    pub preserve_blank_lines: bool,
+
+    /// Most blank lines in a row between blocks (default 1, or 3 with
+    /// --preserve-blank-lines).
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_blank_lines: Option<u16>,
+
+    /// Paragraph alignment. Headings and code are never justified.
+    #[arg(long, value_enum, default_value_t = Align::Left)]
+    pub default_align: Align,
+
+    /// Blank lines between blocks: "compact" drops them after headings and
+    /// before lists, "airy" doubles them around headings.
+    #[arg(long, value_enum, default_value_t = Spacing::Normal)]
+    pub spacing: Spacing,
+
+    /// How to render YAML front matter at the top of the document.
+    #[arg(long, value_enum, default_value_t = FrontMatterMode::Hide)]
+    pub frontmatter: FrontMatterMode,
+
+    /// Raw HTML handling. "render" shows <details> sections (summary in
+    /// bold, body indented); other HTML is always dropped.
+    #[arg(long, value_enum, default_value_t = HtmlMode::Drop)]
+    pub html: HtmlMode,
+
+    /// Print the rendered document to stdout with ANSI styling and exit
+    /// instead of opening the viewer.
+    #[arg(long)]
+    pub dump: bool,
+
+    /// Write the rendered document to stdout in FORMAT and exit instead of
+    /// opening the viewer: "ansi" is --dump, "html" a standalone page with
+    /// inline styles.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+
+    /// With --dump or --output, write only display lines START to END
+    /// (counted from 1 after wrapping, both included), e.g. "10-25" or "7".
+    /// Bounds past the end of the document are clamped to it.
+    #[arg(long, value_name = "START-END")]
+    pub select: Option<String>,
+
+    /// Write the markdown after the YAML front matter to stdout, unrendered,
+    /// and exit: a preprocessing step for other tools. Files without front
+    /// matter are written whole. Can't be combined with --dump or --output,
+    /// which render the document.
+    #[arg(long, conflicts_with_all = ["dump", "output"])]
+    pub strip_frontmatter_only: bool,
+
+    /// Wrap text at most N columns wide (at least 20), like `:set width N`.
+    /// Defaults to $MDINK_WIDTH if set, else the terminal width.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    pub width: Option<u16>,
+
+    /// Indent all content by N columns (the status bar is not indented).
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub left_margin: u16,
+
+    /// Scroll position shown in the status bar: the top visible line, the
+    /// bottom one, or the whole visible range ("12-34/200").
+    #[arg(long, value_enum, default_value_t = StatusLineMode::Top)]
+    pub status_line_mode: StatusLineMode,
+
+    /// Start with the status bar hidden, giving its row to the document;
+    /// `s` shows it again. It still appears while typing a search or command.
+    #[arg(long)]
+    pub no_status: bool,
+
+    /// Syntect theme for code blocks. "auto" asks the terminal for its
+    /// background color and picks a light or dark theme (dark if it doesn't answer).
+    /// Defaults to $MDINK_THEME if set, else "base16-ocean.dark".
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+
+    /// Code block background: the code theme's ("theme", a dark gray for
+    /// themes without one), a dark gray from the terminal palette
+    /// ("terminal"), or none at all, so the terminal's shows through.
+    #[arg(long, value_enum, default_value_t = CodeBackground::Theme)]
+    pub code_bg: CodeBackground,
+
+    /// Blank columns before each code line, on the code background.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub code_padding: u16,
+
+    /// Show a language icon in code block labels. Requires a Nerd Font
+    /// (patched font); other fonts show a missing-glyph box instead.
+    #[arg(long)]
+    pub icons: bool,
+
+    /// Open scrolled to display line N (counted after wrapping, not in the
+    /// source file).
+    #[arg(long, value_name = "N", conflicts_with = "start_at_percent")]
+    pub start_at_line: Option<usize>,
+
+    /// Open scrolled P percent of the way through the document (0–100).
+    #[arg(long, value_name = "P", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub start_at_percent: Option<u8>,
+
+    /// On quit, print the source line of the block at the top of the screen
+    /// (best effort), e.g. for an editor to put its cursor there. With
+    /// --concat, the file too, as "FILE:LINE".
+    #[arg(long)]
+    pub print_position_on_quit: bool,
+
+    /// Like --print-position-on-quit, but write the position to PATH.
+    #[arg(long, value_name = "PATH")]
+    pub position_file: Option<String>,
+
+    /// Lines of context kept above the target of a search, heading or goto
+    /// jump (at most half the screen), like vim's scrolloff.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub scroll_margin: usize,
+
+    /// Follow a heading that is directly followed by a task list with a
+    /// count of its completed tasks, e.g. "(3/7)".
+    #[arg(long)]
+    pub task_summary: bool,
+
+    /// Lay the document out in N side-by-side columns, read down the first
+    /// and on into the next. Meant for wide terminals.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..=2)
+    )]
+    pub columns: u16,
+
+    /// Show `ansi` and `console` code blocks in the colors of the ANSI
+    /// escapes they contain (e.g. captured terminal output).
+    #[arg(long)]
+    pub raw_ansi: bool,
+
+    /// Character or string tiled across the width to draw horizontal rules,
+    /// e.g. "═" or "* ".
+    #[arg(long, value_name = "STR", default_value = "─")]
+    pub rule_char: String,
+
+    /// With --dump or --output, start a new page at each horizontal rule,
+    /// e.g. for slide-style markdown: a form feed in ANSI output, a page
+    /// break when printing HTML.
+    #[arg(long)]
+    pub page_break_on_rule: bool,
+
+    /// Draw faint vertical guides at each level of nested content: list
+    /// items, block quotes and `<details>` bodies (with --html render).
+    #[arg(long)]
+    pub indent_guides: bool,
+
+    /// Which colors to use. "none" keeps bold, italic and the like. When not
+    /// given, a non-empty NO_COLOR environment variable means "none".
+    #[arg(long, value_enum)]
+    pub color: Option<ColorMode>,
+
+    /// Render $inline$ and $$display$$ math with Unicode symbols (Greek
+    /// letters, operators, simple super/subscripts). Unsupported LaTeX is
+    /// shown as written.
+    #[arg(long)]
+    pub math: bool,
+
+    /// Heading colors by level, e.g. "h1=red,h2=#5f87ff,h3=214". Takes color
+    /// names, #rrggbb or 256-color indexes; unlisted levels keep their defaults.
+    #[arg(long, value_name = "SPEC")]
+    pub heading_colors: Option<String>,
+
+    /// Inline code style over the default (bold italic, light gray on dark
+    /// gray), e.g. "no-bold,no-italic,fg=cyan,bg=reset". Takes fg=COLOR,
+    /// bg=COLOR and bold/italic/underline/dim, each with a "no-" form.
+    #[arg(long, value_name = "SPEC")]
+    pub inline_code_style: Option<String>,
+
+    /// Link style over the default (blue, underlined), e.g.
+    /// "fg=magenta,no-underline". Same syntax as --inline-code-style.
+    #[arg(long, value_name = "SPEC")]
+    pub link_style: Option<String>,
+
+    /// Repository base URL, e.g. "https://github.com/owner/repo". Issue
+    /// references (#123) and commit hashes in the text link to its issue
+    /// and commit pages, as terminal hyperlinks.
+    #[arg(long, value_name = "URL")]
+    pub repo_url: Option<String>,
+
+    /// Code theme `B` switches to for dark surroundings.
+    #[arg(long, value_name = "NAME", default_value = "base16-ocean.dark")]
+    pub dark_theme: String,
+
+    /// Code theme `B` switches to for light surroundings; headings and the
+    /// status bar switch to darker colors with it.
+    #[arg(long, value_name = "NAME", default_value = "InspiredGitHub")]
+    pub light_theme: String,
+
+    /// Open the theme picker on startup: arrows preview each code theme,
+    /// Enter keeps it, Esc goes back. (`T` opens it in the viewer.)
+    #[arg(long)]
+    pub select_theme: bool,
+
+    /// Print a code sample highlighted in every theme and exit.
+    #[arg(long)]
+    pub theme_preview: bool,
+
+    /// Print the viewer's key bindings and exit: aligned columns of keys,
+    /// action and description, or with "json" an array of objects.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "table"
+    )]
+    pub print_keys: Option<KeysFormat>,
+
+    /// Remove comments from code blocks (for themes that color comments
+    /// apart). Lines left empty are dropped, or kept blank with "keep-lines".
+    #[arg(
+        long,
+        value_enum,
+        value_name = "LINES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "drop-lines"
+    )]
+    pub strip_comments: Option<StripComments>,
+
+    /// Highlight code blocks that name no language as the one their content
+    /// looks like: JSON, TOML, YAML, or a script by its #! line. Off by
+    /// default, as a wrong guess reads worse than plain text.
+    #[arg(long)]
+    pub guess_language: bool,
+
+    /// Threads used to highlight code blocks (0 = one per CPU).
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub jobs: usize,
+
+    // Later phases will add: --style, --pager, --list-themes
+}
+
+/// Paragraph alignment accepted by `--default-align`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Align {
+    /// Ragged right edge.
+    Left,
+    /// Stretch every line but a paragraph's last to the full width.
+    Justify,
+}
+
+/// Spacing presets accepted by `--spacing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Spacing {
+    /// Less space around headings and lists.
+    Compact,
+    /// One blank line between blocks.
+    Normal,
+    /// More space around headings.
+    Airy,
+}
+
+/// Front matter rendering accepted by `--frontmatter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FrontMatterMode {
+    /// Don't render it.
+    Hide,
+    /// Show the raw YAML in a dim code block.
+    Show,
+    /// Render the `title` value as a top-level heading.
+    Title,
+}
+
+/// Raw HTML handling accepted by `--html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HtmlMode {
+    /// Drop raw HTML.
+    Drop,
+    /// Render `<details>`/`<summary>` sections.
+    Render,
+}
+
+/// Status bar position format accepted by `--status-line-mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusLineMode {
+    /// Top visible line.
+    Top,
+    /// Bottom visible line.
+    Bottom,
+    /// First and last visible lines.
+    Range,
+}
+
+/// Export format accepted by `--output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Text with ANSI escapes, as --dump writes.
+    Ansi,
+    /// A standalone HTML page.
+    Html,
+}
+
+/// What `--strip-comments` does with lines that held only a comment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StripComments {
+    /// Remove them.
+    DropLines,
+    /// Keep them as blank lines.
+    KeepLines,
+}
+
+/// Output accepted by `--print-keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeysFormat {
+    /// One binding per line, in aligned columns.
+    Table,
+    /// A JSON array of `{"keys", "action", "help"}` objects.
+    Json,
+}
+
+/// Code block background accepted by `--code-bg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CodeBackground {
+    /// The code theme's background.
+    Theme,
+    /// Palette color 235.
+    Terminal,
+    /// No background.
+    None,
+}
+
+/// Color output accepted by `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// Every color the document and theme use.
+    Full,
+    /// No colors; text attributes only.
+    None,
 }
@@ -0,0 +1,567 @@
+//! Export: renders a laid-out document to a string instead of a terminal.
+//!
+//! This is the non-interactive counterpart to `renderer.rs`. It walks a
+//! `PreRenderedDocument` line by line and writes ANSI SGR escapes for each
+//! span's style, so the result can be printed (`--dump`) or used by other
+//! programs (`to_ansi()`) without a terminal. `to_html()` writes the same
+//! lines as a standalone HTML page (`--output html`), and `theme_preview()`
+//! shows a code sample in every theme (`--theme-preview`).
+//!
+//! Like the renderer, this module never imports `pulldown_cmark`.
+
+use std::fmt::Write;
+
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use unicode_width::UnicodeWidthStr;
+
+use crate::highlight::{self, Highlighter};
+use crate::layout::{self, DocumentLine, PreRenderedDocument};
+use crate::parser::{self, ParseOptions};
+
+/// Background of code lines with `CodeBackground::Terminal`, and with
+/// `Theme` when the theme has none. Shared with the TUI renderer.
+pub const CODE_BG: Color = Color::Indexed(235);
+
+/// Blank columns before each code line, on its background, unless
+/// `--code-padding` says otherwise. Shared with the TUI renderer.
+pub const DEFAULT_CODE_PADDING: usize = 1;
+
+/// Which colors an export may use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Emit every color the document uses (256-color and RGB included).
+    #[default]
+    Full,
+    /// Emit no colors. Text attributes (bold, italic, …) are kept.
+    None,
+}
+
+/// Where code blocks get their background color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodeBackground {
+    /// The code theme's background, or `CODE_BG` for themes without one.
+    #[default]
+    Theme,
+    /// `CODE_BG`, a dark gray from the terminal's 256-color palette.
+    Terminal,
+    /// None: the terminal's own background shows through.
+    None,
+}
+
+/// Returns the background of a laid-out code line under `mode`: the
+/// theme's travels in the line's style (see `Highlighter::highlight_code()`).
+pub fn code_background(line: &Line, mode: CodeBackground) -> Option<Color> {
+    match mode {
+        CodeBackground::Theme => Some(line.style.bg.unwrap_or(CODE_BG)),
+        CodeBackground::Terminal => Some(CODE_BG),
+        CodeBackground::None => None,
+    }
+}
+
+/// Options for `to_ansi()`, `to_html()` and their `document_to_*()` forms.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Syntect theme for code blocks.
+    pub theme: String,
+    /// Which colors to emit.
+    pub color: ColorMode,
+    /// Where code blocks get their background color.
+    pub code_bg: CodeBackground,
+    /// Blank columns before each code line, on its background.
+    pub code_padding: usize,
+    /// Maximum content width. `None` uses the full width passed to `to_ansi()`.
+    pub width: Option<u16>,
+    /// Prefix each line with its line number.
+    pub line_numbers: bool,
+    /// Pattern tiled across the width for thematic breaks.
+    pub rule: String,
+    /// Start a new page at each thematic break: a form feed instead of the
+    /// rule in ANSI output, a page-breaking `<hr>` in HTML.
+    pub page_break_on_rule: bool,
+    /// Repository base URL (`--repo-url`): issue and commit references are
+    /// parsed out and written as OSC 8 hyperlinks to it.
+    pub repo_url: Option<String>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            theme: highlight::DEFAULT_THEME.to_string(),
+            color: ColorMode::Full,
+            code_bg: CodeBackground::Theme,
+            code_padding: DEFAULT_CODE_PADDING,
+            width: None,
+            line_numbers: false,
+            rule: layout::DEFAULT_RULE.to_string(),
+            page_break_on_rule: false,
+            repo_url: None,
+        }
+    }
+}
+
+/// Renders markdown to an ANSI-styled string wrapped to `width` columns.
+///
+/// Runs the whole pipeline (building a fresh `Highlighter`, ~100ms) and
+/// then `document_to_ansi()`. Lines end in `\n`; images render as alt text.
+pub fn to_ansi(source: &str, width: u16, mut opts: RenderOptions) -> String {
+    let width = opts.width.map_or(width, |max| max.min(width));
+    let highlighter = Highlighter::new();
+    let parse_options = ParseOptions {
+        theme: opts.theme.clone(),
+        repo_url: opts.repo_url.clone(),
+        ..ParseOptions::default()
+    };
+    let blocks = parser::parse_with_options(source, &highlighter, &parse_options);
+
+    let mut document = crate::layout::flatten(&blocks, width);
+    if opts.line_numbers {
+        // The gutter width depends on the line count, which depends on the
+        // wrap width: re-layout until the gutter stops growing. If it stops
+        // fitting, drop the numbers instead.
+        let mut gutter = 0;
+        loop {
+            match line_number_gutter(document.total_height, width) {
+                needed if needed == gutter => break,
+                0 => {
+                    opts.line_numbers = false;
+                    document = crate::layout::flatten(&blocks, width);
+                    break;
+                }
+                needed => {
+                    gutter = needed;
+                    document = crate::layout::flatten(&blocks, width - gutter);
+                }
+            }
+        }
+    }
+    document_to_ansi(&document, width, &opts)
+}
+
+/// Writes a laid-out document as ANSI-styled text, one `\n`-terminated
+/// line per `DocumentLine`.
+///
+/// `width` is the full output width: code backgrounds and rules extend to
+/// it. With `line_numbers`, the number gutter is taken out of `width`, so
+/// the document should have been flattened that much narrower. Widths too
+/// narrow for the gutter plus `MIN_CONTENT_WIDTH` columns get no numbers.
+pub fn document_to_ansi(
+    document: &PreRenderedDocument,
+    width: u16,
+    opts: &RenderOptions,
+) -> String {
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let gutter = if opts.line_numbers {
+        line_number_gutter(document.total_height, width)
+    } else {
+        0
+    };
+    let content_width = (width - gutter) as usize;
+    let digits = gutter.saturating_sub(3) as usize;
+
+    let mut out = String::new();
+    for (i, line) in document.lines.iter().enumerate() {
+        if gutter > 0 {
+            let number = format!("{:>digits$} │ ", i + 1);
+            push_styled(&mut out, &number, dim, opts.color);
+        }
+        match line {
+            DocumentLine::Text(line) => {
+                push_line(&mut out, line, opts.color, |span| document.reference(i, span));
+            }
+            DocumentLine::Code(line) => {
+                let bg = Style { bg: code_background(line, opts.code_bg), ..Style::default() };
+                if opts.code_padding > 0 {
+                    push_styled(&mut out, &" ".repeat(opts.code_padding), bg, opts.color);
+                }
+                for span in &line.spans {
+                    let style = Style { bg: bg.bg, ..span.style };
+                    push_styled(&mut out, &span.content, style, opts.color);
+                }
+                let fill = content_width.saturating_sub(opts.code_padding + printed_width(line));
+                if fill > 0 {
+                    push_styled(&mut out, &" ".repeat(fill), bg, opts.color);
+                }
+            }
+            DocumentLine::Empty | DocumentLine::ImageContinuation => {}
+            DocumentLine::Rule if opts.page_break_on_rule => out.push('\x0c'), // form feed
+            DocumentLine::Rule => {
+                let rule = layout::tile_to_width(&opts.rule, content_width);
+                push_styled(&mut out, &rule, dim, opts.color);
+            }
+            DocumentLine::ImageStart { alt_text, .. } => {
+                let alt = format!("[image: {alt_text}]");
+                push_styled(&mut out, &alt, dim, opts.color);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders markdown to a standalone HTML page wrapped to `width` columns.
+///
+/// Like `to_ansi()`, but the lines are written by `document_to_html()`.
+/// `line_numbers` is ignored.
+pub fn to_html(source: &str, width: u16, opts: RenderOptions) -> String {
+    let width = opts.width.map_or(width, |max| max.min(width));
+    let highlighter = Highlighter::new();
+    let parse_options = ParseOptions {
+        theme: opts.theme.clone(),
+        repo_url: opts.repo_url.clone(),
+        ..ParseOptions::default()
+    };
+    let blocks = parser::parse_with_options(source, &highlighter, &parse_options);
+    let document = crate::layout::flatten(&blocks, width);
+    document_to_html(&document, width, &opts)
+}
+
+/// Writes a laid-out document as a standalone HTML page: one `<pre>` with
+/// a line per `DocumentLine` and a `<span>` with inline CSS per styled
+/// span, so colors and attributes survive without a stylesheet.
+///
+/// Code backgrounds and rules extend to `width`, as in `document_to_ansi()`.
+/// Issue and commit references become `<a>` links. With
+/// `page_break_on_rule`, each rule closes the `<pre>` and opens a new one
+/// after an `<hr>` that printing breaks the page at.
+pub fn document_to_html(
+    document: &PreRenderedDocument,
+    width: u16,
+    opts: &RenderOptions,
+) -> String {
+    let dim = Style::default().add_modifier(Modifier::DIM);
+    let width = width as usize;
+    let mut out = String::from(HTML_HEAD);
+    for (i, line) in document.lines.iter().enumerate() {
+        match line {
+            DocumentLine::Text(line) => {
+                for (j, span) in line.spans.iter().enumerate() {
+                    let style = line.style.patch(span.style);
+                    let html = styled_html(&span.content, style, opts.color);
+                    match document.reference(i, j) {
+                        Some(url) => {
+                            let _ = write!(out, "<a href=\"{}\">{html}</a>", escape_html(url));
+                        }
+                        None => out.push_str(&html),
+                    }
+                }
+            }
+            DocumentLine::Code(line) => {
+                let bg = Style { bg: code_background(line, opts.code_bg), ..Style::default() };
+                if opts.code_padding > 0 {
+                    out.push_str(&styled_html(&" ".repeat(opts.code_padding), bg, opts.color));
+                }
+                for span in &line.spans {
+                    let style = Style { bg: bg.bg, ..span.style };
+                    out.push_str(&styled_html(&span.content, style, opts.color));
+                }
+                let fill = width.saturating_sub(opts.code_padding + printed_width(line));
+                if fill > 0 {
+                    out.push_str(&styled_html(&" ".repeat(fill), bg, opts.color));
+                }
+            }
+            DocumentLine::Empty | DocumentLine::ImageContinuation => {}
+            DocumentLine::Rule if opts.page_break_on_rule => {
+                out.push_str("</pre>\n<hr class=\"page-break\">\n<pre>");
+                continue;
+            }
+            DocumentLine::Rule => {
+                let rule = layout::tile_to_width(&opts.rule, width);
+                out.push_str(&styled_html(&rule, dim, opts.color));
+            }
+            DocumentLine::ImageStart { alt_text, .. } => {
+                out.push_str(&styled_html(&format!("[image: {alt_text}]"), dim, opts.color));
+            }
+        }
+        out.push('\n');
+    }
+    out.push_str("</pre>\n</body>\n</html>\n");
+    out
+}
+
+/// Code highlighted by `theme_preview()`: keywords, a string, a number and
+/// a comment, so the themes' differences show.
+const THEME_PREVIEW_SAMPLE: &str = r#"// Greet everyone twice.
+fn main() {
+    let names = ["Ada", "Linus"];
+    for name in names {
+        println!("hello, {name}: {}", 2 * 21);
+    }
+}"#;
+
+/// Highlights a short Rust sample in every built-in theme, each under its
+/// name in bold (`--theme-preview`). Lines are padded to the same width in
+/// the theme's background so light and dark themes are easy to tell apart.
+pub fn theme_preview(highlighter: &Highlighter, color: ColorMode) -> String {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let width = THEME_PREVIEW_SAMPLE.lines().map(str::len).max().unwrap_or(0) + 2;
+    let mut out = String::new();
+    for theme in highlighter.theme_names() {
+        push_styled(&mut out, &theme, bold, color);
+        out.push('\n');
+        for line in highlighter.highlight_code(THEME_PREVIEW_SAMPLE, "rust", &theme) {
+            let bg = Style { bg: line.style.bg, ..Style::default() };
+            push_styled(&mut out, " ", bg, color);
+            for span in &line.spans {
+                push_styled(&mut out, &span.content, span.style, color);
+            }
+            let fill = width.saturating_sub(1 + printed_width(&line));
+            push_styled(&mut out, &" ".repeat(fill), bg, color);
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Everything up to the document's `<pre>`: a dark page, as in a terminal.
+const HTML_HEAD: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>mdink</title>
+<style>
+body { background: #1c1c1c; color: #d0d0d0; margin: 1em; }
+pre { font-family: ui-monospace, Menlo, Consolas, monospace; line-height: 1.3; }
+a { color: inherit; }
+hr.page-break { border: 0; margin: 0; break-after: page; page-break-after: always; }
+</style>
+</head>
+<body>
+<pre>";
+
+/// Returns `text`, HTML-escaped, in a `<span>` with the inline CSS for
+/// `style`. Unstyled text is returned bare.
+fn styled_html(text: &str, style: Style, color: ColorMode) -> String {
+    let css = css_declarations(style, color);
+    if css.is_empty() {
+        escape_html(text)
+    } else {
+        format!("<span style=\"{css}\">{}</span>", escape_html(text))
+    }
+}
+
+/// Returns the CSS declarations for `style`, the counterpart of `sgr_codes()`.
+fn css_declarations(style: Style, color: ColorMode) -> String {
+    let modifiers = style.add_modifier;
+    let mut css = Vec::new();
+    if color == ColorMode::Full {
+        // Reversed video swaps the colors, defaulting to the page's own.
+        let (fg, bg) = if modifiers.contains(Modifier::REVERSED) {
+            (style.bg.or(Some(Color::Black)), style.fg.or(Some(Color::Gray)))
+        } else {
+            (style.fg, style.bg)
+        };
+        css.extend(fg.and_then(css_color).map(|c| format!("color:{c}")));
+        css.extend(bg.and_then(css_color).map(|c| format!("background:{c}")));
+    }
+    if modifiers.contains(Modifier::BOLD) {
+        css.push("font-weight:bold".to_string());
+    }
+    if modifiers.contains(Modifier::ITALIC) {
+        css.push("font-style:italic".to_string());
+    }
+    if modifiers.contains(Modifier::DIM) {
+        css.push("opacity:0.6".to_string());
+    }
+    let decorations: Vec<&str> = [
+        (Modifier::UNDERLINED, "underline"),
+        (Modifier::CROSSED_OUT, "line-through"),
+    ]
+    .iter()
+    .filter(|(modifier, _)| modifiers.contains(*modifier))
+    .map(|(_, decoration)| *decoration)
+    .collect();
+    if !decorations.is_empty() {
+        css.push(format!("text-decoration:{}", decorations.join(" ")));
+    }
+    if modifiers.contains(Modifier::HIDDEN) {
+        css.push("visibility:hidden".to_string());
+    }
+    css.join(";")
+}
+
+/// Returns `color` as a CSS `#rrggbb`, using xterm's default palette for
+/// the 16 named colors and the 256-color indexes. `Reset` yields `None`.
+fn css_color(color: Color) -> Option<String> {
+    const NAMED: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    let index = match color {
+        Color::Reset => return None,
+        Color::Rgb(r, g, b) => return Some(format!("#{r:02x}{g:02x}{b:02x}")),
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Indexed(n) => n,
+    };
+    let (r, g, b) = match index {
+        0..=15 => NAMED[usize::from(index)],
+        // The 6×6×6 color cube.
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = index - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        // The grayscale ramp.
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    };
+    Some(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+/// Escapes the characters HTML treats as markup.
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Returns the columns taken by the line-number gutter (`"NNN │ "`), or 0
+/// when it doesn't fit in `width` (see `fit_gutters()`).
+fn line_number_gutter(line_count: usize, width: u16) -> u16 {
+    let gutter = line_count.max(1).to_string().len() as u16 + 3;
+    let [gutter] = crate::layout::fit_gutters(width, [gutter]);
+    gutter
+}
+
+/// Returns the columns `line` takes once its spans are printed back to
+/// back. Unlike `Line::width()`, which adds up the spans, this measures
+/// the joined text, so a ZWJ emoji sequence that a highlighter split across
+/// spans counts once, as the terminal draws it, and a combining mark that
+/// starts a span counts nothing.
+fn printed_width(line: &Line) -> usize {
+    let text: String = line.spans.iter().map(|span| span.content.as_ref()).collect();
+    text.width()
+}
+
+/// Appends `line`, with the spans `reference` returns a page for (by
+/// index) wrapped in hyperlinks to it.
+fn push_line<'a>(
+    out: &mut String,
+    line: &Line,
+    color: ColorMode,
+    reference: impl Fn(usize) -> Option<&'a str>,
+) {
+    for (i, span) in line.spans.iter().enumerate() {
+        let style = line.style.patch(span.style);
+        match reference(i) {
+            Some(url) => out.push_str(&hyperlink(url, &span.content, style, color)),
+            None => push_styled(out, &span.content, style, color),
+        }
+    }
+}
+
+/// Returns `text`, styled as `push_styled()` would, wrapped in an OSC 8
+/// hyperlink to `url`.
+pub fn hyperlink(url: &str, text: &str, style: Style, color: ColorMode) -> String {
+    let mut out = format!("\x1b]8;;{url}\x1b\\");
+    push_styled(&mut out, text, style, color);
+    out.push_str("\x1b]8;;\x1b\\");
+    out
+}
+
+/// Appends `text` wrapped in the SGR sequence for `style`, then a reset.
+/// Unstyled text is appended bare.
+fn push_styled(out: &mut String, text: &str, style: Style, color: ColorMode) {
+    let sgr = sgr_codes(style, color);
+    if sgr.is_empty() {
+        out.push_str(text);
+    } else {
+        let _ = write!(out, "\x1b[{sgr}m{text}\x1b[0m");
+    }
+}
+
+/// Returns the `;`-separated SGR parameters for `style`.
+fn sgr_codes(style: Style, color: ColorMode) -> String {
+    const MODIFIERS: [(Modifier, u8); 8] = [
+        (Modifier::BOLD, 1),
+        (Modifier::DIM, 2),
+        (Modifier::ITALIC, 3),
+        (Modifier::UNDERLINED, 4),
+        (Modifier::SLOW_BLINK, 5),
+        (Modifier::REVERSED, 7),
+        (Modifier::HIDDEN, 8),
+        (Modifier::CROSSED_OUT, 9),
+    ];
+    let mut codes: Vec<String> = MODIFIERS
+        .iter()
+        .filter(|(modifier, _)| style.add_modifier.contains(*modifier))
+        .map(|(_, code)| code.to_string())
+        .collect();
+    if color == ColorMode::Full {
+        codes.extend(style.fg.and_then(|c| color_code(c, false)));
+        codes.extend(style.bg.and_then(|c| color_code(c, true)));
+    }
+    codes.join(";")
+}
+
+/// Returns the SGR parameter for a foreground (or background) color.
+/// `Reset` yields `None`: every styled span already ends in a full reset.
+fn color_code(color: Color, background: bool) -> Option<String> {
+    let base = if background { 10 } else { 0 };
+    let ansi = |n: u8| Some((n + base).to_string());
+    match color {
+        Color::Reset => None,
+        Color::Black => ansi(30),
+        Color::Red => ansi(31),
+        Color::Green => ansi(32),
+        Color::Yellow => ansi(33),
+        Color::Blue => ansi(34),
+        Color::Magenta => ansi(35),
+        Color::Cyan => ansi(36),
+        Color::Gray => ansi(37),
+        Color::DarkGray => ansi(90),
+        Color::LightRed => ansi(91),
+        Color::LightGreen => ansi(92),
+        Color::LightYellow => ansi(93),
+        Color::LightBlue => ansi(94),
+        Color::LightMagenta => ansi(95),
+        Color::LightCyan => ansi(96),
+        Color::White => ansi(97),
+        Color::Indexed(n) => Some(format!("{};5;{n}", 38 + base)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{r};{g};{b}", 38 + base)),
+    }
+}
+
+#[cfg(test)]
+#[path = "export_tests.rs"]
+mod tests;
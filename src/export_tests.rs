@@ -0,0 +1,263 @@
+    use super::*;
+    use ratatui::text::Span;
+
+    #[test]
+    fn test_export_to_ansi_bold_heading_escapes() {
+        let out = to_ansi("# Title\n\nplain text", 40, RenderOptions::default());
+        // h1 is bold light cyan; the span is closed with a full reset.
+        assert!(out.starts_with("\x1b[1;96mTitle\x1b[0m\n"), "{out:?}");
+        assert!(out.ends_with("\nplain text\n"), "{out:?}");
+    }
+
+    #[test]
+    fn test_export_to_ansi_color_none_keeps_attributes() {
+        let opts = RenderOptions {
+            color: ColorMode::None,
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("# Title\n\n```rust\nlet x = 1;\n```", 20, opts);
+        assert!(out.starts_with("\x1b[1mTitle\x1b[0m\n"), "{out:?}");
+        assert!(!out.contains("38;"), "no foreground colors expected: {out:?}");
+        assert!(!out.contains("48;"), "no background colors expected: {out:?}");
+    }
+
+    #[test]
+    fn test_export_to_ansi_wraps_to_width() {
+        let out = to_ansi("one two three four five", 10, RenderOptions::default());
+        assert!(out.lines().all(|line| line.chars().count() <= 10), "{out:?}");
+        assert!(out.lines().count() > 1);
+    }
+
+    #[test]
+    fn test_export_to_ansi_max_width_option_narrows() {
+        let opts = RenderOptions {
+            width: Some(10),
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("one two three four five", 80, opts);
+        assert!(out.lines().all(|line| line.chars().count() <= 10), "{out:?}");
+    }
+
+    #[test]
+    fn test_export_to_ansi_line_numbers() {
+        let opts = RenderOptions {
+            line_numbers: true,
+            color: ColorMode::None,
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("a\n\nb", 40, opts);
+        assert_eq!(out, "\x1b[2m1 │ \x1b[0ma\n\x1b[2m2 │ \x1b[0m\n\x1b[2m3 │ \x1b[0mb\n");
+    }
+
+    #[test]
+    fn test_export_code_line_fills_width_with_background() {
+        let opts = RenderOptions { code_bg: CodeBackground::Terminal, ..RenderOptions::default() };
+        let out = to_ansi("```\nab\n```", 10, opts);
+        // " " pad + "ab" + 7 columns of fill, all on the code background.
+        assert!(out.contains("\x1b[48;5;235m       \x1b[0m"), "{out:?}");
+    }
+
+    #[test]
+    fn test_export_code_padding_counts_toward_the_fill() {
+        for padding in [0, 3] {
+            let opts = RenderOptions {
+                color: ColorMode::None,
+                code_padding: padding,
+                ..RenderOptions::default()
+            };
+            // The fill shrinks by what the padding takes.
+            let expected = format!("{}ab{}\n", " ".repeat(padding), " ".repeat(8 - padding));
+            assert_eq!(to_ansi("```\nab\n```", 10, opts.clone()), expected);
+            let document = crate::render_to_lines("```\nab\n```", 10, highlight::DEFAULT_THEME);
+            assert!(document_to_html(&document, 10, &opts).contains(&expected));
+        }
+    }
+
+    #[test]
+    fn test_export_code_bg_theme_terminal_and_none() {
+        // base16-ocean.dark's background is #2b303b.
+        let out = to_ansi("```\nab\n```", 10, RenderOptions::default());
+        assert!(out.starts_with("\x1b[48;2;43;48;59m \x1b[0m"), "{out:?}");
+        assert!(out.contains("\x1b[48;2;43;48;59m       \x1b[0m"), "{out:?}");
+        let opts = RenderOptions { code_bg: CodeBackground::None, ..RenderOptions::default() };
+        let out = to_ansi("```\nab\n```", 10, opts);
+        assert!(!out.contains("48;"), "{out:?}");
+        assert!(out.starts_with(" \x1b[38;2;"), "{out:?}");
+    }
+
+    #[test]
+    fn test_export_code_background_falls_back_without_theme_background() {
+        let themed = Line::from("x").style(Style::default().bg(Color::Rgb(1, 2, 3)));
+        let plain = Line::from("x");
+        assert_eq!(code_background(&themed, CodeBackground::Theme), Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(code_background(&plain, CodeBackground::Theme), Some(CODE_BG));
+        assert_eq!(code_background(&themed, CodeBackground::Terminal), Some(CODE_BG));
+        assert_eq!(code_background(&themed, CodeBackground::None), None);
+    }
+
+    #[test]
+    fn test_export_sgr_codes_rgb_and_indexed() {
+        let style = Style::default().fg(Color::Rgb(1, 2, 3)).bg(Color::Indexed(42));
+        assert_eq!(sgr_codes(style, ColorMode::Full), "38;2;1;2;3;48;5;42");
+        assert_eq!(sgr_codes(style, ColorMode::None), "");
+    }
+
+    #[test]
+    fn test_export_line_numbers_dropped_when_too_narrow() {
+        let opts = RenderOptions {
+            line_numbers: true,
+            color: ColorMode::None,
+            ..RenderOptions::default()
+        };
+        for width in 1..=10 {
+            let out = to_ansi("some words to wrap\n\n```\ncode\n```", width, opts.clone());
+            assert!(!out.contains('│'), "width={width}: {out:?}");
+        }
+        // 14 columns fit a 4-column gutter plus 10 of content.
+        let out = to_ansi("a\n\nb", 14, opts);
+        assert!(out.starts_with("\x1b[2m1 │ \x1b[0ma\n"), "{out:?}");
+    }
+
+    #[test]
+    fn test_export_rule_tiles_custom_pattern() {
+        let opts = RenderOptions {
+            rule: "=-".to_string(),
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("---", 7, opts);
+        assert_eq!(out, "\x1b[2m=-=-=-=\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_export_page_break_on_rule() {
+        let opts = RenderOptions {
+            color: ColorMode::None,
+            page_break_on_rule: true,
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("one\n\n---\n\ntwo", 10, opts.clone());
+        assert_eq!(out, "one\n\n\x0c\n\ntwo\n");
+
+        let out = to_html("one\n\n---\n\ntwo", 10, opts);
+        assert!(out.contains("one\n\n</pre>\n<hr class=\"page-break\">\n<pre>\ntwo\n"), "{out}");
+        assert!(!out.contains('─'), "{out}");
+    }
+
+    #[test]
+    fn test_export_references_become_hyperlinks() {
+        let opts = RenderOptions {
+            repo_url: Some("https://github.com/o/r".to_string()),
+            ..RenderOptions::default()
+        };
+        let out = to_ansi("See #12.", 40, opts);
+        assert_eq!(
+            out,
+            concat!(
+                "See \x1b]8;;https://github.com/o/r/issues/12\x1b\\",
+                "\x1b[4;34m#12\x1b[0m\x1b]8;;\x1b\\.\n",
+            )
+        );
+        assert_eq!(to_ansi("See #12.", 40, RenderOptions::default()), "See #12.\n");
+    }
+
+    #[test]
+    fn test_export_links_that_read_like_references_keep_their_target() {
+        let opts = RenderOptions {
+            repo_url: Some("https://github.com/o/r".to_string()),
+            color: ColorMode::None,
+            ..RenderOptions::default()
+        };
+        let source = "[#12](https://example.com/elsewhere) and [abc1234](https://example.com/x) \
+                      then #12";
+        let out = to_ansi(source, 80, opts.clone());
+        assert_eq!(out.matches("\x1b]8;;https://github.com/o/r/").count(), 1, "{out:?}");
+        assert!(out.contains("then \x1b]8;;https://github.com/o/r/issues/12\x1b\\"), "{out:?}");
+        assert!(!out.contains("/commit/"), "{out:?}");
+        let html = to_html(source, 80, opts);
+        assert_eq!(html.matches("<a href=").count(), 1, "{html}");
+    }
+
+    #[test]
+    fn test_export_to_html_styles_and_escapes() {
+        let out = to_html("# Title\n\n1 < 2 & **bold**", 40, RenderOptions::default());
+        assert!(out.starts_with("<!DOCTYPE html>"), "{out}");
+        assert!(out.ends_with("</pre>\n</body>\n</html>\n"), "{out}");
+        // h1 is bold light cyan (#00ffff in the xterm palette).
+        assert!(
+            out.contains("<span style=\"color:#00ffff;font-weight:bold\">Title</span>\n"),
+            "{out}"
+        );
+        assert!(out.contains("1 &lt; 2 &amp; <span style=\"font-weight:bold\">bold</span>"));
+    }
+
+    #[test]
+    fn test_export_to_html_code_colors_and_color_none() {
+        let md = "```rust\nlet x = 1;\n```";
+        let opts = RenderOptions { code_bg: CodeBackground::Terminal, ..RenderOptions::default() };
+        let out = to_html(md, 20, opts);
+        // Code lines get the code background (index 235) across the width.
+        assert!(out.contains("background:#262626"), "{out}");
+        assert!(out.contains("color:#"), "{out}");
+        let opts = RenderOptions { color: ColorMode::None, ..RenderOptions::default() };
+        let out = to_html(md, 20, opts);
+        assert!(!out.contains("color:#") && !out.contains("background:#"), "{out}");
+    }
+
+    #[test]
+    fn test_export_css_color_palette() {
+        assert_eq!(css_color(Color::Reset), None);
+        assert_eq!(css_color(Color::Rgb(1, 2, 255)).as_deref(), Some("#0102ff"));
+        assert_eq!(css_color(Color::Red).as_deref(), Some("#cd0000"));
+        assert_eq!(css_color(Color::Indexed(16)).as_deref(), Some("#000000"));
+        assert_eq!(css_color(Color::Indexed(196)).as_deref(), Some("#ff0000"));
+        assert_eq!(css_color(Color::Indexed(252)).as_deref(), Some("#d0d0d0"));
+    }
+
+    #[test]
+    fn test_export_to_html_links_references() {
+        let opts = RenderOptions {
+            repo_url: Some("https://github.com/o/r".to_string()),
+            ..RenderOptions::default()
+        };
+        let out = to_html("Fixes #12.", 40, opts);
+        assert!(out.contains("<a href=\"https://github.com/o/r/issues/12\"><span"), "{out}");
+    }
+
+    #[test]
+    fn test_export_theme_preview_covers_every_theme() {
+        let highlighter = Highlighter::new();
+        let out = theme_preview(&highlighter, ColorMode::Full);
+        for theme in highlighter.theme_names() {
+            assert!(out.contains(&format!("\x1b[1m{theme}\x1b[0m\n")), "{theme} missing");
+        }
+        let sample_lines = THEME_PREVIEW_SAMPLE.lines().count();
+        let sections = highlighter.theme_names().len();
+        assert_eq!(out.lines().count(), sections * (sample_lines + 2));
+        // Theme colors are RGB; without color only attributes remain.
+        assert!(out.contains("38;2;"), "{out:?}");
+        let plain = theme_preview(&highlighter, ColorMode::None);
+        assert!(!plain.contains("38;") && plain.contains("fn main() {"), "{plain:?}");
+    }
+
+    #[test]
+    fn test_export_printed_width_joins_split_graphemes() {
+        // A family emoji split at its joiner, as a highlighter might: two
+        // emoji by the spans' widths, one joined sequence when printed.
+        let family = Line::from(vec![Span::raw("a👨\u{200d}"), Span::raw("👩\u{200d}👧")]);
+        assert_eq!(family.width(), 5);
+        assert_eq!(printed_width(&family), 3);
+        let accent = Line::from(vec![Span::raw("cafe"), Span::raw("\u{301}!")]);
+        assert_eq!(printed_width(&accent), 5);
+    }
+
+    #[test]
+    fn test_export_code_fill_reaches_edge_with_zwj_and_combining() {
+        let md = "```\nlet f = \"👨\u{200d}👩\u{200d}👧\";\nlet c = \"cafe\u{301}\";\n```";
+        let opts = RenderOptions { color: ColorMode::None, ..RenderOptions::default() };
+        let out = to_ansi(md, 30, opts);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2, "{out:?}");
+        for line in lines {
+            assert_eq!(line.width(), 30, "{line:?}");
+        }
+    }
@@ -7,11 +7,14 @@
 //! This isolation means `syntect` types never leak into the parser, layout,
 //! or renderer — Dependency Inversion per standards §2.
 
+use std::cell::RefCell;
+use std::sync::Arc;
+
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
 use syntect::easy::HighlightLines;
-use syntect::highlighting::{FontStyle, Style as SyntectStyle, ThemeSet};
-use syntect::parsing::{Scope, SyntaxSet};
+use syntect::highlighting::{FontStyle, HighlightState, Style as SyntectStyle, Theme, ThemeSet};
+use syntect::parsing::{ParseState, Scope, SyntaxReference, SyntaxSet};
 use syntect::util::LinesWithEndings;
 
 /// Wraps syntect's syntax and theme sets, loaded once at startup.
@@ -19,22 +22,84 @@ use syntect::util::LinesWithEndings;
 /// `SyntaxSet` and `ThemeSet` are expensive to construct (~50ms each).
 /// This struct ensures they are loaded once and reused for every code block.
 pub struct Highlighter {
-    syntax_set: SyntaxSet,
+    /// Shared with every `LazyHighlight`, which needs it for each line.
+    syntax_set: Arc<SyntaxSet>,
     theme_set: ThemeSet,
 }
 
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What `Highlighter::highlight_code_with()` does with comments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Comments {
+    /// Highlight them like the rest of the code.
+    #[default]
+    Keep,
+    /// Remove them, and the lines that held nothing else.
+    Strip,
+    /// Remove them, leaving the lines that held nothing else blank.
+    StripKeepLines,
+}
+
 /// Default syntect theme used for code highlighting.
-const DEFAULT_THEME: &str = "base16-ocean.dark";
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+/// Theme picked by `--theme auto` on light terminal backgrounds.
+pub const LIGHT_THEME: &str = "InspiredGitHub";
+
+/// Picks a code theme for a terminal background color.
+///
+/// Light backgrounds get `LIGHT_THEME`; dark or unknown backgrounds (the
+/// terminal didn't answer the query) get `DEFAULT_THEME`.
+pub fn theme_for_background(background: Option<(u8, u8, u8)>) -> &'static str {
+    match background {
+        // Rec. 601 luma, scaled by 1000 to stay in integers.
+        Some((r, g, b)) if 299 * r as u32 + 587 * g as u32 + 114 * b as u32 > 127_500 => {
+            LIGHT_THEME
+        }
+        _ => DEFAULT_THEME,
+    }
+}
+
+/// Parses a terminal's reply to the OSC 11 background-color query.
+///
+/// The reply looks like `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` terminated by BEL
+/// or `ESC \`, with 1–4 hex digits per channel. Returns the color scaled
+/// to 8 bits per channel, or `None` if `reply` is not such a response.
+pub fn parse_osc11_reply(reply: &str) -> Option<(u8, u8, u8)> {
+    let start = reply.find("\x1b]11;rgb:")? + "\x1b]11;rgb:".len();
+    let body = &reply[start..];
+    let end = body.find(['\x07', '\x1b']).unwrap_or(body.len());
+    let mut channels = body[..end].split('/').map(|hex| {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(rgb)
+}
 
 impl Highlighter {
     /// Creates a new `Highlighter` with default syntax and theme sets.
     pub fn new() -> Self {
         Self {
-            syntax_set: SyntaxSet::load_defaults_newlines(),
+            syntax_set: Arc::new(SyntaxSet::load_defaults_newlines()),
             theme_set: ThemeSet::load_defaults(),
         }
     }
 
+    /// Returns the names of the built-in themes, sorted.
+    pub fn theme_names(&self) -> Vec<String> {
+        self.theme_set.themes.keys().cloned().collect()
+    }
+
     /// Highlights a code block, returning one `Line<'static>` per source line.
     ///
     /// - `language` is matched via `find_syntax_by_token` (e.g. "rust", "py", "js").
@@ -43,76 +108,449 @@ impl Highlighter {
     ///   `"base16-ocean.dark"` if not found.
     /// - Trailing newlines are stripped from each span (ratatui uses separate
     ///   `Line` objects, not embedded newlines).
+    /// - `diff` and `patch` blocks are colored line by line instead (see
+    ///   `highlight_diff`), so added and removed lines stand out in any theme.
+    /// - Each line's own style carries the theme's background color, if the
+    ///   theme defines one; spans only have a background when it does.
     pub fn highlight_code(
         &self,
         code: &str,
         language: &str,
         theme_name: &str,
     ) -> Vec<Line<'static>> {
-        // Guard against unbounded memory/CPU: Oniguruma (syntect's regex engine) can
-        // exhaust memory on large inputs, surfacing as a panic rather than an Err.
-        // Blocks exceeding the limit are rendered as plain unstyled text instead.
-        const MAX_HIGHLIGHT_BYTES: usize = 512 * 1024; // 512 KB
-        if code.len() > MAX_HIGHLIGHT_BYTES {
-            return code
-                .lines()
-                .map(|l| Line::from(Span::raw(l.to_string())))
-                .collect();
+        self.highlight_code_with(code, language, theme_name, Comments::Keep)
+    }
+
+    /// Like `highlight_code()`, but `comments` may remove the spans the
+    /// theme colors as comments. Themes that give comments the default
+    /// foreground color can't tell them apart, so they keep them.
+    pub fn highlight_code_with(
+        &self,
+        code: &str,
+        language: &str,
+        theme_name: &str,
+        comments: Comments,
+    ) -> Vec<Line<'static>> {
+        if is_diff(language) {
+            return highlight_diff(code);
         }
 
-        let syntax = if language.is_empty() {
-            self.syntax_set.find_syntax_plain_text()
-        } else {
-            self.syntax_set
-                .find_syntax_by_token(language)
-                .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
-        };
+        // Blocks over the size limit are rendered as plain unstyled text.
+        if code.len() > MAX_HIGHLIGHT_BYTES {
+            return plain_lines(code);
+        }
 
-        let Some(theme) = self
-            .theme_set
-            .themes
-            .get(theme_name)
-            .or_else(|| self.theme_set.themes.get(DEFAULT_THEME))
-        else {
+        let syntax = self.syntax(language);
+        let Some(theme) = self.theme(theme_name) else {
             // No theme available at all (e.g. syntect built without default themes).
             // Fall back to unstyled plain text so the app never panics at startup.
-            return code
-                .lines()
-                .map(|l| Line::from(Span::raw(l.to_string())))
-                .collect();
+            return plain_lines(code);
         };
 
         let comment_color = resolve_comment_color(theme);
+        let background = theme_background(theme);
         let mut highlighter = HighlightLines::new(syntax, theme);
         let mut result = Vec::new();
 
         for line in LinesWithEndings::from(code) {
             let Ok(ranges) = highlighter.highlight_line(line, &self.syntax_set) else {
                 // On highlight failure, emit the raw line as plain text.
-                // Strip both \n and \r\n — LinesWithEndings includes the line ending.
-                result.push(Line::from(Span::raw(
-                    line.trim_end_matches(['\r', '\n']).to_string(),
-                )));
+                result.push(raw_line(line));
                 continue;
             };
 
-            let spans: Vec<Span<'static>> = ranges
-                .iter()
-                .map(|(style, text)| {
-                    // Strip \r\n, not just \n, for files with Windows line endings.
-                    let trimmed = text.trim_end_matches(['\r', '\n']);
-                    let is_comment =
-                        comment_color.is_some_and(|cc| style.foreground == cc);
-                    syntect_style_to_span(trimmed, *style, is_comment)
+            let mut stripped = false;
+            let mut spans: Vec<Span<'static>> = line_spans(&ranges, comment_color, background)
+                .into_iter()
+                .filter_map(|(span, is_comment)| {
+                    if is_comment && comments != Comments::Keep {
+                        stripped = true;
+                        return None;
+                    }
+                    Some(span)
                 })
-                .filter(|span| !span.content.is_empty())
                 .collect();
 
-            result.push(Line::from(spans));
+            if !stripped {
+                result.push(Line::from(spans));
+                continue;
+            }
+            // Drop the whitespace that led up to the removed comment.
+            while spans.last().is_some_and(|span| span.content.trim().is_empty()) {
+                spans.pop();
+            }
+            if let Some(last) = spans.last_mut() {
+                last.content = last.content.trim_end().to_string().into();
+            }
+            if !spans.is_empty() || comments == Comments::StripKeepLines {
+                result.push(Line::from(spans));
+            }
         }
 
+        // The block's background, for the padding after each line.
+        for line in &mut result {
+            line.style.bg = background;
+        }
         result
     }
+
+    /// Prepares `code` to be highlighted a line at a time as it is shown,
+    /// for blocks too long to highlight up front. Comments are kept.
+    ///
+    /// Returns the lines unstyled (on the theme's background) to show until
+    /// then, or `None` where `highlight_code()` doesn't use syntect (diffs,
+    /// blocks over the size limit): highlight those directly.
+    pub fn highlight_lazily(
+        &self,
+        code: &str,
+        language: &str,
+        theme_name: &str,
+    ) -> Option<(Vec<Line<'static>>, LazyHighlight)> {
+        if is_diff(language) || code.len() > MAX_HIGHLIGHT_BYTES {
+            return None;
+        }
+        let theme = self.theme(theme_name)?;
+        let (highlight, parse) = HighlightLines::new(self.syntax(language), theme).state();
+        let background = theme_background(theme);
+        let lines = plain_lines(code)
+            .into_iter()
+            .map(|line| line.style(Style { bg: background, ..Style::default() }))
+            .collect();
+        let lazy = LazyHighlight {
+            syntax_set: Arc::clone(&self.syntax_set),
+            theme: theme.clone(),
+            source: LinesWithEndings::from(code).map(str::to_string).collect(),
+            comment_color: resolve_comment_color(theme),
+            background,
+            state: RefCell::new(LazyState { highlight, parse, lines: Vec::new() }),
+        };
+        Some((lines, lazy))
+    }
+
+    /// Guesses the language of a code block without one (`--guess-language`):
+    /// from a first line syntect recognizes (`#!/bin/sh`, `<?xml ...`), else
+    /// JSON, TOML or YAML by shape. Returns a token for `highlight_code()`,
+    /// or `None` when nothing fits.
+    pub fn guess_language(&self, code: &str) -> Option<String> {
+        let first = code.lines().find(|line| !line.trim().is_empty())?;
+        if let Some(syntax) = self.syntax_set.find_syntax_by_first_line(first)
+            && let Some(extension) = syntax.file_extensions.first()
+        {
+            return Some(extension.clone());
+        }
+        guess_data_language(code).map(str::to_string)
+    }
+
+    /// Returns the syntax for a fence language, plain text if unknown.
+    fn syntax(&self, language: &str) -> &SyntaxReference {
+        if language.is_empty() {
+            return self.syntax_set.find_syntax_plain_text();
+        }
+        self.syntax_set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+
+    /// Returns the named theme, or `DEFAULT_THEME` if there is none by that name.
+    fn theme(&self, name: &str) -> Option<&Theme> {
+        self.theme_set.themes.get(name).or_else(|| self.theme_set.themes.get(DEFAULT_THEME))
+    }
+}
+
+/// Guard against unbounded memory/CPU: Oniguruma (syntect's regex engine) can
+/// exhaust memory on large inputs, surfacing as a panic rather than an Err.
+const MAX_HIGHLIGHT_BYTES: usize = 512 * 1024; // 512 KB
+
+/// A code block highlighted on demand, from `Highlighter::highlight_lazily()`.
+///
+/// syntect carries its state from each line to the next, so lines are
+/// highlighted in order: asking for one highlights the lines before it
+/// first. Highlighted lines are kept, so each is highlighted once.
+pub struct LazyHighlight {
+    syntax_set: Arc<SyntaxSet>,
+    theme: Theme,
+    /// Source lines, each with its line ending.
+    source: Vec<String>,
+    comment_color: Option<syntect::highlighting::Color>,
+    background: Option<Color>,
+    state: RefCell<LazyState>,
+}
+
+/// How far a `LazyHighlight` has got.
+struct LazyState {
+    highlight: HighlightState,
+    parse: ParseState,
+    /// The lines highlighted so far, from the first.
+    lines: Vec<Line<'static>>,
+}
+
+impl LazyHighlight {
+    /// Number of lines in the block.
+    pub fn line_count(&self) -> usize {
+        self.source.len()
+    }
+
+    /// Returns line `index` highlighted, or `None` past the last line.
+    pub fn line(&self, index: usize) -> Option<Line<'static>> {
+        if index >= self.source.len() {
+            return None;
+        }
+        let mut state = self.state.borrow_mut();
+        let LazyState { highlight, parse, lines } = &mut *state;
+        if index >= lines.len() {
+            let mut highlighter =
+                HighlightLines::from_state(&self.theme, highlight.clone(), parse.clone());
+            for source in &self.source[lines.len()..=index] {
+                let line = match highlighter.highlight_line(source, &self.syntax_set) {
+                    Ok(ranges) => {
+                        let spans = line_spans(&ranges, self.comment_color, self.background);
+                        Line::from(spans.into_iter().map(|(span, _)| span).collect::<Vec<_>>())
+                    }
+                    Err(_) => raw_line(source),
+                };
+                lines.push(line.style(Style { bg: self.background, ..Style::default() }));
+            }
+            (*highlight, *parse) = highlighter.state();
+        }
+        Some(lines[index].clone())
+    }
+}
+
+/// Recognizes JSON, TOML and YAML by shape: JSON is one object or array;
+/// in TOML and YAML every line that isn't blank, a comment or indented is
+/// a `[table]` or `key = value`, or a `key:` or `- item` respectively.
+fn guess_data_language(code: &str) -> Option<&'static str> {
+    let code = code.trim();
+    if code.starts_with('{') && code.ends_with('}') {
+        return Some("json");
+    }
+    let lines: Vec<&str> = code
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .filter(|line| !line.starts_with([' ', '\t']))
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let is_key = |key: &str| {
+        let key = key.trim().trim_matches('"');
+        !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || "_-.".contains(c))
+    };
+    let toml = |line: &&str| {
+        (line.starts_with('[') && line.trim_end().ends_with(']'))
+            || line.split_once('=').is_some_and(|(key, _)| is_key(key))
+    };
+    if lines.iter().all(toml) {
+        return Some("toml");
+    }
+    // Checked after TOML, whose `[table]` lines look like arrays too.
+    if code.starts_with('[') && code.ends_with(']') && code.contains('\n') {
+        return Some("json");
+    }
+    let yaml = |line: &&str| {
+        *line == "---"
+            || *line == "-"
+            || line.starts_with("- ")
+            || line.split_once(':').is_some_and(|(key, rest)| {
+                is_key(key) && (rest.is_empty() || rest.starts_with(' '))
+            })
+    };
+    if lines.iter().all(yaml) {
+        return Some("yaml");
+    }
+    None
+}
+
+/// Returns true for the languages `highlight_diff()` colors.
+fn is_diff(language: &str) -> bool {
+    language.eq_ignore_ascii_case("diff") || language.eq_ignore_ascii_case("patch")
+}
+
+/// The theme's background color. Without one syntect reports black, which
+/// would clash with the terminal: the background is left unset instead.
+fn theme_background(theme: &Theme) -> Option<Color> {
+    theme.settings.background.map(|c| Color::Rgb(c.r, c.g, c.b))
+}
+
+/// Splits `code` into unstyled lines.
+fn plain_lines(code: &str) -> Vec<Line<'static>> {
+    code.lines().map(raw_line).collect()
+}
+
+/// Returns a source line as plain text, without its line ending.
+fn raw_line(line: &str) -> Line<'static> {
+    // Strip both \n and \r\n — LinesWithEndings includes the line ending.
+    Line::from(Span::raw(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Converts one line's highlighted ranges to spans, each paired with
+/// whether the theme colors it as a comment. Empty spans are dropped.
+fn line_spans(
+    ranges: &[(SyntectStyle, &str)],
+    comment_color: Option<syntect::highlighting::Color>,
+    background: Option<Color>,
+) -> Vec<(Span<'static>, bool)> {
+    ranges
+        .iter()
+        .filter_map(|(style, text)| {
+            // Strip \r\n, not just \n, for files with Windows line endings.
+            let trimmed = text.trim_end_matches(['\r', '\n']);
+            if trimmed.is_empty() {
+                return None;
+            }
+            let is_comment = comment_color.is_some_and(|cc| style.foreground == cc);
+            let mut span = syntect_style_to_span(trimmed, *style, is_comment);
+            if background.is_none() {
+                span.style.bg = None;
+            }
+            Some((span, is_comment))
+        })
+        .collect()
+}
+
+/// Colors a unified diff by line prefix: added lines green, removed lines
+/// red, hunk headers cyan, file headers bold.
+///
+/// Uses the terminal's own palette rather than a syntect theme so the
+/// coloring is strong on any background. Only the foreground is set; the
+/// renderer fills in the code block background as usual.
+fn highlight_diff(code: &str) -> Vec<Line<'static>> {
+    code.lines()
+        .map(|line| {
+            let style = if line.starts_with("+++ ")
+                || line.starts_with("--- ")
+                || line.starts_with("diff ")
+            {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else if line.starts_with("@@") {
+                Style::default().fg(Color::Cyan)
+            } else if line.starts_with('+') {
+                Style::default().fg(Color::Green)
+            } else if line.starts_with('-') {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(line.to_string(), style))
+        })
+        .collect()
+}
+
+/// Converts captured terminal output with embedded ANSI escapes into styled
+/// lines, one per source line (`--raw-ansi`).
+///
+/// SGR sequences (`ESC [ ... m`) set the foreground color and text modifiers,
+/// carrying over from line to line as in a terminal. Background colors are
+/// dropped so the code block keeps its own. Every other escape sequence
+/// (cursor movement, OSC titles, ...) and control character is stripped.
+pub fn ansi_to_lines(code: &str) -> Vec<Line<'static>> {
+    let mut style = Style::default();
+    code.lines()
+        .map(|line| {
+            let mut spans = Vec::new();
+            let mut text = String::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                if c != '\x1b' {
+                    if c == '\t' || !c.is_control() {
+                        text.push(c);
+                    }
+                    continue;
+                }
+                match chars.next() {
+                    // CSI: parameters up to a final byte in `@`..=`~`.
+                    Some('[') => {
+                        let mut params = String::new();
+                        let final_byte = chars.by_ref().find(|c| {
+                            let is_final = ('@'..='~').contains(c);
+                            if !is_final {
+                                params.push(*c);
+                            }
+                            is_final
+                        });
+                        if final_byte == Some('m') {
+                            if !text.is_empty() {
+                                spans.push(Span::styled(std::mem::take(&mut text), style));
+                            }
+                            style = apply_sgr(style, &params);
+                        }
+                    }
+                    // OSC: up to BEL or ST (`ESC \`).
+                    Some(']') => {
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1b' {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    // Character set designation takes one more byte.
+                    Some('(' | ')') => {
+                        chars.next();
+                    }
+                    // Any other escape is two bytes long.
+                    _ => {}
+                }
+            }
+            if !text.is_empty() {
+                spans.push(Span::styled(text, style));
+            }
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Applies the `;`-separated SGR parameters `params` to `style`.
+/// An empty parameter list resets, as `ESC [ m` does in a terminal.
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let mut codes = params.split([';', ':']).map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            5 => style.add_modifier(Modifier::SLOW_BLINK),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            25 => style.remove_modifier(Modifier::SLOW_BLINK),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed((code - 30) as u8)),
+            90..=97 => style.fg(Color::Indexed((code - 90 + 8) as u8)),
+            38 => match extended_color(&mut codes) {
+                Some(color) => style.fg(color),
+                None => style,
+            },
+            39 => Style { fg: None, ..style },
+            // Extended backgrounds carry their color too; skip past it.
+            48 => {
+                extended_color(&mut codes);
+                style
+            }
+            // Backgrounds (40–49, 100–107) and codes with no style equivalent.
+            _ => style,
+        };
+    }
+    style
+}
+
+/// Reads the color after a `38`/`48` SGR code: `5;N` (256-color palette)
+/// or `2;R;G;B` (truecolor).
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut channel = || codes.next().and_then(|c| u8::try_from(c).ok());
+    match channel()? {
+        5 => channel().map(Color::Indexed),
+        2 => Some(Color::Rgb(channel()?, channel()?, channel()?)),
+        _ => None,
+    }
 }
 
 /// Resolves the foreground color that the given theme assigns to the `comment` scope.
@@ -147,6 +585,10 @@ fn resolve_comment_color(
 /// Maps syntect RGB colors → `Color::Rgb` and syntect `FontStyle` flags
 /// → ratatui `Modifier` flags. When `is_comment` is true, `ITALIC` is
 /// forced so that comments land in the italic font slot.
+///
+/// `FontStyle` has only bold, italic and underline: syntect has no
+/// strikethrough flag (a theme using `strikethrough` fails to load), so
+/// `CROSSED_OUT` is never set here.
 /// Returns `Span<'static>` because we call `to_string()` to create owned data.
 fn syntect_style_to_span(text: &str, style: SyntectStyle, is_comment: bool) -> Span<'static> {
     let fg = Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b);
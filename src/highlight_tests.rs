@@ -154,3 +154,196 @@
             "non-comment code should not have forced ITALIC"
         );
     }
+
+    fn line_texts(lines: &[Line]) -> Vec<String> {
+        lines.iter().map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect()).collect()
+    }
+
+    #[test]
+    fn test_highlight_strip_comments_drops_comment_only_lines() {
+        let code = "// leading\nlet x = 1; // trailing\n/* block\n   more */\n\nlet y = 2;\n";
+        let h = highlighter();
+        let kept = h.highlight_code_with(code, "rust", DEFAULT_THEME, Comments::Keep);
+        assert_eq!(line_texts(&kept), line_texts(&h.highlight_code(code, "rust", DEFAULT_THEME)));
+        let lines = h.highlight_code_with(code, "rust", DEFAULT_THEME, Comments::Strip);
+        // Blank lines that never held a comment stay.
+        assert_eq!(line_texts(&lines), ["let x = 1;", "", "let y = 2;"]);
+        let lines = h.highlight_code_with(code, "rust", DEFAULT_THEME, Comments::StripKeepLines);
+        assert_eq!(line_texts(&lines), ["", "let x = 1;", "", "", "", "let y = 2;"]);
+    }
+
+    #[test]
+    fn test_highlight_strip_comments_leaves_strings_and_diffs() {
+        let h = highlighter();
+        let code = "s = \"# not a comment\"  # comment\n";
+        let lines = h.highlight_code_with(code, "python", DEFAULT_THEME, Comments::Strip);
+        assert_eq!(line_texts(&lines), ["s = \"# not a comment\""]);
+        let diff = "+// added comment\n";
+        let lines = h.highlight_code_with(diff, "diff", DEFAULT_THEME, Comments::Strip);
+        assert_eq!(line_texts(&lines), ["+// added comment"]);
+    }
+
+    #[test]
+    fn test_highlight_parse_osc11_reply_bel_and_st() {
+        assert_eq!(
+            parse_osc11_reply("\x1b]11;rgb:ffff/ffff/ffff\x07"),
+            Some((255, 255, 255))
+        );
+        assert_eq!(
+            parse_osc11_reply("\x1b]11;rgb:2b2b/3030/3b3b\x1b\\"),
+            Some((43, 48, 59))
+        );
+    }
+
+    #[test]
+    fn test_highlight_parse_osc11_reply_short_channels() {
+        assert_eq!(parse_osc11_reply("\x1b]11;rgb:f/8/0\x07"), Some((255, 136, 0)));
+        assert_eq!(parse_osc11_reply("\x1b]11;rgb:ff/80/00\x07"), Some((255, 128, 0)));
+    }
+
+    #[test]
+    fn test_highlight_parse_osc11_reply_rejects_garbage() {
+        assert_eq!(parse_osc11_reply(""), None);
+        assert_eq!(parse_osc11_reply("\x1b]11;rgb:ffff/ffff\x07"), None);
+        assert_eq!(parse_osc11_reply("\x1b]11;rgb:zz/00/00\x07"), None);
+        assert_eq!(parse_osc11_reply("\x1b]11;rgb:0/0/0/0\x07"), None);
+    }
+
+    #[test]
+    fn test_highlight_theme_for_background() {
+        assert_eq!(theme_for_background(Some((255, 255, 255))), LIGHT_THEME);
+        assert_eq!(theme_for_background(Some((253, 246, 227))), LIGHT_THEME);
+        assert_eq!(theme_for_background(Some((43, 48, 59))), DEFAULT_THEME);
+        assert_eq!(theme_for_background(None), DEFAULT_THEME);
+    }
+
+    #[test]
+    fn test_highlight_light_theme_exists() {
+        assert!(Highlighter::new().theme_set.themes.contains_key(LIGHT_THEME));
+    }
+
+    #[test]
+    fn test_highlight_diff_colors_by_line_prefix() {
+        let code = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1,2 +1,2 @@\n context\n-removed\n+added\n";
+        let lines = highlighter().highlight_code(code, "diff", DEFAULT_THEME);
+        let fg = |i: usize| lines[i].spans[0].style.fg;
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].spans[0].style.add_modifier.contains(Modifier::BOLD));
+        assert!(lines[1].spans[0].style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(fg(2), Some(Color::Cyan));
+        assert_eq!(fg(3), None);
+        assert_eq!(fg(4), Some(Color::Red));
+        assert_eq!(fg(5), Some(Color::Green));
+        assert_eq!(lines[5].spans[0].content, "+added");
+    }
+
+    #[test]
+    fn test_highlight_patch_language_is_a_diff() {
+        let lines = highlighter().highlight_code("+added\n", "Patch", DEFAULT_THEME);
+        assert_eq!(lines[0].spans[0].style.fg, Some(Color::Green));
+    }
+
+    #[test]
+    fn test_highlight_ansi_to_lines_maps_sgr() {
+        let code = "\x1b[1;32mok\x1b[0m plain \x1b[38;5;208mhot\x1b[39m\n\x1b[38;2;1;2;3mrgb";
+        let lines = ansi_to_lines(code);
+        assert_eq!(lines.len(), 2);
+        let spans = &lines[0].spans;
+        let texts: Vec<&str> = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(texts, ["ok", " plain ", "hot"]);
+        assert_eq!(spans[0].style.fg, Some(Color::Indexed(2)));
+        assert!(spans[0].style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(spans[1].style, Style::default());
+        assert_eq!(spans[2].style.fg, Some(Color::Indexed(208)));
+        // The color set on line 2 doesn't leak back, and carries to its end.
+        assert_eq!(lines[1].spans[0].style.fg, Some(Color::Rgb(1, 2, 3)));
+    }
+
+    #[test]
+    fn test_highlight_ansi_to_lines_strips_other_escapes() {
+        let code = "\x1b]0;title\x07\x1b[2K\x1b[1Aa\rb\x1b(Bc\x1b[41m\x1b[48;5;1md";
+        let lines = ansi_to_lines(code);
+        let text: String = lines[0].spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(text, "abcd");
+        assert!(lines[0].spans.iter().all(|s| s.style.bg.is_none()));
+    }
+
+    #[test]
+    fn test_highlight_font_style_maps_every_flag() {
+        let style = SyntectStyle {
+            font_style: FontStyle::BOLD | FontStyle::ITALIC | FontStyle::UNDERLINE,
+            ..SyntectStyle::default()
+        };
+        let span = syntect_style_to_span("x", style, false);
+        assert_eq!(
+            span.style.add_modifier,
+            Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED
+        );
+        let plain = syntect_style_to_span("x", SyntectStyle::default(), false);
+        assert_eq!(plain.style.add_modifier, Modifier::empty());
+    }
+
+    #[test]
+    fn test_highlight_theme_names_sorted_with_defaults() {
+        let names = highlighter().theme_names();
+        assert!(names.is_sorted());
+        assert!(names.iter().any(|n| n == DEFAULT_THEME));
+        assert!(names.iter().any(|n| n == LIGHT_THEME));
+    }
+
+    #[test]
+    fn test_highlight_line_style_carries_theme_background() {
+        let h = highlighter();
+        let lines = h.highlight_code("let x = 1;\n\nlet y = 2;\n", "rust", DEFAULT_THEME);
+        // base16-ocean.dark defines #2b303b, empty lines included.
+        assert!(lines.iter().all(|line| line.style.bg == Some(Color::Rgb(0x2b, 0x30, 0x3b))));
+        assert_eq!(lines[0].spans[0].style.bg, Some(Color::Rgb(0x2b, 0x30, 0x3b)));
+    }
+
+    #[test]
+    fn test_highlight_theme_without_background_leaves_it_unset() {
+        let mut h = Highlighter::new();
+        let mut theme = h.theme_set.themes[DEFAULT_THEME].clone();
+        theme.settings.background = None;
+        h.theme_set.themes.insert("no-bg".to_string(), theme);
+        let lines = h.highlight_code("let x = 1;\n", "rust", "no-bg");
+        assert_eq!(lines[0].style.bg, None);
+        assert!(lines[0].spans.iter().all(|span| span.style.bg.is_none()));
+        assert!(lines[0].spans.iter().any(|span| span.style.fg.is_some()));
+    }
+
+    #[test]
+    fn test_highlight_lazily_matches_eager_highlighting() {
+        let h = highlighter();
+        let code = "/* a comment\n   spanning lines */\nfn main() {\n    let s = \"x\";\n}\n";
+        let eager = h.highlight_code(code, "rust", DEFAULT_THEME);
+        let (plain, lazy) = h.highlight_lazily(code, "rust", DEFAULT_THEME).unwrap();
+        assert_eq!(lazy.line_count(), eager.len());
+        assert_eq!(plain[1].spans[0].content, "   spanning lines */");
+        assert_eq!(plain[1].style.bg, eager[1].style.bg);
+        // Out of order: line 3 highlights 0..=3 first, carrying the comment state.
+        assert_eq!(lazy.line(3).as_ref(), Some(&eager[3]));
+        for (i, line) in eager.iter().enumerate() {
+            assert_eq!(lazy.line(i).as_ref(), Some(line), "line {i}");
+        }
+        assert_eq!(lazy.line(eager.len()), None);
+    }
+
+    #[test]
+    fn test_highlight_lazily_declines_diffs() {
+        assert!(highlighter().highlight_lazily("+a\n", "diff", DEFAULT_THEME).is_none());
+    }
+
+    #[test]
+    fn test_highlight_guess_language_by_shape_and_first_line() {
+        let h = highlighter();
+        let guess = |code: &str| h.guess_language(code);
+        assert_eq!(guess("{\n  \"a\": [1, 2]\n}\n").as_deref(), Some("json"));
+        assert_eq!(guess("[\n  1,\n  2\n]").as_deref(), Some("json"));
+        assert_eq!(guess("[package]\nname = \"x\"\n\n[dependencies]\n").as_deref(), Some("toml"));
+        assert_eq!(guess("# config\nname: x\nitems:\n  - a\n  - b\n").as_deref(), Some("yaml"));
+        assert_eq!(guess("- one\n- two\n").as_deref(), Some("yaml"));
+        assert_eq!(guess("#!/bin/bash\necho hi\n").as_deref(), Some("sh"));
+        assert_eq!(guess("just some words\nand more"), None);
+        assert_eq!(guess(""), None);
+    }
@@ -0,0 +1,295 @@
+//! Terminal image support: loads local images and encodes graphics escapes.
+//!
+//! This is a **leaf module** — it never imports from other mdink modules.
+//! The parser asks `ImageManager::load_image()` for an image index and cell
+//! size; the event loop later asks for the escape sequence that draws that
+//! image at the cursor. Everything in between only ever sees the index.
+//!
+//! Two protocols are supported, both emitted directly as escape sequences:
+//!
+//! - **Kitty** graphics protocol (kitty, Ghostty): PNG data is transmitted
+//!   once per image and then placed by id on every frame.
+//! - **iTerm2** inline images (iTerm2, WezTerm): the file is re-sent each
+//!   time it is drawn.
+//!
+//! Sixel is not supported, and detection is disabled inside tmux/screen
+//! (which need passthrough wrapping). Those terminals get the alt-text fallback.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+/// Terminal graphics protocol used to draw images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    /// Kitty graphics protocol (`ESC _ G ... ESC \`).
+    Kitty,
+    /// iTerm2 inline image protocol (`ESC ] 1337 ; File= ... BEL`).
+    Iterm2,
+}
+
+/// Why an image could not be loaded. The caller falls back to alt text.
+#[derive(Debug)]
+pub enum ImageError {
+    /// The terminal has no supported graphics protocol.
+    Unsupported,
+    /// The source is a remote URL or data URI, not a local file.
+    NotLocal,
+    /// The file exceeds `MAX_IMAGE_BYTES`.
+    TooLarge,
+    /// The file could not be read.
+    Io(std::io::Error),
+    /// The file could not be decoded or re-encoded as an image.
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Unsupported => write!(f, "terminal has no graphics support"),
+            ImageError::NotLocal => write!(f, "remote images are not supported"),
+            ImageError::TooLarge => write!(f, "image file too large"),
+            ImageError::Io(e) => write!(f, "failed to read image: {e}"),
+            ImageError::Decode(e) => write!(f, "failed to decode image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Images larger than this are not loaded (decoding is memory-intensive).
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024; // 20 MB
+
+/// Cell size assumed when the terminal does not report its pixel size.
+pub const DEFAULT_CELL_SIZE: (u16, u16) = (8, 16);
+
+/// Maximum base64 payload per kitty escape (protocol limit).
+const KITTY_CHUNK: usize = 4096;
+
+/// A loaded image ready to be drawn.
+struct LoadedImage {
+    /// Resolved path, used to deduplicate repeated references.
+    path: PathBuf,
+    /// Base64-encoded payload: PNG for kitty, the original file for iTerm2.
+    payload: String,
+    /// Whether the payload has been transmitted (kitty only).
+    transmitted: bool,
+}
+
+/// Loads images and produces the escape sequences that draw them.
+///
+/// Images are stored in an arena and referenced by index from the IR,
+/// so the IR never owns image data.
+pub struct ImageManager {
+    protocol: Option<GraphicsProtocol>,
+    images: Vec<LoadedImage>,
+    base_path: PathBuf,
+    /// Pixel size of one terminal cell (width, height).
+    cell_size: (u16, u16),
+}
+
+impl ImageManager {
+    /// Creates a manager resolving relative paths against `base_path`.
+    ///
+    /// `protocol` is `None` when the terminal cannot draw images; every
+    /// `load_image()` call then fails with `ImageError::Unsupported`.
+    pub fn new(
+        base_path: PathBuf,
+        protocol: Option<GraphicsProtocol>,
+        cell_size: (u16, u16),
+    ) -> Self {
+        let cell_size = if cell_size.0 == 0 || cell_size.1 == 0 {
+            DEFAULT_CELL_SIZE
+        } else {
+            cell_size
+        };
+        Self {
+            protocol,
+            images: Vec::new(),
+            base_path,
+            cell_size,
+        }
+    }
+
+    /// Returns the protocol in use, if any.
+    pub fn protocol(&self) -> Option<GraphicsProtocol> {
+        self.protocol
+    }
+
+    /// Loads a local image and returns `(index, width_cells, height_cells)`.
+    ///
+    /// The cell size is the image's natural size, scaled down (preserving
+    /// aspect ratio) to fit within `max_width` × `max_height` cells.
+    /// Repeated references to the same file share one index.
+    pub fn load_image(
+        &mut self,
+        src: &str,
+        max_width: u16,
+        max_height: u16,
+    ) -> Result<(usize, u16, u16), ImageError> {
+        let protocol = self.protocol.ok_or(ImageError::Unsupported)?;
+        if src.contains("://") || src.starts_with("data:") {
+            return Err(ImageError::NotLocal);
+        }
+
+        let path = self.base_path.join(src);
+        if std::fs::metadata(&path).map_err(ImageError::Io)?.len() > MAX_IMAGE_BYTES {
+            return Err(ImageError::TooLarge);
+        }
+
+        let (px_width, px_height) = image::image_dimensions(&path).map_err(ImageError::Decode)?;
+        let (width, height) = fit_cells(
+            px_width.div_ceil(self.cell_size.0 as u32),
+            px_height.div_ceil(self.cell_size.1 as u32),
+            max_width,
+            max_height,
+        );
+
+        if let Some(index) = self.images.iter().position(|img| img.path == path) {
+            return Ok((index, width, height));
+        }
+
+        let payload = match protocol {
+            GraphicsProtocol::Kitty => BASE64.encode(png_bytes(&path)?),
+            GraphicsProtocol::Iterm2 => {
+                BASE64.encode(std::fs::read(&path).map_err(ImageError::Io)?)
+            }
+        };
+        self.images.push(LoadedImage {
+            path,
+            payload,
+            transmitted: false,
+        });
+        Ok((self.images.len() - 1, width, height))
+    }
+
+    /// Returns the escape sequence that draws image `index` at the cursor,
+    /// scaled to `width` × `height` cells.
+    ///
+    /// For kitty, the first call also transmits the image data; later calls
+    /// only place it. Returns `None` for an unknown index.
+    pub fn draw_sequence(&mut self, index: usize, width: u16, height: u16) -> Option<String> {
+        let protocol = self.protocol?;
+        let image = self.images.get_mut(index)?;
+        let id = index + 1; // kitty ids must be non-zero
+        match protocol {
+            GraphicsProtocol::Kitty => {
+                let mut seq = String::new();
+                if !image.transmitted {
+                    seq.push_str(&kitty_transmit(id, &image.payload));
+                    image.transmitted = true;
+                }
+                seq.push_str(&kitty_place(id, width, height));
+                Some(seq)
+            }
+            GraphicsProtocol::Iterm2 => Some(iterm2_inline(&image.payload, width, height)),
+        }
+    }
+
+    /// Returns the escape sequence that removes all drawn images, if the
+    /// protocol needs one. iTerm2 images live in the cells themselves and
+    /// are erased by redrawing those cells instead.
+    pub fn clear_sequence(&self) -> Option<&'static str> {
+        match self.protocol? {
+            GraphicsProtocol::Kitty => Some("\x1b_Ga=d,d=a,q=2\x1b\\"),
+            GraphicsProtocol::Iterm2 => None,
+        }
+    }
+}
+
+/// Detects the graphics protocol from the process environment.
+pub fn detect_protocol() -> Option<GraphicsProtocol> {
+    let var = |name: &str| std::env::var(name).ok();
+    protocol_from_env(
+        var("TERM").as_deref(),
+        var("TERM_PROGRAM").as_deref(),
+        var("KITTY_WINDOW_ID").is_some(),
+        var("TMUX").is_some() || var("STY").is_some(),
+    )
+}
+
+/// Pure detection logic behind `detect_protocol()`.
+///
+/// Multiplexers are excluded because they swallow graphics escapes unless
+/// each one is wrapped in a passthrough sequence.
+fn protocol_from_env(
+    term: Option<&str>,
+    term_program: Option<&str>,
+    kitty_window: bool,
+    multiplexed: bool,
+) -> Option<GraphicsProtocol> {
+    if multiplexed {
+        return None;
+    }
+    match term_program {
+        Some("iTerm.app" | "WezTerm") => return Some(GraphicsProtocol::Iterm2),
+        Some("ghostty") => return Some(GraphicsProtocol::Kitty),
+        _ => {}
+    }
+    if kitty_window || term == Some("xterm-kitty") {
+        return Some(GraphicsProtocol::Kitty);
+    }
+    None
+}
+
+/// Scales a natural cell size down to fit `max_width` × `max_height`,
+/// preserving aspect ratio. Both dimensions are at least 1.
+fn fit_cells(width: u32, height: u32, max_width: u16, max_height: u16) -> (u16, u16) {
+    let width = width.max(1) as f64;
+    let height = height.max(1) as f64;
+    let scale = (max_width.max(1) as f64 / width)
+        .min(max_height.max(1) as f64 / height)
+        .min(1.0);
+    let fit = |v: f64| ((v * scale).round() as u16).max(1);
+    (fit(width), fit(height))
+}
+
+/// Returns the image at `path` as PNG bytes, re-encoding other formats.
+fn png_bytes(path: &Path) -> Result<Vec<u8>, ImageError> {
+    let bytes = std::fs::read(path).map_err(ImageError::Io)?;
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Ok(bytes);
+    }
+    let decoded = image::load_from_memory(&bytes).map_err(ImageError::Decode)?;
+    let mut png = Vec::new();
+    decoded
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(ImageError::Decode)?;
+    Ok(png)
+}
+
+/// Kitty: transmit base64 PNG data under `id` without displaying it.
+/// Payloads are split into 4096-byte chunks as the protocol requires.
+fn kitty_transmit(id: usize, payload: &str) -> String {
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut seq = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // Chunks are slices of ASCII base64, so they are always valid UTF-8.
+        let data = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            seq.push_str(&format!("\x1b_Ga=t,f=100,i={id},q=2,m={more};{data}\x1b\\"));
+        } else {
+            seq.push_str(&format!("\x1b_Gm={more};{data}\x1b\\"));
+        }
+    }
+    seq
+}
+
+/// Kitty: place a transmitted image at the cursor without moving it.
+fn kitty_place(id: usize, width: u16, height: u16) -> String {
+    format!("\x1b_Ga=p,i={id},c={width},r={height},C=1,q=2\x1b\\")
+}
+
+/// iTerm2: draw base64 file data inline at the cursor.
+fn iterm2_inline(payload: &str, width: u16, height: u16) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;width={width};height={height};preserveAspectRatio=1:{payload}\x07"
+    )
+}
+
+#[cfg(test)]
+#[path = "images_tests.rs"]
+mod tests;
@@ -0,0 +1,111 @@
+    use super::*;
+
+    /// Writes a solid-color PNG of the given pixel size to a fresh temp dir
+    /// and returns (dir, file name).
+    fn write_test_png(name: &str, width: u32, height: u32) -> (PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("mdink-images-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = image::RgbImage::from_pixel(width, height, image::Rgb([200, 30, 30]));
+        let file = format!("{name}.png");
+        img.save(dir.join(&file)).unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn test_images_protocol_from_env_kitty() {
+        let p = protocol_from_env(Some("xterm-kitty"), None, false, false);
+        assert_eq!(p, Some(GraphicsProtocol::Kitty));
+        let p = protocol_from_env(Some("xterm-256color"), None, true, false);
+        assert_eq!(p, Some(GraphicsProtocol::Kitty));
+    }
+
+    #[test]
+    fn test_images_protocol_from_env_iterm2_and_wezterm() {
+        let p = protocol_from_env(None, Some("iTerm.app"), false, false);
+        assert_eq!(p, Some(GraphicsProtocol::Iterm2));
+        let p = protocol_from_env(None, Some("WezTerm"), false, false);
+        assert_eq!(p, Some(GraphicsProtocol::Iterm2));
+    }
+
+    #[test]
+    fn test_images_protocol_from_env_unknown_or_multiplexed_is_none() {
+        assert_eq!(protocol_from_env(Some("xterm-256color"), None, false, false), None);
+        assert_eq!(protocol_from_env(Some("xterm-kitty"), None, true, true), None);
+    }
+
+    #[test]
+    fn test_images_fit_cells_scales_down_preserving_aspect() {
+        assert_eq!(fit_cells(10, 5, 80, 24), (10, 5));
+        assert_eq!(fit_cells(160, 40, 80, 24), (80, 20));
+        assert_eq!(fit_cells(40, 96, 80, 24), (10, 24));
+    }
+
+    #[test]
+    fn test_images_fit_cells_never_zero() {
+        assert_eq!(fit_cells(0, 0, 0, 0), (1, 1));
+    }
+
+    #[test]
+    fn test_images_kitty_transmit_chunks_payload() {
+        let payload = "A".repeat(KITTY_CHUNK + 10);
+        let seq = kitty_transmit(3, &payload);
+        assert!(seq.starts_with("\x1b_Ga=t,f=100,i=3,q=2,m=1;"));
+        assert!(seq.ends_with(&format!("\x1b_Gm=0;{}\x1b\\", "A".repeat(10))));
+        assert_eq!(seq.matches("\x1b_G").count(), 2);
+    }
+
+    #[test]
+    fn test_images_load_without_protocol_is_unsupported() {
+        let mut manager = ImageManager::new(PathBuf::new(), None, DEFAULT_CELL_SIZE);
+        let result = manager.load_image("x.png", 80, 24);
+        assert!(matches!(result, Err(ImageError::Unsupported)));
+    }
+
+    #[test]
+    fn test_images_load_remote_url_is_rejected() {
+        let mut manager =
+            ImageManager::new(PathBuf::new(), Some(GraphicsProtocol::Kitty), DEFAULT_CELL_SIZE);
+        let result = manager.load_image("https://example.com/a.png", 80, 24);
+        assert!(matches!(result, Err(ImageError::NotLocal)));
+    }
+
+    #[test]
+    fn test_images_load_missing_file_is_io_error() {
+        let mut manager =
+            ImageManager::new(PathBuf::new(), Some(GraphicsProtocol::Kitty), DEFAULT_CELL_SIZE);
+        let result = manager.load_image("definitely/not/here.png", 80, 24);
+        assert!(matches!(result, Err(ImageError::Io(_))));
+    }
+
+    #[test]
+    fn test_images_load_png_reports_cell_size_and_dedupes() {
+        let (dir, file) = write_test_png("load", 80, 64);
+        let mut manager = ImageManager::new(dir, Some(GraphicsProtocol::Kitty), (8, 16));
+        let (index, width, height) = manager.load_image(&file, 80, 24).unwrap();
+        assert_eq!((index, width, height), (0, 10, 4));
+        let (again, _, _) = manager.load_image(&file, 80, 24).unwrap();
+        assert_eq!(again, index, "same file should reuse its index");
+    }
+
+    #[test]
+    fn test_images_kitty_draw_transmits_once_then_places() {
+        let (dir, file) = write_test_png("kitty", 16, 16);
+        let mut manager = ImageManager::new(dir, Some(GraphicsProtocol::Kitty), (8, 16));
+        let (index, width, height) = manager.load_image(&file, 80, 24).unwrap();
+        let first = manager.draw_sequence(index, width, height).unwrap();
+        assert!(first.contains("a=t"));
+        assert!(first.contains("a=p,i=1,c=2,r=1"));
+        let second = manager.draw_sequence(index, width, height).unwrap();
+        assert!(!second.contains("a=t"));
+    }
+
+    #[test]
+    fn test_images_iterm2_draw_sequence_inline() {
+        let (dir, file) = write_test_png("iterm", 16, 16);
+        let mut manager = ImageManager::new(dir, Some(GraphicsProtocol::Iterm2), (8, 16));
+        let (index, width, height) = manager.load_image(&file, 80, 24).unwrap();
+        let seq = manager.draw_sequence(index, width, height).unwrap();
+        assert!(seq.starts_with("\x1b]1337;File=inline=1;width=2;height=1;"));
+        assert!(seq.ends_with('\x07'));
+        assert!(manager.clear_sequence().is_none());
+    }
@@ -0,0 +1,254 @@
+//! The viewer's key bindings, as data.
+//!
+//! `KEYMAP` is the single list of what each key does in normal mode: the
+//! viewer looks keys up in it (`action_for()`), the `?` overlay shows it
+//! (`help_rows()`) and `mdink --print-keys` prints it (`to_table()`,
+//! `to_json()`). Keys that only mean something in an overlay or while
+//! typing (the outline's arrows, the theme picker) are handled by their
+//! modes and not listed.
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthStr;
+
+/// Something a key does in normal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    LineDown,
+    LineUp,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+    Goto,
+    NextHeading,
+    PreviousHeading,
+    NextCodeBlock,
+    PreviousCodeBlock,
+    Search,
+    NextMatch,
+    Outline,
+    Wrap,
+    StatusBar,
+    Wider,
+    Narrower,
+    ThemePicker,
+    Brightness,
+    Raw,
+    Diagram,
+    CopyVisible,
+    CopyDocument,
+    CopyReflowed,
+    Edit,
+    Reload,
+    Command,
+    Help,
+    Quit,
+}
+
+impl Action {
+    /// The action's stable name in `--print-keys` output, e.g. "line-down".
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::LineDown => "line-down",
+            Action::LineUp => "line-up",
+            Action::HalfPageDown => "half-page-down",
+            Action::HalfPageUp => "half-page-up",
+            Action::PageDown => "page-down",
+            Action::PageUp => "page-up",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::Goto => "goto",
+            Action::NextHeading => "next-heading",
+            Action::PreviousHeading => "previous-heading",
+            Action::NextCodeBlock => "next-code-block",
+            Action::PreviousCodeBlock => "previous-code-block",
+            Action::Search => "search",
+            Action::NextMatch => "next-match",
+            Action::Outline => "outline",
+            Action::Wrap => "wrap",
+            Action::StatusBar => "status-bar",
+            Action::Wider => "wider",
+            Action::Narrower => "narrower",
+            Action::ThemePicker => "theme-picker",
+            Action::Brightness => "brightness",
+            Action::Raw => "raw",
+            Action::Diagram => "diagram",
+            Action::CopyVisible => "copy-visible",
+            Action::CopyDocument => "copy-document",
+            Action::CopyReflowed => "copy-reflowed",
+            Action::Edit => "edit",
+            Action::Reload => "reload",
+            Action::Command => "command",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// Keys bound to one action.
+///
+/// Key names are a character for itself, `^X` for Ctrl+X, `1-9` for any
+/// digit but 0, or one of `↓ ↑ PgDn PgUp Home End Space Esc`. Other names
+/// match with any modifiers but Ctrl (Shift+G is `G`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub keys: &'static [&'static str],
+    pub action: Action,
+    /// What the `?` overlay says; adjacent bindings with the same text
+    /// share a row.
+    pub help: &'static str,
+}
+
+const fn bind(keys: &'static [&'static str], action: Action, help: &'static str) -> Binding {
+    Binding { keys, action, help }
+}
+
+/// Every normal-mode binding, in the order the `?` overlay lists them.
+/// Ctrl+C quits from any mode, before the mode sees the key.
+pub const KEYMAP: &[Binding] = &[
+    bind(&["j", "↓"], Action::LineDown, "scroll one line"),
+    bind(&["k", "↑"], Action::LineUp, "scroll one line"),
+    bind(&["d", "PgDn", "^D"], Action::HalfPageDown, "scroll half a page"),
+    bind(&["u", "PgUp", "^U"], Action::HalfPageUp, "scroll half a page"),
+    // Space / b and Ctrl+F / Ctrl+B, as in less.
+    bind(&["Space", "^F"], Action::PageDown, "scroll a page"),
+    bind(&["b", "^B"], Action::PageUp, "scroll a page"),
+    bind(&["g", "Home"], Action::Top, "top / bottom"),
+    bind(&["G", "End"], Action::Bottom, "top / bottom"),
+    bind(&["1-9"], Action::Goto, "go to line N (type N, Enter)"),
+    bind(&["}"], Action::NextHeading, "next / previous heading"),
+    bind(&["{"], Action::PreviousHeading, "next / previous heading"),
+    bind(&["]"], Action::NextCodeBlock, "next / previous code block"),
+    bind(&["["], Action::PreviousCodeBlock, "next / previous code block"),
+    bind(&["/"], Action::Search, "search / next match"),
+    bind(&["n"], Action::NextMatch, "search / next match"),
+    bind(&["o"], Action::Outline, "toggle outline"),
+    bind(&["w"], Action::Wrap, "toggle wrapping"),
+    bind(&["s"], Action::StatusBar, "toggle status bar"),
+    bind(&["+", "="], Action::Wider, "wider / narrower text"),
+    bind(&["-"], Action::Narrower, "wider / narrower text"),
+    bind(&["T"], Action::ThemePicker, "pick code theme"),
+    bind(&["B"], Action::Brightness, "dark / light theme"),
+    bind(&["R"], Action::Raw, "raw markdown source"),
+    bind(&["x"], Action::Diagram, "collapse / expand diagram"),
+    bind(&["y"], Action::CopyVisible, "copy visible / all / unwrapped"),
+    bind(&["Y"], Action::CopyDocument, "copy visible / all / unwrapped"),
+    bind(&["c"], Action::CopyReflowed, "copy visible / all / unwrapped"),
+    bind(&["e"], Action::Edit, "edit / reload file"),
+    bind(&["r"], Action::Reload, "edit / reload file"),
+    bind(&[":"], Action::Command, "command: goto N, theme X, set width N"),
+    bind(&["?"], Action::Help, "this help"),
+    bind(&["q", "Esc", "^C"], Action::Quit, "close / quit"),
+];
+
+/// Returns the action `key` is bound to in `KEYMAP`, if any.
+pub fn action_for(key: KeyEvent) -> Option<Action> {
+    KEYMAP
+        .iter()
+        .find(|binding| binding.keys.iter().any(|&name| key_matches(name, key)))
+        .map(|binding| binding.action)
+}
+
+/// Returns true if `key` is the key called `name` (see `Binding`).
+fn key_matches(name: &str, key: KeyEvent) -> bool {
+    if name == "1-9" {
+        return matches!(key.code, KeyCode::Char('1'..='9'));
+    }
+    if let Some(code) = named_key(name) {
+        return key.code == code;
+    }
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('^'), Some(c), None) => ctrl && key.code == KeyCode::Char(c.to_ascii_lowercase()),
+        (Some(c), None, None) => !ctrl && key.code == KeyCode::Char(c),
+        _ => false,
+    }
+}
+
+/// Returns the key a multi-character key name stands for.
+fn named_key(name: &str) -> Option<KeyCode> {
+    Some(match name {
+        "↓" => KeyCode::Down,
+        "↑" => KeyCode::Up,
+        "PgDn" => KeyCode::PageDown,
+        "PgUp" => KeyCode::PageUp,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "Space" => KeyCode::Char(' '),
+        "Esc" => KeyCode::Esc,
+        _ => return None,
+    })
+}
+
+/// Returns the `?` overlay's rows: the keys of adjacent bindings sharing a
+/// help text, joined by spaces, and that text.
+pub fn help_rows() -> Vec<(String, &'static str)> {
+    let mut rows: Vec<(String, &'static str)> = Vec::new();
+    for binding in KEYMAP {
+        let keys = binding.keys.join(" ");
+        match rows.last_mut() {
+            Some((row_keys, help)) if *help == binding.help => {
+                row_keys.push(' ');
+                row_keys.push_str(&keys);
+            }
+            _ => rows.push((keys, binding.help)),
+        }
+    }
+    rows
+}
+
+/// Formats `KEYMAP` as aligned columns of keys, action name and help text,
+/// one binding per line (`--print-keys`).
+pub fn to_table() -> String {
+    let keys: Vec<String> = KEYMAP.iter().map(|binding| binding.keys.join(" ")).collect();
+    let keys_width = keys.iter().map(|keys| keys.width()).max().unwrap_or(0);
+    let name_width = KEYMAP.iter().map(|binding| binding.action.name().len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (keys, binding) in keys.iter().zip(KEYMAP) {
+        let pad = keys_width - keys.width();
+        let name = binding.action.name();
+        out.push_str(&format!("{keys}{:pad$}  {name:<name_width$}  {}\n", "", binding.help));
+    }
+    out
+}
+
+/// Formats `KEYMAP` as a JSON array of `{"keys", "action", "help"}`
+/// objects (`--print-keys=json`).
+pub fn to_json() -> String {
+    let objects: Vec<String> = KEYMAP
+        .iter()
+        .map(|binding| {
+            let keys: Vec<String> = binding.keys.iter().map(|key| json_string(key)).collect();
+            format!(
+                "  {{\"keys\": [{}], \"action\": {}, \"help\": {}}}",
+                keys.join(", "),
+                json_string(binding.action.name()),
+                json_string(binding.help)
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+/// Quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+#[path = "keymap_tests.rs"]
+mod tests;
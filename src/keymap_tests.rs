@@ -0,0 +1,66 @@
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_keymap_every_key_name_is_understood() {
+        for binding in KEYMAP {
+            for &name in binding.keys {
+                let single = name.chars().count() == 1;
+                let ctrl = name.len() == 2 && name.starts_with('^');
+                assert!(
+                    single || ctrl || name == "1-9" || named_key(name).is_some(),
+                    "unknown key name {name:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_keymap_action_for_matches_names_and_modifiers() {
+        let none = KeyModifiers::empty();
+        let ctrl = KeyModifiers::CONTROL;
+        assert_eq!(action_for(key(KeyCode::Char('j'), none)), Some(Action::LineDown));
+        assert_eq!(action_for(key(KeyCode::Down, none)), Some(Action::LineDown));
+        assert_eq!(action_for(key(KeyCode::Char(' '), none)), Some(Action::PageDown));
+        assert_eq!(action_for(key(KeyCode::Char('f'), ctrl)), Some(Action::PageDown));
+        assert_eq!(action_for(key(KeyCode::Char('f'), none)), None);
+        assert_eq!(action_for(key(KeyCode::Char('b'), ctrl)), Some(Action::PageUp));
+        assert_eq!(action_for(key(KeyCode::Char('c'), ctrl)), Some(Action::Quit));
+        assert_eq!(action_for(key(KeyCode::Char('c'), none)), Some(Action::CopyReflowed));
+        assert_eq!(action_for(key(KeyCode::Char('G'), KeyModifiers::SHIFT)), Some(Action::Bottom));
+        assert_eq!(action_for(key(KeyCode::Char('7'), none)), Some(Action::Goto));
+        assert_eq!(action_for(key(KeyCode::Char('0'), none)), None);
+        assert_eq!(action_for(key(KeyCode::Char('='), none)), Some(Action::Wider));
+        assert_eq!(action_for(key(KeyCode::Esc, none)), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_keymap_help_rows_join_bindings_that_share_a_text() {
+        let rows = help_rows();
+        assert_eq!(rows[0], ("j ↓ k ↑".to_string(), "scroll one line"));
+        assert!(rows.contains(&("y Y c".to_string(), "copy visible / all / unwrapped")));
+        let helps: Vec<&str> = rows.iter().map(|(_, help)| *help).collect();
+        let mut unique = helps.clone();
+        unique.dedup();
+        assert_eq!(helps, unique);
+    }
+
+    #[test]
+    fn test_keymap_table_and_json_list_every_binding() {
+        let table = to_table();
+        assert_eq!(table.lines().count(), KEYMAP.len());
+        let line = table.lines().find(|line| line.contains("next-code-block")).unwrap();
+        assert!(line.starts_with("]"), "{line:?}");
+        assert!(line.ends_with("next / previous code block"), "{line:?}");
+
+        let json = to_json();
+        assert!(json.starts_with("[\n") && json.ends_with("]\n"));
+        assert_eq!(json.matches("\"action\": ").count(), KEYMAP.len());
+        assert!(json.contains(
+            r#"{"keys": ["q", "Esc", "^C"], "action": "quit", "help": "close / quit"}"#
+        ));
+        assert_eq!(json_string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
+    }
@@ -4,20 +4,268 @@
 //! the block-level IR from the parser and produces a flat sequence of
 //! `DocumentLine`s sized to fit a given terminal width.
 
+use std::ops::Range;
+use std::rc::Rc;
+
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthStr;
 
-use crate::parser::{RenderedBlock, StyledSpan};
+use crate::highlight::LazyHighlight;
+use crate::parser::{ListItem, RenderedBlock, StyledSpan};
 
 /// A pre-rendered document ready for viewport slicing and rendering.
 ///
-/// Contains all lines laid out for a specific terminal width. Created
+/// Contains the lines laid out for a specific terminal width. Created
 /// once on load and again on terminal resize.
+///
+/// Documents from `flatten()` are complete. Documents from `flatten_lazy()`
+/// may hold only a laid-out prefix: `ensure_lines()` extends it in block
+/// order, so lines already laid out never move.
 pub struct PreRenderedDocument {
-    /// All document lines in display order.
+    /// Document lines in display order (the laid-out prefix, if lazy).
     pub lines: Vec<DocumentLine>,
-    /// Total number of lines (== `lines.len()`).
+    /// Total number of lines. Equals `lines.len()` once layout is complete;
+    /// before that, an estimate that is refined as more blocks are laid out.
     pub total_height: usize,
+    /// Headings in document order, for the outline sidebar. Only headings
+    /// inside the laid-out prefix are listed until layout is complete.
+    pub headings: Vec<HeadingEntry>,
+    /// Index into `lines` where each laid-out block starts (at its spacing
+    /// line, if any). Empty for documents made with `from_lines()`.
+    pub block_starts: Vec<usize>,
+    /// Index into `lines` where each laid-out code block starts (at its
+    /// label, if any), in document order.
+    pub code_starts: Vec<usize>,
+    /// Index into `lines` where each laid-out diagram block (see
+    /// `is_diagram()`) starts, collapsed or not. A diagram's position here
+    /// is its number in `LayoutOptions::flipped_diagrams`.
+    pub diagrams: Vec<usize>,
+    /// Issue and commit references (`ParseOptions::repo_url`) in the
+    /// laid-out lines, in document order.
+    pub references: Vec<ReferenceSpan>,
+    /// Laid-out code blocks highlighted on demand; see `highlight_code()`.
+    lazy_code: Vec<LazyCode>,
+    /// Blocks not laid out yet; `None` once layout is complete.
+    pending: Option<PendingLayout>,
+}
+
+/// A top-level code block with `CodeBlock::lazy` set, whose lines are
+/// swapped for highlighted ones as they are needed.
+struct LazyCode {
+    /// Index into `lines` of the block's first code line.
+    first_line: usize,
+    /// How many of its lines have been swapped so far.
+    highlighted: usize,
+    highlight: Rc<LazyHighlight>,
+}
+
+/// The not-yet-laid-out tail of a lazily flattened document.
+struct PendingLayout {
+    blocks: Rc<[RenderedBlock]>,
+    /// Index of the next block to lay out.
+    next: usize,
+    width: usize,
+    options: LayoutOptions,
+    /// Estimated height of `blocks[next..]`, including spacing.
+    estimate: usize,
+}
+
+/// `flatten_lazy()` lays out at least this many lines up front, so typical
+/// documents are complete (and `total_height` exact) from the start.
+const EAGER_LINES: usize = 5_000;
+
+impl PreRenderedDocument {
+    /// Creates a complete document from already laid-out lines.
+    pub fn from_lines(lines: Vec<DocumentLine>) -> Self {
+        let mut code_starts = Vec::new();
+        track_code_starts(&lines, 0, &mut code_starts);
+        Self {
+            total_height: lines.len(),
+            lines,
+            headings: Vec::new(),
+            block_starts: Vec::new(),
+            code_starts,
+            diagrams: Vec::new(),
+            references: Vec::new(),
+            lazy_code: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Returns the page that span `span` of line `line` links to, if it is
+    /// an issue or commit reference.
+    pub fn reference(&self, line: usize, span: usize) -> Option<&str> {
+        let first = self.references.partition_point(|r| (r.line, r.span) < (line, span));
+        self.references
+            .get(first)
+            .filter(|r| (r.line, r.span) == (line, span))
+            .map(|r| r.url.as_str())
+    }
+
+    /// Returns true once every block has been laid out.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_none()
+    }
+
+    /// Lays out further blocks until at least `count` lines exist or the
+    /// document is complete.
+    pub fn ensure_lines(&mut self, count: usize) {
+        let Some(pending) = self.pending.as_mut() else {
+            return;
+        };
+        while self.lines.len() < count && pending.next < pending.blocks.len() {
+            let block = &pending.blocks[pending.next];
+            pending.estimate = pending
+                .estimate
+                .saturating_sub(estimate_height(block, pending.next, pending.width));
+            let start = self.lines.len();
+            self.block_starts.push(start);
+            layout_block(
+                block,
+                spacing_before(&pending.blocks, pending.next, &pending.options),
+                pending.width,
+                &pending.options,
+                &mut self.lines,
+                &mut self.headings,
+                &mut self.diagrams,
+            );
+            track_lazy_code(block, &self.lines, &mut self.lazy_code);
+            track_code_starts(&self.lines, start, &mut self.code_starts);
+            track_references(block, &mut self.lines, start, &mut self.references);
+            pending.next += 1;
+        }
+        if pending.next == pending.blocks.len() {
+            self.pending = None;
+            self.total_height = self.lines.len();
+        } else {
+            self.total_height = self.lines.len() + pending.estimate;
+        }
+    }
+
+    /// Lays out all remaining blocks.
+    pub fn complete(&mut self) {
+        self.ensure_lines(usize::MAX);
+    }
+
+    /// Highlights the lines of lazily highlighted code blocks (see
+    /// `ParseOptions::lazy_highlight`) that fall in `range`, along with the
+    /// lines before them in the same block. Call it before lines are shown.
+    pub fn highlight_code(&mut self, range: Range<usize>) {
+        for code in &mut self.lazy_code {
+            let end = range.end.min(code.first_line + code.highlight.line_count());
+            if end <= code.first_line.max(range.start) {
+                continue;
+            }
+            for i in code.highlighted..end - code.first_line {
+                if let Some(line) = code.highlight.line(i) {
+                    self.lines[code.first_line + i] = DocumentLine::Code(line);
+                }
+            }
+            code.highlighted = code.highlighted.max(end - code.first_line);
+        }
+    }
+
+    /// Completes the layout and drops every line outside `range`, clamped
+    /// to the document. Headings and code block starts outside it are
+    /// dropped too; block starts no longer apply and are cleared.
+    pub fn keep_lines(&mut self, range: Range<usize>) {
+        self.complete();
+        let end = range.end.min(self.lines.len());
+        let start = range.start.min(end);
+        self.lines.truncate(end);
+        self.lines.drain(..start);
+        self.total_height = self.lines.len();
+        self.headings.retain(|h| (start..end).contains(&h.line));
+        for heading in &mut self.headings {
+            heading.line -= start;
+        }
+        self.code_starts.retain(|line| (start..end).contains(line));
+        for line in &mut self.code_starts {
+            *line -= start;
+        }
+        self.diagrams.retain(|line| (start..end).contains(line));
+        for line in &mut self.diagrams {
+            *line -= start;
+        }
+        self.references.retain(|r| (start..end).contains(&r.line));
+        for reference in &mut self.references {
+            reference.line -= start;
+        }
+        self.block_starts.clear();
+        self.lazy_code.clear();
+    }
+
+    /// Returns where `line` falls in the block structure, so the same spot
+    /// can be found again with `anchor_line()` after a re-layout at another
+    /// width. `None` if `line` isn't laid out or blocks aren't tracked.
+    pub fn anchor(&self, line: usize) -> Option<Anchor> {
+        if line >= self.lines.len() {
+            return None;
+        }
+        let block = self.block_starts.partition_point(|&start| start <= line).checked_sub(1)?;
+        let start = self.block_starts[block];
+        Some(Anchor {
+            block,
+            offset: line - start,
+            height: self.block_end(block) - start,
+        })
+    }
+
+    /// Returns the line `anchor` (taken from another layout of the same
+    /// blocks) points to here, laying out blocks as far as needed. The
+    /// offset into the block is scaled by how much the block grew or shrank.
+    pub fn anchor_line(&mut self, anchor: Anchor) -> usize {
+        // The block's end is known once the block after it has started.
+        while !self.is_complete() && self.block_starts.len() <= anchor.block + 1 {
+            self.ensure_lines(self.lines.len() + 1);
+        }
+        let Some(&start) = self.block_starts.get(anchor.block) else {
+            return self.lines.len().saturating_sub(1);
+        };
+        let height = self.block_end(anchor.block) - start;
+        let offset = anchor.offset * height / anchor.height.max(1);
+        start + offset.min(height.saturating_sub(1))
+    }
+
+    /// Index one past the last laid-out line of `blocks[block]`.
+    fn block_end(&self, block: usize) -> usize {
+        self.block_starts.get(block + 1).copied().unwrap_or(self.lines.len())
+    }
+}
+
+/// A position within the document's blocks, from `PreRenderedDocument::anchor()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    /// Index of the block in the parsed document.
+    pub block: usize,
+    /// Line offset from the block's first line.
+    pub offset: usize,
+    /// The block's height in lines when the anchor was taken.
+    pub height: usize,
+}
+
+/// A heading and the document line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadingEntry {
+    /// Heading level, 1–6.
+    pub level: u8,
+    /// Unstyled heading text.
+    pub text: String,
+    /// Index into `PreRenderedDocument::lines` of the heading's first line.
+    pub line: usize,
+}
+
+/// An issue or commit reference in `PreRenderedDocument::lines`. A
+/// reference broken over two lines has an entry for each part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReferenceSpan {
+    /// Index into `PreRenderedDocument::lines` of its line.
+    pub line: usize,
+    /// Index of its span in the line.
+    pub span: usize,
+    /// The issue or commit page it links to.
+    pub url: String,
 }
 
 /// A single line of the pre-rendered document.
@@ -32,6 +280,192 @@ pub enum DocumentLine {
     Empty,
     /// A horizontal rule spanning the terminal width.
     Rule,
+    /// First line of an image drawn with terminal graphics. The following
+    /// `height - 1` lines are `ImageContinuation` placeholders reserving
+    /// vertical space for the image.
+    ImageStart {
+        protocol_index: usize,
+        /// Drawn size in cells, fitted to the layout width.
+        width: u16,
+        height: u16,
+        /// Shown instead of the image when it can't be drawn in full.
+        alt_text: String,
+    },
+    /// A placeholder line covered by the image above it.
+    ImageContinuation,
+}
+
+impl DocumentLine {
+    /// Returns the line's text content without styling.
+    ///
+    /// Used for clipboard export: text and code lines yield their span
+    /// content verbatim, empty lines yield `""`, and rules yield `---` so
+    /// the pasted text stays valid markdown.
+    pub fn plain_text(&self) -> String {
+        match self {
+            DocumentLine::Text(line) | DocumentLine::Code(line) => {
+                line.spans.iter().map(|s| s.content.as_ref()).collect()
+            }
+            DocumentLine::Empty | DocumentLine::ImageContinuation => String::new(),
+            DocumentLine::Rule => "---".to_string(),
+            DocumentLine::ImageStart { alt_text, .. } => format!("[image: {alt_text}]"),
+        }
+    }
+}
+
+/// Horizontal alignment of wrapped paragraph text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Ragged right edge (the default).
+    #[default]
+    Left,
+    /// Widen inter-word spaces so every line but a paragraph's last fills
+    /// the width. Headings and code are never justified.
+    Justify,
+}
+
+/// Whether prose is wrapped to the layout width.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Word-wrap headings and paragraphs to fit the width (the default).
+    #[default]
+    Wrap,
+    /// One line per paragraph (plus one per hard break), left for the
+    /// renderer to clip at the terminal edge.
+    Clip,
+}
+
+/// Options that change how blocks are laid out.
+///
+/// `Default` matches the behavior of `flatten()`.
+#[derive(Debug, Clone)]
+pub struct LayoutOptions {
+    pub align: TextAlign,
+    pub wrap: WrapMode,
+    /// Prefix code-block language labels with a Nerd Font glyph. Needs a
+    /// patched font; other fonts show a missing-glyph box.
+    pub icons: bool,
+    /// Draw a dim `│` guide at each nesting level of indented content
+    /// (list items, block quotes, `<details>` bodies) instead of blank
+    /// indentation.
+    pub indent_guides: bool,
+    /// Longest run of blank lines kept between blocks. Inter-block
+    /// spacing, `Spacer`s and empty paragraphs beyond it are dropped.
+    pub max_blank_lines: usize,
+    /// How many blank lines separate adjacent blocks; see
+    /// `blank_lines_between()`.
+    pub spacing: Spacing,
+    /// Show diagram blocks (`is_diagram()`) as a one-line placeholder
+    /// instead of their source.
+    pub collapse_diagrams: bool,
+    /// Diagrams, numbered from 0 in document order, shown the other way
+    /// from `collapse_diagrams` (toggled one by one in the viewer).
+    pub flipped_diagrams: Vec<usize>,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            align: TextAlign::default(),
+            wrap: WrapMode::default(),
+            icons: false,
+            indent_guides: false,
+            max_blank_lines: DEFAULT_MAX_BLANK_LINES,
+            spacing: Spacing::default(),
+            collapse_diagrams: false,
+            flipped_diagrams: Vec::new(),
+        }
+    }
+}
+
+/// Spacing presets for `blank_lines_between()` (`--spacing`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Spacing {
+    /// No blank line after a heading, between a paragraph and the list it
+    /// introduces, or between adjacent lists.
+    Compact,
+    /// One blank line between any two blocks (the default).
+    #[default]
+    Normal,
+    /// Two blank lines before and after a heading.
+    Airy,
+}
+
+/// Returns how many blank lines go between the adjacent blocks `prev` and
+/// `next`. Runs longer than `LayoutOptions::max_blank_lines` are allowed
+/// here: the preset asked for them.
+pub fn blank_lines_between(spacing: Spacing, prev: &RenderedBlock, next: &RenderedBlock) -> usize {
+    use RenderedBlock::{Heading, List, Paragraph};
+    match (spacing, prev, next) {
+        (Spacing::Compact, Heading { .. }, _)
+        | (Spacing::Compact, Paragraph { .. } | List { .. }, List { .. }) => 0,
+        (Spacing::Airy, Heading { .. }, _) | (Spacing::Airy, _, Heading { .. }) => 2,
+        _ => 1,
+    }
+}
+
+/// Blank lines before `blocks[index]`: none before the first block.
+fn spacing_before(blocks: &[RenderedBlock], index: usize, options: &LayoutOptions) -> usize {
+    match index.checked_sub(1) {
+        Some(prev) => blank_lines_between(options.spacing, &blocks[prev], &blocks[index]),
+        None => 0,
+    }
+}
+
+/// Code block languages that describe a diagram rather than code.
+const DIAGRAM_LANGUAGES: &[&str] = &["mermaid", "plantuml", "puml", "dot", "graphviz"];
+
+/// Returns true if a code block in `language` is a diagram, which the
+/// terminal can't draw (mermaid, PlantUML, Graphviz).
+pub fn is_diagram(language: &str) -> bool {
+    DIAGRAM_LANGUAGES.iter().any(|name| name.eq_ignore_ascii_case(language))
+}
+
+/// Default for `LayoutOptions::max_blank_lines`: blocks are one blank
+/// line apart, however many blank lines the source has.
+pub const DEFAULT_MAX_BLANK_LINES: usize = 1;
+
+/// Narrowest content width that optional gutters are allowed to leave.
+pub const MIN_CONTENT_WIDTH: u16 = 10;
+
+/// Returns the gutter widths that fit beside the content in `cols` columns.
+///
+/// `gutters` lists optional gutters (sidebar, margin, line numbers, …) from
+/// first to last to be given up. They are dropped — set to 0 — in that
+/// order until `MIN_CONTENT_WIDTH` columns remain, or none are left.
+pub fn fit_gutters<const N: usize>(cols: u16, mut gutters: [u16; N]) -> [u16; N] {
+    for i in 0..N {
+        let used = gutters.iter().fold(0u16, |sum, &g| sum.saturating_add(g));
+        if cols.saturating_sub(used) >= MIN_CONTENT_WIDTH {
+            break;
+        }
+        gutters[i] = 0;
+    }
+    gutters
+}
+
+/// Default pattern drawn for a `DocumentLine::Rule` (`--rule-char`).
+pub const DEFAULT_RULE: &str = "─";
+
+/// Repeats `pattern` to exactly `width` display columns.
+///
+/// The last copy is cut short where it would overflow; a wide character
+/// that doesn't fit in the last column leaves a space instead. Patterns
+/// with no display width fall back to `DEFAULT_RULE`.
+pub fn tile_to_width(pattern: &str, width: usize) -> String {
+    let pattern = if pattern.width() == 0 { DEFAULT_RULE } else { pattern };
+    let mut out = String::new();
+    let mut used = 0;
+    for c in pattern.chars().cycle() {
+        let w = unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        out.push(c);
+        used += w;
+    }
+    out.extend(std::iter::repeat_n(' ', width - used));
+    out
 }
 
 /// Flattens a sequence of `RenderedBlock`s into a `PreRenderedDocument`.
@@ -39,102 +473,744 @@ pub enum DocumentLine {
 /// Each block is converted to one or more `DocumentLine`s. Text blocks
 /// are word-wrapped to fit within `width` columns. An `Empty` line is
 /// inserted between adjacent blocks for visual spacing.
+///
+/// With `LayoutOptions::spacing`, the number of blank lines depends on the
+/// kinds of the two blocks instead.
 pub fn flatten(blocks: &[RenderedBlock], width: u16) -> PreRenderedDocument {
+    flatten_with_options(blocks, width, &LayoutOptions::default())
+}
+
+/// Like `flatten()`, but with explicit layout options.
+pub fn flatten_with_options(
+    blocks: &[RenderedBlock],
+    width: u16,
+    options: &LayoutOptions,
+) -> PreRenderedDocument {
     let mut lines: Vec<DocumentLine> = Vec::new();
+    let mut headings: Vec<HeadingEntry> = Vec::new();
+    let mut diagrams = Vec::new();
+    let mut references = Vec::new();
+    let mut block_starts = Vec::with_capacity(blocks.len());
+    let mut code_starts = Vec::new();
+    let mut lazy_code = Vec::new();
     // Clamp to minimum width of 1 to avoid undefined textwrap behavior.
     let width = (width as usize).max(1);
 
     for (i, block) in blocks.iter().enumerate() {
-        // Inter-block spacing (not before the first block).
-        if i > 0 {
-            lines.push(DocumentLine::Empty);
+        let start = lines.len();
+        block_starts.push(start);
+        let spacing = spacing_before(blocks, i, options);
+        layout_block(block, spacing, width, options, &mut lines, &mut headings, &mut diagrams);
+        track_lazy_code(block, &lines, &mut lazy_code);
+        track_code_starts(&lines, start, &mut code_starts);
+        track_references(block, &mut lines, start, &mut references);
+    }
+
+    let total_height = lines.len();
+    PreRenderedDocument {
+        lines,
+        total_height,
+        headings,
+        block_starts,
+        code_starts,
+        diagrams,
+        references,
+        lazy_code,
+        pending: None,
+    }
+}
+
+/// Like `flatten_with_options()`, but lays out only the first
+/// `EAGER_LINES` lines; the rest is laid out on demand by
+/// `PreRenderedDocument::ensure_lines()`.
+///
+/// Wrapping dominates layout time, so an exact `total_height` would cost as
+/// much as the layout itself. Until the document is complete, the height
+/// of the remaining blocks is estimated from their text width instead.
+pub fn flatten_lazy(
+    blocks: Rc<[RenderedBlock]>,
+    width: u16,
+    options: &LayoutOptions,
+) -> PreRenderedDocument {
+    let width = (width as usize).max(1);
+    let estimate = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| estimate_height(block, i, width))
+        .sum();
+    let mut document = PreRenderedDocument {
+        lines: Vec::new(),
+        total_height: estimate,
+        headings: Vec::new(),
+        block_starts: Vec::new(),
+        code_starts: Vec::new(),
+        diagrams: Vec::new(),
+        references: Vec::new(),
+        lazy_code: Vec::new(),
+        pending: Some(PendingLayout {
+            blocks,
+            next: 0,
+            width,
+            options: options.clone(),
+            estimate,
+        }),
+    };
+    document.ensure_lines(EAGER_LINES);
+    document
+}
+
+/// Records `block`, just laid out at the end of `lines`, in `lazy_code` if
+/// it is a lazily highlighted code block. Its code lines come last; a
+/// collapsed diagram has none.
+fn track_lazy_code(block: &RenderedBlock, lines: &[DocumentLine], lazy_code: &mut Vec<LazyCode>) {
+    if let RenderedBlock::CodeBlock { highlighted_lines, lazy: Some(highlight), .. } = block
+        && matches!(lines.last(), Some(DocumentLine::Code(_)))
+    {
+        lazy_code.push(LazyCode {
+            first_line: lines.len() - highlighted_lines.len(),
+            highlighted: 0,
+            highlight: Rc::clone(highlight),
+        });
+    }
+}
+
+/// Records in `code_starts` where each run of code lines in `lines[from..]`
+/// begins: every code block, nested ones included, is one such run.
+fn track_code_starts(lines: &[DocumentLine], from: usize, code_starts: &mut Vec<usize>) {
+    for i in from..lines.len() {
+        let after_code = i > 0 && matches!(lines[i - 1], DocumentLine::Code(_));
+        if matches!(lines[i], DocumentLine::Code(_)) && !after_code {
+            code_starts.push(i);
         }
+    }
+}
 
-        match block {
-            RenderedBlock::Heading { content, .. } => {
-                let wrapped = wrap_styled_spans(content, width);
-                if wrapped.is_empty() {
-                    lines.push(DocumentLine::Empty);
-                } else {
-                    for line in wrapped {
-                        lines.push(DocumentLine::Text(line));
+/// Records in `references` where the issue and commit references of
+/// `block`, just laid out into `lines[from..]`, ended up, and clears their
+/// `REFERENCE_MARK`. Marked spans come out in the order of the references,
+/// a reference broken over two lines as two spans.
+fn track_references(
+    block: &RenderedBlock,
+    lines: &mut [DocumentLine],
+    from: usize,
+    references: &mut Vec<ReferenceSpan>,
+) {
+    let mut expected = Vec::new();
+    block_references(block, &mut expected);
+    let mut expected = expected.into_iter().peekable();
+    let mut seen = String::new();
+    for (i, line) in lines.iter_mut().enumerate().skip(from) {
+        let DocumentLine::Text(line) = line else {
+            continue;
+        };
+        for (j, span) in line.spans.iter_mut().enumerate() {
+            if !span.style.sub_modifier.contains(REFERENCE_MARK) {
+                continue;
+            }
+            span.style.sub_modifier.remove(REFERENCE_MARK);
+            let Some(&(text, url)) = expected.peek() else {
+                continue;
+            };
+            seen.push_str(&span.content);
+            if !text.starts_with(seen.as_str()) {
+                // Lost track: better no links than links to the wrong page.
+                expected = Vec::new().into_iter().peekable();
+                continue;
+            }
+            references.push(ReferenceSpan { line: i, span: j, url: url.to_string() });
+            if seen == text {
+                seen.clear();
+                expected.next();
+            }
+        }
+    }
+}
+
+/// Collects the text and page of every reference in `block`, in the order
+/// `layout_block()` lays them out.
+fn block_references<'a>(block: &'a RenderedBlock, out: &mut Vec<(&'a str, &'a str)>) {
+    let references = |spans: &'a [StyledSpan]| {
+        spans.iter().filter_map(|s| Some((s.text.as_str(), s.reference.as_deref()?)))
+    };
+    match block {
+        RenderedBlock::Heading { content, .. } | RenderedBlock::Paragraph { content } => {
+            out.extend(references(content));
+        }
+        RenderedBlock::Details { summary, body } => {
+            out.extend(references(summary));
+            body.iter().for_each(|block| block_references(block, out));
+        }
+        RenderedBlock::List { items, .. } => {
+            let blocks = items.iter().flat_map(|item| &item.blocks);
+            blocks.for_each(|block| block_references(block, out));
+        }
+        RenderedBlock::BlockQuote { body, attribution } => {
+            body.iter().for_each(|block| block_references(block, out));
+            out.extend(references(attribution.as_deref().unwrap_or_default()));
+        }
+        _ => {}
+    }
+}
+
+/// Appends the lines for `block`, preceded by `spacing` blank lines (see
+/// `spacing_before()`).
+fn layout_block(
+    block: &RenderedBlock,
+    spacing: usize,
+    width: usize,
+    options: &LayoutOptions,
+    lines: &mut Vec<DocumentLine>,
+    headings: &mut Vec<HeadingEntry>,
+    diagrams: &mut Vec<usize>,
+) {
+    push_spacing(lines, spacing, options);
+
+    match block {
+        RenderedBlock::Heading { level, content } => {
+            headings.push(HeadingEntry {
+                level: *level,
+                text: content.iter().map(|s| s.text.as_str()).collect(),
+                line: lines.len(),
+            });
+            let wrapped = match options.wrap {
+                WrapMode::Wrap => wrap_styled_spans(content, width),
+                WrapMode::Clip => unwrapped_lines(content),
+            };
+            if wrapped.is_empty() {
+                push_blank(lines, options);
+            } else {
+                for line in wrapped {
+                    lines.push(DocumentLine::Text(line));
+                }
+            }
+        }
+        RenderedBlock::Paragraph { content } => {
+            let wrapped = match options.wrap {
+                WrapMode::Wrap => wrap_aligned(content, width, options.align),
+                WrapMode::Clip => unwrapped_lines(content),
+            };
+            if wrapped.is_empty() {
+                push_blank(lines, options);
+            } else {
+                for line in wrapped {
+                    lines.push(DocumentLine::Text(line));
+                }
+            }
+        }
+        RenderedBlock::CodeBlock {
+            language,
+            title,
+            highlighted_lines,
+            ..
+        } => {
+            if is_diagram(language) {
+                let number = diagrams.len();
+                diagrams.push(lines.len());
+                if options.collapse_diagrams != options.flipped_diagrams.contains(&number) {
+                    let count = highlighted_lines.len();
+                    let noun = if count == 1 { "line" } else { "lines" };
+                    let text = format!("[{language} diagram — {count} {noun}]");
+                    let style = Style::default().add_modifier(Modifier::DIM);
+                    lines.push(DocumentLine::Text(Line::from(Span::styled(text, style))));
+                    return;
+                }
+            }
+            // Caption from the fence's `title=` attribute, above the block.
+            if let Some(title) = title {
+                let caption = StyledSpan {
+                    text: title.clone(),
+                    style: Style::default().add_modifier(Modifier::DIM),
+                    reference: None,
+                };
+                let caption_lines = match options.wrap {
+                    WrapMode::Wrap => wrap_styled_spans(std::slice::from_ref(&caption), width),
+                    WrapMode::Clip => unwrapped_lines(std::slice::from_ref(&caption)),
+                };
+                lines.extend(caption_lines.into_iter().map(DocumentLine::Text));
+            }
+            // Emit language label header if language is specified.
+            if !language.is_empty() {
+                let label = match language_icon(language).filter(|_| options.icons) {
+                    Some(icon) => format!(" {icon} {language} "),
+                    None => format!(" {language} "),
+                };
+                let label = Span::styled(
+                    label,
+                    Style::default()
+                        .fg(Color::Indexed(245))
+                        .bg(Color::Indexed(235))
+                        .add_modifier(Modifier::ITALIC),
+                );
+                // The label takes the block's (theme) background.
+                let style = highlighted_lines.first().map(|line| line.style).unwrap_or_default();
+                lines.push(DocumentLine::Code(Line::from(label).style(style)));
+            }
+            // Emit each highlighted line (no wrapping — code is literal).
+            for line in highlighted_lines {
+                lines.push(DocumentLine::Code(line.clone()));
+            }
+        }
+        RenderedBlock::Image {
+            protocol_index,
+            alt_text,
+            width_cells,
+            height_cells,
+        } => {
+            let (img_width, img_height) = fit_image(*width_cells, *height_cells, width);
+            lines.push(DocumentLine::ImageStart {
+                protocol_index: *protocol_index,
+                width: img_width,
+                height: img_height,
+                alt_text: alt_text.clone(),
+            });
+            for _ in 1..img_height {
+                lines.push(DocumentLine::ImageContinuation);
+            }
+        }
+        RenderedBlock::Details { summary, body } => {
+            let mut spans = vec![StyledSpan {
+                text: "▾ ".to_string(),
+                style: Style::default().add_modifier(Modifier::BOLD),
+                reference: None,
+            }];
+            spans.extend(summary.iter().map(|s| StyledSpan {
+                text: s.text.clone(),
+                style: s.style,
+                reference: s.reference.clone(),
+            }));
+            let summary_lines = match options.wrap {
+                WrapMode::Wrap => wrap_styled_spans(&spans, width),
+                WrapMode::Clip => unwrapped_lines(&spans),
+            };
+            lines.extend(summary_lines.into_iter().map(DocumentLine::Text));
+            // The body is laid out narrower, then shifted right. Rules and
+            // images keep their column; they can't take a prefix.
+            let first_body_line = lines.len();
+            let body_width = width.saturating_sub(DETAILS_INDENT.len()).max(1);
+            for (i, block) in body.iter().enumerate() {
+                let spacing = spacing_before(body, i, options);
+                layout_block(block, spacing, body_width, options, lines, headings, diagrams);
+            }
+            let indent = if options.indent_guides {
+                Span::styled(INDENT_GUIDE, Style::default().add_modifier(Modifier::DIM))
+            } else {
+                Span::raw(DETAILS_INDENT)
+            };
+            for line in &mut lines[first_body_line..] {
+                match line {
+                    DocumentLine::Text(line) | DocumentLine::Code(line) => {
+                        line.spans.insert(0, indent.clone());
+                    }
+                    // Guides run unbroken through the blank lines between blocks.
+                    DocumentLine::Empty if options.indent_guides => {
+                        *line = DocumentLine::Text(Line::from(indent.clone()));
                     }
+                    _ => {}
                 }
             }
-            RenderedBlock::Paragraph { content } => {
-                let wrapped = wrap_styled_spans(content, width);
-                if wrapped.is_empty() {
-                    lines.push(DocumentLine::Empty);
+        }
+        RenderedBlock::List { start, items } => {
+            for (item, marker) in items.iter().zip(list_markers(*start, items)) {
+                let item_width = width.saturating_sub(marker.width()).max(1);
+                let indent = if options.indent_guides {
+                    let guide = format!("{INDENT_GUIDE:<width$}", width = marker.width());
+                    Span::styled(guide, Style::default().add_modifier(Modifier::DIM))
                 } else {
-                    for line in wrapped {
-                        lines.push(DocumentLine::Text(line));
+                    Span::raw(" ".repeat(marker.width()))
+                };
+                let first_line = lines.len();
+                for (i, block) in item.blocks.iter().enumerate() {
+                    let spacing = match item_block_index(block, i) {
+                        0 => 0,
+                        _ => spacing_before(&item.blocks, i, options),
+                    };
+                    layout_block(block, spacing, item_width, options, lines, headings, diagrams);
+                }
+                // The marker goes on the item's first line: an empty item, or
+                // one opening with a rule or image, gets a line of its own.
+                let starts_with_text = matches!(
+                    lines.get(first_line),
+                    Some(DocumentLine::Text(_) | DocumentLine::Code(_))
+                );
+                if !starts_with_text {
+                    lines.insert(first_line, DocumentLine::Text(Line::default()));
+                    for heading in headings.iter_mut().filter(|h| h.line >= first_line) {
+                        heading.line += 1;
+                    }
+                    for line in diagrams.iter_mut().filter(|line| **line >= first_line) {
+                        *line += 1;
+                    }
+                }
+                // Later lines of the item (continuation paragraphs, code,
+                // nested lists) line up with its text, not the marker. Guides
+                // run through the blank lines inside the item, not past it.
+                let last_line = lines.iter().rposition(|line| !matches!(line, DocumentLine::Empty));
+                let mut prefix = Span::raw(marker);
+                for (i, line) in lines.iter_mut().enumerate().skip(first_line) {
+                    match line {
+                        DocumentLine::Text(line) | DocumentLine::Code(line) => {
+                            line.spans.insert(0, std::mem::replace(&mut prefix, indent.clone()));
+                        }
+                        DocumentLine::Empty
+                            if options.indent_guides && last_line.is_some_and(|last| i < last) =>
+                        {
+                            *line = DocumentLine::Text(Line::from(indent.clone()));
+                        }
+                        _ => {}
                     }
                 }
             }
-            RenderedBlock::CodeBlock {
-                language,
-                highlighted_lines,
-            } => {
-                // Emit language label header if language is specified.
-                if !language.is_empty() {
-                    let label = Span::styled(
-                        format!(" {language} "),
-                        Style::default()
-                            .fg(Color::Indexed(245))
-                            .bg(Color::Indexed(235))
-                            .add_modifier(Modifier::ITALIC),
-                    );
-                    lines.push(DocumentLine::Code(Line::from(label)));
+        }
+        RenderedBlock::BlockQuote { body, attribution } => {
+            let first_line = lines.len();
+            let body_width = width.saturating_sub(QUOTE_BAR.width()).max(1);
+            let mut nested_quotes = Vec::new();
+            for (i, block) in body.iter().enumerate() {
+                let spacing = spacing_before(body, i, options);
+                let start = lines.len();
+                layout_block(block, spacing, body_width, options, lines, headings, diagrams);
+                if matches!(block, RenderedBlock::BlockQuote { .. }) {
+                    nested_quotes.push(start..lines.len());
                 }
-                // Emit each highlighted line (no wrapping — code is literal).
-                for line in highlighted_lines {
-                    lines.push(DocumentLine::Code(line.clone()));
+            }
+            // The attribution goes under the quote in italics, flush right.
+            if let Some(attribution) = attribution {
+                let spans: Vec<StyledSpan> = attribution
+                    .iter()
+                    .map(|s| StyledSpan {
+                        text: s.text.trim_start().to_string(),
+                        style: s.style.add_modifier(Modifier::ITALIC),
+                        reference: s.reference.clone(),
+                    })
+                    .filter(|s| !s.text.is_empty())
+                    .collect();
+                let wrapped = match options.wrap {
+                    WrapMode::Wrap => wrap_styled_spans(&spans, body_width),
+                    WrapMode::Clip => unwrapped_lines(&spans),
+                };
+                for mut line in wrapped {
+                    let pad = body_width.saturating_sub(line.width());
+                    if pad > 0 {
+                        line.spans.insert(0, Span::raw(" ".repeat(pad)));
+                    }
+                    lines.push(DocumentLine::Text(line));
                 }
             }
-            RenderedBlock::ThematicBreak => {
-                lines.push(DocumentLine::Rule);
+            if lines.len() == first_line {
+                lines.push(DocumentLine::Empty);
             }
-            RenderedBlock::Spacer { lines: count } => {
-                for _ in 0..*count {
-                    lines.push(DocumentLine::Empty);
+            // The bar runs unbroken down the quote, blank lines included.
+            // Rules and images keep their column; they can't take a prefix.
+            // With guides, a nested quote keeps its own bar and this quote
+            // marks the level as a guide to its left.
+            let bar = Span::styled(QUOTE_BAR, Style::default().add_modifier(Modifier::DIM));
+            let guide = Span::styled(INDENT_GUIDE, Style::default().add_modifier(Modifier::DIM));
+            for (i, line) in lines.iter_mut().enumerate().skip(first_line) {
+                match line {
+                    DocumentLine::Text(line) | DocumentLine::Code(line) => {
+                        let nested = nested_quotes.iter().any(|range| range.contains(&i));
+                        let prefix = if options.indent_guides && nested { &guide } else { &bar };
+                        line.spans.insert(0, prefix.clone());
+                    }
+                    DocumentLine::Empty => *line = DocumentLine::Text(Line::from(bar.clone())),
+                    _ => {}
                 }
             }
         }
+        RenderedBlock::MathBlock { lines: math } => {
+            // Each line is centered; one too long for the width wraps.
+            for text in math {
+                let style = Style::default();
+                let span = StyledSpan { text: text.clone(), style, reference: None };
+                let wrapped = match options.wrap {
+                    WrapMode::Wrap => wrap_styled_spans(std::slice::from_ref(&span), width),
+                    WrapMode::Clip => unwrapped_lines(std::slice::from_ref(&span)),
+                };
+                for mut line in wrapped {
+                    let pad = width.saturating_sub(line.width()) / 2;
+                    if pad > 0 {
+                        line.spans.insert(0, Span::raw(" ".repeat(pad)));
+                    }
+                    lines.push(DocumentLine::Text(line));
+                }
+            }
+        }
+        RenderedBlock::ThematicBreak => {
+            lines.push(DocumentLine::Rule);
+        }
+        RenderedBlock::Spacer { lines: count } => {
+            for _ in 0..*count {
+                push_blank(lines, options);
+            }
+        }
     }
+}
 
-    let total_height = lines.len();
-    PreRenderedDocument {
-        lines,
-        total_height,
+/// Appends a blank line unless `lines` already ends in
+/// `options.max_blank_lines` of them.
+fn push_blank(lines: &mut Vec<DocumentLine>, options: &LayoutOptions) {
+    let trailing = lines.iter().rev().take_while(|l| matches!(l, DocumentLine::Empty)).count();
+    if trailing < options.max_blank_lines {
+        lines.push(DocumentLine::Empty);
     }
 }
 
+/// Appends `count` blank lines, fewer if `lines` already ends in blank
+/// lines and the run would exceed `options.max_blank_lines` (or `count`,
+/// if larger).
+fn push_spacing(lines: &mut Vec<DocumentLine>, count: usize, options: &LayoutOptions) {
+    let trailing = lines.iter().rev().take_while(|l| matches!(l, DocumentLine::Empty)).count();
+    let room = count.max(options.max_blank_lines).saturating_sub(trailing);
+    lines.extend(std::iter::repeat_with(|| DocumentLine::Empty).take(count.min(room)));
+}
+
+/// Cheaply estimates the lines `layout_block()` will produce, without
+/// wrapping: text blocks are assumed to fill every line. Inter-block
+/// spacing is taken to be one line.
+fn estimate_height(block: &RenderedBlock, index: usize, width: usize) -> usize {
+    let spacing = usize::from(index > 0);
+    let height = match block {
+        RenderedBlock::Heading { content, .. } | RenderedBlock::Paragraph { content } => {
+            let text_width: usize = content.iter().map(|s| s.text.width()).sum();
+            text_width.div_ceil(width).max(1)
+        }
+        RenderedBlock::CodeBlock {
+            language,
+            title,
+            highlighted_lines,
+            ..
+        } => {
+            let headers = usize::from(!language.is_empty()) + usize::from(title.is_some());
+            highlighted_lines.len() + headers
+        }
+        RenderedBlock::Image {
+            width_cells,
+            height_cells,
+            ..
+        } => fit_image(*width_cells, *height_cells, width).1 as usize,
+        RenderedBlock::Details { body, .. } => {
+            let body_width = width.saturating_sub(DETAILS_INDENT.len()).max(1);
+            let body_height: usize = body
+                .iter()
+                .enumerate()
+                .map(|(i, block)| estimate_height(block, i, body_width))
+                .sum();
+            1 + body_height
+        }
+        RenderedBlock::List { start, items } => {
+            let item_height = |(item, marker): (&ListItem, String)| {
+                let item_width = width.saturating_sub(marker.width()).max(1);
+                let height: usize = item
+                    .blocks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, block)| {
+                        estimate_height(block, item_block_index(block, i), item_width)
+                    })
+                    .sum();
+                height.max(1)
+            };
+            items.iter().zip(list_markers(*start, items)).map(item_height).sum()
+        }
+        RenderedBlock::BlockQuote { body, attribution } => {
+            let body_width = width.saturating_sub(QUOTE_BAR.width()).max(1);
+            let body_height: usize = body
+                .iter()
+                .enumerate()
+                .map(|(i, block)| estimate_height(block, i, body_width))
+                .sum();
+            let attribution_width: usize =
+                attribution.iter().flatten().map(|s| s.text.width()).sum();
+            (body_height + attribution_width.div_ceil(body_width)).max(1)
+        }
+        RenderedBlock::MathBlock { lines } => {
+            lines.iter().map(|line| line.width().div_ceil(width).max(1)).sum()
+        }
+        RenderedBlock::ThematicBreak => 1,
+        RenderedBlock::Spacer { lines } => *lines as usize,
+    };
+    spacing + height
+}
+
+/// Returns the document's text with paragraphs unwrapped.
+///
+/// Built from the IR rather than from `DocumentLine`s so that copied prose
+/// carries no artificial wrap points: each paragraph and heading becomes a
+/// single line (hard breaks are kept), code blocks keep their real
+/// newlines, and blocks are separated by a blank line.
+pub fn reflowed_text(blocks: &[RenderedBlock]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for block in blocks {
+        match block {
+            RenderedBlock::Heading { content, .. } | RenderedBlock::Paragraph { content } => {
+                parts.push(content.iter().map(|s| s.text.as_str()).collect());
+            }
+            RenderedBlock::CodeBlock { title, highlighted_lines, .. } => {
+                parts.extend(title.clone());
+                let code: Vec<String> = highlighted_lines
+                    .iter()
+                    .map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect())
+                    .collect();
+                parts.push(code.join("\n"));
+            }
+            RenderedBlock::Image { alt_text, .. } => parts.push(format!("[image: {alt_text}]")),
+            RenderedBlock::Details { summary, body } => {
+                parts.push(summary.iter().map(|s| s.text.as_str()).collect());
+                if !body.is_empty() {
+                    parts.push(reflowed_text(body));
+                }
+            }
+            RenderedBlock::List { start, items } => {
+                let mut list = Vec::new();
+                for (item, marker) in items.iter().zip(list_markers(*start, items)) {
+                    let indent = " ".repeat(marker.width());
+                    let mut text = marker;
+                    for (n, line) in reflowed_text(&item.blocks).lines().enumerate() {
+                        if n > 0 {
+                            text.push('\n');
+                            if !line.is_empty() {
+                                text.push_str(&indent);
+                            }
+                        }
+                        text.push_str(line);
+                    }
+                    list.push(text);
+                }
+                parts.push(list.join("\n"));
+            }
+            RenderedBlock::BlockQuote { body, attribution } => {
+                let mut text = reflowed_text(body);
+                if let Some(attribution) = attribution {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.extend(attribution.iter().map(|s| s.text.as_str()));
+                }
+                let quoted: Vec<String> = text
+                    .lines()
+                    .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {line}") })
+                    .collect();
+                parts.push(quoted.join("\n"));
+            }
+            RenderedBlock::MathBlock { lines } => parts.push(lines.join("\n")),
+            RenderedBlock::ThematicBreak => parts.push("---".to_string()),
+            RenderedBlock::Spacer { .. } => {}
+        }
+    }
+    parts.join("\n\n")
+}
+
+/// Scales an image's cell size down to fit `width` columns, preserving
+/// aspect ratio. Images narrower than `width` keep their natural size.
+fn fit_image(width_cells: u16, height_cells: u16, width: usize) -> (u16, u16) {
+    let natural = (width_cells.max(1) as usize, height_cells.max(1) as usize);
+    if natural.0 <= width {
+        return (natural.0 as u16, natural.1 as u16);
+    }
+    let height = (natural.1 * width).div_ceil(natural.0).max(1);
+    (width as u16, height as u16)
+}
+
+/// Returns the Nerd Font (devicons) glyph for a fence language, if known.
+fn language_icon(language: &str) -> Option<char> {
+    let icon = match language.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => '\u{e7a8}',
+        "python" | "py" => '\u{e73c}',
+        "javascript" | "js" | "jsx" => '\u{e74e}',
+        "typescript" | "ts" | "tsx" => '\u{e628}',
+        "go" | "golang" => '\u{e626}',
+        "c" | "h" => '\u{e61e}',
+        "cpp" | "c++" | "cc" | "hpp" => '\u{e61d}',
+        "java" => '\u{e738}',
+        "ruby" | "rb" => '\u{e739}',
+        "php" => '\u{e73d}',
+        "lua" => '\u{e620}',
+        "html" => '\u{e736}',
+        "css" => '\u{e749}',
+        "json" => '\u{e60b}',
+        "markdown" | "md" => '\u{e73e}',
+        "sh" | "bash" | "zsh" | "shell" | "console" => '\u{f489}',
+        "dockerfile" | "docker" => '\u{f308}',
+        _ => return None,
+    };
+    Some(icon)
+}
+
+/// Marker of a bullet list item.
+const BULLET: &str = "• ";
+
+/// Returns the marker of each of a list's `items`: a bullet, or a number
+/// counting up from `start` right-aligned on its dot, then the checkbox of
+/// a task item (`1. [x] `).
+fn list_markers(start: Option<u64>, items: &[ListItem]) -> Vec<String> {
+    let last = start.map(|start| start.saturating_add(items.len().saturating_sub(1) as u64));
+    let digits = last.map_or(0, |last| last.to_string().len());
+    let task_box = |task: Option<bool>| match task {
+        Some(true) => "[x] ",
+        Some(false) => "[ ] ",
+        None => "",
+    };
+    items
+        .iter()
+        .zip(0u64..)
+        .map(|(item, i)| match start {
+            Some(start) => format!("{:>digits$}. {}", start.saturating_add(i), task_box(item.task)),
+            None => format!("{BULLET}{}", task_box(item.task)),
+        })
+        .collect()
+}
+
+/// The `index` a block in a list item is laid out with: a nested list hugs
+/// the text it follows instead of getting a blank line before it.
+fn item_block_index(block: &RenderedBlock, index: usize) -> usize {
+    if matches!(block, RenderedBlock::List { .. }) { 0 } else { index }
+}
+
+/// Indentation of a `<details>` body under its summary line.
+const DETAILS_INDENT: &str = "  ";
+
+/// `DETAILS_INDENT` with an indent guide, as wide as the plain indent. List
+/// items pad it to their marker's width.
+const INDENT_GUIDE: &str = "│ ";
+
+/// Prefix of every line of a block quote.
+const QUOTE_BAR: &str = "│ ";
+
+/// Paragraphs longer than this are wrapped first-fit instead of optimal-fit,
+/// or, with no whitespace in them at all, chunked at the width directly.
+const OPTIMAL_FIT_MAX_BYTES: usize = 32 * 1024;
+
 /// Wraps styled spans to fit within a given width, preserving styles.
 ///
 /// Algorithm:
-/// 1. Concatenate all span text into a single plain-text string, building
-///    a parallel byte-to-style map.
+/// 1. Concatenate all span text into a single plain-text string, noting
+///    the byte offset each span's style starts at.
 /// 2. Use `textwrap::wrap()` to determine line break positions.
 /// 3. Walk a cursor through the plain text for each wrapped line, skipping
 ///    whitespace break points, then extract styled spans by consulting
-///    the byte-to-style map.
+///    the style offsets.
 fn wrap_styled_spans(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
+    wrap_aligned(spans, width, TextAlign::Left)
+}
+
+/// `wrap_styled_spans()` with an explicit alignment. Hard-break segments
+/// are aligned independently, so the line before a hard break is never
+/// stretched.
+fn wrap_aligned(spans: &[StyledSpan], width: usize, align: TextAlign) -> Vec<Line<'static>> {
     if spans.is_empty() {
         return Vec::new();
     }
 
     // Handle hard breaks (\n) by splitting into sub-paragraphs.
     if spans.iter().any(|s| s.text.contains('\n')) {
-        return wrap_with_hard_breaks(spans, width);
+        return wrap_with_hard_breaks(spans, width, align);
     }
 
-    // 1. Build plain text and parallel byte-to-style map.
+    // 1. Build plain text and the offset each span's style starts at.
     let mut plain = String::new();
-    let mut byte_styles: Vec<Style> = Vec::new();
+    let mut style_runs: Vec<(usize, Style)> = Vec::with_capacity(spans.len());
     for span in spans {
-        for _ in span.text.bytes() {
-            byte_styles.push(span.style);
-        }
+        style_runs.push((plain.len(), marked_style(span)));
         plain.push_str(&span.text);
     }
 
@@ -142,9 +1218,29 @@ fn wrap_styled_spans(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
         return Vec::new();
     }
 
-    // 2. Wrap the plain text.
+    // A huge run without whitespace (minified code, a long CJK run, a
+    // base64 blob) can only be broken by width: cut it directly instead of
+    // having textwrap find its break opportunities.
+    if plain.len() > OPTIMAL_FIT_MAX_BYTES && !plain.contains(char::is_whitespace) {
+        return chunk_unbreakable(&plain, &style_runs, width);
+    }
+
+    // 2. Wrap the plain text. Optimal-fit wrapping is quadratic in the number
+    // of break opportunities, which one huge paragraph (minified text, a long
+    // CJK run, a path-like token) makes pathological: fall back to greedy
+    // first-fit for those.
+    let wrap_algorithm = if plain.len() > OPTIMAL_FIT_MAX_BYTES {
+        textwrap::WrapAlgorithm::FirstFit
+    } else {
+        textwrap::WrapAlgorithm::new_optimal_fit()
+    };
+    // textwrap measures `width` in display columns (its default
+    // `unicode-width` feature), so wide CJK and fullwidth characters count
+    // as two, as the terminal draws them.
     let wrap_options = textwrap::Options::new(width)
-        .word_separator(textwrap::WordSeparator::UnicodeBreakProperties);
+        .word_separator(textwrap::WordSeparator::UnicodeBreakProperties)
+        .word_splitter(textwrap::WordSplitter::Custom(split_points))
+        .wrap_algorithm(wrap_algorithm);
     let wrapped_lines = textwrap::wrap(&plain, &wrap_options);
 
     // 3. Map each wrapped line back to styled spans using a monotonic cursor.
@@ -153,6 +1249,12 @@ fn wrap_styled_spans(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
 
     for wrapped_text in &wrapped_lines {
         let wrapped_str: &str = wrapped_text.as_ref();
+        // A line broken at a soft hyphen comes back with a `-` appended
+        // (a `Cow::Owned`): map the text before it, then add it back.
+        let (wrapped_str, hyphenated) = match wrapped_str.strip_suffix('-') {
+            Some(text) if text.ends_with(SOFT_HYPHEN) => (text, true),
+            _ => (wrapped_str, false),
+        };
 
         // Skip whitespace between wrapped lines (break points consumed by textwrap).
         // Only advance forward — the cursor never goes backward.
@@ -175,7 +1277,7 @@ fn wrap_styled_spans(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
         // either produce empty spans (silent data loss) or slice on a non-char
         // boundary (panic). Fall back to emitting the wrapped text directly instead.
         if cursor >= plain.len() && !plain.ends_with(wrapped_str) {
-            result.push(Line::from(Span::raw(wrapped_str.to_string())));
+            result.push(Line::from(Span::raw(wrapped_text.replace(SOFT_HYPHEN, ""))));
             continue;
         }
 
@@ -187,28 +1289,95 @@ fn wrap_styled_spans(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
         // Verify the end is on a char boundary before slicing. If not (can only
         // happen with Cow::Owned from textwrap), emit the text directly.
         if !plain.is_char_boundary(line_end) {
-            result.push(Line::from(Span::raw(wrapped_str.to_string())));
+            result.push(Line::from(Span::raw(wrapped_text.replace(SOFT_HYPHEN, ""))));
             cursor = line_end.min(plain.len());
             continue;
         }
 
-        let line_spans = build_spans_for_range(&plain, &byte_styles, line_start, line_end);
+        let mut line_spans = build_spans_for_range(&plain, &style_runs, line_start, line_end);
+        resolve_soft_hyphens(&mut line_spans, hyphenated);
         result.push(Line::from(line_spans));
 
         cursor = line_end;
     }
 
+    if align == TextAlign::Justify
+        && let Some((_, body)) = result.split_last_mut()
+    {
+        for line in body {
+            justify_line(line, width);
+        }
+    }
+
     result
 }
 
+/// Soft hyphen (U+00AD): an invisible break opportunity inside a word that
+/// shows as `-` when the line breaks there.
+const SOFT_HYPHEN: char = '\u{AD}';
+
+/// Where textwrap may split a word: after a soft hyphen, and where its
+/// default `HyphenSplitter` would (after `-` in hyphenated words).
+fn split_points(word: &str) -> Vec<usize> {
+    let mut points = textwrap::WordSplitter::HyphenSplitter.split_points(word);
+    points.extend(word.match_indices(SOFT_HYPHEN).map(|(i, s)| i + s.len()));
+    points.retain(|&i| i < word.len());
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+/// Removes soft hyphens from a wrapped line's spans, then ends the line
+/// with a visible `-` (in the last span's style) if it was broken at one.
+fn resolve_soft_hyphens(spans: &mut Vec<Span<'static>>, hyphenated: bool) {
+    for span in spans.iter_mut() {
+        if span.content.contains(SOFT_HYPHEN) {
+            span.content = span.content.replace(SOFT_HYPHEN, "").into();
+        }
+    }
+    spans.retain(|span| !span.content.is_empty());
+    if hyphenated && let Some(last) = spans.last_mut() {
+        last.content.to_mut().push('-');
+    }
+}
+
+/// Stretches `line` to `width` columns by widening its spaces. Extra
+/// columns are spread evenly, with leftovers going to the leftmost gaps.
+/// Lines without spaces, or already at full width, are left unchanged.
+fn justify_line(line: &mut Line<'static>, width: usize) {
+    let gaps: usize = line.spans.iter().map(|s| s.content.matches(' ').count()).sum();
+    let line_width = line.width();
+    if gaps == 0 || line_width >= width {
+        return;
+    }
+    let extra = width - line_width;
+    let (per_gap, remainder) = (extra / gaps, extra % gaps);
+
+    let mut gap = 0;
+    for span in &mut line.spans {
+        if !span.content.contains(' ') {
+            continue;
+        }
+        let mut text = String::with_capacity(span.content.len() + extra);
+        for ch in span.content.chars() {
+            text.push(ch);
+            if ch == ' ' {
+                text.extend(std::iter::repeat_n(' ', per_gap + usize::from(gap < remainder)));
+                gap += 1;
+            }
+        }
+        span.content = text.into();
+    }
+}
+
 /// Builds styled `Span`s for a byte range of the plain text.
 ///
-/// Walks through the range by characters, grouping consecutive bytes
-/// that share the same style into a single `Span`. All slicing happens
-/// at character boundaries.
+/// `style_runs` holds the byte offset each style starts at, in order, the
+/// first at 0. Consecutive runs that share a style become a single `Span`.
+/// `start` and `end` must be character boundaries.
 fn build_spans_for_range(
     plain: &str,
-    byte_styles: &[Style],
+    style_runs: &[(usize, Style)],
     start: usize,
     end: usize,
 ) -> Vec<Span<'static>> {
@@ -216,20 +1385,20 @@ fn build_spans_for_range(
         return Vec::new();
     }
 
-    let segment = &plain[start..end];
+    let first = style_runs.partition_point(|&(offset, _)| offset <= start).saturating_sub(1);
     let mut spans: Vec<Span<'static>> = Vec::new();
     let mut run_start = start;
-    let mut run_style = byte_styles[start];
+    let mut run_style = style_runs[first].1;
 
-    for (i, _ch) in segment.char_indices() {
-        let abs_pos = start + i;
-        if byte_styles[abs_pos] != run_style {
-            let text = &plain[run_start..abs_pos];
+    let later_runs = style_runs[first + 1..].iter().take_while(|&&(offset, _)| offset < end);
+    for &(offset, style) in later_runs {
+        if style != run_style {
+            let text = &plain[run_start..offset];
             if !text.is_empty() {
                 spans.push(Span::styled(text.to_string(), run_style));
             }
-            run_start = abs_pos;
-            run_style = byte_styles[abs_pos];
+            run_start = offset;
+            run_style = style;
         }
     }
 
@@ -242,9 +1411,92 @@ fn build_spans_for_range(
     spans
 }
 
+/// Cuts `plain`, which has no whitespace to break at, into lines of at most
+/// `width` display columns (one character per line if a character is wider).
+fn chunk_unbreakable(
+    plain: &str,
+    style_runs: &[(usize, Style)],
+    width: usize,
+) -> Vec<Line<'static>> {
+    let mut result = Vec::new();
+    let mut line_start = 0;
+    let mut columns = 0;
+    for (i, ch) in plain.char_indices() {
+        let ch_width = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+        if columns + ch_width > width && i > line_start {
+            let mut spans = build_spans_for_range(plain, style_runs, line_start, i);
+            resolve_soft_hyphens(&mut spans, false);
+            result.push(Line::from(spans));
+            line_start = i;
+            columns = 0;
+        }
+        columns += ch_width;
+    }
+    let mut spans = build_spans_for_range(plain, style_runs, line_start, plain.len());
+    resolve_soft_hyphens(&mut spans, false);
+    result.push(Line::from(spans));
+    result
+}
+
 /// Handles text containing hard breaks by splitting at `\n` boundaries
 /// first, then wrapping each segment independently.
-fn wrap_with_hard_breaks(spans: &[StyledSpan], width: usize) -> Vec<Line<'static>> {
+fn wrap_with_hard_breaks(
+    spans: &[StyledSpan],
+    width: usize,
+    align: TextAlign,
+) -> Vec<Line<'static>> {
+    let mut result = Vec::new();
+    for group in &split_hard_breaks(spans) {
+        let wrapped = wrap_aligned(group, width, align);
+        if wrapped.is_empty() {
+            result.push(Line::from(Vec::<Span<'static>>::new()));
+        } else {
+            result.extend(wrapped);
+        }
+    }
+
+    result
+}
+
+/// Lays spans out without wrapping (`WrapMode::Clip`): one line per
+/// hard-break segment, however wide.
+fn unwrapped_lines(spans: &[StyledSpan]) -> Vec<Line<'static>> {
+    if spans.is_empty() {
+        return Vec::new();
+    }
+    split_hard_breaks(spans)
+        .into_iter()
+        .map(|group| {
+            let mut spans: Vec<Span<'static>> = group
+                .into_iter()
+                .map(|s| {
+                    let style = marked_style(&s);
+                    Span::styled(s.text, style)
+                })
+                .collect();
+            resolve_soft_hyphens(&mut spans, false);
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Style a span is wrapped with: its own, plus `REFERENCE_MARK` for a
+/// reference, so `track_references()` can find what it became.
+fn marked_style(span: &StyledSpan) -> Style {
+    match span.reference {
+        Some(_) => span.style.remove_modifier(REFERENCE_MARK),
+        None => span.style,
+    }
+}
+
+/// Set in the `sub_modifier` of reference spans while their block is laid
+/// out (see `marked_style()`). Nothing else removes `HIDDEN`, and removing
+/// a modifier no cell has draws nothing differently.
+const REFERENCE_MARK: Modifier = Modifier::HIDDEN;
+
+/// Splits spans at `\n` into the segments between hard breaks, keeping
+/// each piece's style.
+fn split_hard_breaks(spans: &[StyledSpan]) -> Vec<Vec<StyledSpan>> {
     let mut groups: Vec<Vec<StyledSpan>> = Vec::new();
     let mut current_group: Vec<StyledSpan> = Vec::new();
 
@@ -256,6 +1508,7 @@ fn wrap_with_hard_breaks(spans: &[StyledSpan], width: usize) -> Vec<Line<'static
                     current_group.push(StyledSpan {
                         text: part.to_string(),
                         style: span.style,
+                        reference: span.reference.clone(),
                     });
                 }
                 if i < parts.len() - 1 {
@@ -266,24 +1519,14 @@ fn wrap_with_hard_breaks(spans: &[StyledSpan], width: usize) -> Vec<Line<'static
             current_group.push(StyledSpan {
                 text: span.text.clone(),
                 style: span.style,
+                reference: span.reference.clone(),
             });
         }
     }
     if !current_group.is_empty() {
         groups.push(current_group);
     }
-
-    let mut result = Vec::new();
-    for group in &groups {
-        let wrapped = wrap_styled_spans(group, width);
-        if wrapped.is_empty() {
-            result.push(Line::from(Vec::<Span<'static>>::new()));
-        } else {
-            result.extend(wrapped);
-        }
-    }
-
-    result
+    groups
 }
 
 #[cfg(test)]
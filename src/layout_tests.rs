@@ -6,6 +6,7 @@
         StyledSpan {
             text: text.to_string(),
             style: Style::default(),
+            reference: None,
         }
     }
 
@@ -13,6 +14,7 @@
         StyledSpan {
             text: text.to_string(),
             style,
+            reference: None,
         }
     }
 
@@ -88,13 +90,81 @@
     #[test]
     fn test_layout_spacer() {
         let blocks = vec![RenderedBlock::Spacer { lines: 3 }];
-        let doc = flatten(&blocks, 80);
+        let options = LayoutOptions { max_blank_lines: 3, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 80, &options);
         assert_eq!(doc.total_height, 3);
         for line in &doc.lines {
             assert!(matches!(line, DocumentLine::Empty));
         }
     }
 
+    fn sandwiched_spacer() -> Vec<RenderedBlock> {
+        vec![
+            RenderedBlock::Paragraph { content: vec![plain_span("a")] },
+            RenderedBlock::Spacer { lines: 2 },
+            RenderedBlock::Paragraph { content: vec![plain_span("b")] },
+        ]
+    }
+
+    fn blank_lines(doc: &PreRenderedDocument) -> usize {
+        doc.lines.iter().filter(|l| matches!(l, DocumentLine::Empty)).count()
+    }
+
+    #[test]
+    fn test_layout_spacer_between_paragraphs_collapses_by_default() {
+        let doc = flatten(&sandwiched_spacer(), 80);
+        assert_eq!(doc.total_height, 3, "a, one blank line, b");
+        assert_eq!(blank_lines(&doc), 1);
+    }
+
+    #[test]
+    fn test_layout_spacer_between_paragraphs_respects_max_blank_lines() {
+        for (max, blanks) in [(2, 2), (4, 4), (9, 4)] {
+            let options = LayoutOptions { max_blank_lines: max, ..LayoutOptions::default() };
+            let doc = flatten_with_options(&sandwiched_spacer(), 80, &options);
+            // Spacing before the spacer, its 2 lines, spacing after it.
+            assert_eq!(blank_lines(&doc), blanks, "max {max}");
+            assert!(matches!(doc.lines.last(), Some(DocumentLine::Text(_))));
+        }
+    }
+
+    #[test]
+    fn test_layout_spacing_presets() {
+        let item = || ListItem {
+            task: None,
+            blocks: vec![RenderedBlock::Paragraph { content: vec![plain_span("item")] }],
+        };
+        let blocks = vec![
+            RenderedBlock::Heading { level: 1, content: vec![plain_span("Title")] },
+            RenderedBlock::Paragraph { content: vec![plain_span("Intro:")] },
+            RenderedBlock::List { start: None, items: vec![item(), item()] },
+            RenderedBlock::Heading { level: 2, content: vec![plain_span("Next")] },
+        ];
+        let layout = |spacing| {
+            let options = LayoutOptions { spacing, ..LayoutOptions::default() };
+            let doc = flatten_with_options(&blocks, 80, &options);
+            doc.lines.iter().map(|line| line.plain_text()).collect::<Vec<_>>()
+        };
+        let normal = ["Title", "", "Intro:", "", "• item", "• item", "", "Next"];
+        assert_eq!(layout(Spacing::Normal), normal);
+        assert_eq!(layout(Spacing::Compact), ["Title", "Intro:", "• item", "• item", "", "Next"]);
+        let airy = ["Title", "", "", "Intro:", "", "• item", "• item", "", "", "Next"];
+        assert_eq!(layout(Spacing::Airy), airy);
+        assert_eq!(flatten(&blocks, 80).total_height, normal.len());
+    }
+
+    #[test]
+    fn test_layout_empty_paragraphs_collapse_with_spacing() {
+        let blocks = vec![
+            RenderedBlock::Paragraph { content: vec![plain_span("a")] },
+            RenderedBlock::Paragraph { content: vec![] },
+            RenderedBlock::Heading { level: 2, content: vec![plain_span("h")] },
+        ];
+        let doc = flatten(&blocks, 80);
+        assert_eq!(doc.total_height, 3);
+        assert_eq!(doc.headings[0].line, 2, "heading lines account for dropped blanks");
+    }
+
     #[test]
     fn test_layout_single_long_word() {
         let blocks = vec![RenderedBlock::Paragraph {
@@ -191,6 +261,76 @@
         assert!(doc.total_height >= 1);
     }
 
+    #[test]
+    fn test_layout_cjk_wraps_at_display_columns() {
+        // 13 characters, two columns each: 26 columns make three lines of
+        // at most 10, not two of up to 10 characters.
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("日本語のテキスト処理テスト")],
+        }];
+        let doc = flatten(&blocks, 10);
+        assert_eq!(line_texts(&doc), ["日本語のテ", "キスト処理", "テスト"]);
+
+        // Fullwidth punctuation is two columns too, and stays off the
+        // start of a line.
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("東京、大阪。ＡＢＣ！終わり")],
+        }];
+        let doc = flatten(&blocks, 8);
+        let lines = line_texts(&doc);
+        assert!(lines.iter().all(|line| line.width() <= 8), "{lines:?}");
+        assert!(lines.iter().all(|line| !line.starts_with(['、', '。', '！'])), "{lines:?}");
+        assert_eq!(lines.concat(), "東京、大阪。ＡＢＣ！終わり");
+    }
+
+    fn line_texts(doc: &PreRenderedDocument) -> Vec<String> {
+        doc.lines
+            .iter()
+            .filter_map(|line| match line {
+                DocumentLine::Text(l) => Some(l.spans.iter().map(|s| s.content.as_ref()).collect()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_nbsp_joined_words_never_split() {
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("see page\u{A0}42 and Mr.\u{A0}Smith and 10\u{A0}km today")],
+        }];
+        let lines = line_texts(&flatten(&blocks, 10));
+        assert!(lines.len() > 1, "{lines:?}");
+        for joined in ["page\u{A0}42", "Mr.\u{A0}Smith", "10\u{A0}km"] {
+            assert!(lines.iter().any(|l| l.contains(joined)), "{joined:?} split: {lines:?}");
+        }
+        let words: String = lines.join(" ");
+        assert_eq!(words, "see page\u{A0}42 and Mr.\u{A0}Smith and 10\u{A0}km today");
+    }
+
+    #[test]
+    fn test_layout_soft_hyphen_breaks_with_visible_hyphen() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![styled_span("extra\u{AD}ordinary\u{AD}ness", bold)],
+        }];
+        let doc = flatten(&blocks, 10);
+        assert_eq!(line_texts(&doc), ["extra-", "ordinary-", "ness"]);
+        let DocumentLine::Text(first) = &doc.lines[0] else {
+            panic!("expected Text line");
+        };
+        assert!(first.spans.iter().all(|s| s.style == bold), "hyphen keeps the word's style");
+    }
+
+    #[test]
+    fn test_layout_soft_hyphen_invisible_when_not_broken() {
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("an extra\u{AD}ordinary day")],
+        }];
+        assert_eq!(line_texts(&flatten(&blocks, 80)), ["an extraordinary day"]);
+        // Broken between words, not at the soft hyphen: still no hyphen shown.
+        assert_eq!(line_texts(&flatten(&blocks, 16)), ["an extraordinary", "day"]);
+    }
+
     #[test]
     fn test_layout_zero_width_no_panic() {
         let blocks = vec![RenderedBlock::Paragraph {
@@ -241,7 +381,9 @@
         let long_line = "x".repeat(200);
         let blocks = vec![RenderedBlock::CodeBlock {
             language: String::new(),
+            title: None,
             highlighted_lines: vec![make_code_line(&long_line)],
+            lazy: None,
         }];
         let doc = flatten(&blocks, 40);
         // Code lines should NOT wrap — still 1 Code line.
@@ -253,26 +395,107 @@
         assert_eq!(code_count, 1, "code should not wrap");
     }
 
+    #[test]
+    fn test_layout_collapse_diagrams_shows_a_placeholder() {
+        let code = |language: &str, count: usize| RenderedBlock::CodeBlock {
+            language: language.to_string(),
+            title: None,
+            highlighted_lines: (0..count).map(|i| make_code_line(&format!("l{i}"))).collect(),
+            lazy: None,
+        };
+        let blocks = vec![code("mermaid", 3), code("rust", 2), code("Dot", 1)];
+        let doc = flatten(&blocks, 40);
+        assert_eq!(doc.total_height, 4 + 1 + 3 + 1 + 2);
+        assert_eq!(doc.diagrams, [0, 9]);
+
+        let mut options = LayoutOptions { collapse_diagrams: true, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 40, &options);
+        let texts: Vec<String> = doc.lines.iter().map(|line| line.plain_text()).collect();
+        assert_eq!(texts[0], "[mermaid diagram — 3 lines]");
+        assert_eq!(texts.last().unwrap(), "[Dot diagram — 1 line]");
+        assert_eq!(doc.total_height, 1 + 1 + 3 + 1 + 1);
+        assert_eq!(doc.diagrams, [0, 6]);
+        assert_eq!(doc.code_starts, [2]);
+
+        // A flipped diagram goes the other way.
+        options.flipped_diagrams = vec![1];
+        let doc = flatten_with_options(&blocks, 40, &options);
+        assert_eq!(doc.lines[0].plain_text(), "[mermaid diagram — 3 lines]");
+        assert_eq!(doc.total_height, 1 + 1 + 3 + 1 + 2);
+    }
+
+    #[test]
+    fn test_layout_highlight_code_swaps_in_lazy_lines_up_to_range() {
+        use crate::highlight::{DEFAULT_THEME, Highlighter};
+        let code = "let x = 1;\n".repeat(10);
+        let highlighter = Highlighter::new();
+        let (plain, lazy) = highlighter.highlight_lazily(&code, "rust", DEFAULT_THEME).unwrap();
+        let blocks = vec![
+            RenderedBlock::Paragraph { content: vec![plain_span("intro")] },
+            RenderedBlock::CodeBlock {
+                language: "rust".to_string(),
+                title: None,
+                highlighted_lines: plain,
+                lazy: Some(Rc::new(lazy)),
+            },
+        ];
+        let mut doc = flatten(&blocks, 40);
+        // "intro", spacing, label, then the code from line 3.
+        let spans = |doc: &PreRenderedDocument, i: usize| match &doc.lines[i] {
+            DocumentLine::Code(line) => line.spans.len(),
+            _ => panic!("expected Code line"),
+        };
+        assert_eq!(spans(&doc, 3), 1);
+
+        doc.highlight_code(0..5);
+        assert!(spans(&doc, 3) > 1 && spans(&doc, 4) > 1);
+        assert_eq!(spans(&doc, 5), 1, "past the range");
+        assert_eq!(doc.lines[4].plain_text(), "let x = 1;");
+    }
+
     #[test]
     fn test_layout_code_block_empty_language_no_label() {
         let blocks = vec![RenderedBlock::CodeBlock {
             language: String::new(),
+            title: None,
             highlighted_lines: vec![make_code_line("code")],
+            lazy: None,
         }];
         let doc = flatten(&blocks, 80);
         // No language → no label line, just the code line.
         assert_eq!(doc.total_height, 1);
     }
 
+    #[test]
+    fn test_layout_code_block_title_is_dim_caption_above_label() {
+        let blocks = vec![RenderedBlock::CodeBlock {
+            language: "python".to_string(),
+            title: Some("server.py".to_string()),
+            highlighted_lines: vec![make_code_line("pass")],
+            lazy: None,
+        }];
+        let doc = flatten(&blocks, 80);
+        assert_eq!(doc.total_height, 3, "caption, label, code");
+        let DocumentLine::Text(caption) = &doc.lines[0] else {
+            panic!("expected a Text caption line");
+        };
+        assert_eq!(doc.lines[0].plain_text(), "server.py");
+        assert!(caption.spans[0].style.add_modifier.contains(Modifier::DIM));
+        assert!(matches!(doc.lines[1], DocumentLine::Code(_)));
+        assert_eq!(reflowed_text(&blocks), "server.py\n\npass");
+    }
+
     #[test]
     fn test_layout_code_block_with_language_has_label() {
         let blocks = vec![RenderedBlock::CodeBlock {
             language: "rust".to_string(),
+            title: None,
             highlighted_lines: vec![
                 make_code_line("fn main() {"),
                 make_code_line("    println!(\"hello\");"),
                 make_code_line("}"),
             ],
+            lazy: None,
         }];
         let doc = flatten(&blocks, 80);
         // 1 label + 3 code lines = 4
@@ -290,10 +513,12 @@
     fn test_layout_code_block_multiple_lines_correct_count() {
         let blocks = vec![RenderedBlock::CodeBlock {
             language: "python".to_string(),
+            title: None,
             highlighted_lines: vec![
                 make_code_line("def f():"),
                 make_code_line("    pass"),
             ],
+            lazy: None,
         }];
         let doc = flatten(&blocks, 80);
         // 1 label + 2 code lines = 3
@@ -304,3 +529,708 @@
             .count();
         assert_eq!(code_count, 3);
     }
+
+    #[test]
+    fn test_layout_plain_text_strips_styles() {
+        let blocks = vec![
+            RenderedBlock::Paragraph {
+                content: vec![
+                    plain_span("plain "),
+                    styled_span("bold", Style::default().add_modifier(Modifier::BOLD)),
+                ],
+            },
+            RenderedBlock::ThematicBreak,
+        ];
+        let doc = flatten(&blocks, 80);
+        let texts: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(texts, vec!["plain bold", "", "---"]);
+    }
+
+    #[test]
+    fn test_layout_image_reserves_height() {
+        let blocks = vec![RenderedBlock::Image {
+            protocol_index: 0,
+            alt_text: "alt".to_string(),
+            width_cells: 10,
+            height_cells: 4,
+        }];
+        let doc = flatten(&blocks, 80);
+        assert_eq!(doc.total_height, 4);
+        assert!(matches!(
+            &doc.lines[0],
+            DocumentLine::ImageStart { width: 10, height: 4, .. }
+        ));
+        assert!(matches!(&doc.lines[3], DocumentLine::ImageContinuation));
+    }
+
+    #[test]
+    fn test_layout_image_wider_than_width_is_scaled_down() {
+        let blocks = vec![RenderedBlock::Image {
+            protocol_index: 0,
+            alt_text: "alt".to_string(),
+            width_cells: 100,
+            height_cells: 20,
+        }];
+        let doc = flatten(&blocks, 50);
+        assert_eq!(doc.total_height, 10);
+        assert!(matches!(
+            &doc.lines[0],
+            DocumentLine::ImageStart { width: 50, height: 10, .. }
+        ));
+    }
+
+    fn justified(blocks: &[RenderedBlock], width: u16) -> Vec<String> {
+        let options = LayoutOptions {
+            align: TextAlign::Justify,
+            ..LayoutOptions::default()
+        };
+        flatten_with_options(blocks, width, &options)
+            .lines
+            .iter()
+            .map(DocumentLine::plain_text)
+            .collect()
+    }
+
+    #[test]
+    fn test_layout_justify_fills_all_but_last_line() {
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("aa bb cc dd ee ff gg")],
+        }];
+        // Left-aligned (optimal-fit) wrap at 10: "aa bb", "cc dd ee", "ff gg".
+        let lines = justified(&blocks, 10);
+        assert_eq!(lines, vec!["aa      bb", "cc  dd  ee", "ff gg"]);
+    }
+
+    #[test]
+    fn test_layout_justify_leftover_spaces_go_left() {
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("a b c d next")],
+        }];
+        // "a b c d" is 7 wide: 4 extra columns over 3 gaps.
+        let lines = justified(&blocks, 11);
+        assert_eq!(lines[0], "a   b  c  d");
+    }
+
+    #[test]
+    fn test_layout_justify_preserves_styles() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![styled_span("aa bb", bold), plain_span(" cc dd")],
+        }];
+        let options = LayoutOptions {
+            align: TextAlign::Justify,
+            ..LayoutOptions::default()
+        };
+        let doc = flatten_with_options(&blocks, 10, &options);
+        match &doc.lines[0] {
+            DocumentLine::Text(line) => {
+                assert_eq!(line.width(), 10);
+                assert_eq!(line.spans[0].style, bold);
+                assert!(line.spans[0].content.starts_with("aa"));
+            }
+            _ => panic!("expected Text line"),
+        }
+    }
+
+    #[test]
+    fn test_layout_justify_skips_headings_and_hard_break_lines() {
+        let blocks = vec![
+            RenderedBlock::Heading {
+                level: 1,
+                content: vec![plain_span("aa bb cc dd")],
+            },
+            RenderedBlock::Paragraph {
+                content: vec![plain_span("aa bb\ncc dd ee ff")],
+            },
+        ];
+        let lines = justified(&blocks, 10);
+        assert_eq!(lines, vec!["aa bb", "cc dd", "", "aa bb", "cc      dd", "ee ff"]);
+    }
+
+    #[test]
+    fn test_layout_records_heading_lines() {
+        let blocks = vec![
+            RenderedBlock::Heading {
+                level: 1,
+                content: vec![plain_span("Title")],
+            },
+            RenderedBlock::Paragraph {
+                content: vec![plain_span("one two three four")],
+            },
+            RenderedBlock::Heading {
+                level: 2,
+                content: vec![plain_span("Sub "), plain_span("section")],
+            },
+        ];
+        let doc = flatten(&blocks, 10);
+        let entries: Vec<(u8, &str, usize)> =
+            doc.headings.iter().map(|h| (h.level, h.text.as_str(), h.line)).collect();
+        // Title, empty, 2 paragraph lines, empty, then the subheading.
+        assert_eq!(entries, vec![(1, "Title", 0), (2, "Sub section", 5)]);
+    }
+
+    #[test]
+    fn test_layout_reflowed_text_unwraps_paragraphs() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![
+            RenderedBlock::Heading {
+                level: 1,
+                content: vec![plain_span("Title")],
+            },
+            RenderedBlock::Paragraph {
+                content: vec![
+                    plain_span("a long line that"),
+                    plain_span(" "),
+                    styled_span("wraps", bold),
+                    plain_span(" when narrow"),
+                ],
+            },
+            RenderedBlock::CodeBlock {
+                language: "rust".to_string(),
+                title: None,
+                highlighted_lines: vec![make_code_line("fn a() {"), make_code_line("}")],
+                lazy: None,
+            },
+            RenderedBlock::ThematicBreak,
+        ];
+        // At width 10 the paragraph wraps; the reflowed text doesn't.
+        assert!(flatten(&blocks, 10).total_height > 6);
+        assert_eq!(
+            reflowed_text(&blocks),
+            "Title\n\na long line that wraps when narrow\n\nfn a() {\n}\n\n---"
+        );
+    }
+
+    #[test]
+    fn test_layout_reflowed_text_keeps_hard_breaks() {
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("first"), plain_span("\n"), plain_span("second")],
+        }];
+        assert_eq!(reflowed_text(&blocks), "first\nsecond");
+    }
+
+    #[test]
+    fn test_layout_huge_unbreakable_paragraph_is_chunked_at_width() {
+        // ~1.2 MB without whitespace, in two styles. How long this takes is
+        // watched by the `huge_word` and `cjk_run` benches in pipeline.rs.
+        let text = "漢".repeat(400 * 1024);
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span(&text), styled_span("tail", bold)],
+        }];
+        let doc = flatten(&blocks, 80);
+        assert_eq!(doc.total_height, (400 * 1024usize).div_ceil(40) + 1);
+        let joined: String = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(joined, format!("{text}tail"));
+        assert!(doc.lines.iter().all(|l| l.plain_text().width() <= 80));
+        let DocumentLine::Text(last) = doc.lines.last().unwrap() else {
+            panic!("expected Text line");
+        };
+        assert_eq!(last.spans.len(), 1);
+        assert_eq!(last.spans[0].style, bold);
+    }
+
+    #[test]
+    fn test_layout_huge_paragraph_preserves_text() {
+        let word = "x".repeat(100 * 1024);
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("start "), plain_span(&word), plain_span(" end")],
+        }];
+        let doc = flatten(&blocks, 80);
+        let joined: String = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(joined, format!("start{word}end"));
+        assert!(doc.lines.iter().all(|l| l.plain_text().len() <= 80));
+    }
+
+    /// Enough paragraphs that `flatten_lazy` stops before the end.
+    fn many_blocks() -> Rc<[RenderedBlock]> {
+        (0..4000)
+            .map(|i| {
+                if i % 100 == 0 {
+                    RenderedBlock::Heading {
+                        level: 2,
+                        content: vec![plain_span(&format!("Section {i}"))],
+                    }
+                } else {
+                    RenderedBlock::Paragraph {
+                        content: vec![plain_span("some words that wrap onto a second line here")],
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn texts(doc: &PreRenderedDocument) -> Vec<String> {
+        doc.lines.iter().map(DocumentLine::plain_text).collect()
+    }
+
+    #[test]
+    fn test_layout_lazy_small_document_is_complete() {
+        let blocks: Rc<[RenderedBlock]> = vec![
+            RenderedBlock::Heading {
+                level: 1,
+                content: vec![plain_span("Title")],
+            },
+            RenderedBlock::Paragraph {
+                content: vec![plain_span("Hello world")],
+            },
+        ]
+        .into();
+        let lazy = flatten_lazy(Rc::clone(&blocks), 80, &LayoutOptions::default());
+        let eager = flatten(&blocks, 80);
+        assert!(lazy.is_complete());
+        assert_eq!(texts(&lazy), texts(&eager));
+        assert_eq!(lazy.total_height, eager.total_height);
+        assert_eq!(lazy.headings, eager.headings);
+    }
+
+    #[test]
+    fn test_layout_lazy_large_document_lays_out_prefix() {
+        let blocks = many_blocks();
+        let doc = flatten_lazy(Rc::clone(&blocks), 30, &LayoutOptions::default());
+        assert!(!doc.is_complete());
+        assert!(doc.lines.len() >= EAGER_LINES);
+        assert!(doc.lines.len() < flatten(&blocks, 30).total_height);
+        assert!(doc.total_height > doc.lines.len(), "rest of the document is estimated");
+    }
+
+    #[test]
+    fn test_layout_lazy_ensure_lines_matches_eager_layout() {
+        let blocks = many_blocks();
+        let eager = flatten(&blocks, 30);
+        let mut doc = flatten_lazy(Rc::clone(&blocks), 30, &LayoutOptions::default());
+
+        doc.ensure_lines(EAGER_LINES + 100);
+        assert!(doc.lines.len() >= EAGER_LINES + 100);
+        assert_eq!(texts(&doc)[..], texts(&eager)[..doc.lines.len()]);
+
+        doc.complete();
+        assert!(doc.is_complete());
+        assert_eq!(texts(&doc), texts(&eager));
+        assert_eq!(doc.total_height, eager.total_height);
+        assert_eq!(doc.headings, eager.headings);
+        assert_eq!(doc.block_starts, eager.block_starts);
+    }
+
+    #[test]
+    fn test_layout_keep_lines_clamps_and_shifts_headings() {
+        let blocks = many_blocks();
+        let mut doc = flatten_lazy(Rc::clone(&blocks), 30, &LayoutOptions::default());
+        let eager = flatten(&blocks, 30);
+        let section = eager.headings[1].line;
+
+        doc.keep_lines(section - 1..section + 2);
+        assert_eq!(texts(&doc), texts(&eager)[section - 1..section + 2]);
+        assert_eq!(doc.total_height, 3);
+        assert_eq!(doc.headings.len(), 1);
+        assert_eq!(doc.headings[0].line, 1);
+
+        doc.keep_lines(2..100);
+        assert_eq!(texts(&doc), texts(&eager)[section + 1..section + 2]);
+        assert!(doc.headings.is_empty());
+        doc.keep_lines(5..9);
+        assert!(doc.lines.is_empty());
+    }
+
+    #[test]
+    fn test_layout_anchor_finds_same_block_at_another_width() {
+        let blocks = many_blocks();
+        let wide = flatten(&blocks, 80);
+        let narrow = flatten(&blocks, 30);
+        // Blocks 0 and 1 fit on one line at 80 columns; block 2 starts at
+        // its spacing line, 2 lines further.
+        assert_eq!(wide.block_starts[..3], [0, 1, 3]);
+        let anchor = wide.anchor(3).unwrap();
+        assert_eq!(anchor, Anchor { block: 2, offset: 0, height: 2 });
+
+        let mut narrow_lazy = flatten_lazy(Rc::clone(&blocks), 30, &LayoutOptions::default());
+        let line = narrow_lazy.anchor_line(anchor);
+        assert_eq!(line, narrow.block_starts[2]);
+        assert_eq!(narrow_lazy.anchor(line).unwrap().block, 2);
+    }
+
+    #[test]
+    fn test_layout_anchor_line_lays_out_blocks_past_the_prefix() {
+        let blocks = many_blocks();
+        let eager = flatten(&blocks, 30);
+        let mut doc = flatten_lazy(Rc::clone(&blocks), 30, &LayoutOptions::default());
+        assert!(doc.block_starts.len() < 3900);
+
+        let anchor = Anchor { block: 3901, offset: 2, height: 3 };
+        assert_eq!(doc.anchor_line(anchor), eager.block_starts[3901] + 2);
+        assert_eq!(doc.anchor(doc.lines.len()), None);
+        assert_eq!(PreRenderedDocument::from_lines(vec![DocumentLine::Empty]).anchor(0), None);
+    }
+
+    #[test]
+    fn test_layout_newline_in_styled_span_keeps_style_on_both_lines() {
+        let code = Style::default().bg(Color::Indexed(236)).add_modifier(Modifier::BOLD);
+        let blocks = vec![RenderedBlock::Paragraph {
+            content: vec![plain_span("see "), styled_span("foo\nbar", code)],
+        }];
+        let doc = flatten(&blocks, 40);
+        assert_eq!(doc.lines.len(), 2);
+        let spans: Vec<_> = doc
+            .lines
+            .iter()
+            .map(|line| match line {
+                DocumentLine::Text(l) => l.spans.clone(),
+                _ => panic!("expected Text line"),
+            })
+            .collect();
+        let last = |i: usize| spans[i].last().unwrap().clone();
+        assert_eq!((last(0).content.as_ref(), last(0).style), ("foo", code));
+        assert_eq!((spans[1][0].content.as_ref(), spans[1][0].style), ("bar", code));
+    }
+
+    #[test]
+    fn test_layout_fit_gutters_drops_in_priority_order() {
+        assert_eq!(fit_gutters(40, [5, 3, 4]), [5, 3, 4]);
+        // 40 - 12 = 28 is plenty; at 20, 8 columns would remain.
+        assert_eq!(fit_gutters(20, [5, 3, 4]), [0, 3, 4]);
+        assert_eq!(fit_gutters(15, [5, 3, 4]), [0, 0, 4]);
+        assert_eq!(fit_gutters(13, [5, 3, 4]), [0, 0, 0]);
+        for cols in 1..=10 {
+            assert_eq!(fit_gutters(cols, [1, 1]), [0, 0], "cols={cols}");
+        }
+        assert_eq!(fit_gutters(5, [u16::MAX]), [0]);
+    }
+
+    #[test]
+    fn test_layout_clip_mode_keeps_paragraph_on_one_line() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![
+            RenderedBlock::Heading {
+                level: 1,
+                content: vec![plain_span("a heading far wider than ten columns")],
+            },
+            RenderedBlock::Paragraph {
+                content: vec![
+                    plain_span("one two three "),
+                    styled_span("four five", bold),
+                    plain_span("\nsix seven eight nine"),
+                ],
+            },
+        ];
+        let options = LayoutOptions {
+            wrap: WrapMode::Clip,
+            ..LayoutOptions::default()
+        };
+        let doc = flatten_with_options(&blocks, 10, &options);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(
+            text,
+            [
+                "a heading far wider than ten columns",
+                "",
+                "one two three four five",
+                "six seven eight nine",
+            ]
+        );
+        let DocumentLine::Text(line) = &doc.lines[2] else {
+            panic!("expected Text line");
+        };
+        assert_eq!(line.spans[1].style, bold);
+        // Wrapped, the same paragraph takes more lines than it has hard breaks.
+        assert!(flatten(&blocks, 10).lines.len() > doc.lines.len());
+    }
+
+    #[test]
+    fn test_layout_details_indents_body_under_summary() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let blocks = vec![RenderedBlock::Details {
+            summary: vec![styled_span("More", bold)],
+            body: vec![
+                RenderedBlock::Heading { level: 2, content: vec![plain_span("Inner")] },
+                RenderedBlock::Paragraph { content: vec![plain_span("aaa bbb ccc")] },
+            ],
+        }];
+        let doc = flatten(&blocks, 9);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        // The body wraps at 9 - 2 columns.
+        assert_eq!(text, ["▾ More", "  Inner", "", "  aaa bbb", "  ccc"]);
+        assert_eq!(doc.headings[0].line, 1);
+        assert_eq!(reflowed_text(&blocks), "More\n\nInner\n\naaa bbb ccc");
+    }
+
+    #[test]
+    fn test_layout_code_label_icon_only_with_icons_option() {
+        let blocks = vec![RenderedBlock::CodeBlock {
+            language: "rust".to_string(),
+            title: None,
+            highlighted_lines: vec![make_code_line("fn main() {}")],
+            lazy: None,
+        }];
+        let plain = flatten(&blocks, 40);
+        assert_eq!(plain.lines[0].plain_text(), " rust ");
+        let options = LayoutOptions { icons: true, ..LayoutOptions::default() };
+        let with_icons = flatten_with_options(&blocks, 40, &options);
+        assert_eq!(with_icons.lines[0].plain_text(), " \u{e7a8} rust ");
+    }
+
+    #[test]
+    fn test_layout_code_label_unknown_language_has_no_icon() {
+        assert_eq!(language_icon("Python"), Some('\u{e73c}'));
+        assert_eq!(language_icon("brainfuck"), None);
+        let blocks = vec![RenderedBlock::CodeBlock {
+            language: "brainfuck".to_string(),
+            title: None,
+            highlighted_lines: vec![make_code_line("+++")],
+            lazy: None,
+        }];
+        let options = LayoutOptions { icons: true, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 40, &options);
+        assert_eq!(doc.lines[0].plain_text(), " brainfuck ");
+    }
+
+    #[test]
+    fn test_layout_tile_to_width_fills_exactly() {
+        assert_eq!(tile_to_width("─", 4), "────");
+        assert_eq!(tile_to_width("* ", 5), "* * *");
+        assert_eq!(tile_to_width("", 3), "───");
+        assert_eq!(tile_to_width("x", 0), "");
+        // A double-width character that would overflow leaves a space.
+        let rule = tile_to_width("═字", 5);
+        assert_eq!(rule, "═字═ ");
+        assert_eq!(rule.width(), 5);
+    }
+
+    #[test]
+    fn test_layout_indent_guides_mark_each_nesting_level() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let inner = RenderedBlock::Details {
+            summary: vec![styled_span("Inner", bold)],
+            body: vec![RenderedBlock::Paragraph { content: vec![plain_span("deep")] }],
+        };
+        let blocks = vec![RenderedBlock::Details {
+            summary: vec![styled_span("Outer", bold)],
+            body: vec![RenderedBlock::Paragraph { content: vec![plain_span("top")] }, inner],
+        }];
+        let options = LayoutOptions { indent_guides: true, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 20, &options);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(text, ["▾ Outer", "│ top", "│ ", "│ ▾ Inner", "│ │ deep"]);
+        let DocumentLine::Text(line) = &doc.lines[4] else {
+            panic!("expected Text line");
+        };
+        assert!(line.spans[..2].iter().all(|s| s.style.add_modifier.contains(Modifier::DIM)));
+        // Without the option the body is indented with blanks, as before.
+        let plain = flatten(&blocks, 20);
+        assert_eq!(plain.lines[4].plain_text(), "    deep");
+    }
+
+    #[test]
+    fn test_layout_indent_guides_in_nested_lists() {
+        let list = |items| RenderedBlock::List { start: None, items };
+        let third = list(vec![item(vec![para("c")])]);
+        let second = list(vec![item(vec![para("b"), third])]);
+        let blocks = vec![list(vec![
+            item(vec![para("a"), second, para("more")]),
+            item(vec![para("next")]),
+        ])];
+        let options = LayoutOptions { indent_guides: true, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 20, &options);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(text, ["• a", "│ • b", "│ │ • c", "│ ", "│ more", "• next"]);
+        let DocumentLine::Text(line) = &doc.lines[2] else {
+            panic!("expected Text line");
+        };
+        assert!(line.spans[..2].iter().all(|s| s.style.add_modifier.contains(Modifier::DIM)));
+        // Without the option the nested items are indented with blanks.
+        let plain = flatten(&blocks, 20);
+        assert_eq!(plain.lines[2].plain_text(), "    • c");
+        assert!(matches!(plain.lines[3], DocumentLine::Empty));
+    }
+
+    #[test]
+    fn test_layout_indent_guides_in_nested_quotes() {
+        let quote = |body| RenderedBlock::BlockQuote { body, attribution: None };
+        let inner = quote(vec![para("deep")]);
+        let blocks = vec![quote(vec![para("top"), inner])];
+        let options = LayoutOptions { indent_guides: true, ..LayoutOptions::default() };
+        let doc = flatten_with_options(&blocks, 20, &options);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(text, ["│ top", "│ ", "│ │ deep"]);
+        let DocumentLine::Text(line) = &doc.lines[2] else {
+            panic!("expected Text line");
+        };
+        // The outer level is a guide to the left of the inner quote's bar.
+        assert_eq!(line.spans[0].content, INDENT_GUIDE);
+        assert_eq!(line.spans[1].content, QUOTE_BAR);
+        assert!(line.spans[..2].iter().all(|s| s.style.add_modifier.contains(Modifier::DIM)));
+    }
+
+    #[test]
+    fn test_layout_tracks_references_through_nesting_and_wrapping() {
+        let reference = |text: &str, url: &str| StyledSpan {
+            text: text.to_string(),
+            style: Style::default(),
+            reference: Some(url.to_string()),
+        };
+        let item = ListItem {
+            task: None,
+            blocks: vec![RenderedBlock::Paragraph {
+                content: vec![plain_span("see "), reference("#12", "issue"), plain_span(" too")],
+            }],
+        };
+        let blocks = vec![RenderedBlock::BlockQuote {
+            body: vec![RenderedBlock::List { start: None, items: vec![item] }],
+            attribution: None,
+        }];
+        let doc = flatten(&blocks, 40);
+        assert_eq!(doc.lines[0].plain_text(), "│ • see #12 too");
+        // The quote bar and list marker come first on the line.
+        assert_eq!(doc.reference(0, 3), Some("issue"));
+        assert_eq!(doc.references.len(), 1);
+        // The mark that found it is gone.
+        let DocumentLine::Text(line) = &doc.lines[0] else {
+            panic!("expected Text line");
+        };
+        assert!(line.spans.iter().all(|s| s.style.sub_modifier.is_empty()));
+
+        // A reference too long for the line is chunked: each part links.
+        let hash = "4f2a9c1e8b";
+        let blocks = vec![RenderedBlock::Paragraph { content: vec![reference(hash, "commit")] }];
+        let doc = flatten(&blocks, 4);
+        let parts: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(parts.concat(), hash);
+        assert_eq!(doc.references.len(), parts.len());
+        assert!(doc.references.iter().all(|r| r.url == "commit" && r.span == 0));
+    }
+
+    #[test]
+    fn test_layout_math_block_is_centered() {
+        let blocks = vec![RenderedBlock::MathBlock {
+            lines: vec!["a = b".to_string(), "c = d + e".to_string()],
+        }];
+        let doc = flatten(&blocks, 15);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(text, ["     a = b", "   c = d + e"]);
+        assert_eq!(reflowed_text(&blocks), "a = b\nc = d + e");
+    }
+
+
+    fn para(text: &str) -> RenderedBlock {
+        RenderedBlock::Paragraph { content: vec![plain_span(text)] }
+    }
+
+    fn item(blocks: Vec<RenderedBlock>) -> ListItem {
+        ListItem { task: None, blocks }
+    }
+
+    #[test]
+    fn test_layout_list_continuation_paragraphs_align_with_item_text() {
+        let blocks = vec![RenderedBlock::List {
+            start: None,
+            items: vec![
+                item(vec![para("first para"), para("second para here")]),
+                item(vec![para("next")]),
+            ],
+        }];
+        let doc = flatten(&blocks, 12);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        // Items wrap at 12 - 2 columns, continuation lines under the text.
+        assert_eq!(text, ["• first para", "", "  second", "  para here", "• next"]);
+        assert_eq!(
+            reflowed_text(&blocks),
+            "• first para\n\n  second para here\n• next"
+        );
+    }
+
+    #[test]
+    fn test_layout_list_code_block_indented_to_item_text() {
+        let code = RenderedBlock::CodeBlock {
+            language: "rust".to_string(),
+            title: None,
+            highlighted_lines: vec![make_code_line("let x = 1;")],
+            lazy: None,
+        };
+        let blocks = vec![RenderedBlock::List {
+            start: Some(9),
+            items: vec![item(vec![para("nine"), code]), item(vec![para("ten")])],
+        }];
+        let doc = flatten(&blocks, 40);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        // Numbers are right-aligned on their dots; code starts under "nine".
+        assert_eq!(text, [" 9. nine", "", "     rust ", "    let x = 1;", "10. ten"]);
+        assert!(matches!(doc.lines[3], DocumentLine::Code(_)));
+    }
+
+    #[test]
+    fn test_layout_nested_list_hugs_item_text() {
+        let nested = RenderedBlock::List { start: None, items: vec![item(vec![para("inner")])] };
+        let blocks = vec![RenderedBlock::List {
+            start: None,
+            items: vec![item(vec![para("outer"), nested]), item(vec![])],
+        }];
+        let doc = flatten(&blocks, 40);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        // An empty item still shows its marker.
+        assert_eq!(text, ["• outer", "  • inner", "• "]);
+        assert_eq!(estimate_height(&blocks[0], 0, 40), 3);
+    }
+
+    #[test]
+    fn test_layout_ordered_task_list_shows_number_and_checkbox() {
+        let blocks = vec![RenderedBlock::List {
+            start: Some(1),
+            items: vec![
+                ListItem { task: Some(false), blocks: vec![para("do the thing")] },
+                ListItem { task: Some(true), blocks: vec![para("done")] },
+                item(vec![para("plain")]),
+            ],
+        }];
+        let doc = flatten(&blocks, 14);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        // Wrapped task text continues under the text, past the checkbox.
+        assert_eq!(text, ["1. [ ] do the", "       thing", "2. [x] done", "3. plain"]);
+        assert_eq!(estimate_height(&blocks[0], 0, 14), 4);
+        assert_eq!(reflowed_text(&blocks), "1. [ ] do the thing\n2. [x] done\n3. plain");
+    }
+
+    #[test]
+    fn test_layout_block_quote_bar_and_right_aligned_attribution() {
+        let blocks = vec![RenderedBlock::BlockQuote {
+            body: vec![para("Simple is better than complex."), para("Flat too.")],
+            attribution: Some(vec![plain_span("— Tim Peters")]),
+        }];
+        let doc = flatten(&blocks, 24);
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(
+            text,
+            [
+                "│ Simple is better than",
+                "│ complex.",
+                "│ ",
+                "│ Flat too.",
+                "│           — Tim Peters",
+            ]
+        );
+        let DocumentLine::Text(line) = &doc.lines[4] else {
+            panic!("expected a text line");
+        };
+        assert!(line.spans.last().unwrap().style.add_modifier.contains(Modifier::ITALIC));
+        assert_eq!(
+            reflowed_text(&blocks),
+            "> Simple is better than complex.\n>\n> Flat too.\n> — Tim Peters"
+        );
+    }
+
+    #[test]
+    fn test_layout_code_starts_mark_each_code_block() {
+        let source = "# T\n\n```rust\nfn a() {}\n```\n\ntext\n\n- item\n\n  ```\n  x\n  ```\n\n\
+                      ```\ny\n```\n";
+        let highlighter = crate::highlight::Highlighter::new();
+        let blocks = crate::parser::parse(source, &highlighter);
+        let doc = flatten(&blocks, 40);
+        assert_eq!(doc.code_starts.len(), 3);
+        for &start in &doc.code_starts {
+            assert!(matches!(doc.lines[start], DocumentLine::Code(_)));
+            assert!(!matches!(doc.lines[start - 1], DocumentLine::Code(_)));
+        }
+
+        let lazy = flatten_lazy(blocks.into(), 40, &LayoutOptions::default());
+        assert_eq!(lazy.code_starts, doc.code_starts);
+        let starts = doc.code_starts;
+        assert_eq!(PreRenderedDocument::from_lines(doc.lines).code_starts, starts);
+    }
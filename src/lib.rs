@@ -0,0 +1,50 @@
+//! mdink as a library: the markdown → terminal rendering pipeline.
+//!
+//! The `mdink` binary is a thin TUI over these stages, and other Rust
+//! programs can embed them directly:
+//!
+//! ```text
+//! &str ─ parse() ─▶ Vec<RenderedBlock> ─ flatten() ─▶ PreRenderedDocument
+//! ```
+//!
+//! `render_to_lines()` runs both stages in one call. The resulting
+//! `DocumentLine`s hold ratatui `Line`s, ready to draw in any ratatui app.
+//! `to_ansi()` goes one step further and returns an ANSI-styled string.
+
+pub mod app;
+pub mod export;
+pub mod highlight;
+pub mod images;
+pub mod keymap;
+pub mod layout;
+pub mod math;
+pub mod parser;
+pub mod renderer;
+pub mod testing;
+
+pub use export::{ColorMode, RenderOptions, to_ansi};
+pub use highlight::Highlighter;
+pub use layout::{DocumentLine, PreRenderedDocument, flatten};
+pub use parser::{ParseOptions, RenderedBlock, StyledSpan, parse};
+
+/// Parses `source` and lays it out at `width` columns in one call.
+///
+/// `theme` names the syntect theme for code blocks (unknown names fall
+/// back to `highlight::DEFAULT_THEME`). Images render as alt text.
+///
+/// Builds a fresh `Highlighter` on every call, which costs ~100ms; callers
+/// rendering repeatedly should keep a `Highlighter` and call `parse()` /
+/// `flatten()` themselves.
+pub fn render_to_lines(source: &str, width: u16, theme: &str) -> PreRenderedDocument {
+    let highlighter = Highlighter::new();
+    let options = ParseOptions {
+        theme: theme.to_string(),
+        ..ParseOptions::default()
+    };
+    let blocks = parser::parse_with_options(source, &highlighter, &options);
+    flatten(&blocks, width)
+}
+
+#[cfg(test)]
+#[path = "lib_tests.rs"]
+mod tests;
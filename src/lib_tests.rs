@@ -0,0 +1,20 @@
+    use super::*;
+
+    #[test]
+    fn test_lib_render_to_lines_runs_whole_pipeline() {
+        let source = "# Title\n\nsome text\n\n```rust\nfn main() {}\n```";
+        let doc = render_to_lines(source, 40, "InspiredGitHub");
+        let text: Vec<String> = doc.lines.iter().map(DocumentLine::plain_text).collect();
+        assert_eq!(text, vec!["Title", "", "some text", "", " rust ", "fn main() {}"]);
+        assert_eq!(doc.total_height, 6);
+    }
+
+    #[test]
+    fn test_lib_render_to_lines_theme_changes_code_colors() {
+        let source = "```rust\nfn main() {}\n```";
+        let code_style = |theme: &str| match &render_to_lines(source, 40, theme).lines[1] {
+            DocumentLine::Code(line) => line.spans[0].style,
+            _ => panic!("expected Code line"),
+        };
+        assert_ne!(code_style("InspiredGitHub"), code_style("base16-ocean.dark"));
+    }
@@ -1,25 +1,31 @@
 //! Entry point: wires CLI → parser → layout → TUI event loop.
 //!
-//! This is the thin orchestrator that connects all pipeline stages.
+//! This is the thin orchestrator that connects the library's pipeline stages.
 //! It handles CLI argument parsing, file I/O, terminal initialization,
 //! the event loop, and graceful shutdown.
 
-mod app;
 mod cli;
-mod highlight;
-mod layout;
-mod parser;
-mod renderer;
 
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 use clap::Parser;
 use ratatui::crossterm::event::{self, Event};
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+
+use mdink::app::{App, CopyScope, MIN_WRAP_WIDTH, StatusLineMode, column_width};
+use mdink::export::ColorMode;
+use mdink::parser::RenderedBlock;
+use mdink::{export, highlight, images, keymap, layout, parser, renderer};
 
-use crate::app::App;
 use crate::cli::Cli;
-use crate::parser::RenderedBlock;
 
 /// Set to `true` immediately after `ratatui::init()` so the panic hook knows
 /// whether the terminal has been initialised and needs restoring.
@@ -29,6 +35,32 @@ use crate::parser::RenderedBlock;
 /// display on some terminals and multiplexers.
 static TERMINAL_ACTIVE: AtomicBool = AtomicBool::new(false);
 
+/// Largest input accepted, from disk or over the network, unless
+/// `--max-file-bytes` says otherwise.
+const DEFAULT_MAX_FILE_BYTES: u64 = 100 * 1024 * 1024; // 100 MB
+
+/// Size `--dump` and `--output` lay the document out for when stdout is
+/// not a terminal, or there is no terminal to ask.
+const DUMP_SIZE: (u16, u16) = (80, 24);
+
+/// How often the event loop checks whether the file changed on disk.
+const MTIME_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Longest the viewer goes without drawing while it applies a burst of
+/// queued key presses.
+const MAX_BATCH_DELAY: Duration = Duration::from_millis(50);
+
+/// How long `--theme auto` waits for the terminal to report its background.
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// The sample document `--demo` shows.
+const DEMO: &str = include_str!("../testdata/demo.md");
+
+/// Stands in for a path for the `--demo` document, which
+/// `SourceFile::read()` returns instead of reading a file. Also its name in
+/// the status bar.
+const DEMO_PATH: &str = "(demo)";
+
 fn main() -> color_eyre::Result<()> {
     // Install color_eyre error/panic hooks for pretty backtraces.
     color_eyre::install()?;
@@ -45,47 +77,141 @@ fn main() -> color_eyre::Result<()> {
 
     // Parse CLI arguments.
     let cli = Cli::parse();
-
-    // Guard against OOM: reject files that exceed a reasonable size threshold.
-    // The check happens before ratatui::init() so the error prints to the normal
-    // terminal instead of a raw alternate screen.
-    const MAX_FILE_BYTES: u64 = 100 * 1024 * 1024; // 100 MB
-    let file_size = fs::metadata(&cli.file)?.len();
-    if file_size > MAX_FILE_BYTES {
-        return Err(color_eyre::eyre::eyre!(
-            "{}: file too large ({} bytes; limit is {} bytes)",
-            cli.file,
-            file_size,
-            MAX_FILE_BYTES
-        ));
+    // The print commands come before `Options::from_cli()`: `--theme auto`
+    // would have it query the terminal, and they need none of it.
+    if cli.theme_preview {
+        let color = resolve_color_mode(cli.color, std::env::var_os("NO_COLOR").as_deref());
+        print!("{}", export::theme_preview(&highlight::Highlighter::new(), color));
+        return Ok(());
+    }
+    if let Some(format) = cli.print_keys {
+        print!("{}", match format {
+            cli::KeysFormat::Table => keymap::to_table(),
+            cli::KeysFormat::Json => keymap::to_json(),
+        });
+        return Ok(());
     }
+    let options = Options::from_cli(&cli, |name| std::env::var_os(name))?;
+    let dump = options.output.is_some();
+    // `--concat` files follow FILE, if given.
+    let paths: Vec<&str> = if cli.demo {
+        vec![DEMO_PATH]
+    } else {
+        cli.file.iter().chain(&cli.concat).map(String::as_str).collect()
+    };
+    let file = *paths.first().expect("clap requires FILE, --concat or --demo unless printing");
 
-    // Read the markdown source file.
-    let source = fs::read_to_string(&cli.file)?;
+    // Read the sources before ratatui::init() so that errors (missing file,
+    // oversized input) print to the normal terminal instead of a raw alternate screen.
+    let max_file_bytes = cli.max_file_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES);
+    let files = paths
+        .iter()
+        .map(|path| SourceFile::read(path, max_file_bytes))
+        .collect::<color_eyre::Result<Vec<_>>>()?;
+    if cli.warn_mixed_indent {
+        for file in &files {
+            warn_mixed_indent(file);
+        }
+    }
+    if cli.strip_frontmatter_only {
+        for file in &files {
+            print!("{}", parser::strip_front_matter(&file.source));
+        }
+        return Ok(());
+    }
 
     // Load syntax highlighting resources (expensive, done once).
     let highlighter = highlight::Highlighter::new();
 
-    // Parse markdown into IR blocks (done once — blocks don't depend on width).
-    let blocks = parser::parse(&source, &highlighter);
+    // Get initial terminal size for layout. Dumps into a pipe or file use
+    // `DUMP_SIZE` instead: a terminal found elsewhere (crossterm falls back
+    // to /dev/tty) is not where the text is going.
+    let stdout_is_terminal = std::io::IsTerminal::is_terminal(&std::io::stdout());
+    let (cols, rows) = layout_size(dump, stdout_is_terminal, ratatui::crossterm::terminal::size)?;
 
-    // Get initial terminal size for layout.
-    let (cols, _rows) = ratatui::crossterm::terminal::size()?;
+    // Image support: relative image paths resolve against the file's directory.
+    // Remote documents have no local directory, and dumped output can't carry
+    // graphics, so in both cases images show as text.
+    let images = (!cli.no_images).then(|| {
+        let base_path = Path::new(file).parent().unwrap_or(Path::new("")).to_path_buf();
+        let protocol = images::detect_protocol().filter(|_| !is_url(file) && !dump);
+        images::ImageManager::new(base_path, protocol, cell_pixel_size())
+    });
+    let mut pipeline = Pipeline {
+        files,
+        highlighter: &highlighter,
+        options: options.parse,
+        heading_palettes: options.heading_palettes,
+        raw: false,
+        latest: cli.latest,
+        layout: options.layout,
+        images,
+        max_file_bytes,
+        file_starts: Vec::new(),
+        source_lines: Vec::new(),
+    };
 
-    // Flatten blocks into document lines at the current width.
-    let document = layout::flatten(&blocks, cols);
+    // Parse markdown into IR blocks (done once — blocks don't depend on width).
+    let blocks: Rc<[RenderedBlock]> = pipeline.parse((cols, rows)).into();
+
+    // Flatten blocks into document lines at the current width, less the margin
+    // (the sidebar starts hidden), split into `--columns` in the viewer.
+    // `App::column_width()` takes over from here.
+    let [margin] = layout::fit_gutters(cols, [cli.left_margin]);
+    let width = if dump {
+        cols - margin
+    } else {
+        column_width(cols - margin, cli.columns)
+    };
+    let width = options.wrap_width.map_or(width, |max| max.min(width));
+    let mut document = pipeline.flatten(&blocks, width);
 
-    // Sanitize filename for display: strip control characters and ANSI escape
-    // sequences so a crafted filename cannot inject terminal escape codes into
-    // the status bar output.
-    let safe_filename = cli
-        .file
-        .chars()
-        .filter(|c| !c.is_control())
-        .collect::<String>();
+    let render = options.render;
+    if let Some(format) = options.output {
+        document.complete();
+        if let Some(range) = options.select {
+            document.keep_lines(range);
+        }
+        let out = match format {
+            cli::OutputFormat::Ansi => export::document_to_ansi(&document, cols, &render),
+            cli::OutputFormat::Html => export::document_to_html(&document, cols, &render),
+        };
+        print!("{out}");
+        return Ok(());
+    }
 
     // Create the application state.
-    let mut app = App::new(document, safe_filename);
+    let mut app = App::new(document, display_name(file));
+    app.files = pipeline.file_labels();
+    app.left_margin = cli.left_margin;
+    app.scroll_margin = cli.scroll_margin;
+    app.wrap_width = options.wrap_width;
+    app.columns = cli.columns;
+    app.rule = render.rule;
+    app.color = render.color;
+    app.code_bg = render.code_bg;
+    app.code_padding = render.code_padding;
+    app.themes = highlighter.theme_names();
+    app.theme = render.theme;
+    app.theme_pair = [cli.dark_theme.clone(), cli.light_theme.clone()];
+    app.light = options.light;
+    if cli.select_theme {
+        app.open_theme_picker();
+    }
+    // The starting position clamps against the viewport, so size it first.
+    app.status_bar = !cli.no_status;
+    app.viewport_height = usize::from(app.content_rows(rows));
+    app.terminal_width = cols;
+    if let Some(line) = cli.start_at_line {
+        app.scroll_to_line(line);
+    } else if let Some(percent) = cli.start_at_percent {
+        app.scroll_to_percent(percent);
+    }
+    app.status_line = match cli.status_line_mode {
+        cli::StatusLineMode::Top => StatusLineMode::Top,
+        cli::StatusLineMode::Bottom => StatusLineMode::Bottom,
+        cli::StatusLineMode::Range => StatusLineMode::Range,
+    };
 
     // Initialize the terminal (enters raw mode + alternate screen).
     // TERMINAL_ACTIVE must be set immediately after so the panic hook is correct.
@@ -93,51 +219,869 @@ fn main() -> color_eyre::Result<()> {
     TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
 
     // Main event loop.
-    let result = run_event_loop(&mut terminal, &mut app, &blocks);
+    let result = run_event_loop(&mut terminal, &mut app, blocks, &mut pipeline);
 
     // Always restore the terminal, even if the loop returned an error.
     ratatui::restore();
 
+    if result.is_ok() {
+        let position = pipeline.source_position(&app);
+        if cli.print_position_on_quit {
+            println!("{position}");
+        }
+        if let Some(path) = &cli.position_file {
+            fs::write(path, format!("{position}\n"))
+                .map_err(|e| color_eyre::eyre::eyre!("--position-file {path}: {e}"))?;
+        }
+    }
     result
 }
 
+/// Everything needed to (re-)parse the document, owned across the event loop.
+struct Pipeline<'a> {
+    /// The files displayed, one after the other (several with `--concat`).
+    files: Vec<SourceFile<'a>>,
+    highlighter: &'a highlight::Highlighter,
+    options: parser::ParseOptions,
+    /// Heading colors for the dark and light theme, in that order.
+    heading_palettes: [[Color; 6]; 2],
+    /// When true, `parse` returns the markdown source as a highlighted code
+    /// block instead of rendering it (`R` in the viewer).
+    raw: bool,
+    /// Keep only each file's newest changelog entry (`--latest`).
+    latest: bool,
+    layout: layout::LayoutOptions,
+    /// `None` when images are disabled with `--no-images`.
+    images: Option<images::ImageManager>,
+    /// Largest file or download accepted when re-reading the files.
+    max_file_bytes: u64,
+    /// Index of the first block of each file in the last `parse()` result.
+    file_starts: Vec<usize>,
+    /// Source line each block of the last `parse()` result starts on, in
+    /// its file (best effort; see `parser::parse_with_source_lines()`).
+    source_lines: Vec<usize>,
+}
+
+/// A file (or URL, or stdin) and what was last read from it.
+struct SourceFile<'a> {
+    /// Path of the file (`-` for stdin).
+    path: &'a str,
+    /// The markdown last read from `path`, kept to re-highlight it when
+    /// the theme changes.
+    source: String,
+    /// Modification time of `path` when it was last read; `None` for stdin
+    /// and URLs, which are never checked for changes.
+    mtime: Option<SystemTime>,
+}
+
+impl<'a> SourceFile<'a> {
+    /// Reads `path`. The modification time is taken first so a write that
+    /// races the read still marks the document stale.
+    fn read(path: &'a str, max_file_bytes: u64) -> color_eyre::Result<Self> {
+        if path == DEMO_PATH {
+            return Ok(Self { path, source: DEMO.to_string(), mtime: None });
+        }
+        let mtime = file_mtime(path);
+        let source = read_source(path, max_file_bytes)?;
+        Ok(Self { path, source, mtime })
+    }
+}
+
+impl Pipeline<'_> {
+    /// Parses the sources into one document, a rule between each file and
+    /// the next, loading standalone images no larger than the terminal
+    /// content area when image support is enabled.
+    fn parse(&mut self, (cols, rows): (u16, u16)) -> Vec<RenderedBlock> {
+        let mut blocks = Vec::new();
+        self.file_starts.clear();
+        self.source_lines.clear();
+        for (i, file) in self.files.iter().enumerate() {
+            if i > 0 {
+                // The rule between two files counts as the end of the first.
+                blocks.push(RenderedBlock::ThematicBreak);
+                self.source_lines.push(self.source_lines.last().copied().unwrap_or(1));
+            }
+            self.file_starts.push(blocks.len());
+            let source = &file.source;
+            let (mut file_blocks, mut lines) = if self.raw {
+                let raw = parser::parse_as_source(source, self.highlighter, &self.options);
+                let lines = vec![1; raw.len()];
+                (raw, lines)
+            } else {
+                let images = self.images.as_mut().filter(|images| images.protocol().is_some());
+                let images = images.map(|images| (images, (cols, rows.saturating_sub(1))));
+                parser::parse_with_source_lines(source, self.highlighter, &self.options, images)
+            };
+            if self.latest && !self.raw {
+                let entry = parser::latest_entry(&file_blocks);
+                file_blocks.truncate(entry.end);
+                file_blocks.drain(..entry.start);
+                lines.truncate(entry.end);
+                lines.drain(..entry.start);
+            }
+            blocks.extend(file_blocks);
+            self.source_lines.extend(lines);
+        }
+        blocks
+    }
+
+    /// Returns true if a file has been modified (or removed) since it was
+    /// last read. Always false for stdin and URLs.
+    fn file_changed(&self) -> bool {
+        self.files.iter().any(|file| file.mtime.is_some() && file_mtime(file.path) != file.mtime)
+    }
+
+    /// Returns true if the document is read from stdin, which can't be read again.
+    fn reads_stdin(&self) -> bool {
+        self.files.iter().any(|file| file.path == "-")
+    }
+
+    /// Re-reads the files from disk (or fetches the URLs again) and parses them.
+    fn reload(&mut self, size: (u16, u16)) -> color_eyre::Result<Vec<RenderedBlock>> {
+        for file in &mut self.files {
+            *file = SourceFile::read(file.path, self.max_file_bytes)?;
+        }
+        Ok(self.parse(size))
+    }
+
+    /// Returns each file's first block and display name, for
+    /// `App::files`; empty for a single file.
+    fn file_labels(&self) -> Vec<(usize, String)> {
+        if self.files.len() < 2 {
+            return Vec::new();
+        }
+        let names = self.files.iter().map(|file| display_name(file.path));
+        self.file_starts.iter().copied().zip(names).collect()
+    }
+
+    /// Returns where `app`'s top visible line comes from: the line its
+    /// block starts on, as "LINE", or "FILE:LINE" with several files.
+    fn source_position(&self, app: &App) -> String {
+        let block = app.document.anchor(app.scroll_offset).map_or(0, |anchor| anchor.block);
+        let line = self.source_lines.get(block).copied().unwrap_or(1);
+        if self.files.len() < 2 {
+            return line.to_string();
+        }
+        let file = self.file_starts.partition_point(|&start| start <= block).saturating_sub(1);
+        format!("{}:{line}", self.files[file].path)
+    }
+
+    /// Lays `blocks` out at `cols` columns. Large documents are laid out
+    /// lazily, as the viewport reaches them.
+    fn flatten(&self, blocks: &Rc<[RenderedBlock]>, cols: u16) -> layout::PreRenderedDocument {
+        layout::flatten_lazy(Rc::clone(blocks), cols, &self.layout)
+    }
+}
+
+/// Returns the pixel size of one terminal cell, or the default when the
+/// terminal doesn't report its pixel dimensions.
+fn cell_pixel_size() -> (u16, u16) {
+    match ratatui::crossterm::terminal::window_size() {
+        Ok(size) if size.columns > 0 && size.rows > 0 && size.width > 0 => {
+            (size.width / size.columns, size.height / size.rows)
+        }
+        _ => images::DEFAULT_CELL_SIZE,
+    }
+}
+
+/// Returns the size to lay the document out for: the terminal's, from
+/// `terminal_size`, except that a dump gets `DUMP_SIZE` when stdout is not
+/// a terminal or the size can't be read.
+fn layout_size(
+    dump: bool,
+    stdout_is_terminal: bool,
+    terminal_size: impl FnOnce() -> std::io::Result<(u16, u16)>,
+) -> std::io::Result<(u16, u16)> {
+    if dump && !stdout_is_terminal {
+        return Ok(DUMP_SIZE);
+    }
+    match terminal_size() {
+        Err(_) if dump => Ok(DUMP_SIZE),
+        result => result,
+    }
+}
+
+/// Everything the command line and the environment decide about rendering,
+/// resolved once into the option structs the pipeline stages take.
+struct Options {
+    /// `--output`, or ANSI for `--dump`; `None` opens the viewer.
+    output: Option<cli::OutputFormat>,
+    /// Display lines to export (`--select`).
+    select: Option<Range<usize>>,
+    /// Most columns the text is laid out in (`--width`, `MDINK_WIDTH`).
+    wrap_width: Option<u16>,
+    /// Heading colors for the dark and light theme, in that order.
+    heading_palettes: [[Color; 6]; 2],
+    /// True if the starting theme is the light one of `B`'s pair.
+    light: bool,
+    parse: parser::ParseOptions,
+    layout: layout::LayoutOptions,
+    /// Colors, code backgrounds, padding and rules: for exports, and for
+    /// the viewer, which draws the same way.
+    render: export::RenderOptions,
+}
+
+impl Options {
+    /// Resolves `cli`, reading `NO_COLOR`, `MDINK_THEME` and `MDINK_WIDTH`
+    /// through `env`. Flags that only apply to exports are reported when
+    /// given without one; spec strings with bad parts, part by part.
+    fn from_cli(cli: &Cli, env: impl Fn(&str) -> Option<OsString>) -> color_eyre::Result<Self> {
+        // `--dump` is `--output ansi`.
+        let output = cli.output.or(cli.dump.then_some(cli::OutputFormat::Ansi));
+        let dump = output.is_some();
+        let select = match &cli.select {
+            Some(spec) => Some(
+                parse_line_range(spec)
+                    .map_err(|e| color_eyre::eyre::eyre!("--select {spec}: {e}"))?,
+            ),
+            None => None,
+        };
+        if select.is_some() && !dump {
+            eprintln!("mdink: --select only applies with --dump or --output");
+        }
+        if cli.page_break_on_rule && !dump {
+            eprintln!("mdink: --page-break-on-rule only applies with --dump or --output");
+        }
+        // `B` switches between the dark and light theme of the pair; the
+        // heading colors follow unless --heading-colors pins them.
+        let theme = resolve_theme(&resolve_theme_name(
+            cli.theme.as_deref(),
+            env("MDINK_THEME").as_deref(),
+        ));
+        let light = theme == cli.light_theme;
+        let heading_palettes = match &cli.heading_colors {
+            Some(spec) => {
+                let (colors, warnings) = parse_heading_colors(spec);
+                for warning in warnings {
+                    eprintln!("mdink: --heading-colors: {warning}");
+                }
+                [colors; 2]
+            }
+            None => [parser::DEFAULT_HEADING_COLORS, parser::LIGHT_HEADING_COLORS],
+        };
+        let code_style = match &cli.inline_code_style {
+            Some(spec) => {
+                let (style, warnings) = parse_style_spec(spec, parser::default_code_style());
+                for warning in warnings {
+                    eprintln!("mdink: --inline-code-style: {warning}");
+                }
+                style
+            }
+            None => parser::default_code_style(),
+        };
+        let link_style = match &cli.link_style {
+            Some(spec) => {
+                let (style, warnings) = parse_style_spec(spec, parser::default_link_style());
+                for warning in warnings {
+                    eprintln!("mdink: --link-style: {warning}");
+                }
+                style
+            }
+            None => parser::default_link_style(),
+        };
+        let parse = parser::ParseOptions {
+            show_images: !cli.no_images,
+            smart_punctuation: cli.smart_punctuation,
+            frontmatter: match cli.frontmatter {
+                cli::FrontMatterMode::Hide => parser::FrontMatter::Hide,
+                cli::FrontMatterMode::Show => parser::FrontMatter::Show,
+                cli::FrontMatterMode::Title => parser::FrontMatter::Title,
+            },
+            html: match cli.html {
+                cli::HtmlMode::Drop => parser::HtmlMode::Drop,
+                cli::HtmlMode::Render => parser::HtmlMode::Render,
+            },
+            preserve_blank_lines: cli.preserve_blank_lines,
+            theme,
+            jobs: cli.jobs,
+            task_summary: cli.task_summary,
+            raw_ansi: cli.raw_ansi,
+            math: cli.math,
+            repo_url: cli.repo_url.clone(),
+            heading_colors: heading_palettes[usize::from(light)],
+            code_style,
+            link_style,
+            collapse_badges: cli.collapse_badges,
+            tab_width: cli.tab_to_spaces.map(usize::from),
+            comments: match cli.strip_comments {
+                None => highlight::Comments::Keep,
+                Some(cli::StripComments::DropLines) => highlight::Comments::Strip,
+                Some(cli::StripComments::KeepLines) => highlight::Comments::StripKeepLines,
+            },
+            // Exports need every line highlighted anyway.
+            lazy_highlight: !dump,
+            guess_language: cli.guess_language,
+        };
+        let layout = layout::LayoutOptions {
+            align: match cli.default_align {
+                cli::Align::Left => layout::TextAlign::Left,
+                cli::Align::Justify => layout::TextAlign::Justify,
+            },
+            icons: cli.icons,
+            indent_guides: cli.indent_guides,
+            collapse_diagrams: cli.collapse_diagrams,
+            spacing: match cli.spacing {
+                cli::Spacing::Compact => layout::Spacing::Compact,
+                cli::Spacing::Normal => layout::Spacing::Normal,
+                cli::Spacing::Airy => layout::Spacing::Airy,
+            },
+            max_blank_lines: match cli.max_blank_lines {
+                Some(max) => usize::from(max),
+                None if cli.preserve_blank_lines => parser::MAX_BLANK_LINES,
+                None => layout::DEFAULT_MAX_BLANK_LINES,
+            },
+            ..layout::LayoutOptions::default()
+        };
+        let render = export::RenderOptions {
+            theme: parse.theme.clone(),
+            color: resolve_color_mode(cli.color, env("NO_COLOR").as_deref()),
+            code_bg: match cli.code_bg {
+                cli::CodeBackground::Theme => export::CodeBackground::Theme,
+                cli::CodeBackground::Terminal => export::CodeBackground::Terminal,
+                cli::CodeBackground::None => export::CodeBackground::None,
+            },
+            code_padding: usize::from(cli.code_padding),
+            rule: cli.rule_char.clone(),
+            page_break_on_rule: cli.page_break_on_rule,
+            repo_url: cli.repo_url.clone(),
+            ..export::RenderOptions::default()
+        };
+        Ok(Self {
+            output,
+            select,
+            wrap_width: resolve_wrap_width(cli.width, env("MDINK_WIDTH").as_deref()),
+            heading_palettes,
+            light,
+            parse,
+            layout,
+            render,
+        })
+    }
+}
+
+/// Resolves `--color`, falling back to the `NO_COLOR` convention: when the
+/// flag is absent, a non-empty `NO_COLOR` (its value doesn't matter)
+/// turns color off.
+fn resolve_color_mode(flag: Option<cli::ColorMode>, no_color: Option<&OsStr>) -> ColorMode {
+    match flag {
+        Some(cli::ColorMode::Full) => ColorMode::Full,
+        Some(cli::ColorMode::None) => ColorMode::None,
+        None if no_color.is_some_and(|value| !value.is_empty()) => ColorMode::None,
+        None => ColorMode::Full,
+    }
+}
+
+/// Resolves the `--theme` name, falling back to `MDINK_THEME` when the flag
+/// is absent and to `DEFAULT_THEME` when neither is set (or the variable is
+/// empty).
+fn resolve_theme_name(flag: Option<&str>, env: Option<&OsStr>) -> String {
+    match flag {
+        Some(name) => name.to_string(),
+        None => env
+            .filter(|value| !value.is_empty())
+            .map_or(highlight::DEFAULT_THEME.into(), |value| value.to_string_lossy().into()),
+    }
+}
+
+/// Resolves `--width`, falling back to `MDINK_WIDTH` when the flag is
+/// absent; `None` means the full text width. Widths are raised to
+/// `MIN_WRAP_WIDTH`, as with `:set width`, and a variable that isn't a
+/// number is reported and ignored.
+fn resolve_wrap_width(flag: Option<u16>, env: Option<&OsStr>) -> Option<u16> {
+    let width = match flag {
+        Some(width) => width,
+        None => {
+            let value = env.filter(|value| !value.is_empty())?.to_string_lossy();
+            match value.trim().parse::<u16>() {
+                Ok(width) if width > 0 => width,
+                _ => {
+                    eprintln!("mdink: MDINK_WIDTH={value}: not a width, ignored");
+                    return None;
+                }
+            }
+        }
+    };
+    Some(width.max(MIN_WRAP_WIDTH))
+}
+
+/// Parses a `--select` range of 1-based display lines, "START-END" with
+/// both ends included or a single "N", into 0-based line indexes.
+fn parse_line_range(spec: &str) -> Result<Range<usize>, String> {
+    let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+    let number = |s: &str| {
+        s.trim().parse::<usize>().map_err(|_| format!("expected START-END, got {spec:?}"))
+    };
+    let (start, end) = (number(start)?, number(end)?);
+    if start > end {
+        return Err(format!("start {start} is after end {end}"));
+    }
+    // Line 0 doesn't exist; treat it as line 1.
+    Ok(start.saturating_sub(1)..end)
+}
+
+/// Parses a `--heading-colors` spec (`h1=red,h2=#5f87ff,...`) over the
+/// default colors.
+///
+/// Entries that don't parse are skipped and described in the returned
+/// warnings, so one typo doesn't cost the rest of the spec.
+fn parse_heading_colors(spec: &str) -> ([Color; 6], Vec<String>) {
+    let mut colors = parser::DEFAULT_HEADING_COLORS;
+    let mut warnings = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let Some((level, color)) = entry.split_once('=') else {
+            warnings.push(format!("expected LEVEL=COLOR, got \"{entry}\""));
+            continue;
+        };
+        let level = level.trim().trim_start_matches(['h', 'H']);
+        let index = level.parse::<usize>().ok().and_then(|level| level.checked_sub(1));
+        let Some(slot) = index.and_then(|index| colors.get_mut(index)) else {
+            warnings.push(format!("unknown heading level in \"{entry}\" (use h1–h6)"));
+            continue;
+        };
+        match color.trim().parse::<Color>() {
+            Ok(color) => *slot = color,
+            Err(_) => warnings.push(format!("unknown color \"{}\"", color.trim())),
+        }
+    }
+    (colors, warnings)
+}
+
+/// Parses an `--inline-code-style` or `--link-style` spec (`fg=COLOR`,
+/// `bg=COLOR`, `bold`, `no-italic`, ...) over `base`, the option's default
+/// style. `fg=reset` and `bg=reset` drop a color.
+///
+/// As with `parse_heading_colors`, bad entries are skipped with a warning.
+fn parse_style_spec(spec: &str, base: Style) -> (Style, Vec<String>) {
+    let mut style = base;
+    let mut warnings = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        if let Some((key, color)) = entry.split_once('=') {
+            let Ok(color) = color.trim().parse::<Color>() else {
+                warnings.push(format!("unknown color \"{}\"", color.trim()));
+                continue;
+            };
+            match key.trim() {
+                "fg" => style.fg = Some(color),
+                "bg" => style.bg = Some(color),
+                key => warnings.push(format!("unknown key \"{key}\" (use fg or bg)")),
+            }
+            continue;
+        }
+        let (enable, name) = match entry.strip_prefix("no-") {
+            Some(name) => (false, name),
+            None => (true, entry),
+        };
+        let modifier = match name {
+            "bold" => Modifier::BOLD,
+            "italic" => Modifier::ITALIC,
+            "underline" => Modifier::UNDERLINED,
+            "dim" => Modifier::DIM,
+            _ => {
+                warnings.push(format!("unknown attribute \"{entry}\""));
+                continue;
+            }
+        };
+        style = if enable {
+            style.add_modifier(modifier)
+        } else {
+            style.remove_modifier(modifier)
+        };
+    }
+    (style, warnings)
+}
+
+/// Resolves `--theme`: `auto` picks a theme for the terminal's background,
+/// any other value is a syntect theme name.
+fn resolve_theme(theme: &str) -> String {
+    if theme == "auto" {
+        highlight::theme_for_background(query_terminal_background()).to_string()
+    } else {
+        theme.to_string()
+    }
+}
+
+/// Asks the terminal for its background color with an OSC 11 query.
+///
+/// Runs before `ratatui::init()`, in raw mode so the reply isn't echoed.
+/// Terminals that don't support the query never answer, so the reply is
+/// awaited for at most `BACKGROUND_QUERY_TIMEOUT`; `None` means no usable
+/// answer (or stdout is not a terminal) and the caller falls back to dark.
+#[cfg(unix)]
+fn query_terminal_background() -> Option<(u8, u8, u8)> {
+    use std::io::{IsTerminal, Read};
+    use std::os::fd::AsRawFd;
+
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let mut tty = fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    ratatui::crossterm::terminal::enable_raw_mode().ok()?;
+
+    let mut read_reply = || -> Option<String> {
+        tty.write_all(b"\x1b]11;?\x07").ok()?;
+        tty.flush().ok()?;
+        let deadline = Instant::now() + BACKGROUND_QUERY_TIMEOUT;
+        let mut reply = Vec::new();
+        while !(reply.ends_with(b"\x07") || reply.ends_with(b"\x1b\\")) {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            let mut pollfd = libc::pollfd {
+                fd: tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = remaining.as_millis() as libc::c_int;
+            // SAFETY: `pollfd` is a valid, initialised pollfd for the whole call.
+            let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+            if ready <= 0 {
+                return None;
+            }
+            let mut buf = [0u8; 64];
+            match tty.read(&mut buf) {
+                Ok(0) | Err(_) => return None,
+                Ok(n) => reply.extend_from_slice(&buf[..n]),
+            }
+        }
+        Some(String::from_utf8_lossy(&reply).into_owned())
+    };
+    let reply = read_reply();
+
+    let _ = ratatui::crossterm::terminal::disable_raw_mode();
+    highlight::parse_osc11_reply(&reply?)
+}
+
+/// Background queries need `poll(2)` on the tty; elsewhere assume dark.
+#[cfg(not(unix))]
+fn query_terminal_background() -> Option<(u8, u8, u8)> {
+    None
+}
+
+/// Returns the modification time of a file on disk, or `None` for stdin,
+/// URLs and files whose metadata can't be read.
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    if path == "-" || is_url(path) {
+        return None;
+    }
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Returns true if `path` names an `http(s)://` URL rather than a file.
+fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// Reads a markdown file from disk (or fetches it when `path` is a URL,
+/// or reads stdin when it is `-`), enforcing the file size guard.
+///
+/// Guards against OOM by rejecting files larger than `max_bytes` before
+/// reading them into memory.
+fn read_source(path: &str, max_bytes: u64) -> color_eyre::Result<String> {
+    if is_url(path) {
+        return fetch_source(path, max_bytes);
+    }
+    if path == "-" {
+        return read_limited(std::io::stdin().lock(), "stdin", max_bytes);
+    }
+    let file_size = fs::metadata(path)?.len();
+    if file_size > max_bytes {
+        return Err(color_eyre::eyre::eyre!(
+            "{}: file too large ({} bytes; limit is {} bytes)",
+            path,
+            file_size,
+            max_bytes
+        ));
+    }
+
+    // Read raw bytes so that non-UTF-8 input can be decoded lossily instead
+    // of refusing to open.
+    let bytes = fs::read(path)?;
+    check_content(&bytes, path)?;
+    Ok(decode_source(bytes, path))
+}
+
+/// Downloads a markdown document with a blocking GET.
+///
+/// The body is read with a `max_bytes` limit, so an oversized (or endless)
+/// response is rejected without buffering more than the cap.
+/// HTTP error statuses are reported as errors.
+fn fetch_source(url: &str, max_bytes: u64) -> color_eyre::Result<String> {
+    let mut response = ureq::get(url)
+        .call()
+        .map_err(|e| color_eyre::eyre::eyre!("{url}: {e}"))?;
+    let bytes = response
+        .body_mut()
+        .with_config()
+        .limit(max_bytes)
+        .read_to_vec()
+        .map_err(|e| match e {
+            ureq::Error::BodyExceedsLimit(_) => color_eyre::eyre::eyre!(
+                "{url}: download too large (limit is {max_bytes} bytes)"
+            ),
+            e => color_eyre::eyre::eyre!("{url}: {e}"),
+        })?;
+    check_content(&bytes, url)?;
+    Ok(decode_source(bytes, url))
+}
+
+/// Reads a document of unknown size, such as stdin, from `reader`. As
+/// with a download, no more than `max_bytes` are buffered: longer input is
+/// rejected.
+fn read_limited(
+    reader: impl std::io::Read,
+    name: &str,
+    max_bytes: u64,
+) -> color_eyre::Result<String> {
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    reader.take(max_bytes.saturating_add(1)).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_bytes {
+        return Err(color_eyre::eyre::eyre!("{name}: input too large (limit is {max_bytes} bytes)"));
+    }
+    check_content(&bytes, name)?;
+    Ok(decode_source(bytes, name))
+}
+
+/// Rejects input that is clearly not markdown, such as an image, PDF or
+/// executable opened by mistake (`mdink image.png`), rather than rendering
+/// garbage. HTML is still rendered, after a warning.
+fn check_content(bytes: &[u8], name: &str) -> color_eyre::Result<()> {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG", "a PNG image"),
+        (b"GIF8", "a GIF image"),
+        (b"\xFF\xD8\xFF", "a JPEG image"),
+        (b"%PDF-", "a PDF document"),
+        (b"PK\x03\x04", "a ZIP archive"),
+        (b"\x1F\x8B", "a gzip archive"),
+        (b"\x7FELF", "an executable"),
+    ];
+    if let Some((_, kind)) = MAGIC.iter().find(|(magic, _)| bytes.starts_with(magic)) {
+        return Err(color_eyre::eyre::eyre!("{name}: looks like {kind}, not markdown"));
+    }
+    // Text has no NUL bytes and few other control characters. Only the
+    // start is sampled, so a huge file is cheap to check.
+    let sample = &bytes[..bytes.len().min(8192)];
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0C | 0x1B))
+        .count();
+    if sample.contains(&0) || control * 10 > sample.len() {
+        return Err(color_eyre::eyre::eyre!("{name}: looks like binary data, not markdown"));
+    }
+    let start = String::from_utf8_lossy(&sample[..sample.len().min(256)]).to_lowercase();
+    let start = start.trim_start_matches('\u{FEFF}').trim_start();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        eprintln!("mdink: warning: {name} looks like an HTML page, not markdown");
+    }
+    Ok(())
+}
+
+/// Prints a warning for each code block of `file` indented with both tabs
+/// and spaces (`--warn-mixed-indent`).
+fn warn_mixed_indent(file: &SourceFile) {
+    for (line, language) in parser::mixed_indent_code_blocks(&file.source) {
+        let block = if language.is_empty() { "code".to_string() } else { language };
+        eprintln!(
+            "mdink: warning: {}:{line}: {block} block mixes tabs and spaces in its indentation",
+            display_name(file.path)
+        );
+    }
+}
+
+/// Decodes raw file bytes into a markdown source string.
+///
+/// Strips a leading UTF-8 byte order mark, which would otherwise end up
+/// as an invisible character at the start of the first block. Input that
+/// is not valid UTF-8 (commonly Latin-1 or Windows-1252) is decoded lossily
+/// with a one-line warning on stderr rather than rejected outright. Lone
+/// `\r` line endings (classic Mac OS) become `\n`.
+fn decode_source(bytes: Vec<u8>, name: &str) -> String {
+    let source = match String::from_utf8(bytes) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("mdink: warning: {name} is not valid UTF-8; invalid bytes replaced");
+            String::from_utf8_lossy(err.as_bytes()).into_owned()
+        }
+    };
+    let source = match source.strip_prefix('\u{FEFF}') {
+        Some(stripped) => stripped.to_string(),
+        None => source,
+    };
+    normalize_line_endings(source)
+}
+
+/// Turns each `\r` not followed by `\n` into `\n`. `\r\n` is left alone:
+/// pulldown-cmark and the highlighter already handle it.
+fn normalize_line_endings(source: String) -> String {
+    if !source.contains('\r') {
+        return source;
+    }
+    let mut normalized = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' && chars.peek() != Some(&'\n') {
+            normalized.push('\n');
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
 /// Runs the TUI event loop until the user quits or an error occurs.
 ///
 /// Separated from `main()` so that `ratatui::restore()` always runs
-/// regardless of how this function exits. Takes a reference to the
-/// parsed blocks so resize can re-flatten without re-parsing.
+/// regardless of how this function exits. Owns the parsed blocks so resize
+/// can re-flatten without re-parsing, and editor round trips can replace them.
 fn run_event_loop(
     terminal: &mut ratatui::DefaultTerminal,
     app: &mut App,
-    blocks: &[RenderedBlock],
+    mut blocks: Rc<[RenderedBlock]>,
+    pipeline: &mut Pipeline,
 ) -> color_eyre::Result<()> {
+    // Opened lazily on the first copy and kept alive afterwards: on X11 the
+    // clipboard contents are served by the owning process, so dropping the
+    // handle right after `set_text` would lose the copied text.
+    let mut clipboard: Option<arboard::Clipboard> = None;
+    // Images drawn in the previous frame; graphics are only re-sent when
+    // this changes.
+    let mut drawn_images: Vec<renderer::ImagePlacement> = Vec::new();
+    let mut last_draw = Instant::now();
+
     loop {
         // Update viewport height from current terminal size.
-        app.viewport_height = terminal.size()?.height.saturating_sub(1) as usize;
+        let size = terminal.size()?;
+        app.viewport_height = usize::from(app.content_rows(size.height));
+        app.terminal_width = size.width;
+
+        // Lay out as much of a lazily flattened document as the viewport
+        // shows. Completing the layout can shrink the height estimate.
+        app.document.ensure_lines(app.scroll_offset + app.screen_lines());
+        app.clamp_scroll();
+        // Highlight the parts of long code blocks left for later that are
+        // on screen, and a screen further so scrolling down finds them done.
+        let ahead = app.scroll_offset + 2 * app.screen_lines();
+        app.document.highlight_code(app.scroll_offset..ahead);
 
-        // Draw the current frame.
-        terminal.draw(|frame| renderer::draw(frame, app))?;
+        // While more input is already queued (a held `j`), a frame would be
+        // stale at once: apply the burst first and draw where it ends, but
+        // at least every `MAX_BATCH_DELAY` so a steady stream still shows.
+        let batching = last_draw.elapsed() < MAX_BATCH_DELAY && event::poll(Duration::ZERO)?;
+        if !batching {
+            draw_frame(terminal, app, pipeline, &mut drawn_images, size)?;
+            last_draw = Instant::now();
 
-        // Block until the next event.
+            // Block until the next event, waking up periodically to check the
+            // file on disk. A change only marks the view stale; `r` reloads.
+            let mut marked_stale = false;
+            while !marked_stale && !event::poll(MTIME_POLL_INTERVAL)? {
+                if !app.stale && pipeline.file_changed() {
+                    app.stale = true;
+                    marked_stale = true;
+                }
+            }
+            if marked_stale {
+                // Redraw with the `[modified]` marker.
+                continue;
+            }
+        }
         let event = event::read()?;
 
         match event {
             Event::Key(key) => {
+                let had_outline = app.outline.is_some();
+                let width = app.column_width(size.width);
                 app.handle_key(key);
+                // The sidebar narrows the content area, `:set width` the
+                // text, `w` switches between wrapped and clipped prose and
+                // `x` collapses or expands a diagram: any of these means a
+                // re-layout.
+                if app.outline.is_some() != had_outline
+                    || app.column_width(size.width) != width
+                    || app.wrap != pipeline.layout.wrap
+                    || app.flipped_diagrams != pipeline.layout.flipped_diagrams
+                {
+                    pipeline.layout.wrap = app.wrap;
+                    pipeline.layout.flipped_diagrams = app.flipped_diagrams.clone();
+                    relayout(app, pipeline, &blocks, size.width);
+                }
+                if app.raw != pipeline.raw {
+                    // Keep roughly the same place in the document across views.
+                    let percent = if app.max_scroll() == 0 { 0 } else { app.scroll_percent() };
+                    pipeline.raw = app.raw;
+                    blocks = pipeline.parse((size.width, size.height)).into();
+                    relayout(app, pipeline, &blocks, size.width);
+                    app.scroll_to_percent(u8::try_from(percent).unwrap_or(100));
+                }
             }
             Event::Resize(cols, _rows) => {
-                // Re-flatten at the new width (blocks are unchanged).
-                app.document = layout::flatten(blocks, cols);
-                // Clamp scroll offset to the new max.
-                let max = app.max_scroll();
-                if app.scroll_offset > max {
-                    app.scroll_offset = max;
+                // Re-flatten at the new width (blocks are unchanged). Wrapping
+                // changes, so scroll back to the spot that was at the top.
+                let anchor = app.document.anchor(app.scroll_offset);
+                relayout(app, pipeline, &blocks, cols);
+                if let Some(anchor) = anchor {
+                    app.scroll_offset = app.document.anchor_line(anchor);
+                    app.clamp_scroll();
                 }
             }
             // Ignore mouse, focus, and paste events.
             _ => {}
         }
 
+        if app.edit_requested {
+            app.edit_requested = false;
+            // Stdin, URLs and the demo have no file on disk to hand to the editor.
+            let on_disk = |path| path != "-" && path != DEMO_PATH && !is_url(path);
+            if !pipeline.files.iter().all(|file| on_disk(file.path)) {
+                app.status_message = Some("nothing to edit: not a file on disk".to_string());
+            } else {
+                // The terminal is re-initialized, so any drawn images are gone.
+                drawn_images.clear();
+                // A failed edit keeps the old files and blocks on screen.
+                match edit_and_reparse(terminal, pipeline) {
+                    Ok(edited) => {
+                        blocks = edited.into();
+                        relayout(app, pipeline, &blocks, terminal.size()?.width);
+                        app.stale = false;
+                    }
+                    Err(e) => app.status_message = Some(format!("edit failed: {e}")),
+                }
+            }
+        }
+
+        if app.reload_requested {
+            app.reload_requested = false;
+            // Stdin was consumed at startup; files are re-read, URLs re-fetched.
+            let message = if pipeline.reads_stdin() {
+                "nothing to reload: reading from stdin".to_string()
+            } else {
+                match pipeline.reload((size.width, size.height)) {
+                    Ok(reloaded) => {
+                        blocks = reloaded.into();
+                        relayout(app, pipeline, &blocks, size.width);
+                        app.stale = false;
+                        "reloaded".to_string()
+                    }
+                    Err(e) => format!("reload failed: {e}"),
+                }
+            };
+            app.status_message = Some(message);
+        }
+
+        if let Some(theme) = app.theme_request.take() {
+            // Re-highlight with the picked (or restored) theme.
+            pipeline.options.theme = theme;
+            pipeline.options.heading_colors = pipeline.heading_palettes[usize::from(app.light)];
+            blocks = pipeline.parse((size.width, size.height)).into();
+            relayout(app, pipeline, &blocks, size.width);
+        }
+
+        if let Some(scope) = app.copy_request.take() {
+            if scope == CopyScope::Document {
+                app.document.complete();
+            }
+            app.status_message = Some(copy_to_clipboard(&mut clipboard, app, &blocks, scope));
+        }
+
         if app.quit {
             break;
         }
@@ -145,3 +1089,189 @@ fn run_event_loop(
 
     Ok(())
 }
+
+/// Draws `app` in a frame `size` large, with its images and reference
+/// links. Graphics are only re-sent when the placements differ from
+/// `drawn_images`, which is updated.
+fn draw_frame(
+    terminal: &mut ratatui::DefaultTerminal,
+    app: &App,
+    pipeline: &mut Pipeline,
+    drawn_images: &mut Vec<renderer::ImagePlacement>,
+    size: ratatui::layout::Size,
+) -> color_eyre::Result<()> {
+    let placements = renderer::image_placements(app, Rect::from((Default::default(), size)));
+    let images_changed = placements != *drawn_images;
+    if images_changed && !drawn_images.is_empty() {
+        if let Some(images) = pipeline.images.as_ref() {
+            match images.clear_sequence() {
+                Some(seq) => write!(terminal.backend_mut(), "{seq}")?,
+                // iTerm2 images live in the cells: force a full redraw to erase them.
+                None => terminal.clear()?,
+            }
+        }
+    }
+
+    // Draw the current frame.
+    terminal.draw(|frame| renderer::draw(frame, app))?;
+
+    if images_changed {
+        if let Some(images) = pipeline.images.as_mut() {
+            draw_images(terminal, images, &placements)?;
+        }
+        *drawn_images = placements;
+    }
+    draw_links(terminal, app, Rect::from((Default::default(), size)))
+}
+
+/// Returns `path` fit for the status bar: control characters are stripped so
+/// a crafted filename cannot inject terminal escape codes into it.
+fn display_name(path: &str) -> String {
+    path.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Re-flattens `blocks` (perhaps just parsed) for a terminal `cols` wide
+/// and clamps the scroll.
+fn relayout(app: &mut App, pipeline: &Pipeline, blocks: &Rc<[RenderedBlock]>, cols: u16) {
+    app.document = pipeline.flatten(blocks, app.column_width(cols));
+    app.files = pipeline.file_labels();
+    // The outline lists every heading, which needs the complete layout.
+    if app.outline.is_some() {
+        app.document.complete();
+    }
+    app.clamp_scroll();
+}
+
+/// Writes the graphics escapes for each placement directly to the terminal.
+fn draw_images(
+    terminal: &mut ratatui::DefaultTerminal,
+    images: &mut images::ImageManager,
+    placements: &[renderer::ImagePlacement],
+) -> color_eyre::Result<()> {
+    use ratatui::crossterm::{cursor::MoveTo, queue};
+
+    let backend = terminal.backend_mut();
+    for p in placements {
+        if let Some(seq) = images.draw_sequence(p.protocol_index, p.width, p.height) {
+            queue!(backend, MoveTo(p.x, p.y))?;
+            write!(backend, "{seq}")?;
+        }
+    }
+    backend.flush()?;
+    Ok(())
+}
+
+/// Rewrites the visible issue and commit references as OSC 8 hyperlinks.
+/// The text is redrawn with the same style, so only the link is added.
+fn draw_links(
+    terminal: &mut ratatui::DefaultTerminal,
+    app: &App,
+    area: Rect,
+) -> color_eyre::Result<()> {
+    use ratatui::crossterm::{cursor::MoveTo, queue};
+
+    let links = renderer::link_placements(app, area);
+    if links.is_empty() {
+        return Ok(());
+    }
+    let backend = terminal.backend_mut();
+    for link in links {
+        queue!(backend, MoveTo(link.x, link.y))?;
+        let text = export::hyperlink(&link.url, &link.text, link.style, app.color);
+        write!(backend, "{text}")?;
+    }
+    backend.flush()?;
+    Ok(())
+}
+
+/// Copies the lines covered by `scope` to the system clipboard.
+/// `Reflowed` copies are built from `blocks` instead of the laid-out lines.
+///
+/// Returns the status bar message describing the outcome. Clipboard
+/// failures (no display server, headless SSH session) are reported in the
+/// message rather than propagated, so copying can never crash the viewer.
+fn copy_to_clipboard(
+    clipboard: &mut Option<arboard::Clipboard>,
+    app: &App,
+    blocks: &[RenderedBlock],
+    scope: CopyScope,
+) -> String {
+    let text = match scope {
+        CopyScope::Reflowed => layout::reflowed_text(blocks),
+        scope => app.copy_text(scope),
+    };
+    let line_count = text.lines().count();
+
+    if clipboard.is_none() {
+        match arboard::Clipboard::new() {
+            Ok(handle) => *clipboard = Some(handle),
+            Err(e) => return format!("clipboard unavailable: {e}"),
+        }
+    }
+    let Some(handle) = clipboard.as_mut() else {
+        return "clipboard unavailable".to_string();
+    };
+
+    match handle.set_text(text) {
+        Ok(()) => format!("copied {line_count} lines"),
+        Err(e) => format!("copy failed: {e}"),
+    }
+}
+
+/// Suspends the TUI, runs `$EDITOR` on the files (all of them with
+/// `--concat`), and re-parses them on exit.
+///
+/// The terminal is restored before the editor starts (so it gets a normal
+/// screen and cooked mode) and re-initialized afterwards. `TERMINAL_ACTIVE`
+/// tracks both transitions so the panic hook stays correct throughout.
+/// Falls back to `vi` when `$EDITOR` is unset.
+///
+/// If the editor can't be launched, fails, or a file can't be re-read
+/// afterwards, returns the error with the terminal back up and
+/// `pipeline.files` as they were.
+fn edit_and_reparse(
+    terminal: &mut ratatui::DefaultTerminal,
+    pipeline: &mut Pipeline,
+) -> color_eyre::Result<Vec<RenderedBlock>> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+
+    ratatui::restore();
+    TERMINAL_ACTIVE.store(false, Ordering::SeqCst);
+
+    let status = editor_command(&editor, pipeline.files.iter().map(|file| file.path)).status();
+    // Re-read before re-entering the alternate screen so any decode warning
+    // lands on the normal terminal.
+    let files = match status {
+        Err(e) => Err(color_eyre::eyre::eyre!("failed to launch editor `{editor}`: {e}")),
+        Ok(status) if !status.success() => {
+            Err(color_eyre::eyre::eyre!("editor `{editor}` failed ({status})"))
+        }
+        Ok(_) => {
+            let max_file_bytes = pipeline.max_file_bytes;
+            pipeline.files.iter().map(|file| SourceFile::read(file.path, max_file_bytes)).collect()
+        }
+    };
+
+    *terminal = ratatui::init();
+    TERMINAL_ACTIVE.store(true, Ordering::SeqCst);
+
+    pipeline.files = files?;
+    let size = terminal.size()?;
+    Ok(pipeline.parse((size.width, size.height)))
+}
+
+/// Returns the command that opens `paths` in `editor`, a `$EDITOR` value.
+/// As git does, it runs the value through `sh`, so it may carry arguments
+/// and quoting (`code --wait`, `"/opt/My Editor/edit"`).
+fn editor_command<'a>(
+    editor: &str,
+    paths: impl IntoIterator<Item = &'a str>,
+) -> std::process::Command {
+    let mut command = std::process::Command::new("sh");
+    command.arg("-c").arg(format!("{editor} \"$@\"")).arg(editor).args(paths);
+    command
+}
+
+#[cfg(test)]
+#[path = "main_tests.rs"]
+mod tests;
@@ -0,0 +1,441 @@
+    use super::*;
+
+    #[test]
+    fn test_main_decode_source_plain_utf8_unchanged() {
+        let source = decode_source(b"# Hello".to_vec(), "test.md");
+        assert_eq!(source, "# Hello");
+    }
+
+    #[test]
+    fn test_main_decode_source_strips_bom() {
+        let source = decode_source(b"\xEF\xBB\xBF# Hello".to_vec(), "test.md");
+        assert_eq!(source, "# Hello");
+    }
+
+    #[test]
+    fn test_main_decode_source_latin1_decodes_lossily() {
+        // "caf\xE9" is "café" in Latin-1 — invalid as UTF-8.
+        let source = decode_source(b"caf\xE9 au lait".to_vec(), "test.md");
+        assert_eq!(source, "caf\u{FFFD} au lait");
+    }
+
+    #[test]
+    fn test_main_decode_source_normalizes_lone_carriage_returns() {
+        let source = decode_source(b"# Title\r\rFirst\r\rSecond\r\n".to_vec(), "mac.md");
+        assert_eq!(source, "# Title\n\nFirst\n\nSecond\r\n");
+        let highlighter = highlight::Highlighter::new();
+        let blocks = parser::parse(&source, &highlighter);
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(blocks[0], RenderedBlock::Heading { .. }));
+    }
+
+    #[test]
+    fn test_main_decode_source_bom_heading_parses_as_heading() {
+        let source = decode_source(b"\xEF\xBB\xBF# Title".to_vec(), "test.md");
+        let highlighter = highlight::Highlighter::new();
+        let blocks = parser::parse(&source, &highlighter);
+        match &blocks[0] {
+            RenderedBlock::Heading { content, .. } => assert_eq!(content[0].text, "Title"),
+            _ => panic!("expected Heading block"),
+        }
+    }
+
+    #[test]
+    fn test_main_is_url_detects_http_schemes() {
+        assert!(is_url("https://raw.githubusercontent.com/o/r/main/README.md"));
+        assert!(is_url("http://localhost:8000/doc.md"));
+        assert!(!is_url("README.md"));
+        assert!(!is_url("-"));
+        assert!(!is_url("docs/https:/not-a-url.md"));
+    }
+
+    #[test]
+    fn test_main_read_source_url_network_error_is_reported() {
+        // Port 9 (discard) on localhost is closed in test environments.
+        let err = read_source("http://127.0.0.1:9/doc.md", DEFAULT_MAX_FILE_BYTES).unwrap_err();
+        assert!(err.to_string().starts_with("http://127.0.0.1:9/doc.md: "), "{err}");
+    }
+
+    #[test]
+    fn test_main_read_source_enforces_max_file_bytes() {
+        let err = read_source("Cargo.toml", 10).unwrap_err().to_string();
+        assert!(err.starts_with("Cargo.toml: file too large ("), "{err}");
+        assert!(err.ends_with("; limit is 10 bytes)"), "{err}");
+        assert!(read_source("Cargo.toml", DEFAULT_MAX_FILE_BYTES).is_ok());
+    }
+
+    #[test]
+    fn test_main_read_limited_enforces_max_file_bytes() {
+        let source = read_limited("# Title\n".as_bytes(), "stdin", 8).unwrap();
+        assert_eq!(source, "# Title\n");
+        let err = read_limited("# Title\n".as_bytes(), "stdin", 7).unwrap_err().to_string();
+        assert_eq!(err, "stdin: input too large (limit is 7 bytes)");
+        let err = read_limited(&b"\x89PNG\r\n\x1a\n"[..], "stdin", 100).unwrap_err().to_string();
+        assert!(err.starts_with("stdin: "), "{err}");
+    }
+
+    #[test]
+    fn test_main_file_mtime_skips_stdin_and_urls() {
+        assert_eq!(file_mtime("-"), None);
+        assert_eq!(file_mtime("https://example.com/README.md"), None);
+        assert!(file_mtime("Cargo.toml").is_some());
+    }
+
+    #[test]
+    fn test_main_pipeline_notices_modified_file_and_reloads() {
+        let path = std::env::temp_dir().join(format!("mdink-stale-{}.md", std::process::id()));
+        fs::write(&path, "# Before").unwrap();
+        let path_str = path.to_str().unwrap();
+        let highlighter = highlight::Highlighter::new();
+        let mut pipeline = Pipeline {
+            files: vec![SourceFile {
+                path: path_str,
+                source: String::new(),
+                mtime: file_mtime(path_str),
+            }],
+            highlighter: &highlighter,
+            options: parser::ParseOptions::default(),
+            heading_palettes: [parser::DEFAULT_HEADING_COLORS; 2],
+            raw: false,
+            latest: false,
+            layout: layout::LayoutOptions::default(),
+            images: None,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            file_starts: Vec::new(),
+            source_lines: Vec::new(),
+        };
+        assert!(!pipeline.file_changed());
+
+        fs::write(&path, "# After").unwrap();
+        // Coarse filesystem timestamps may not tick between writes: move it explicitly.
+        let later = SystemTime::now() + Duration::from_secs(10);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(pipeline.file_changed());
+
+        let blocks = pipeline.reload((80, 24)).unwrap();
+        assert!(!pipeline.file_changed());
+        match &blocks[0] {
+            RenderedBlock::Heading { content, .. } => assert_eq!(content[0].text, "After"),
+            _ => panic!("expected Heading block"),
+        }
+        fs::remove_file(&path).unwrap();
+        assert!(pipeline.file_changed(), "a removed file counts as changed");
+    }
+
+    #[test]
+    fn test_main_concat_joins_files_with_rules_and_names_the_top_one() {
+        let highlighter = highlight::Highlighter::new();
+        let file =
+            |path, source: &str| SourceFile { path, source: source.to_string(), mtime: None };
+        let mut pipeline = Pipeline {
+            files: vec![file("a.md", "# A\n\ntext"), file("b.md", "# B"), file("c.md", "c")],
+            highlighter: &highlighter,
+            options: parser::ParseOptions::default(),
+            heading_palettes: [parser::DEFAULT_HEADING_COLORS; 2],
+            raw: false,
+            latest: false,
+            layout: layout::LayoutOptions::default(),
+            images: None,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            file_starts: Vec::new(),
+            source_lines: Vec::new(),
+        };
+        let blocks: Rc<[RenderedBlock]> = pipeline.parse((80, 24)).into();
+        assert_eq!(blocks.len(), 6);
+        assert!(matches!(blocks[2], RenderedBlock::ThematicBreak));
+        assert!(matches!(blocks[4], RenderedBlock::ThematicBreak));
+        assert_eq!(pipeline.file_starts, [0, 3, 5]);
+
+        let mut app = App::new(pipeline.flatten(&blocks, 80), "a.md".to_string());
+        relayout(&mut app, &pipeline, &blocks, 80);
+        app.viewport_height = 2;
+        assert_eq!(app.current_file(), "a.md");
+        // "A", blank, "text", blank, rule, blank, then "B".
+        app.scroll_offset = 6;
+        assert_eq!(app.current_file(), "b.md");
+        app.scroll_to_bottom();
+        assert_eq!(app.current_file(), "c.md");
+    }
+
+    #[test]
+    fn test_main_demo_reads_the_built_in_document() {
+        let demo = SourceFile::read(DEMO_PATH, DEFAULT_MAX_FILE_BYTES).unwrap();
+        assert_eq!(demo.source, DEMO);
+        assert_eq!(demo.mtime, None);
+        let blocks = parser::parse(DEMO, &highlight::Highlighter::new());
+        let has = |wanted: fn(&RenderedBlock) -> bool| blocks.iter().any(wanted);
+        assert!(has(|b| matches!(b, RenderedBlock::Heading { level: 6, .. })));
+        assert!(has(|b| matches!(b, RenderedBlock::List { .. })));
+        assert!(has(|b| matches!(b, RenderedBlock::BlockQuote { .. })));
+        assert!(has(
+            |b| matches!(b, RenderedBlock::CodeBlock { language, .. } if language == "rust")
+        ));
+        assert!(has(|b| matches!(b, RenderedBlock::ThematicBreak)));
+    }
+
+    #[test]
+    fn test_main_source_position_maps_the_top_block_to_its_line() {
+        let highlighter = highlight::Highlighter::new();
+        let file =
+            |path, source: &str| SourceFile { path, source: source.to_string(), mtime: None };
+        let mut pipeline = Pipeline {
+            files: vec![file("a.md", "# A\n\n\n\ntext\n")],
+            highlighter: &highlighter,
+            options: parser::ParseOptions::default(),
+            heading_palettes: [parser::DEFAULT_HEADING_COLORS; 2],
+            raw: false,
+            latest: false,
+            layout: layout::LayoutOptions::default(),
+            images: None,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            file_starts: Vec::new(),
+            source_lines: Vec::new(),
+        };
+        let blocks: Rc<[RenderedBlock]> = pipeline.parse((80, 24)).into();
+        let mut app = App::new(pipeline.flatten(&blocks, 80), "a.md".to_string());
+        assert_eq!(pipeline.source_position(&app), "1");
+        // "A", blank, then "text" from line 5.
+        app.scroll_offset = 2;
+        assert_eq!(pipeline.source_position(&app), "5");
+
+        pipeline.files.push(file("b.md", "\n\nb"));
+        let blocks: Rc<[RenderedBlock]> = pipeline.parse((80, 24)).into();
+        app.document = pipeline.flatten(&blocks, 80);
+        app.scroll_offset = app.document.block_starts[3];
+        assert_eq!(pipeline.source_position(&app), "b.md:3");
+    }
+
+    #[test]
+    fn test_main_relayout_after_reload_clamps_scroll() {
+        let highlighter = highlight::Highlighter::new();
+        let pipeline = Pipeline {
+            files: vec![SourceFile { path: "doc.md", source: String::new(), mtime: None }],
+            highlighter: &highlighter,
+            options: parser::ParseOptions::default(),
+            heading_palettes: [parser::DEFAULT_HEADING_COLORS; 2],
+            raw: false,
+            latest: false,
+            layout: layout::LayoutOptions::default(),
+            images: None,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            file_starts: Vec::new(),
+            source_lines: Vec::new(),
+        };
+        let long: Rc<[RenderedBlock]> = parser::parse(&"para\n\n".repeat(50), &highlighter).into();
+        let mut app = App::new(pipeline.flatten(&long, 80), "doc.md".to_string());
+        app.viewport_height = 10;
+        app.scroll_to_bottom();
+        assert!(app.scroll_offset > 50);
+
+        // The file shrank on disk: the old offset is past the new end.
+        let short: Rc<[RenderedBlock]> = parser::parse(&"para\n\n".repeat(10), &highlighter).into();
+        relayout(&mut app, &pipeline, &short, 80);
+        assert_eq!(app.scroll_offset, app.max_scroll());
+        assert_eq!(app.scroll_offset, 19 - 10);
+    }
+
+    #[test]
+    fn test_main_layout_size_dump_to_pipe_uses_fallback() {
+        let terminal = || Ok((200, 50));
+        assert_eq!(layout_size(true, false, terminal).unwrap(), DUMP_SIZE);
+        assert_eq!(layout_size(true, true, terminal).unwrap(), (200, 50));
+        assert_eq!(layout_size(false, false, terminal).unwrap(), (200, 50));
+        let no_terminal = || Err(std::io::Error::other("no tty"));
+        assert_eq!(layout_size(true, true, no_terminal).unwrap(), DUMP_SIZE);
+        assert!(layout_size(false, true, no_terminal).is_err());
+    }
+
+    #[test]
+    fn test_main_parse_line_range_is_inclusive_and_one_based() {
+        assert_eq!(parse_line_range("10-25"), Ok(9..25));
+        assert_eq!(parse_line_range("7"), Ok(6..7));
+        assert_eq!(parse_line_range("0-2"), Ok(0..2));
+        assert!(parse_line_range("5-3").is_err());
+        assert!(parse_line_range("a-3").is_err());
+        assert!(parse_line_range("3-").is_err());
+    }
+
+    #[test]
+    fn test_main_no_color_env_disables_colors() {
+        let no_color = Some(OsStr::new("1"));
+        let color = resolve_color_mode(None, no_color);
+        assert_eq!(color, ColorMode::None);
+        let opts = export::RenderOptions { color, ..Default::default() };
+        let out = export::to_ansi("# Title\n\n```rust\nlet x = 1;\n```", 20, opts);
+        assert!(out.starts_with("\x1b[1mTitle\x1b[0m\n"), "{out:?}");
+        assert!(!out.contains("38;") && !out.contains("48;"), "{out:?}");
+    }
+
+    #[test]
+    fn test_main_color_flag_overrides_no_color() {
+        let no_color = Some(OsStr::new("1"));
+        assert_eq!(resolve_color_mode(Some(cli::ColorMode::Full), no_color), ColorMode::Full);
+        assert_eq!(resolve_color_mode(Some(cli::ColorMode::None), None), ColorMode::None);
+        // An empty NO_COLOR doesn't count as set.
+        assert_eq!(resolve_color_mode(None, Some(OsStr::new(""))), ColorMode::Full);
+        assert_eq!(resolve_color_mode(None, None), ColorMode::Full);
+    }
+
+    #[test]
+    fn test_main_theme_flag_overrides_mdink_theme() {
+        let env = Some(OsStr::new("Solarized (dark)"));
+        assert_eq!(resolve_theme_name(Some("InspiredGitHub"), env), "InspiredGitHub");
+        assert_eq!(resolve_theme_name(None, env), "Solarized (dark)");
+        assert_eq!(resolve_theme_name(None, Some(OsStr::new(""))), highlight::DEFAULT_THEME);
+        assert_eq!(resolve_theme_name(None, None), highlight::DEFAULT_THEME);
+    }
+
+    #[test]
+    fn test_main_width_flag_overrides_mdink_width() {
+        let env = Some(OsStr::new("72"));
+        assert_eq!(resolve_wrap_width(Some(60), env), Some(60));
+        assert_eq!(resolve_wrap_width(None, env), Some(72));
+        assert_eq!(resolve_wrap_width(None, None), None);
+        // Too narrow is raised like `:set width`; junk is ignored.
+        assert_eq!(resolve_wrap_width(Some(5), env), Some(MIN_WRAP_WIDTH));
+        assert_eq!(resolve_wrap_width(None, Some(OsStr::new("wide"))), None);
+        assert_eq!(resolve_wrap_width(None, Some(OsStr::new(""))), None);
+    }
+
+    #[test]
+    fn test_main_parse_heading_colors_overrides_listed_levels() {
+        let (colors, warnings) = parse_heading_colors("h1=red, h3=#5f87ff,H6=214");
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(colors[0], Color::Red);
+        assert_eq!(colors[1], parser::DEFAULT_HEADING_COLORS[1]);
+        assert_eq!(colors[2], Color::Rgb(0x5f, 0x87, 0xff));
+        assert_eq!(colors[5], Color::Indexed(214));
+    }
+
+    #[test]
+    fn test_main_parse_heading_colors_warns_and_skips_bad_entries() {
+        let (colors, warnings) = parse_heading_colors("h2=blue,h7=red,h1=notacolor,h4");
+        assert_eq!(colors[1], Color::Blue);
+        assert_eq!(colors[0], parser::DEFAULT_HEADING_COLORS[0]);
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[1].contains("notacolor"));
+    }
+
+    #[test]
+    fn test_main_heading_colors_reach_parsed_headings() {
+        let (heading_colors, _) = parse_heading_colors("h2=magenta");
+        let options = parser::ParseOptions { heading_colors, ..Default::default() };
+        let highlighter = highlight::Highlighter::new();
+        let blocks = parser::parse_with_options("## Two", &highlighter, &options);
+        let RenderedBlock::Heading { content, .. } = &blocks[0] else {
+            panic!("expected Heading block");
+        };
+        assert_eq!(content[0].style.fg, Some(Color::Magenta));
+    }
+
+    #[test]
+    fn test_main_parse_inline_code_style_adjusts_default() {
+        let spec = "no-bold, no-italic,fg=cyan,underline";
+        let (style, warnings) = parse_style_spec(spec, parser::default_code_style());
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(style.fg, Some(Color::Cyan));
+        assert_eq!(style.bg, parser::default_code_style().bg);
+        assert!(style.add_modifier.contains(Modifier::UNDERLINED));
+        assert!(!style.add_modifier.intersects(Modifier::BOLD | Modifier::ITALIC));
+        let (style, _) = parse_style_spec("bg=reset", parser::default_code_style());
+        assert_eq!(style.bg, Some(Color::Reset));
+    }
+
+    #[test]
+    fn test_main_parse_inline_code_style_warns_and_skips_bad_entries() {
+        let spec = "blink,fg=nocolor,size=2,no-bold";
+        let (style, warnings) = parse_style_spec(spec, parser::default_code_style());
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("blink"));
+        assert!(!style.add_modifier.contains(Modifier::BOLD));
+        assert_eq!(style.fg, parser::default_code_style().fg);
+    }
+
+    #[test]
+    fn test_main_inline_code_style_reaches_parsed_code_spans() {
+        let (code_style, _) = parse_style_spec("no-italic", parser::default_code_style());
+        let options = parser::ParseOptions { code_style, ..Default::default() };
+        let highlighter = highlight::Highlighter::new();
+        let blocks = parser::parse_with_options("a `b`", &highlighter, &options);
+        let RenderedBlock::Paragraph { content } = &blocks[0] else {
+            panic!("expected Paragraph block");
+        };
+        assert_eq!(content[1].style, code_style);
+    }
+
+    #[test]
+    fn test_main_editor_command_keeps_quoted_arguments_and_paths() {
+        let output = editor_command("printf '%s|' --wait", ["my notes.md", "b.md"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "--wait|my notes.md|b.md|");
+        let status = editor_command("false", ["a.md"]).status().unwrap();
+        assert!(!status.success());
+    }
+
+    #[test]
+    fn test_main_check_content_rejects_known_binary_formats() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let err = check_content(png, "image.png").unwrap_err().to_string();
+        assert_eq!(err, "image.png: looks like a PNG image, not markdown");
+        let err = check_content(b"%PDF-1.7\n", "doc.pdf").unwrap_err().to_string();
+        assert!(err.contains("a PDF document"), "{err}");
+    }
+
+    #[test]
+    fn test_main_check_content_rejects_mostly_non_text() {
+        let err = check_content(b"abc\0def", "data.bin").unwrap_err().to_string();
+        assert_eq!(err, "data.bin: looks like binary data, not markdown");
+        assert!(check_content(&[0x01; 64], "data.bin").is_err());
+    }
+
+    #[test]
+    fn test_main_check_content_accepts_text_and_html() {
+        assert!(check_content(b"# Title\r\n\tcode\n\x1b[1mansi\x1b[0m", "a.md").is_ok());
+        assert!(check_content(b"caf\xE9 au lait", "latin1.md").is_ok());
+        assert!(check_content(b"", "empty.md").is_ok());
+        // HTML only warns.
+        assert!(check_content(b"<!DOCTYPE html><html></html>", "page.html").is_ok());
+    }
+
+    #[test]
+    fn test_main_options_from_cli_and_environment() {
+        let no_env = |_: &str| None;
+        let defaults = Options::from_cli(&Cli::parse_from(["mdink", "a.md"]), no_env).unwrap();
+        assert_eq!(defaults.output, None);
+        assert_eq!(defaults.wrap_width, None);
+        assert!(!defaults.light);
+        assert_eq!(defaults.parse.theme, highlight::DEFAULT_THEME);
+        assert!(defaults.parse.lazy_highlight);
+        assert_eq!(defaults.layout.spacing, layout::Spacing::Normal);
+        assert_eq!(defaults.render.color, ColorMode::Full);
+        assert_eq!(defaults.render.code_padding, export::DEFAULT_CODE_PADDING);
+        assert_eq!(defaults.render.rule, layout::DEFAULT_RULE);
+
+        let cli = Cli::parse_from([
+            "mdink", "a.md", "--dump", "--select", "2-3", "--spacing", "compact",
+            "--code-padding", "3", "--rule-char", "=", "--theme", "InspiredGitHub",
+        ]);
+        let env = |name: &str| match name {
+            "NO_COLOR" => Some(OsString::from("1")),
+            "MDINK_WIDTH" => Some(OsString::from("40")),
+            "MDINK_THEME" => Some(OsString::from("Solarized (dark)")),
+            _ => None,
+        };
+        let options = Options::from_cli(&cli, env).unwrap();
+        assert_eq!(options.output, Some(cli::OutputFormat::Ansi));
+        assert_eq!(options.select, Some(1..3));
+        assert_eq!(options.wrap_width, Some(40));
+        assert!(!options.parse.lazy_highlight);
+        assert_eq!(options.parse.theme, "InspiredGitHub");
+        assert_eq!(options.render.theme, "InspiredGitHub");
+        assert!(options.light, "the default light theme of the pair");
+        assert_eq!(options.layout.spacing, layout::Spacing::Compact);
+        assert_eq!(options.render.color, ColorMode::None);
+        assert_eq!(options.render.code_padding, 3);
+        assert_eq!(options.render.rule, "=");
+
+        let cli = Cli::parse_from(["mdink", "a.md", "--select", "x"]);
+        assert!(Options::from_cli(&cli, no_env).is_err());
+    }
@@ -0,0 +1,359 @@
+//! LaTeX math → Unicode approximation (`--math`).
+//!
+//! This is a **leaf module**: it turns the source of `$...$` / `$$...$$`
+//! into plain text and knows nothing about styles or layout.
+//!
+//! Only the common cases are covered — Greek letters, operators, relations
+//! and arrows, superscripts and subscripts that have Unicode forms,
+//! `\frac`, `\sqrt` and `\text`. Anything else makes `to_unicode()` return
+//! `None`, so the caller shows the raw LaTeX rather than a wrong rendering.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Converts a LaTeX math expression to Unicode text.
+///
+/// Runs of whitespace collapse to one space; `\\` line breaks become `\n`.
+/// Returns `None` when the expression uses a command, or a superscript or
+/// subscript character, that has no Unicode approximation.
+pub fn to_unicode(latex: &str) -> Option<String> {
+    let mut chars = latex.chars().peekable();
+    let text = convert(&mut chars, false)?;
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+    Some(lines.join("\n").trim().to_string())
+}
+
+/// Converts up to the end of input, or up to and including the `}` that
+/// closes a group when `in_group` is set. Unbalanced braces are `None`.
+fn convert(chars: &mut Peekable<Chars>, in_group: bool) -> Option<String> {
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' if in_group => return Some(out),
+            '}' => return None,
+            '{' => out.push_str(&convert(chars, true)?),
+            '\\' => out.push_str(&command(chars)?),
+            '^' => out.push_str(&script(&argument(chars)?, superscript)?),
+            '_' => out.push_str(&script(&argument(chars)?, subscript)?),
+            // Alignment points in `aligned`-style blocks.
+            '&' => out.push(' '),
+            '\'' => out.push('′'),
+            c => out.push(c),
+        }
+    }
+    (!in_group).then_some(out)
+}
+
+/// Reads one argument of a command or script: a `{...}` group, a single
+/// command, or a single character.
+fn argument(chars: &mut Peekable<Chars>) -> Option<String> {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    match chars.next()? {
+        '{' => convert(chars, true),
+        '\\' => command(chars),
+        c => Some(c.to_string()),
+    }
+}
+
+/// Converts the command after a `\`: a run of letters, or one other character.
+fn command(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+        name.push(c);
+    }
+    if name.is_empty() {
+        name.push(chars.next()?);
+    }
+    if let Some(symbol) = symbol(&name) {
+        return Some(symbol.to_string());
+    }
+    let text = match name.as_str() {
+        "frac" | "dfrac" | "tfrac" => {
+            let numerator = argument(chars)?;
+            let denominator = argument(chars)?;
+            format!("{}/{}", parenthesize(&numerator), parenthesize(&denominator))
+        }
+        "sqrt" => {
+            let root = if chars.next_if_eq(&'[').is_some() {
+                let index: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                match index.trim() {
+                    "3" => '∛',
+                    "4" => '∜',
+                    _ => return None,
+                }
+            } else {
+                '√'
+            };
+            format!("{root}{}", parenthesize(&argument(chars)?))
+        }
+        "text" | "textrm" | "mathrm" | "mathit" | "mathbf" | "mathsf" | "operatorname" => {
+            argument(chars)?
+        }
+        // Delimiter sizing: the delimiter itself follows (`.` means none).
+        "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => {
+            chars.next_if_eq(&'.');
+            String::new()
+        }
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan"
+        | "sinh" | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "lim" | "max" | "min"
+        | "sup" | "inf" | "det" | "deg" | "dim" | "gcd" | "mod" | "arg" => name,
+        "," | ":" | ";" | " " | "quad" | "qquad" => " ".to_string(),
+        "!" => String::new(),
+        "\\" => "\n".to_string(),
+        "{" | "}" | "|" | "%" | "$" | "#" | "_" | "&" => name,
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Wraps `text` in parentheses unless it is a single character.
+fn parenthesize(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() > 1 {
+        format!("({text})")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Maps every character of `text` through `map` (ignoring spaces), or
+/// returns `None` if one has no raised or lowered form.
+fn script(text: &str, map: fn(char) -> Option<char>) -> Option<String> {
+    text.chars().filter(|c| !c.is_whitespace()).map(map).collect()
+}
+
+fn superscript(c: char) -> Option<char> {
+    let raised = match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'T' => 'ᵀ',
+        '′' | '*' | '∗' => c,
+        _ => return None,
+    };
+    Some(raised)
+}
+
+fn subscript(c: char) -> Option<char> {
+    let lowered = match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        _ => return None,
+    };
+    Some(lowered)
+}
+
+/// Returns the Unicode character for a symbol command (`alpha`, `leq`, …).
+fn symbol(name: &str) -> Option<char> {
+    let c = match name {
+        // Greek, lowercase
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        // Greek, uppercase (the rest look like Latin capitals)
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        // Big operators and calculus
+        "sum" => '∑',
+        "prod" => '∏',
+        "coprod" => '∐',
+        "int" => '∫',
+        "iint" => '∬',
+        "oint" => '∮',
+        "infty" => '∞',
+        "partial" => '∂',
+        "nabla" => '∇',
+        // Binary operators
+        "pm" => '±',
+        "mp" => '∓',
+        "times" => '×',
+        "div" => '÷',
+        "cdot" => '⋅',
+        "ast" => '∗',
+        "star" => '⋆',
+        "circ" => '∘',
+        "bullet" => '∙',
+        "oplus" => '⊕',
+        "otimes" => '⊗',
+        "cup" => '∪',
+        "cap" => '∩',
+        "setminus" => '∖',
+        "land" | "wedge" => '∧',
+        "lor" | "vee" => '∨',
+        // Relations
+        "leq" | "le" => '≤',
+        "geq" | "ge" => '≥',
+        "neq" | "ne" => '≠',
+        "approx" => '≈',
+        "sim" => '∼',
+        "simeq" => '≃',
+        "cong" => '≅',
+        "equiv" => '≡',
+        "propto" => '∝',
+        "ll" => '≪',
+        "gg" => '≫',
+        "in" => '∈',
+        "notin" => '∉',
+        "ni" => '∋',
+        "subset" => '⊂',
+        "supset" => '⊃',
+        "subseteq" => '⊆',
+        "supseteq" => '⊇',
+        "mid" => '∣',
+        "parallel" => '∥',
+        "perp" => '⊥',
+        // Arrows
+        "to" | "rightarrow" => '→',
+        "leftarrow" | "gets" => '←',
+        "leftrightarrow" => '↔',
+        "Rightarrow" => '⇒',
+        "Leftarrow" => '⇐',
+        "Leftrightarrow" => '⇔',
+        "implies" => '⟹',
+        "iff" => '⟺',
+        "mapsto" => '↦',
+        "uparrow" => '↑',
+        "downarrow" => '↓',
+        // Logic, sets and miscellany
+        "forall" => '∀',
+        "exists" => '∃',
+        "nexists" => '∄',
+        "neg" | "lnot" => '¬',
+        "emptyset" | "varnothing" => '∅',
+        "ldots" | "dots" => '…',
+        "cdots" => '⋯',
+        "vdots" => '⋮',
+        "ddots" => '⋱',
+        "prime" => '′',
+        "angle" => '∠',
+        "hbar" => 'ℏ',
+        "ell" => 'ℓ',
+        "Re" => 'ℜ',
+        "Im" => 'ℑ',
+        "aleph" => 'ℵ',
+        "langle" => '⟨',
+        "rangle" => '⟩',
+        "lfloor" => '⌊',
+        "rfloor" => '⌋',
+        "lceil" => '⌈',
+        "rceil" => '⌉',
+        "vert" => '|',
+        "Vert" => '‖',
+        _ => return None,
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+#[path = "math_tests.rs"]
+mod tests;
@@ -0,0 +1,40 @@
+    use super::*;
+
+    #[test]
+    fn test_math_greek_and_operators() {
+        assert_eq!(to_unicode(r"\alpha \leq \beta").as_deref(), Some("α ≤ β"));
+        assert_eq!(to_unicode(r"\sum_{i=1}^{n} x_i").as_deref(), Some("∑ᵢ₌₁ⁿ xᵢ"));
+        assert_eq!(to_unicode(r"a \times b \neq \infty").as_deref(), Some("a × b ≠ ∞"));
+    }
+
+    #[test]
+    fn test_math_superscripts_and_subscripts() {
+        assert_eq!(to_unicode("x^2 + y^{10}").as_deref(), Some("x² + y¹⁰"));
+        assert_eq!(to_unicode("a_0 + a_{n-1}").as_deref(), Some("a₀ + aₙ₋₁"));
+        assert_eq!(to_unicode("f'(x)").as_deref(), Some("f′(x)"));
+    }
+
+    #[test]
+    fn test_math_frac_sqrt_and_text() {
+        assert_eq!(to_unicode(r"\frac{1}{2}").as_deref(), Some("1/2"));
+        assert_eq!(to_unicode(r"\frac{a+b}{c}").as_deref(), Some("(a+b)/c"));
+        assert_eq!(to_unicode(r"\sqrt{x^2 + 1}").as_deref(), Some("√(x² + 1)"));
+        assert_eq!(to_unicode(r"\sqrt[3]{8}").as_deref(), Some("∛8"));
+        assert_eq!(to_unicode(r"x \text{ if } x > 0").as_deref(), Some("x if x > 0"));
+        assert_eq!(to_unicode(r"\left( \sin x \right)").as_deref(), Some("( sin x )"));
+    }
+
+    #[test]
+    fn test_math_line_breaks() {
+        assert_eq!(to_unicode(r"a &= b \\ c &= d").as_deref(), Some("a = b\nc = d"));
+    }
+
+    #[test]
+    fn test_math_unsupported_constructs_return_none() {
+        // No Unicode superscript π, no such command, unbalanced braces.
+        assert_eq!(to_unicode(r"e^{i\pi}"), None);
+        assert_eq!(to_unicode(r"\begin{matrix} a \end{matrix}"), None);
+        assert_eq!(to_unicode(r"\frac{1}{2"), None);
+        assert_eq!(to_unicode("x}"), None);
+        assert_eq!(to_unicode(r"\sqrt[5]{x}"), None);
+    }
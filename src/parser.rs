@@ -4,9 +4,17 @@
 //! a markdown source string and produces a `Vec<RenderedBlock>` — the
 //! intermediate representation consumed by the layout engine.
 
-use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::rc::Rc;
+
+use pulldown_cmark::{
+    CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeWithOffset,
+};
 use ratatui::style::{Color, Modifier, Style};
-use ratatui::text::Line;
+use ratatui::text::{Line, Span};
+use rayon::prelude::*;
+
+use crate::highlight::{Comments, LazyHighlight};
+use crate::images::ImageManager;
 
 /// A rendered markdown block ready for layout.
 ///
@@ -23,15 +31,65 @@ pub enum RenderedBlock {
     CodeBlock {
         /// Language from the fence info string (empty for indented/unfenced).
         language: String,
+        /// `title="…"` (or `caption="…"`) from the fence info string, shown
+        /// as a caption above the block.
+        title: Option<String>,
         /// Pre-highlighted lines ready for layout.
         highlighted_lines: Vec<Line<'static>>,
+        /// For a long block parsed with `ParseOptions::lazy_highlight`: the
+        /// source to highlight as it is shown. `highlighted_lines` are
+        /// unstyled until then.
+        lazy: Option<Rc<LazyHighlight>>,
+    },
+    /// A standalone image drawn with terminal graphics.
+    ///
+    /// Only produced when an `ImageManager` successfully loaded the image;
+    /// otherwise the image's alt text stays inline in a `Paragraph`.
+    Image {
+        /// Index into the `ImageManager` arena.
+        protocol_index: usize,
+        /// Alt text, shown when the image can't be drawn in full.
+        alt_text: String,
+        /// Natural size in terminal cells (already capped at load time).
+        width_cells: u16,
+        height_cells: u16,
+    },
+    /// A `<details>` section (with `--html render`): a summary line and the
+    /// blocks between `<details>` and `</details>`, shown indented.
+    Details {
+        summary: Vec<StyledSpan>,
+        body: Vec<RenderedBlock>,
     },
+    /// A bullet list, or a numbered one counting up from `start`. Each
+    /// item's blocks are laid out indented under its marker.
+    List {
+        start: Option<u64>,
+        items: Vec<ListItem>,
+    },
+    /// A block quote, laid out behind a bar. A final line starting with a
+    /// dash (`— Author`) is split out as the `attribution`.
+    BlockQuote {
+        body: Vec<RenderedBlock>,
+        attribution: Option<Vec<StyledSpan>>,
+    },
+    /// Display math (`$$...$$`, with `--math`), shown centered: one entry
+    /// per line, converted to Unicode or kept as raw LaTeX when it can't be.
+    MathBlock { lines: Vec<String> },
     /// A horizontal rule / thematic break.
     ThematicBreak,
-    /// Vertical spacing between blocks.
+    /// Vertical spacing between blocks, on top of the blank line the layout
+    /// puts before every block. Capped by `LayoutOptions::max_blank_lines`.
     Spacer { lines: u16 },
 }
 
+/// One item of a `RenderedBlock::List`.
+pub struct ListItem {
+    /// `Some(checked)` for a task list item (`- [ ]` / `- [x]`).
+    pub task: Option<bool>,
+    /// The blocks inside the item: paragraphs, code, nested lists.
+    pub blocks: Vec<RenderedBlock>,
+}
+
 /// A text span with associated style information.
 ///
 /// Multiple `StyledSpan`s compose a line of styled text. Each span
@@ -41,6 +99,123 @@ pub struct StyledSpan {
     pub text: String,
     /// The ratatui style to apply when rendering.
     pub style: Style,
+    /// The page an issue or commit reference split out for
+    /// `ParseOptions::repo_url` links to; `None` for any other span.
+    pub reference: Option<String>,
+}
+
+/// How YAML front matter (a `---` block at the very top) is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrontMatter {
+    /// Drop it (the default).
+    #[default]
+    Hide,
+    /// Show the raw YAML as a dim code block.
+    Show,
+    /// Render only its `title` value, as a level-1 heading.
+    Title,
+}
+
+/// What to do with raw HTML blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HtmlMode {
+    /// Drop them (the default).
+    #[default]
+    Drop,
+    /// Render the few HTML constructs that have a terminal equivalent:
+    /// `<details>`/`<summary>` sections. Other HTML is still dropped.
+    Render,
+}
+
+/// Options that change how markdown is converted to the IR.
+///
+/// `Default` matches the behavior of `parse()`.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Show image references as `🖼 alt text (path)`. When false, images are
+    /// dropped from the output entirely.
+    pub show_images: bool,
+    /// Typeset prose: curly quotes, `--`/`---` as dashes, `...` as an ellipsis.
+    /// Code spans and code blocks are never affected.
+    pub smart_punctuation: bool,
+    /// Syntect theme used to highlight code blocks.
+    pub theme: String,
+    /// How to render YAML front matter.
+    pub frontmatter: FrontMatter,
+    /// What to do with raw HTML blocks.
+    pub html: HtmlMode,
+    /// Keep extra blank lines between top-level blocks (up to
+    /// `MAX_BLANK_LINES`) as `Spacer` blocks instead of collapsing each run
+    /// to the single blank line the layout always inserts.
+    pub preserve_blank_lines: bool,
+    /// Threads used to highlight code blocks. `0` uses one per CPU; `1`
+    /// highlights serially on the calling thread.
+    pub jobs: usize,
+    /// Annotate a heading directly followed by a task list with a dim
+    /// `(done/total)` count of its tasks.
+    pub task_summary: bool,
+    /// Render `ansi` and `console` code blocks with the colors of the ANSI
+    /// escapes they contain instead of syntax highlighting them.
+    pub raw_ansi: bool,
+    /// Render `$...$` and `$$...$$` math with a Unicode approximation of the
+    /// LaTeX. Off, dollar signs are ordinary text.
+    pub math: bool,
+    /// Foreground color of each heading level, h1 first.
+    pub heading_colors: [Color; 6],
+    /// Base URL of the document's repository (`--repo-url`). When set,
+    /// `#123` and commit hashes in prose are split out as reference links.
+    pub repo_url: Option<String>,
+    /// Style of inline code spans (`--inline-code-style`).
+    pub code_style: Style,
+    /// Style of link text and bare URLs (`--link-style`).
+    pub link_style: Style,
+    /// Shrink a paragraph made only of images and links, such as a row of
+    /// README badges, to one dim line of their alt texts (`--collapse-badges`).
+    pub collapse_badges: bool,
+    /// Expand tabs to spaces, with tab stops this many columns apart
+    /// (`--tab-to-spaces`): in code blocks from the start of each line, in
+    /// prose from the start of each text span. `None` keeps tabs, whose
+    /// width is up to the terminal.
+    pub tab_width: Option<usize>,
+    /// Whether code blocks keep their comments (`--strip-comments`).
+    pub comments: Comments,
+    /// Leave top-level code blocks over `LAZY_HIGHLIGHT_LINES` lines to be
+    /// highlighted as they come into view (see `CodeBlock::lazy`), so a huge
+    /// block doesn't hold up startup. For the viewer; exports want it off.
+    pub lazy_highlight: bool,
+    /// Highlight code blocks without a language as the language their
+    /// content looks like (`--guess-language`), if any.
+    pub guess_language: bool,
+}
+
+/// Code blocks longer than this are highlighted lazily with
+/// `ParseOptions::lazy_highlight`.
+pub const LAZY_HIGHLIGHT_LINES: usize = 1_000;
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            show_images: true,
+            smart_punctuation: false,
+            frontmatter: FrontMatter::Hide,
+            html: HtmlMode::Drop,
+            preserve_blank_lines: false,
+            theme: crate::highlight::DEFAULT_THEME.to_string(),
+            jobs: 0,
+            task_summary: false,
+            raw_ansi: false,
+            math: false,
+            heading_colors: DEFAULT_HEADING_COLORS,
+            repo_url: None,
+            code_style: default_code_style(),
+            link_style: default_link_style(),
+            collapse_badges: false,
+            tab_width: None,
+            comments: Comments::Keep,
+            lazy_highlight: false,
+            guess_language: false,
+        }
+    }
 }
 
 /// Parser state machine states.
@@ -54,24 +229,56 @@ enum ParserState {
     InHeading { level: u8 },
     /// Inside a paragraph block.
     InParagraph,
+    /// Inside an image's alt text. `first_span` is the index into
+    /// `current_spans` where the alt text starts.
+    InImage { dest_url: String, first_span: usize },
     /// Inside a fenced or indented code block; accumulating text.
-    InCodeBlock { language: String, buffer: String },
+    InCodeBlock { language: String, title: Option<String>, buffer: String },
+    /// Inside the YAML front matter block; accumulating its text.
+    InFrontMatter { buffer: String },
+    /// Inside a raw HTML block (`--html render` only); accumulating its text.
+    InHtmlBlock { buffer: String },
+    /// Inside a list; `items` holds the finished items.
+    InList { start: Option<u64>, items: Vec<ListItem> },
+    /// Inside a list item whose blocks start at `first_block` in `blocks`.
+    /// Text directly in the item (a tight list) collects in `current_spans`.
+    InListItem { first_block: usize, task: Option<bool> },
+    /// Inside a block quote whose blocks start at `first_block` in `blocks`.
+    /// `last_line` is where the last line of its latest paragraph starts:
+    /// `(block, span)` indices, for finding an attribution.
+    InBlockQuote { first_block: usize, last_line: Option<(usize, usize)> },
     /// Inside an unrecognized block that we skip in this phase.
     /// We count nesting depth so we know when the matching End arrives.
     Skipping { depth: u32 },
 }
 
-/// Returns the default heading style for a given level (1–6).
+/// Heading colors for levels 1–6 unless `ParseOptions::heading_colors`
+/// overrides them: h4–h6 all use white.
+pub const DEFAULT_HEADING_COLORS: [Color; 6] = [
+    Color::LightCyan,
+    Color::Green,
+    Color::Yellow,
+    Color::White,
+    Color::White,
+    Color::White,
+];
+
+/// Heading colors used with a light code theme (`B` toggles to it): darker
+/// hues, and the terminal's own text color where the defaults use white.
+pub const LIGHT_HEADING_COLORS: [Color; 6] = [
+    Color::Blue,
+    Color::Green,
+    Color::Magenta,
+    Color::Reset,
+    Color::Reset,
+    Color::Reset,
+];
+
+/// Returns the heading style for a given level (1–6), colored from `colors`.
 ///
-/// Centralized here as the single swap point for Phase 5 theming.
-fn default_heading_style(level: u8) -> Style {
-    let color = match level {
-        1 => Color::LightCyan,
-        2 => Color::Green,
-        3 => Color::Yellow,
-        // h4–h6 all use white
-        _ => Color::White,
-    };
+/// Centralized here as the single swap point for theming.
+fn heading_style(colors: &[Color; 6], level: u8) -> Style {
+    let color = colors[usize::from(level.clamp(1, 6)) - 1];
     let modifier = match level {
         1..=3 => Modifier::BOLD,
         _ => Modifier::BOLD | Modifier::ITALIC,
@@ -82,13 +289,372 @@ fn default_heading_style(level: u8) -> Style {
 /// Returns the default inline code style.
 ///
 /// Dark gray background with light gray foreground.
-fn default_code_style() -> Style {
+pub fn default_code_style() -> Style {
     Style::default()
         .bg(Color::Indexed(236))
         .fg(Color::Indexed(252))
         .add_modifier(Modifier::BOLD | Modifier::ITALIC)
 }
 
+/// Returns the default style of link text (explicit links, autolinks, bare
+/// URLs and `--repo-url` references): blue and underlined, so links stand
+/// out from emphasis.
+pub fn default_link_style() -> Style {
+    Style::default().fg(Color::Blue).add_modifier(Modifier::UNDERLINED)
+}
+
+/// Returns true if `code` is long enough to highlight lazily.
+fn is_long_code(code: &str) -> bool {
+    code.lines().nth(LAZY_HIGHLIGHT_LINES).is_some()
+}
+
+/// Calls `f` with every `CodeBlock` in `blocks`, including those inside
+/// `Details`, lists and quotes, in document order. `top_level` is false
+/// for the nested ones.
+fn for_each_code_block(
+    blocks: &mut [RenderedBlock],
+    top_level: bool,
+    f: &mut impl FnMut(&mut RenderedBlock, bool),
+) {
+    for block in blocks {
+        match block {
+            RenderedBlock::CodeBlock { .. } => f(block, top_level),
+            RenderedBlock::Details { body, .. } | RenderedBlock::BlockQuote { body, .. } => {
+                for_each_code_block(body, false, f);
+            }
+            RenderedBlock::List { items, .. } => {
+                for item in items {
+                    for_each_code_block(&mut item.blocks, false, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Splits the attribution off a quote's body: the last line of its last
+/// paragraph, from span `line_start` on, if it starts with a dash (`—`,
+/// `--`, or the `–` smart punctuation makes of `--`). The break before the
+/// line goes with it, and a paragraph left empty is removed.
+fn split_attribution(body: &mut Vec<RenderedBlock>, line_start: usize) -> Option<Vec<StyledSpan>> {
+    let Some(RenderedBlock::Paragraph { content }) = body.last_mut() else {
+        return None;
+    };
+    let line: String = content.get(line_start..)?.iter().map(|s| s.text.as_str()).collect();
+    if !["—", "–", "--"].iter().any(|dash| line.trim_start().starts_with(dash)) {
+        return None;
+    }
+    let attribution = content.split_off(line_start);
+    content.pop();
+    if content.is_empty() {
+        body.pop();
+    }
+    Some(attribution)
+}
+
+/// Returns the language of a fence info string: pulldown-cmark yields the
+/// full string (e.g. "rust,no_run" or "python title=\"x.py\""), and only
+/// its first token, up to a space or comma, names the language for syntect
+/// lookup and the label.
+fn fence_language(info: &str) -> String {
+    let token = info.split_whitespace().next().unwrap_or("");
+    token.split(',').next().unwrap_or("").to_string()
+}
+
+/// Extracts a `title=` or `caption=` attribute from a fence info string,
+/// as in ```` ```python title="server.py" ````. The value may be double-
+/// or single-quoted, or bare up to the next space, comma or `}`.
+fn fence_title(info: &str) -> Option<String> {
+    let mut rest = info;
+    while let Some(pos) = rest.find('=') {
+        let (before, after) = (&rest[..pos], &rest[pos + 1..]);
+        let key_start = before.rfind(|c: char| c.is_whitespace() || ",{".contains(c));
+        let key = &before[key_start.map_or(0, |i| i + 1)..];
+        // The first token is the language, never an attribute.
+        let is_attribute = key_start.is_some();
+        let (value, remainder) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => match after[1..].find(quote) {
+                Some(end) => (&after[1..=end], &after[end + 2..]),
+                None => (&after[1..], ""),
+            },
+            _ => {
+                let end = after
+                    .find(|c: char| c.is_whitespace() || ",}".contains(c))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if is_attribute && matches!(key, "title" | "caption") && !value.trim().is_empty() {
+            return Some(value.trim().to_string());
+        }
+        rest = remainder;
+    }
+    None
+}
+
+/// Returns true if `html` starts with the tag `name` (e.g. `"<details"`),
+/// followed by `>`, `/` or whitespace rather than more of a longer name.
+fn is_tag(html: &str, name: &str) -> bool {
+    html.strip_prefix(name)
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c == '>' || c == '/' || c.is_whitespace())
+}
+
+/// Removes HTML tags from `html` and collapses whitespace, for summary text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Named character references `decode_entities()` knows, beyond the
+/// numeric ones. pulldown-cmark decodes all of them in markdown text; this
+/// covers the common ones for text taken from raw HTML.
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", '\u{a0}'),
+    ("copy", '©'),
+    ("reg", '®'),
+    ("trade", '™'),
+    ("ndash", '–'),
+    ("mdash", '—'),
+    ("hellip", '…'),
+    ("lsquo", '‘'),
+    ("rsquo", '’'),
+    ("ldquo", '“'),
+    ("rdquo", '”'),
+    ("laquo", '«'),
+    ("raquo", '»'),
+    ("middot", '·'),
+    ("bull", '•'),
+    ("deg", '°'),
+    ("times", '×'),
+    ("divide", '÷'),
+    ("plusmn", '±'),
+    ("euro", '€'),
+    ("pound", '£'),
+    ("sect", '§'),
+    ("larr", '←'),
+    ("rarr", '→'),
+    ("uarr", '↑'),
+    ("darr", '↓'),
+];
+
+/// Replaces character references (`&amp;`, `&#8212;`, `&#x2014;`) in text
+/// from raw HTML with the characters they stand for. Unknown names and
+/// invalid code points are left as written.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => {
+                    u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32)
+                }
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => NAMED_ENTITIES.iter().find(|(n, _)| *n == name).map(|&(_, c)| c),
+            };
+            c.filter(|&c| c != '\0').map(|c| (c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Returns the `title` value of YAML front matter, with surrounding quotes
+/// removed. Only a plain top-level `title: value` line is understood.
+fn front_matter_title(yaml: &str) -> Option<String> {
+    yaml.lines().find_map(|line| {
+        let value = line.strip_prefix("title:")?.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|&q| value.strip_prefix(q)?.strip_suffix(q))
+            .unwrap_or(value);
+        (!value.is_empty()).then(|| value.to_string())
+    })
+}
+
+/// Most consecutive blank lines `preserve_blank_lines` keeps between blocks.
+/// The layout must allow as many (`LayoutOptions::max_blank_lines`).
+pub const MAX_BLANK_LINES: usize = 3;
+
+/// Returns the length of the longest run of blank (whitespace-only) lines
+/// in `text`, a stretch of source between two blocks.
+fn longest_blank_run(text: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+/// Replaces each tab in `text` with spaces up to the next multiple of
+/// `tab_width` columns, counted from the start of `text` and of each line.
+/// A `tab_width` of 0 drops tabs.
+fn expand_tabs(text: &str, tab_width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            '\t' if tab_width > 0 => {
+                let spaces = tab_width - column % tab_width;
+                out.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            '\t' => {}
+            '\n' => {
+                out.push(c);
+                column = 0;
+            }
+            c => {
+                out.push(c);
+                column += unicode_width::UnicodeWidthChar::width(c).unwrap_or(0);
+            }
+        }
+    }
+    out
+}
+
+/// Finds bare URLs (`http://`, `https://`, `www.`) in plain text.
+///
+/// Returns byte ranges into `text`. A URL runs until whitespace or `<`,
+/// then trailing sentence punctuation is trimmed so `see https://x.com.`
+/// doesn't swallow the period. A trailing `)` is kept only when the URL
+/// contains a matching `(` (e.g. Wikipedia links).
+fn find_bare_urls(text: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    while pos < text.len() {
+        let rest = &text[pos..];
+        let Some(offset) = ["https://", "http://", "www."]
+            .iter()
+            .filter_map(|scheme| rest.find(scheme))
+            .min()
+        else {
+            break;
+        };
+        let start = pos + offset;
+        // Require a word boundary before the URL (so "xhttp://" doesn't match).
+        let boundary = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || "([<\"'".contains(c));
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || c == '<')
+            .unwrap_or(text.len() - start);
+        let mut end = start + len;
+        loop {
+            let candidate = &text[start..end];
+            let Some(last) = candidate.chars().next_back() else { break };
+            let unbalanced_paren =
+                last == ')' && candidate.matches('(').count() < candidate.matches(')').count();
+            if ".,:;!?'\"*_".contains(last) || unbalanced_paren {
+                end -= last.len_utf8();
+            } else {
+                break;
+            }
+        }
+        let host_len = text[start..end]
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_start_matches("www.")
+            .len();
+        if boundary && host_len > 0 {
+            ranges.push(start..end);
+        }
+        pos = start + len.max(1);
+    }
+    ranges
+}
+
+/// Finds issue references (`#123`) and commit hashes (7–40 lowercase hex
+/// digits, with at least one digit and one letter) in plain text.
+///
+/// Deliberately conservative: a match must be a whole word, and one that
+/// is part of a path, file name or email (`a1b2c3d.png`, `x/#1`) is skipped.
+fn find_references(text: &str) -> Vec<std::ops::Range<usize>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '#';
+    let joins = |c: char| "./-:@".contains(c);
+    let mut ranges = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut prev: Option<char> = None;
+    while let Some((start, c)) = chars.next() {
+        if !is_word(c) {
+            prev = Some(c);
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some((i, c)) = chars.next_if(|&(_, c)| is_word(c)) {
+            end = i + c.len_utf8();
+        }
+        let mut after = text[end..].chars();
+        let joined_after = after.next().is_some_and(joins)
+            && after.next().is_some_and(|c| c.is_alphanumeric());
+        if !prev.is_some_and(joins) && !joined_after && is_reference(&text[start..end]) {
+            ranges.push(start..end);
+        }
+        prev = text[..end].chars().next_back();
+    }
+    ranges
+}
+
+/// Returns true for `#` and up to 9 digits, or a plausible commit hash.
+fn is_reference(word: &str) -> bool {
+    if let Some(number) = word.strip_prefix('#') {
+        return (1..=9).contains(&number.len()) && number.bytes().all(|b| b.is_ascii_digit());
+    }
+    (7..=40).contains(&word.len())
+        && word.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && word.bytes().any(|b| b.is_ascii_digit())
+        && word.bytes().any(|b| b.is_ascii_alphabetic())
+}
+
+/// Returns the URL a reference found by `find_references()` points to in
+/// the repository at `repo_url`: its issue (`/issues/N`) or commit
+/// (`/commit/HASH`) page. `None` if `reference` is not a reference.
+pub fn reference_url(repo_url: &str, reference: &str) -> Option<String> {
+    if !is_reference(reference) {
+        return None;
+    }
+    let repo_url = repo_url.trim_end_matches('/');
+    Some(match reference.strip_prefix('#') {
+        Some(number) => format!("{repo_url}/issues/{number}"),
+        None => format!("{repo_url}/commit/{reference}"),
+    })
+}
+
 /// Computes the effective style by merging the current base style with
 /// all active inline modifiers from the style stack.
 fn effective_style(style_stack: &[Style]) -> Style {
@@ -118,6 +684,14 @@ fn heading_level_to_u8(level: HeadingLevel) -> u8 {
 /// the final `Vec<RenderedBlock>`. Not part of the public API.
 struct ParseContext<'a> {
     highlighter: &'a crate::highlight::Highlighter,
+    options: &'a ParseOptions,
+    /// Image loader plus the maximum image size in cells; `None` renders
+    /// every image as inline alt text.
+    images: Option<(&'a mut ImageManager, (u16, u16))>,
+    /// A paragraph-leading image that may become a standalone `Image` block:
+    /// its URL, its alt text, and the span count when the image ended. It
+    /// stays a candidate only if no spans are added before the paragraph ends.
+    image_candidate: Option<(String, String, usize)>,
     blocks: Vec<RenderedBlock>,
     /// Block-level state machine (never empty while parsing).
     state_stack: Vec<ParserState>,
@@ -125,35 +699,254 @@ struct ParseContext<'a> {
     style_stack: Vec<Style>,
     /// Spans accumulated for the block currently being built.
     current_spans: Vec<StyledSpan>,
+    /// Number of open `Tag::Link`s; bare-URL detection is skipped inside links.
+    link_depth: u32,
+    /// Source offset where the last top-level block ended; the text from
+    /// here to the next block's start holds the blank lines between them.
+    last_block_end: usize,
+    /// `(index in blocks, 1-based source line)` where each top-level
+    /// markdown construct started, with strictly increasing indexes.
+    block_lines: Vec<(usize, usize)>,
+    /// Line and offset of the last top-level start, to count lines from.
+    line_cursor: (usize, usize),
+    /// Source of each `CodeBlock`, nested ones included, in document order;
+    /// `None` for blocks built already styled (front matter). Highlighting
+    /// is deferred to the end of `process()` so it can run in parallel.
+    code_sources: Vec<Option<String>>,
+    /// Open `<details>` sections: where each one's body starts in `blocks`
+    /// and its summary so far.
+    details_stack: Vec<(usize, Vec<StyledSpan>)>,
+    /// Index in `blocks` of the heading a task list may annotate: set when a
+    /// heading ends, taken by the next top-level block.
+    task_heading: Option<usize>,
+    /// `(done, total)` tasks of the list being counted for `task_heading`.
+    task_tally: Option<(usize, usize)>,
+    /// Alt texts of the images in the current paragraph while it holds
+    /// nothing but images and links; `None` once it has other content.
+    badges: Option<Vec<String>>,
+    /// Index in `current_spans` where the paragraph's current line starts,
+    /// after its last soft or hard break.
+    line_start: usize,
 }
 
 impl<'a> ParseContext<'a> {
-    fn new(highlighter: &'a crate::highlight::Highlighter) -> Self {
+    fn new(highlighter: &'a crate::highlight::Highlighter, options: &'a ParseOptions) -> Self {
         Self {
             highlighter,
+            options,
+            images: None,
+            image_candidate: None,
             blocks: Vec::new(),
             state_stack: vec![ParserState::TopLevel],
             style_stack: Vec::new(),
             current_spans: Vec::new(),
+            link_depth: 0,
+            last_block_end: 0,
+            block_lines: Vec::new(),
+            line_cursor: (1, 0),
+            code_sources: Vec::new(),
+            details_stack: Vec::new(),
+            task_heading: None,
+            task_tally: None,
+            badges: None,
+            line_start: 0,
         }
     }
 
-    /// Drives the pulldown-cmark event stream and returns the finished blocks.
-    fn process(mut self, source: &str) -> Vec<RenderedBlock> {
-        let options =
-            Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    /// Drives the pulldown-cmark event stream and returns the finished
+    /// blocks, with the source line each one starts on.
+    fn process(mut self, source: &str) -> (Vec<RenderedBlock>, Vec<usize>) {
+        let mut options = Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TABLES
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+        if self.options.smart_punctuation {
+            options |= Options::ENABLE_SMART_PUNCTUATION;
+        }
+        if self.options.math {
+            options |= Options::ENABLE_MATH;
+        }
 
-        for event in Parser::new_ext(source, options) {
+        // Merge adjacent Text events (pulldown-cmark splits at `_`, `*`, ...)
+        // so bare-URL detection sees each run of text whole.
+        let events = Parser::new_ext(source, options).into_offset_iter();
+        for (event, range) in TextMergeWithOffset::new(events) {
             if self.state_stack.is_empty() {
-                // State stack underflow — parser invariant violated. Stop here
-                // rather than panic so partially-parsed output is still returned.
+                // State stack underflow — parser invariant violated (every
+                // pop answers a matching End, so balanced events can't cause
+                // it). Start over at the top level, with no inline styles
+                // open, rather than drop the rest of the document.
                 debug_assert!(false, "parser state stack underflow");
-                break;
+                self.state_stack.push(ParserState::TopLevel);
+                self.style_stack.clear();
+                self.link_depth = 0;
+            }
+            if self.options.preserve_blank_lines {
+                self.preserve_blank_lines(source, &event, range.clone());
             }
+            self.track_block_line(source, &event, range.start);
             self.on_event(event);
         }
 
-        self.blocks
+        // An unclosed `<details>` runs to the end of the document.
+        while !self.details_stack.is_empty() {
+            self.close_details();
+        }
+        self.highlight_code_blocks();
+        let lines = self.source_lines();
+        (self.blocks, lines)
+    }
+
+    /// Records the source line of each top-level construct as it starts.
+    /// Of constructs that start the same block (a `<details>` tag and its
+    /// body), the first counts. Dropped HTML is skipped.
+    fn track_block_line(&mut self, source: &str, event: &Event, start: usize) {
+        let at_top_level = matches!(self.state_stack.as_slice(), [ParserState::TopLevel]);
+        let dropped = matches!(event, Event::Start(Tag::HtmlBlock))
+            && self.options.html != HtmlMode::Render;
+        if !at_top_level || dropped || !matches!(event, Event::Start(_) | Event::Rule) {
+            return;
+        }
+        let (line, offset) = self.line_cursor;
+        let line = line + source.get(offset..start).map_or(0, |gap| gap.matches('\n').count());
+        self.line_cursor = (line, start);
+        if self.block_lines.last().is_none_or(|&(block, _)| block < self.blocks.len()) {
+            self.block_lines.push((self.blocks.len(), line));
+        }
+    }
+
+    /// Expands `block_lines` to one line per block: each block starts on
+    /// the line of the construct it came from.
+    fn source_lines(&self) -> Vec<usize> {
+        let mut entries = self.block_lines.iter().peekable();
+        let mut line = 1;
+        (0..self.blocks.len())
+            .map(|block| {
+                while let Some(&(_, start)) = entries.next_if(|&&(first, _)| first <= block) {
+                    line = start;
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Fills in `highlighted_lines` for every `CodeBlock`, or sets up `lazy`
+    /// for long top-level ones with `lazy_highlight`.
+    ///
+    /// `highlight_code` is pure given the shared `Highlighter`, so blocks are
+    /// highlighted in parallel with rayon unless `jobs` is 1. Results are
+    /// collected in order and matched back to the blocks they came from.
+    fn highlight_code_blocks(&mut self) {
+        let sources = std::mem::take(&mut self.code_sources);
+        let mut languages = Vec::new();
+        for_each_code_block(&mut self.blocks, true, &mut |block, top_level| {
+            if let RenderedBlock::CodeBlock { language, .. } = block {
+                languages.push((language.to_string(), top_level));
+            }
+        });
+        debug_assert_eq!(languages.len(), sources.len(), "code block count mismatch");
+        // Only for highlighting: the block keeps no language label.
+        if self.options.guess_language {
+            for (code, (language, _)) in sources.iter().zip(&mut languages) {
+                if let Some(code) = code
+                    && language.is_empty()
+                {
+                    *language = self.highlighter.guess_language(code).unwrap_or_default();
+                }
+            }
+        }
+
+        let (highlighter, theme) = (self.highlighter, self.options.theme.as_str());
+        let comments = self.options.comments;
+        let raw_ansi = self.options.raw_ansi;
+        let is_ansi = |language: &str| {
+            raw_ansi && ["ansi", "console"].iter().any(|l| language.eq_ignore_ascii_case(l))
+        };
+
+        // Lazy highlighting keeps syntect state, which can't cross threads,
+        // so it is set up here. Stripped comments can drop lines, which
+        // needs the whole block highlighted.
+        let lazy = self.options.lazy_highlight && comments == Comments::Keep;
+        let lazies: Vec<Option<(Vec<Line<'static>>, LazyHighlight)>> = sources
+            .iter()
+            .zip(&languages)
+            .map(|(code, (language, top_level))| {
+                let code = code
+                    .as_ref()
+                    .filter(|code| lazy && *top_level && !is_ansi(language) && is_long_code(code))?;
+                highlighter.highlight_lazily(code, language, theme)
+            })
+            .collect();
+
+        // The rest, as (code, language).
+        let work: Vec<Option<(&str, &str)>> = sources
+            .iter()
+            .zip(&languages)
+            .zip(&lazies)
+            .map(|((code, (language, _)), lazy)| {
+                code.as_deref().filter(|_| lazy.is_none()).map(|code| (code, language.as_str()))
+            })
+            .collect();
+        let highlight = |work: &Option<(&str, &str)>| {
+            work.map(|(code, language)| {
+                if is_ansi(language) {
+                    crate::highlight::ansi_to_lines(code)
+                } else {
+                    highlighter.highlight_code_with(code, language, theme, comments)
+                }
+            })
+        };
+        let highlighted: Vec<Option<Vec<Line<'static>>>> = match self.options.jobs {
+            1 => work.iter().map(highlight).collect(),
+            0 => work.par_iter().map(highlight).collect(),
+            jobs => match rayon::ThreadPoolBuilder::new().num_threads(jobs).build() {
+                Ok(pool) => pool.install(|| work.par_iter().map(highlight).collect()),
+                Err(err) => {
+                    eprintln!("mdink: could not start {jobs} highlighting threads: {err}");
+                    work.iter().map(highlight).collect()
+                }
+            },
+        };
+
+        let mut highlighted = highlighted.into_iter().zip(lazies);
+        for_each_code_block(&mut self.blocks, true, &mut |block, _| {
+            let Some((lines, lazy_highlight)) = highlighted.next() else {
+                return;
+            };
+            if let RenderedBlock::CodeBlock { highlighted_lines, lazy, .. } = block {
+                if let Some((lines, lazy_highlight)) = lazy_highlight {
+                    *highlighted_lines = lines;
+                    *lazy = Some(Rc::new(lazy_highlight));
+                } else if let Some(lines) = lines {
+                    *highlighted_lines = lines;
+                }
+            }
+        });
+    }
+
+    /// Before each top-level block, emits a `Spacer` for the blank lines the
+    /// author left beyond the first (capped at `MAX_BLANK_LINES` in total).
+    ///
+    /// pulldown-cmark drops blank lines, so they are counted in the source
+    /// between the previous top-level block's end and this one's start.
+    fn preserve_blank_lines(&mut self, source: &str, event: &Event, range: std::ops::Range<usize>) {
+        let at_top_level = matches!(self.state_stack.as_slice(), [ParserState::TopLevel]);
+        if !at_top_level || !matches!(event, Event::Start(_) | Event::Rule) {
+            return;
+        }
+        let mut gap = source.get(self.last_block_end..range.start).unwrap_or("");
+        // Some blocks (fenced code) end before their closing line's newline:
+        // the rest of that line is not a blank line.
+        if self.last_block_end > 0 && !source[..self.last_block_end].ends_with('\n') {
+            gap = gap.split_once('\n').map_or("", |(_, rest)| rest);
+        }
+        self.last_block_end = range.end;
+        let blank_lines = longest_blank_run(gap).min(MAX_BLANK_LINES);
+        // The layout already puts one blank line before every block,
+        // including the spacer itself.
+        if blank_lines >= 2 && !self.blocks.is_empty() {
+            self.blocks.push(RenderedBlock::Spacer { lines: blank_lines as u16 - 2 });
+        }
     }
 
     // ── Event routing ────────────────────────────────────────────────────────
@@ -162,6 +955,10 @@ impl<'a> ParseContext<'a> {
     fn on_event(&mut self, event: Event) {
         if matches!(self.state_stack.last(), Some(ParserState::InCodeBlock { .. })) {
             self.on_code_block_event(event);
+        } else if matches!(self.state_stack.last(), Some(ParserState::InFrontMatter { .. })) {
+            self.on_front_matter_event(event);
+        } else if matches!(self.state_stack.last(), Some(ParserState::InHtmlBlock { .. })) {
+            self.on_html_block_event(event);
         } else if matches!(self.state_stack.last(), Some(ParserState::Skipping { .. })) {
             self.on_skipping_event(event);
         } else {
@@ -171,8 +968,8 @@ impl<'a> ParseContext<'a> {
 
     /// Handles events when inside a fenced/indented code block.
     ///
-    /// Accumulates text into the buffer; on `End(CodeBlock)` runs syntax
-    /// highlighting and emits the finished `CodeBlock` block.
+    /// Accumulates text into the buffer; on `End(CodeBlock)` emits a
+    /// `CodeBlock` whose lines are highlighted once parsing is done.
     fn on_code_block_event(&mut self, event: Event) {
         match event {
             Event::Text(text) => {
@@ -183,16 +980,20 @@ impl<'a> ParseContext<'a> {
                 }
             }
             Event::End(TagEnd::CodeBlock) => {
-                if let Some(ParserState::InCodeBlock { language, buffer }) =
+                if let Some(ParserState::InCodeBlock { language, title, buffer }) =
                     self.state_stack.pop()
                 {
-                    let highlighted_lines = self.highlighter.highlight_code(
-                        &buffer,
-                        &language,
-                        "base16-ocean.dark",
-                    );
-                    self.blocks
-                        .push(RenderedBlock::CodeBlock { language, highlighted_lines });
+                    let buffer = match self.options.tab_width {
+                        Some(tab_width) => expand_tabs(&buffer, tab_width),
+                        None => buffer,
+                    };
+                    self.code_sources.push(Some(buffer));
+                    self.blocks.push(RenderedBlock::CodeBlock {
+                        language,
+                        title,
+                        highlighted_lines: Vec::new(),
+                        lazy: None,
+                    });
                 }
             }
             // Ignore all other events (syntax, meta) inside a code block.
@@ -200,6 +1001,115 @@ impl<'a> ParseContext<'a> {
         }
     }
 
+    /// Handles events inside the front matter block: accumulates its text and,
+    /// on `End(MetadataBlock)`, renders it as `options.frontmatter` says.
+    fn on_front_matter_event(&mut self, event: Event) {
+        match event {
+            Event::Text(text) => {
+                if let Some(ParserState::InFrontMatter { buffer }) = self.state_stack.last_mut() {
+                    buffer.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                if let Some(ParserState::InFrontMatter { buffer }) = self.state_stack.pop() {
+                    self.end_front_matter(&buffer);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end_front_matter(&mut self, yaml: &str) {
+        match self.options.frontmatter {
+            FrontMatter::Hide => {}
+            FrontMatter::Show => {
+                let dim = Style::default().add_modifier(Modifier::DIM);
+                let highlighted_lines = yaml
+                    .lines()
+                    .map(|line| Line::from(Span::styled(line.to_string(), dim)))
+                    .collect();
+                self.code_sources.push(None);
+                self.blocks.push(RenderedBlock::CodeBlock {
+                    language: String::new(),
+                    title: None,
+                    highlighted_lines,
+                    lazy: None,
+                });
+            }
+            FrontMatter::Title => {
+                if let Some(title) = front_matter_title(yaml) {
+                    let style = self.heading_style(1);
+                    let content = vec![StyledSpan { text: title, style, reference: None }];
+                    self.blocks.push(RenderedBlock::Heading { level: 1, content });
+                }
+            }
+        }
+    }
+
+    /// Handles events inside a raw HTML block: accumulates the HTML and, on
+    /// `End(HtmlBlock)`, acts on any `<details>` tags in it.
+    fn on_html_block_event(&mut self, event: Event) {
+        match event {
+            Event::Html(html) => {
+                if let Some(ParserState::InHtmlBlock { buffer }) = self.state_stack.last_mut() {
+                    buffer.push_str(&html);
+                }
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                if let Some(ParserState::InHtmlBlock { buffer }) = self.state_stack.pop() {
+                    self.end_html_block(&buffer);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens and closes `<details>` sections for the tags in an HTML block,
+    /// in order. A `<summary>` sets the innermost open section's summary.
+    /// Every other tag is ignored.
+    fn end_html_block(&mut self, html: &str) {
+        // ASCII lowercasing keeps byte offsets, so `lower` indexes `html` too.
+        let lower = html.to_ascii_lowercase();
+        let mut pos = 0;
+        while let Some(offset) = lower[pos..].find('<') {
+            let tag = &lower[pos + offset..];
+            pos += offset + 1;
+            if is_tag(tag, "<details") {
+                self.details_stack.push((self.blocks.len(), Vec::new()));
+            } else if is_tag(tag, "</details") {
+                self.close_details();
+            } else if is_tag(tag, "<summary") {
+                let Some(open_end) = tag.find('>') else { continue };
+                // `tag` starts at `pos - 1`; the text starts after its `>`.
+                let text_start = pos + open_end;
+                let text_end =
+                    lower[text_start..].find("</summary").map_or(html.len(), |i| text_start + i);
+                let text = decode_entities(&strip_tags(&html[text_start..text_end]));
+                if let Some((_, summary)) = self.details_stack.last_mut() {
+                    let style = Style::default().add_modifier(Modifier::BOLD);
+                    *summary = vec![StyledSpan { text, style, reference: None }];
+                }
+                pos = text_end;
+            }
+        }
+    }
+
+    /// Closes the innermost `<details>`: the blocks since it opened become
+    /// its body. A missing summary reads "Details", as browsers show it.
+    fn close_details(&mut self) {
+        let Some((first_block, mut summary)) = self.details_stack.pop() else {
+            return;
+        };
+        if summary.iter().all(|span| span.text.is_empty()) {
+            let style = Style::default().add_modifier(Modifier::BOLD);
+            summary = vec![StyledSpan { text: "Details".to_string(), style, reference: None }];
+        }
+        let body = self.blocks.split_off(first_block);
+        self.blocks.push(RenderedBlock::Details { summary, body });
+        // The section starts where its first body block did.
+        self.block_lines.retain(|&(block, _)| block <= first_block);
+    }
+
     /// Handles events when inside an unrecognized block being skipped.
     ///
     /// Tracks nesting depth via `Skipping { depth }` so that nested
@@ -217,30 +1127,71 @@ impl<'a> ParseContext<'a> {
             }
             Event::End(_) if depth == 0 => {
                 self.state_stack.pop();
+                if let Some(tally) = self.task_tally.take() {
+                    self.annotate_task_heading(tally);
+                }
             }
             Event::End(_) => {
                 self.state_stack.pop();
                 self.state_stack.push(ParserState::Skipping { depth: depth - 1 });
             }
+            Event::TaskListMarker(checked) => {
+                if let Some((done, total)) = &mut self.task_tally {
+                    *done += usize::from(checked);
+                    *total += 1;
+                }
+            }
             _ => {}
         }
     }
 
     /// Dispatches normal (non-code-block, non-skipping) events.
     fn dispatch(&mut self, event: Event) {
+        // Only the block right after a heading can be its task list. While
+        // that list is being counted, the heading waits for it to end.
+        let task_heading = match event {
+            Event::Start(_) | Event::Rule if self.task_tally.is_none() => self.task_heading.take(),
+            _ => None,
+        };
+        // Text directly in a list item ends where a block inside it starts.
+        if matches!(
+            event,
+            Event::Start(
+                Tag::Paragraph
+                    | Tag::Heading { .. }
+                    | Tag::CodeBlock(_)
+                    | Tag::List(_)
+                    | Tag::BlockQuote(_)
+                    | Tag::HtmlBlock
+                    | Tag::Table(_)
+            ) | Event::Rule
+        ) {
+            self.end_item_text();
+        }
         match event {
             // ── Block-level start ────────────────────────────────────
             Event::Start(Tag::Heading { level, .. }) => self.start_heading(level),
             Event::Start(Tag::Paragraph) => self.start_paragraph(),
             Event::Start(Tag::CodeBlock(kind)) => self.start_code_block(kind),
+            Event::Start(Tag::HtmlBlock) if self.options.html == HtmlMode::Render => {
+                self.state_stack.push(ParserState::InHtmlBlock { buffer: String::new() });
+            }
+            Event::Start(Tag::MetadataBlock(_)) => {
+                self.state_stack.push(ParserState::InFrontMatter { buffer: String::new() });
+            }
 
             // ── Inline passthrough ───────────────────────────────────
-            // Links: render text in the italic font slot; URL is ignored.
+            // Links: render text in the link style; URL is ignored.
             Event::Start(Tag::Link { .. }) => {
-                self.push_style(Style::default().add_modifier(Modifier::ITALIC));
+                self.link_depth += 1;
+                self.push_style(self.options.link_style);
+            }
+            // Images: alt text is collected as unstyled spans (no style push),
+            // or skipped entirely when images are hidden.
+            Event::Start(Tag::Image { .. }) if !self.options.show_images => {
+                self.state_stack.push(ParserState::Skipping { depth: 0 });
             }
-            // Images: show alt text unstyled (no style push).
-            Event::Start(Tag::Image { .. }) => {}
+            Event::Start(Tag::Image { dest_url, .. }) => self.start_image(dest_url.to_string()),
 
             // ── Inline formatting ────────────────────────────────────
             Event::Start(Tag::Emphasis) => {
@@ -253,6 +1204,25 @@ impl<'a> ParseContext<'a> {
                 self.push_style(Style::default().add_modifier(Modifier::CROSSED_OUT));
             }
 
+            // A list right after a heading has its tasks counted when
+            // `task_summary` is on.
+            Event::Start(Tag::List(start)) => {
+                if task_heading.is_some() {
+                    self.task_heading = task_heading;
+                    self.task_tally = Some((0, 0));
+                }
+                self.state_stack.push(ParserState::InList { start, items: Vec::new() });
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                let first_block = self.blocks.len();
+                self.state_stack.push(ParserState::InBlockQuote { first_block, last_line: None });
+            }
+            Event::Start(Tag::Item) => {
+                self.current_spans.clear();
+                let first_block = self.blocks.len();
+                self.state_stack.push(ParserState::InListItem { first_block, task: None });
+            }
+
             // Any unrecognized block tag — skip until its matching End.
             // MUST be last among Start arms so it doesn't shadow specific variants above.
             Event::Start(_) => self.state_stack.push(ParserState::Skipping { depth: 0 }),
@@ -260,10 +1230,16 @@ impl<'a> ParseContext<'a> {
             // ── Block-level end ──────────────────────────────────────
             Event::End(TagEnd::Heading(_)) => self.end_heading(),
             Event::End(TagEnd::Paragraph) => self.end_paragraph(),
+            Event::End(TagEnd::Item) => self.end_list_item(),
+            Event::End(TagEnd::List(_)) => self.end_list(),
+            Event::End(TagEnd::BlockQuote(_)) => self.end_block_quote(),
 
             // ── Inline end ───────────────────────────────────────────
-            Event::End(TagEnd::Link) => self.pop_style(),
-            Event::End(TagEnd::Image) => {}
+            Event::End(TagEnd::Link) => {
+                self.link_depth = self.link_depth.saturating_sub(1);
+                self.pop_style();
+            }
+            Event::End(TagEnd::Image) => self.end_image(),
             Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough) => {
                 self.pop_style();
             }
@@ -271,27 +1247,33 @@ impl<'a> ParseContext<'a> {
             // ── Text content ─────────────────────────────────────────
             Event::Text(text) => self.push_text(&text),
             Event::Code(text) => self.push_inline_code(&text),
+            Event::InlineMath(latex) => self.push_inline_math(&latex),
+            Event::DisplayMath(latex) => self.push_display_math(&latex),
             Event::SoftBreak => self.push_soft_break(),
             Event::HardBreak => self.push_hard_break(),
             Event::Rule => self.blocks.push(RenderedBlock::ThematicBreak),
 
+            Event::TaskListMarker(checked) => self.set_task(checked),
+
             // ── Ignored ──────────────────────────────────────────────
             // End events for passthrough/skipped tags have no handler.
             Event::End(_) => {}
-            Event::TaskListMarker(_)
-            | Event::FootnoteReference(_)
+            Event::FootnoteReference(_)
             | Event::InlineHtml(_)
-            | Event::InlineMath(_)
-            | Event::DisplayMath(_)
             | Event::Html(_) => {}
         }
     }
 
     // ── Block handlers ───────────────────────────────────────────────────────
 
+    /// Returns the style for a heading of `level`, in the configured colors.
+    fn heading_style(&self, level: u8) -> Style {
+        heading_style(&self.options.heading_colors, level)
+    }
+
     fn start_heading(&mut self, level: HeadingLevel) {
         let lvl = heading_level_to_u8(level);
-        self.style_stack.push(default_heading_style(lvl));
+        self.style_stack.push(self.heading_style(lvl));
         self.current_spans.clear();
         self.state_stack.push(ParserState::InHeading { level: lvl });
     }
@@ -314,36 +1296,200 @@ impl<'a> ParseContext<'a> {
         };
         let content = std::mem::take(&mut self.current_spans);
         self.blocks.push(RenderedBlock::Heading { level, content });
+        if self.options.task_summary {
+            self.task_heading = Some(self.blocks.len() - 1);
+        }
+    }
+
+    /// Appends a dim `(done/total)` to the heading the counted task list
+    /// follows. Lists without task items leave the heading alone.
+    fn annotate_task_heading(&mut self, (done, total): (usize, usize)) {
+        let heading = self.task_heading.take().and_then(|i| self.blocks.get_mut(i));
+        if total == 0 {
+            return;
+        }
+        if let Some(RenderedBlock::Heading { level, content }) = heading {
+            // `heading` borrows `blocks`, so the options are read directly.
+            let colors = &self.options.heading_colors;
+            let style = heading_style(colors, *level).add_modifier(Modifier::DIM);
+            content.push(StyledSpan { text: format!(" ({done}/{total})"), style, reference: None });
+        }
+    }
+
+    /// Turns text collected directly in a list item (a tight list item, or
+    /// the part before a nested block) into a paragraph of the item.
+    fn end_item_text(&mut self) {
+        if matches!(self.state_stack.last(), Some(ParserState::InListItem { .. }))
+            && !self.current_spans.is_empty()
+        {
+            let content = std::mem::take(&mut self.current_spans);
+            self.blocks.push(RenderedBlock::Paragraph { content });
+        }
+    }
+
+    /// Marks the enclosing list item as a task and counts it for the task
+    /// heading. The marker comes inside the item's paragraph in loose lists.
+    fn set_task(&mut self, checked: bool) {
+        let item = self.state_stack.iter_mut().rev().find_map(|state| match state {
+            ParserState::InListItem { task, .. } => Some(task),
+            _ => None,
+        });
+        if let Some(task) = item {
+            *task = Some(checked);
+        }
+        if let Some((done, total)) = &mut self.task_tally {
+            *done += usize::from(checked);
+            *total += 1;
+        }
+    }
+
+    /// Closes a list item: the blocks since it started become the item.
+    fn end_list_item(&mut self) {
+        self.end_item_text();
+        let Some(ParserState::InListItem { first_block, task }) = self.state_stack.pop() else {
+            debug_assert!(false, "End(Item) without InListItem state");
+            return;
+        };
+        let blocks = self.blocks.split_off(first_block);
+        if let Some(ParserState::InList { items, .. }) = self.state_stack.last_mut() {
+            items.push(ListItem { task, blocks });
+        }
+    }
+
+    /// Closes a list, annotating the task heading once the outermost list
+    /// it counted ends.
+    fn end_list(&mut self) {
+        let Some(ParserState::InList { start, items }) = self.state_stack.pop() else {
+            debug_assert!(false, "End(List) without InList state");
+            return;
+        };
+        self.blocks.push(RenderedBlock::List { start, items });
+        let in_list = self.state_stack.iter().any(|s| matches!(s, ParserState::InList { .. }));
+        if !in_list && let Some(tally) = self.task_tally.take() {
+            self.annotate_task_heading(tally);
+        }
+    }
+
+    /// Closes a block quote: the blocks since it started become its body,
+    /// less an attribution on the last line of its last paragraph.
+    fn end_block_quote(&mut self) {
+        let Some(ParserState::InBlockQuote { first_block, last_line }) = self.state_stack.pop()
+        else {
+            debug_assert!(false, "End(BlockQuote) without InBlockQuote state");
+            return;
+        };
+        let mut body = self.blocks.split_off(first_block);
+        let attribution = match last_line {
+            Some((block, span)) if block + 1 == first_block + body.len() => {
+                split_attribution(&mut body, span)
+            }
+            _ => None,
+        };
+        self.blocks.push(RenderedBlock::BlockQuote { body, attribution });
     }
 
     fn start_paragraph(&mut self) {
         self.current_spans.clear();
+        self.line_start = 0;
+        self.image_candidate = None;
+        self.badges = Some(Vec::new());
         self.state_stack.push(ParserState::InParagraph);
     }
 
     fn end_paragraph(&mut self) {
         self.state_stack.pop();
         let content = std::mem::take(&mut self.current_spans);
+        // A paragraph consisting of a single image becomes an Image block
+        // if the image loads; otherwise it keeps the alt text.
+        if let Some((dest_url, alt_text, end)) = self.image_candidate.take()
+            && end == content.len()
+            && let Some(block) = self.load_image(&dest_url, alt_text)
+        {
+            self.blocks.push(block);
+            return;
+        }
+        // All of the paragraph may have gone to display math already.
+        if content.is_empty() && self.options.math {
+            return;
+        }
+        if let Some(badges) = self.badges.take()
+            && self.options.collapse_badges
+            && !badges.is_empty()
+        {
+            let style = Style::default().add_modifier(Modifier::DIM);
+            let content = vec![StyledSpan { text: badges.join(" · "), style, reference: None }];
+            self.blocks.push(RenderedBlock::Paragraph { content });
+            return;
+        }
         self.blocks.push(RenderedBlock::Paragraph { content });
+        if let Some(ParserState::InBlockQuote { last_line, .. }) = self.state_stack.last_mut() {
+            *last_line = Some((self.blocks.len() - 1, self.line_start));
+        }
+    }
+
+    /// Opens an image: emits the `🖼 ` marker; the alt text follows as
+    /// ordinary text events.
+    fn start_image(&mut self, dest_url: String) {
+        let first_span = self.current_spans.len();
+        let style = effective_style(&self.style_stack);
+        self.current_spans.push(StyledSpan { text: "🖼 ".to_string(), style, reference: None });
+        self.state_stack.push(ParserState::InImage { dest_url, first_span });
+    }
+
+    /// Closes an image: emits the dimmed ` (path)` suffix and records the
+    /// image as a standalone candidate if it opened a paragraph.
+    fn end_image(&mut self) {
+        let Some(ParserState::InImage { dest_url, first_span }) = self.state_stack.pop() else {
+            debug_assert!(false, "End(Image) without InImage state");
+            return;
+        };
+        // Skip the marker span when collecting the alt text.
+        let alt_text: String = self.current_spans[first_span + 1..]
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+
+        let path = if dest_url.starts_with("data:") { "data URI" } else { dest_url.as_str() };
+        if !path.is_empty() {
+            let style = effective_style(&self.style_stack).add_modifier(Modifier::DIM);
+            let text = format!(" ({path})");
+            self.current_spans.push(StyledSpan { text, style, reference: None });
+        }
+
+        if let Some(badges) = &mut self.badges {
+            badges.push(alt_text.clone());
+        }
+        // Only an image that opens a paragraph can stand alone.
+        if first_span == 0 && matches!(self.state_stack.last(), Some(ParserState::InParagraph)) {
+            self.image_candidate = Some((dest_url, alt_text, self.current_spans.len()));
+        }
+    }
+
+    /// Loads `dest_url` through the image manager, returning an `Image` block
+    /// on success.
+    fn load_image(&mut self, dest_url: &str, alt_text: String) -> Option<RenderedBlock> {
+        let (images, (max_width, max_height)) = self.images.as_mut()?;
+        let (protocol_index, width_cells, height_cells) =
+            images.load_image(dest_url, *max_width, *max_height).ok()?;
+        Some(RenderedBlock::Image {
+            protocol_index,
+            alt_text,
+            width_cells,
+            height_cells,
+        })
     }
 
     fn start_code_block(&mut self, kind: CodeBlockKind) {
+        let title = match &kind {
+            CodeBlockKind::Fenced(info) => fence_title(info),
+            CodeBlockKind::Indented => None,
+        };
         let language = match kind {
-            // pulldown-cmark yields the full info string (e.g. "rust,no_run" or
-            // "python title=\"x.py\""). Take only the first whitespace-delimited
-            // token so syntect lookup and the label display get the bare language name.
-            CodeBlockKind::Fenced(lang) => lang
-                .split_whitespace()
-                .next()
-                .unwrap_or("")
-                .split(',')
-                .next()
-                .unwrap_or("")
-                .to_string(),
+            CodeBlockKind::Fenced(info) => fence_language(&info),
             CodeBlockKind::Indented => String::new(),
         };
         self.state_stack
-            .push(ParserState::InCodeBlock { language, buffer: String::new() });
+            .push(ParserState::InCodeBlock { language, title, buffer: String::new() });
     }
 
     // ── Style stack helpers ──────────────────────────────────────────────────
@@ -359,24 +1505,127 @@ impl<'a> ParseContext<'a> {
 
     // ── Span builders ────────────────────────────────────────────────────────
 
+    /// Pushes a text span, splitting bare URLs (and, with `repo_url`, issue
+    /// and commit references) out into link-styled spans.
     fn push_text(&mut self, text: &str) {
+        let expanded;
+        let text = match self.options.tab_width {
+            Some(tab_width) if text.contains('\t') => {
+                expanded = expand_tabs(text, tab_width);
+                expanded.as_str()
+            }
+            _ => text,
+        };
+        let in_image = matches!(self.state_stack.last(), Some(ParserState::InImage { .. }));
+        if self.link_depth == 0 && !in_image && !text.trim().is_empty() {
+            self.badges = None;
+        }
         let style = effective_style(&self.style_stack);
-        self.current_spans.push(StyledSpan { text: text.to_string(), style });
+        let urls = if self.link_depth == 0 { find_bare_urls(text) } else { Vec::new() };
+        // Each link is a bare URL (`None`) or a reference with its page.
+        let mut links: Vec<_> = urls.iter().map(|r| (r.clone(), None)).collect();
+        if self.link_depth == 0
+            && let Some(repo_url) = &self.options.repo_url
+        {
+            // References are looked for between the URLs, never inside one.
+            let gaps = std::iter::once(0)
+                .chain(urls.iter().map(|r| r.end))
+                .zip(urls.iter().map(|r| r.start).chain(std::iter::once(text.len())));
+            for (gap_start, gap_end) in gaps {
+                links.extend(find_references(&text[gap_start..gap_end]).into_iter().map(|r| {
+                    let range = r.start + gap_start..r.end + gap_start;
+                    let url = reference_url(repo_url, &text[range.clone()]);
+                    (range, url)
+                }));
+            }
+            links.sort_by_key(|(range, _)| range.start);
+        }
+        let mut pos = 0;
+        for (range, reference) in links {
+            if range.start > pos {
+                let text = text[pos..range.start].to_string();
+                self.current_spans.push(StyledSpan { text, style, reference: None });
+            }
+            self.current_spans.push(StyledSpan {
+                text: text[range.clone()].to_string(),
+                style: style.patch(self.options.link_style),
+                reference,
+            });
+            pos = range.end;
+        }
+        if pos < text.len() || pos == 0 {
+            let text = text[pos..].to_string();
+            self.current_spans.push(StyledSpan { text, style, reference: None });
+        }
     }
 
+    /// Inline code is a single span on one line: line endings become spaces,
+    /// as CommonMark specifies for code spans (pulldown-cmark already does this
+    /// for well-formed input), so a `\n` can never act as a hard break.
+    /// Inside a link it keeps its own colors but takes the link's modifiers.
     fn push_inline_code(&mut self, text: &str) {
-        self.current_spans
-            .push(StyledSpan { text: text.to_string(), style: default_code_style() });
+        let mut style = self.options.code_style;
+        if self.link_depth == 0 {
+            self.badges = None;
+        } else {
+            style = style.add_modifier(self.options.link_style.add_modifier);
+        }
+        let text = text.replace("\r\n", " ").replace(['\n', '\r'], " ");
+        self.current_spans.push(StyledSpan { text, style, reference: None });
+    }
+
+    /// Appends inline math as Unicode, or as the raw `$...$` it was written
+    /// as when it can't be approximated.
+    fn push_inline_math(&mut self, latex: &str) {
+        if self.link_depth == 0 {
+            self.badges = None;
+        }
+        let text = match crate::math::to_unicode(latex) {
+            Some(text) => text.replace('\n', " "),
+            None => format!("${latex}$"),
+        };
+        let style = effective_style(&self.style_stack);
+        self.current_spans.push(StyledSpan { text, style, reference: None });
+    }
+
+    /// Emits display math as its own `MathBlock`, splitting the paragraph it
+    /// sits in: the text before it becomes a paragraph of its own. Inside a
+    /// heading or image alt text it stays inline.
+    fn push_display_math(&mut self, latex: &str) {
+        if !matches!(self.state_stack.last(), Some(ParserState::InParagraph)) {
+            self.push_inline_math(latex);
+            return;
+        }
+        let mut before = std::mem::take(&mut self.current_spans);
+        while before.last().is_some_and(|span| span.text.trim().is_empty()) {
+            before.pop();
+        }
+        if !before.is_empty() {
+            self.blocks.push(RenderedBlock::Paragraph { content: before });
+        }
+        self.image_candidate = None;
+        let lines = match crate::math::to_unicode(latex) {
+            Some(text) => text.lines().map(str::to_string).collect(),
+            None => latex.trim().lines().map(|line| line.trim().to_string()).collect(),
+        };
+        self.blocks.push(RenderedBlock::MathBlock { lines });
     }
 
     fn push_soft_break(&mut self) {
+        // Nothing to separate at the start of a paragraph, which is where
+        // the text after display math resumes.
+        if self.current_spans.is_empty() {
+            return;
+        }
         let style = effective_style(&self.style_stack);
-        self.current_spans.push(StyledSpan { text: " ".to_string(), style });
+        self.current_spans.push(StyledSpan { text: " ".to_string(), style, reference: None });
+        self.line_start = self.current_spans.len();
     }
 
     fn push_hard_break(&mut self) {
         let style = effective_style(&self.style_stack);
-        self.current_spans.push(StyledSpan { text: "\n".to_string(), style });
+        self.current_spans.push(StyledSpan { text: "\n".to_string(), style, reference: None });
+        self.line_start = self.current_spans.len();
     }
 }
 
@@ -386,9 +1635,140 @@ impl<'a> ParseContext<'a> {
 ///
 /// Enables GFM extensions (strikethrough, tables, tasklists) so that
 /// user markdown containing these features doesn't break — even though
-/// tables and lists aren't rendered until later phases.
+/// tables aren't rendered until a later phase.
 pub fn parse(source: &str, highlighter: &crate::highlight::Highlighter) -> Vec<RenderedBlock> {
-    ParseContext::new(highlighter).process(source)
+    parse_with_options(source, highlighter, &ParseOptions::default())
+}
+
+/// Like `parse()`, with explicit `ParseOptions`.
+pub fn parse_with_options(
+    source: &str,
+    highlighter: &crate::highlight::Highlighter,
+    options: &ParseOptions,
+) -> Vec<RenderedBlock> {
+    ParseContext::new(highlighter, options).process(source).0
+}
+
+/// Returns `source` itself as a single code block highlighted as markdown,
+/// for viewing what the document actually contains.
+pub fn parse_as_source(
+    source: &str,
+    highlighter: &crate::highlight::Highlighter,
+    options: &ParseOptions,
+) -> Vec<RenderedBlock> {
+    let lazy = (options.lazy_highlight && is_long_code(source))
+        .then(|| highlighter.highlight_lazily(source, "markdown", &options.theme))
+        .flatten();
+    let (highlighted_lines, lazy) = match lazy {
+        Some((lines, lazy)) => (lines, Some(Rc::new(lazy))),
+        None => (highlighter.highlight_code(source, "markdown", &options.theme), None),
+    };
+    vec![RenderedBlock::CodeBlock {
+        language: "markdown".to_string(),
+        title: None,
+        highlighted_lines,
+        lazy,
+    }]
+}
+
+/// Like `parse_with_options()`, but paragraphs consisting of a single local
+/// image become `RenderedBlock::Image` blocks loaded through `images`.
+///
+/// `max_size` is the largest image size in cells (columns, rows). Images
+/// that fail to load keep the alt-text rendering of `parse()`.
+pub fn parse_with_images(
+    source: &str,
+    highlighter: &crate::highlight::Highlighter,
+    options: &ParseOptions,
+    images: &mut ImageManager,
+    max_size: (u16, u16),
+) -> Vec<RenderedBlock> {
+    parse_with_source_lines(source, highlighter, options, Some((images, max_size))).0
+}
+
+/// Like `parse_with_images()`, or `parse_with_options()` without `images`,
+/// but also returns the 1-based source line each block starts on, one per
+/// block. Best effort: blocks made from one markdown construct (a heading
+/// and its task count, a `<details>` section) share its first line.
+pub fn parse_with_source_lines(
+    source: &str,
+    highlighter: &crate::highlight::Highlighter,
+    options: &ParseOptions,
+    images: Option<(&mut ImageManager, (u16, u16))>,
+) -> (Vec<RenderedBlock>, Vec<usize>) {
+    let mut ctx = ParseContext::new(highlighter, options);
+    ctx.images = images;
+    ctx.process(source)
+}
+
+/// Returns the range of `blocks` holding a changelog's newest entry
+/// (`--latest`): from the first level-2 heading up to the next heading of
+/// level 1 or 2, or the end. All of `blocks` when there is no such heading.
+pub fn latest_entry(blocks: &[RenderedBlock]) -> std::ops::Range<usize> {
+    let Some(start) =
+        blocks.iter().position(|block| matches!(block, RenderedBlock::Heading { level: 2, .. }))
+    else {
+        return 0..blocks.len();
+    };
+    let end = blocks[start + 1..]
+        .iter()
+        .position(|block| matches!(block, RenderedBlock::Heading { level: 1 | 2, .. }))
+        .map_or(blocks.len(), |i| start + 1 + i);
+    start..end
+}
+
+/// Returns `source` without its YAML front matter: everything after the
+/// closing `---` (or `...`) line, unchanged, or all of `source` when it
+/// doesn't open with front matter (`--strip-frontmatter-only`).
+pub fn strip_front_matter(source: &str) -> &str {
+    let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    match Parser::new_ext(source, options).into_offset_iter().next() {
+        // The block's range ends at the closing delimiter, before its newline.
+        Some((Event::Start(Tag::MetadataBlock(_)), range)) => {
+            let rest = &source[range.end..];
+            rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')).unwrap_or(rest)
+        }
+        _ => source,
+    }
+}
+
+/// Finds the code blocks in `source` whose lines are indented with both
+/// tabs and spaces, which misalign once tabs are expanded
+/// (`--warn-mixed-indent`). Returns the 1-based source line of each such
+/// block's start and its language, empty for an untagged block.
+pub fn mixed_indent_code_blocks(source: &str) -> Vec<(usize, String)> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    let mut found = Vec::new();
+    let mut block: Option<(usize, String, bool, bool)> = None;
+    for (event, range) in Parser::new_ext(source, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let line = source[..range.start].matches('\n').count() + 1;
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => fence_language(&info),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                block = Some((line, language, false, false));
+            }
+            Event::Text(text) => {
+                if let Some((_, _, tabs, spaces)) = &mut block {
+                    for line in text.lines() {
+                        let rest = line.trim_start_matches([' ', '\t']);
+                        let indent = &line[..line.len() - rest.len()];
+                        *tabs |= indent.contains('\t');
+                        *spaces |= indent.contains(' ');
+                    }
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((line, language, true, true)) = block.take() {
+                    found.push((line, language));
+                }
+            }
+            _ => {}
+        }
+    }
+    found
 }
 
 /// Allows `ParserState` to be used in debug_assert messages.
@@ -398,9 +1778,19 @@ impl std::fmt::Debug for ParserState {
             ParserState::TopLevel => write!(f, "TopLevel"),
             ParserState::InHeading { level } => write!(f, "InHeading({level})"),
             ParserState::InParagraph => write!(f, "InParagraph"),
+            ParserState::InImage { dest_url, .. } => write!(f, "InImage({dest_url})"),
             ParserState::InCodeBlock { language, .. } => {
                 write!(f, "InCodeBlock({language})")
             }
+            ParserState::InFrontMatter { .. } => write!(f, "InFrontMatter"),
+            ParserState::InHtmlBlock { .. } => write!(f, "InHtmlBlock"),
+            ParserState::InList { items, .. } => write!(f, "InList({} items)", items.len()),
+            ParserState::InListItem { first_block, .. } => {
+                write!(f, "InListItem({first_block})")
+            }
+            ParserState::InBlockQuote { first_block, .. } => {
+                write!(f, "InBlockQuote({first_block})")
+            }
             ParserState::Skipping { depth } => write!(f, "Skipping({depth})"),
         }
     }
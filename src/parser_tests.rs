@@ -37,6 +37,44 @@
         }
     }
 
+    #[test]
+    fn test_parser_setext_headings_levels_one_and_two() {
+        let blocks = parse("Title\n=====\n\nSub *title*\n---\n\ntext", h());
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(&blocks[0], RenderedBlock::Heading { level: 1, .. }));
+        assert_eq!(span_texts(&blocks[0]), vec!["Title"]);
+        assert!(matches!(&blocks[1], RenderedBlock::Heading { level: 2, .. }));
+        assert_eq!(span_texts(&blocks[1]), vec!["Sub ", "title"]);
+        // A setext underline can span several lines of heading text.
+        let blocks = parse("first\nsecond\n---", h());
+        assert!(matches!(&blocks[0], RenderedBlock::Heading { level: 2, .. }));
+        assert_eq!(span_texts(&blocks[0]).concat(), "first second");
+    }
+
+    #[test]
+    fn test_parser_source_lines_give_each_block_its_first_line() {
+        let md = "# Title\n\nsome\ntext\n\n```\ncode\n```\n\n<!-- dropped -->\n\n- a\n- b\n\n---\n";
+        let (blocks, lines) = parse_with_source_lines(md, h(), &ParseOptions::default(), None);
+        assert_eq!(lines.len(), blocks.len());
+        assert_eq!(lines, [1, 3, 6, 12, 15]);
+
+        // A `<details>` section is one block, at its opening tag.
+        let options = ParseOptions { html: HtmlMode::Render, ..ParseOptions::default() };
+        let (blocks, lines) = parse_with_source_lines(DETAILS_DOC, h(), &options, None);
+        assert!(matches!(blocks[1], RenderedBlock::Details { .. }));
+        assert_eq!(lines, [1, 3, 14]);
+    }
+
+    #[test]
+    fn test_parser_dashes_without_text_above_are_a_rule() {
+        // After a blank line, a list or at the start, `---` is a thematic break.
+        for md in ["---\n\ntext", "para\n\n---", "- item\n---", "***\n\n- - -"] {
+            let blocks = parse(md, h());
+            assert!(blocks.iter().any(|b| matches!(b, RenderedBlock::ThematicBreak)), "{md:?}");
+            assert!(!blocks.iter().any(|b| matches!(b, RenderedBlock::Heading { .. })), "{md:?}");
+        }
+    }
+
     #[test]
     fn test_parser_paragraph_plain_text() {
         let blocks = parse("Hello world", h());
@@ -169,9 +207,9 @@
 
     #[test]
     fn test_parser_heading_styles_are_distinct() {
-        let s1 = default_heading_style(1);
-        let s2 = default_heading_style(2);
-        let s3 = default_heading_style(3);
+        let s1 = heading_style(&DEFAULT_HEADING_COLORS, 1);
+        let s2 = heading_style(&DEFAULT_HEADING_COLORS, 2);
+        let s3 = heading_style(&DEFAULT_HEADING_COLORS, 3);
         assert_ne!(s1.fg, s2.fg);
         assert_ne!(s2.fg, s3.fg);
     }
@@ -251,6 +289,7 @@
             RenderedBlock::CodeBlock {
                 language,
                 highlighted_lines,
+                ..
             } => {
                 assert_eq!(language, "rust");
                 assert!(!highlighted_lines.is_empty());
@@ -259,6 +298,43 @@
         }
     }
 
+    #[test]
+    fn test_parser_long_code_block_highlighted_lazily_at_top_level() {
+        let code = "let x = 1;\n".repeat(LAZY_HIGHLIGHT_LINES + 1);
+        let source = format!("```rust\n{code}```\n\n- item\n\n  ```rust\n{code}  ```\n");
+        let lazy = ParseOptions { lazy_highlight: true, ..ParseOptions::default() };
+        let blocks = parse_with_options(&source, h(), &lazy);
+        let RenderedBlock::CodeBlock { highlighted_lines, lazy: Some(highlight), .. } = &blocks[0]
+        else {
+            panic!("expected a lazily highlighted CodeBlock");
+        };
+        assert_eq!(highlight.line_count(), LAZY_HIGHLIGHT_LINES + 1);
+        assert_eq!(highlighted_lines.len(), LAZY_HIGHLIGHT_LINES + 1);
+        assert_eq!(highlighted_lines[0].spans.len(), 1, "unstyled until shown");
+        // Nested blocks are highlighted up front.
+        let RenderedBlock::List { items, .. } = &blocks[1] else {
+            panic!("expected List");
+        };
+        assert!(matches!(&items[0].blocks[1], RenderedBlock::CodeBlock { lazy: None, .. }));
+
+        let blocks = parse_with_options(&source, h(), &ParseOptions::default());
+        assert!(matches!(&blocks[0], RenderedBlock::CodeBlock { lazy: None, .. }));
+    }
+
+    #[test]
+    fn test_parser_guess_language_highlights_unlabeled_blocks() {
+        let source = "```\n{\"key\": true}\n```\n";
+        let spans = |options: &ParseOptions| match &parse_with_options(source, h(), options)[0] {
+            RenderedBlock::CodeBlock { language, highlighted_lines, .. } => {
+                assert_eq!(language, "", "no label for a guessed language");
+                highlighted_lines[0].spans.len()
+            }
+            _ => panic!("expected CodeBlock"),
+        };
+        assert_eq!(spans(&ParseOptions::default()), 1);
+        assert!(spans(&ParseOptions { guess_language: true, ..ParseOptions::default() }) > 1);
+    }
+
     #[test]
     fn test_parser_fenced_code_block_empty_language() {
         let md = "```\nsome code\n```";
@@ -346,7 +422,7 @@
     #[test]
     fn test_parser_list_with_paragraphs_emits_no_stray_paragraphs() {
         // pulldown-cmark wraps list items in Tag::Paragraph when separated by blank lines.
-        // The Skipping guard must suppress those inner paragraphs.
+        // Those inner paragraphs belong to the List block, not the top level.
         let md = "- First item\n\n- Second item\n\nAfter list";
         let blocks = parse(md, h());
         let para_count = blocks
@@ -376,10 +452,51 @@
         }
     }
 
+    #[test]
+    fn test_parser_heading_keeps_color_through_nested_emphasis() {
+        let blocks = parse("## **bold** and *italic* and `code`\n\nafter *em*", h());
+        let RenderedBlock::Heading { content, .. } = &blocks[0] else {
+            panic!("expected Heading block");
+        };
+        assert_eq!(span_texts(&blocks[0]), ["bold", " and ", "italic", " and ", "code"]);
+        let h2 = heading_style(&DEFAULT_HEADING_COLORS, 2);
+        for span in &content[..4] {
+            assert_eq!(span.style.fg, h2.fg, "{:?} lost the heading color", span.text);
+            assert!(span.style.add_modifier.contains(Modifier::BOLD));
+        }
+        assert!(content[2].style.add_modifier.contains(Modifier::ITALIC));
+        assert!(!content[1].style.add_modifier.contains(Modifier::ITALIC));
+        // Inline code keeps its own style, heading or not.
+        assert_eq!(content[4].style, default_code_style());
+        // The heading's style is popped with it: the next paragraph is plain.
+        let RenderedBlock::Paragraph { content } = &blocks[1] else {
+            panic!("expected Paragraph block");
+        };
+        assert_eq!(content[0].style, Style::default());
+        assert_eq!(content[1].style, Style::default().add_modifier(Modifier::ITALIC));
+    }
+
+    #[test]
+    fn test_parser_heading_nested_emphasis_in_h4_and_link() {
+        let blocks = parse("#### ***both*** [link](https://x.y)", h());
+        let RenderedBlock::Heading { content, .. } = &blocks[0] else {
+            panic!("expected Heading block");
+        };
+        let h4 = heading_style(&DEFAULT_HEADING_COLORS, 4);
+        assert_eq!(content[0].text, "both");
+        assert_eq!(content[0].style.fg, h4.fg);
+        assert!(content[0].style.add_modifier.contains(Modifier::BOLD | Modifier::ITALIC));
+        // A link's color wins over the heading's; it adds its modifiers to bold.
+        let link = content.iter().find(|span| span.text == "link").unwrap();
+        assert_eq!(link.style.fg, default_link_style().fg);
+        let modifiers = Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED;
+        assert!(link.style.add_modifier.contains(modifiers));
+    }
+
     #[test]
     fn test_parser_heading_styles_distinct_modifiers() {
-        let h1 = default_heading_style(1);
-        let h4 = default_heading_style(4);
+        let h1 = heading_style(&DEFAULT_HEADING_COLORS, 1);
+        let h4 = heading_style(&DEFAULT_HEADING_COLORS, 4);
         // h1 has BOLD only
         assert!(h1.add_modifier.contains(Modifier::BOLD));
         assert!(!h1.add_modifier.contains(Modifier::ITALIC));
@@ -402,21 +519,46 @@
     }
 
     #[test]
-    fn test_parser_link_text_has_italic() {
+    fn test_parser_link_text_has_link_style() {
         let blocks = parse("[click here](https://example.com)", h());
         assert_eq!(blocks.len(), 1);
         match &blocks[0] {
             RenderedBlock::Paragraph { content } => {
                 assert_eq!(content[0].text, "click here");
-                assert!(
-                    content[0].style.add_modifier.contains(Modifier::ITALIC),
-                    "link text should have ITALIC"
-                );
+                assert_eq!(content[0].style, default_link_style());
+                assert_eq!(content[0].reference, None);
             }
             _ => panic!("expected Paragraph block"),
         }
     }
 
+    #[test]
+    fn test_parser_link_style_merges_with_inline_styles() {
+        let source = "[plain **bold** `code`](https://x.y) https://bare.example";
+        let link_style = Style::default().fg(Color::Magenta).add_modifier(Modifier::UNDERLINED);
+        let options = ParseOptions { link_style, ..Default::default() };
+        let blocks = parse_with_options(source, h(), &options);
+        let RenderedBlock::Paragraph { content } = &blocks[0] else {
+            panic!("expected Paragraph block");
+        };
+        let span = |text: &str| content.iter().find(|span| span.text == text).unwrap().style;
+        assert_eq!(span("plain "), link_style);
+        // Bold inside the link keeps the link's color and underline.
+        assert_eq!(span("bold"), link_style.add_modifier(Modifier::BOLD));
+        // Inline code keeps its own colors but is underlined like the link.
+        let code = span("code");
+        assert_eq!(code.fg, default_code_style().fg);
+        assert!(code.add_modifier.contains(Modifier::UNDERLINED));
+        assert_eq!(span("https://bare.example"), link_style);
+        // The default link style is blue and underlined, on top of italic.
+        let blocks = parse("[x](https://x.y)", h());
+        let RenderedBlock::Paragraph { content } = &blocks[0] else {
+            panic!("expected Paragraph block");
+        };
+        assert_eq!(content[0].style, default_link_style());
+        assert_eq!(content[0].style.fg, Some(Color::Blue));
+    }
+
     #[test]
     fn test_font_slots_file_parses_without_panic() {
         let source = include_str!("../testdata/font-slots.md");
@@ -459,3 +601,955 @@
             }
         }
     }
+
+    #[test]
+    fn test_parser_fence_title_attribute() {
+        assert_eq!(fence_title(r#"python title="server.py""#).as_deref(), Some("server.py"));
+        assert_eq!(fence_title("rust,no_run caption='Main loop'").as_deref(), Some("Main loop"));
+        assert_eq!(fence_title("js {title=app.js, lines=3}").as_deref(), Some("app.js"));
+        assert_eq!(fence_title(r#"sh data-x="a=b" title=run"#).as_deref(), Some("run"));
+        // No title, an empty one, and a language that merely looks like one.
+        assert_eq!(fence_title("rust"), None);
+        assert_eq!(fence_title(r#"rust title="""#), None);
+        assert_eq!(fence_title("title=x"), None);
+        assert_eq!(fence_title("rust subtitle=x"), None);
+    }
+
+    #[test]
+    fn test_parser_code_block_keeps_title() {
+        let md = "```python title=\"server.py\"\ncode\n```\n\n```rust\ncode\n```";
+        let titles: Vec<_> = parse(md, h())
+            .iter()
+            .map(|b| match b {
+                RenderedBlock::CodeBlock { title, .. } => title.clone(),
+                _ => panic!("expected CodeBlock"),
+            })
+            .collect();
+        assert_eq!(titles, [Some("server.py".to_string()), None]);
+    }
+
+    #[test]
+    fn test_parser_image_renders_marker_alt_and_path() {
+        let blocks = parse("![diagram](img.png)", h());
+        assert_eq!(blocks.len(), 1);
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                let texts: Vec<&str> = content.iter().map(|s| s.text.as_str()).collect();
+                assert_eq!(texts, vec!["🖼 ", "diagram", " (img.png)"]);
+                assert!(content[2].style.add_modifier.contains(Modifier::DIM));
+            }
+            _ => panic!("expected Paragraph block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_image_data_uri_path_elided() {
+        let blocks = parse("![dot](data:image/png;base64,iVBORw0KGgo=)", h());
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                assert_eq!(content.last().unwrap().text, " (data URI)");
+            }
+            _ => panic!("expected Paragraph block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_hidden_images_are_dropped() {
+        let options = ParseOptions { show_images: false, ..ParseOptions::default() };
+        let blocks = parse_with_options("Before ![badge](b.svg) after", h(), &options);
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                let all: String = content.iter().map(|s| s.text.as_str()).collect();
+                assert_eq!(all, "Before  after");
+            }
+            _ => panic!("expected Paragraph block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_image_load_failure_falls_back_to_alt_text() {
+        use crate::images::{GraphicsProtocol, ImageManager};
+        let mut images = ImageManager::new(
+            std::path::PathBuf::new(),
+            Some(GraphicsProtocol::Kitty),
+            crate::images::DEFAULT_CELL_SIZE,
+        );
+        let options = ParseOptions::default();
+        let blocks = parse_with_images("![missing](nope.png)", h(), &options, &mut images, (80, 24));
+        assert!(matches!(&blocks[0], RenderedBlock::Paragraph { .. }));
+    }
+
+    #[test]
+    fn test_parser_standalone_image_becomes_image_block() {
+        use crate::images::{GraphicsProtocol, ImageManager};
+        let dir = std::env::temp_dir().join(format!("mdink-parser-img-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::new(16, 32).save(dir.join("pic.png")).unwrap();
+        let mut images = ImageManager::new(dir, Some(GraphicsProtocol::Kitty), (8, 16));
+
+        let options = ParseOptions::default();
+        let blocks = parse_with_images("![a pic](pic.png)", h(), &options, &mut images, (80, 24));
+        match &blocks[0] {
+            RenderedBlock::Image { alt_text, width_cells, height_cells, .. } => {
+                assert_eq!(alt_text, "a pic");
+                assert_eq!((*width_cells, *height_cells), (2, 2));
+            }
+            _ => panic!("expected Image block"),
+        }
+
+        // An image followed by text in the same paragraph stays inline.
+        let blocks =
+            parse_with_images("![a pic](pic.png) and text", h(), &options, &mut images, (80, 24));
+        assert!(matches!(&blocks[0], RenderedBlock::Paragraph { .. }));
+    }
+
+    fn span_texts(block: &RenderedBlock) -> Vec<String> {
+        match block {
+            RenderedBlock::Paragraph { content } | RenderedBlock::Heading { content, .. } => {
+                content.iter().map(|s| s.text.clone()).collect()
+            }
+            _ => panic!("expected a text block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_bare_url_split_into_link_span() {
+        let blocks = parse("see https://x.com/a for more", h());
+        assert_eq!(span_texts(&blocks[0]), vec!["see ", "https://x.com/a", " for more"]);
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                assert_eq!(content[1].style, default_link_style());
+                assert_eq!(content[0].style, Style::default());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parser_bare_url_trailing_period_excluded() {
+        let blocks = parse("see https://x.com.", h());
+        assert_eq!(span_texts(&blocks[0]), vec!["see ", "https://x.com", "."]);
+    }
+
+    #[test]
+    fn test_parser_bare_url_balanced_parens_kept() {
+        let blocks = parse("(https://en.wikipedia.org/wiki/Rust_(language))", h());
+        assert_eq!(
+            span_texts(&blocks[0]),
+            vec!["(", "https://en.wikipedia.org/wiki/Rust_(language)", ")"]
+        );
+    }
+
+    #[test]
+    fn test_parser_bare_url_www_prefix() {
+        assert_eq!(find_bare_urls("go to www.rust-lang.org, now"), vec![6..23]);
+    }
+
+    #[test]
+    fn test_parser_bare_url_requires_word_boundary() {
+        assert!(find_bare_urls("xhttp://nope.com").is_empty());
+        assert!(find_bare_urls("just http:// alone").is_empty());
+    }
+
+    #[test]
+    fn test_parser_url_inside_explicit_link_not_split() {
+        let blocks = parse("[https://x.com](https://x.com)", h());
+        assert_eq!(span_texts(&blocks[0]), vec!["https://x.com"]);
+    }
+
+    #[test]
+    fn test_parser_url_in_inline_code_not_linkified() {
+        let blocks = parse("`https://x.com`", h());
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                assert_eq!(content.len(), 1);
+                assert_eq!(content[0].style, default_code_style());
+            }
+            _ => panic!("expected Paragraph block"),
+        }
+    }
+
+    fn smart() -> ParseOptions {
+        ParseOptions { smart_punctuation: true, ..ParseOptions::default() }
+    }
+
+    #[test]
+    fn test_parser_smart_punctuation_off_by_default() {
+        let blocks = parse("\"quoted\" -- wait...", h());
+        assert_eq!(span_texts(&blocks[0]).concat(), "\"quoted\" -- wait...");
+    }
+
+    #[test]
+    fn test_parser_smart_punctuation_typesets_prose() {
+        let blocks = parse_with_options("\"quoted\" -- wait...", h(), &smart());
+        assert_eq!(span_texts(&blocks[0]).concat(), "\u{201c}quoted\u{201d} \u{2013} wait\u{2026}");
+    }
+
+    #[test]
+    fn test_parser_smart_punctuation_leaves_code_verbatim() {
+        let source = "`\"a\" -- b...`\n\n```\nx = \"a\" -- b...\n```";
+        let blocks = parse_with_options(source, h(), &smart());
+        assert_eq!(span_texts(&blocks[0]), vec!["\"a\" -- b..."]);
+        match &blocks[1] {
+            RenderedBlock::CodeBlock { highlighted_lines, .. } => {
+                let text: String =
+                    highlighted_lines[0].spans.iter().map(|s| s.content.as_ref()).collect();
+                assert_eq!(text, "x = \"a\" -- b...");
+            }
+            _ => panic!("expected CodeBlock"),
+        }
+    }
+
+    fn code_block(block: &RenderedBlock) -> (&str, String) {
+        match block {
+            RenderedBlock::CodeBlock { language, highlighted_lines, .. } => {
+                let text: Vec<String> = highlighted_lines
+                    .iter()
+                    .map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect())
+                    .collect();
+                (language.as_str(), text.join("\n"))
+            }
+            _ => panic!("expected CodeBlock"),
+        }
+    }
+
+    #[test]
+    fn test_parser_tilde_fence_language() {
+        let blocks = parse("~~~~rust\nlet x = 1;\n~~~~", h());
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(code_block(&blocks[0]), ("rust", "let x = 1;".to_string()));
+    }
+
+    #[test]
+    fn test_parser_backtick_fence_inside_tilde_fence_is_content() {
+        let md = "~~~~markdown\n```rust\nfn main() {}\n```\n~~~~\n\nafter";
+        let blocks = parse(md, h());
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            code_block(&blocks[0]),
+            ("markdown", "```rust\nfn main() {}\n```".to_string())
+        );
+        assert!(matches!(blocks[1], RenderedBlock::Paragraph { .. }));
+    }
+
+    #[test]
+    fn test_parser_long_backtick_fence_nests_shorter_fence() {
+        let md = "`````md\n```\ninner\n```\n`````";
+        let blocks = parse(md, h());
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(code_block(&blocks[0]), ("md", "```\ninner\n```".to_string()));
+    }
+
+    #[test]
+    fn test_parser_tilde_fence_info_string_attributes_stripped() {
+        let blocks = parse("~~~ python title=\"x.py\"\npass\n~~~", h());
+        assert_eq!(code_block(&blocks[0]).0, "python");
+    }
+
+    #[test]
+    fn test_parser_parallel_highlighting_matches_serial() {
+        let md: String = (0..24)
+            .map(|i| format!("para {i}\n\n```rust\nlet x{i} = {i};\n```\n\n"))
+            .collect();
+        let render = |jobs| {
+            let options = ParseOptions { jobs, ..ParseOptions::default() };
+            parse_with_options(&md, h(), &options)
+                .iter()
+                .filter(|b| matches!(b, RenderedBlock::CodeBlock { .. }))
+                .map(|b| code_block(b).1)
+                .collect::<Vec<_>>()
+        };
+        let serial = render(1);
+        assert_eq!(serial.len(), 24);
+        assert_eq!(serial[7], "let x7 = 7;");
+        assert_eq!(render(0), serial);
+        assert_eq!(render(3), serial);
+    }
+
+    #[test]
+    fn test_parser_multiline_inline_code_collapses_to_one_span() {
+        let blocks = parse("a `x\ny\nz` b", h());
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                let code: Vec<_> =
+                    content.iter().filter(|s| s.style == default_code_style()).collect();
+                assert_eq!(code.len(), 1);
+                assert_eq!(code[0].text, "x y z");
+            }
+            _ => panic!("expected Paragraph block"),
+        }
+    }
+
+    fn blank_line_blocks(md: &str) -> Vec<String> {
+        let options = ParseOptions { preserve_blank_lines: true, ..ParseOptions::default() };
+        parse_with_options(md, h(), &options)
+            .iter()
+            .map(|block| match block {
+                RenderedBlock::Paragraph { content } => content[0].text.clone(),
+                RenderedBlock::Spacer { lines } => format!("spacer {lines}"),
+                RenderedBlock::ThematicBreak => "rule".to_string(),
+                RenderedBlock::Heading { .. } => "heading".to_string(),
+                RenderedBlock::CodeBlock { .. } => "code".to_string(),
+                _ => "other".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parser_preserve_blank_lines_adds_spacers_for_extra_blanks() {
+        assert_eq!(blank_line_blocks("a\n\nb"), ["a", "b"]);
+        assert_eq!(blank_line_blocks("a\n\n\nb"), ["a", "spacer 0", "b"]);
+        assert_eq!(blank_line_blocks("a\n\n\n\nb"), ["a", "spacer 1", "b"]);
+        assert_eq!(blank_line_blocks("# h\n\n\n```\nx\n```\n\n\n---"), [
+            "heading", "spacer 0", "code", "spacer 0", "rule"
+        ]);
+    }
+
+    #[test]
+    fn test_parser_preserve_blank_lines_caps_and_skips_leading_blanks() {
+        assert_eq!(blank_line_blocks("\n\n\n\na\n\n\n\n\n\n\n\nb"), ["a", "spacer 1", "b"]);
+        // Whitespace-only lines count as blank.
+        assert_eq!(blank_line_blocks("a\n  \n\t\nb"), ["a", "spacer 0", "b"]);
+    }
+
+    #[test]
+    fn test_parser_blank_lines_collapse_by_default() {
+        let blocks = parse("a\n\n\n\n\nb", h());
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_parser_preserve_blank_lines_layout_height() {
+        let options = ParseOptions { preserve_blank_lines: true, ..ParseOptions::default() };
+        let blocks = parse_with_options("a\n\n\n\nb", h(), &options);
+        let layout = crate::layout::LayoutOptions {
+            max_blank_lines: MAX_BLANK_LINES,
+            ..crate::layout::LayoutOptions::default()
+        };
+        let doc = crate::layout::flatten_with_options(&blocks, 80, &layout);
+        // "a", three blank lines, "b".
+        assert_eq!(doc.total_height, 5);
+    }
+
+    const FRONT_MATTER_DOC: &str = "---\ntitle: \"My Post\"\ndate: 2024-01-01\n---\n\nBody text";
+
+    fn parse_front_matter(mode: FrontMatter) -> Vec<RenderedBlock> {
+        let options = ParseOptions { frontmatter: mode, ..ParseOptions::default() };
+        parse_with_options(FRONT_MATTER_DOC, h(), &options)
+    }
+
+    #[test]
+    fn test_parser_front_matter_hidden_by_default() {
+        let blocks = parse(FRONT_MATTER_DOC, h());
+        assert_eq!(blocks.len(), 1);
+        assert!(matches!(
+            &blocks[0],
+            RenderedBlock::Paragraph { content } if content[0].text == "Body text"
+        ));
+    }
+
+    #[test]
+    fn test_parser_front_matter_show_is_dim_code_block() {
+        let blocks = parse_front_matter(FrontMatter::Show);
+        assert_eq!(blocks.len(), 2);
+        let (language, text) = code_block(&blocks[0]);
+        assert_eq!(language, "");
+        assert_eq!(text, "title: \"My Post\"\ndate: 2024-01-01");
+        let RenderedBlock::CodeBlock { highlighted_lines, .. } = &blocks[0] else {
+            unreachable!()
+        };
+        assert!(highlighted_lines[0].spans[0].style.add_modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_parser_front_matter_title_becomes_h1() {
+        let blocks = parse_front_matter(FrontMatter::Title);
+        assert_eq!(blocks.len(), 2);
+        match &blocks[0] {
+            RenderedBlock::Heading { level, content } => {
+                assert_eq!(*level, 1);
+                assert_eq!(content[0].text, "My Post");
+                assert_eq!(content[0].style, heading_style(&DEFAULT_HEADING_COLORS, 1));
+            }
+            _ => panic!("expected Heading block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_front_matter_title_mode_without_title_renders_nothing() {
+        let options = ParseOptions { frontmatter: FrontMatter::Title, ..ParseOptions::default() };
+        let blocks = parse_with_options("---\nauthor: me\n---\n\nBody", h(), &options);
+        assert_eq!(blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_parser_front_matter_title_unquotes() {
+        assert_eq!(front_matter_title("title: 'A'\n").as_deref(), Some("A"));
+        assert_eq!(front_matter_title("title: B \n").as_deref(), Some("B"));
+        assert_eq!(front_matter_title("subtitle: C\n"), None);
+        assert_eq!(front_matter_title("title:\n"), None);
+    }
+
+    #[test]
+    fn test_parser_front_matter_show_keeps_code_highlighting_aligned() {
+        let options = ParseOptions { frontmatter: FrontMatter::Show, ..ParseOptions::default() };
+        let md = "---\na: 1\n---\n\n```rust\nlet x = 1;\n```";
+        let blocks = parse_with_options(md, h(), &options);
+        assert_eq!(code_block(&blocks[0]).1, "a: 1");
+        assert_eq!(code_block(&blocks[1]), ("rust", "let x = 1;".to_string()));
+    }
+
+    #[test]
+    fn test_parser_rule_later_in_document_is_not_front_matter() {
+        let blocks = parse("Intro\n\n---\n\ntitle: x\n\n---", h());
+        assert!(blocks.iter().any(|b| matches!(b, RenderedBlock::ThematicBreak)));
+    }
+
+    fn parse_html_render(md: &str) -> Vec<RenderedBlock> {
+        let options = ParseOptions { html: HtmlMode::Render, ..ParseOptions::default() };
+        parse_with_options(md, h(), &options)
+    }
+
+    const DETAILS_DOC: &str = "Intro\n\n<details>\n<summary>Click <b>me</b></summary>\n\n\
+                               Hidden **text**\n\n```rust\nlet x = 1;\n```\n\n</details>\n\nAfter";
+
+    #[test]
+    fn test_parser_details_dropped_by_default() {
+        let blocks = parse(DETAILS_DOC, h());
+        // The HTML is dropped; the markdown between the tags stays top-level.
+        assert_eq!(blocks.len(), 4);
+        assert!(!blocks.iter().any(|b| matches!(b, RenderedBlock::Details { .. })));
+    }
+
+    #[test]
+    fn test_parser_details_render_nests_body() {
+        let blocks = parse_html_render(DETAILS_DOC);
+        assert_eq!(blocks.len(), 3);
+        match &blocks[1] {
+            RenderedBlock::Details { summary, body } => {
+                assert_eq!(summary[0].text, "Click me");
+                assert!(summary[0].style.add_modifier.contains(Modifier::BOLD));
+                assert_eq!(body.len(), 2);
+                assert!(matches!(body[0], RenderedBlock::Paragraph { .. }));
+                // Nested code blocks are still highlighted.
+                assert_eq!(code_block(&body[1]), ("rust", "let x = 1;".to_string()));
+            }
+            _ => panic!("expected Details block"),
+        }
+        assert!(matches!(
+            &blocks[2],
+            RenderedBlock::Paragraph { content } if content[0].text == "After"
+        ));
+    }
+
+    #[test]
+    fn test_parser_details_nested_and_unclosed() {
+        let md = "<details><summary>Outer</summary>\n\nA\n\n<details>\n\nB\n\n</details>\n\nC";
+        let blocks = parse_html_render(md);
+        assert_eq!(blocks.len(), 1);
+        let RenderedBlock::Details { summary, body } = &blocks[0] else {
+            panic!("expected Details block");
+        };
+        assert_eq!(summary[0].text, "Outer");
+        assert_eq!(body.len(), 3);
+        match &body[1] {
+            RenderedBlock::Details { summary, body } => {
+                assert_eq!(summary[0].text, "Details");
+                assert_eq!(body.len(), 1);
+            }
+            _ => panic!("expected nested Details block"),
+        }
+    }
+
+    #[test]
+    fn test_parser_is_tag_requires_name_boundary() {
+        assert!(is_tag("<details>", "<details"));
+        assert!(is_tag("<details open>", "<details"));
+        assert!(!is_tag("<detailsx>", "<details"));
+        assert_eq!(strip_tags(" a <i>b</i>\n c "), "a b c");
+    }
+
+    #[test]
+    fn test_parser_entities_decode_in_text_and_headings() {
+        let text = |md: &str| span_texts(&parse(md, h())[0]).concat();
+        assert_eq!(text("# Tom &amp; Jerry &copy;"), "Tom & Jerry ©");
+        assert_eq!(text("a &#x2014; b &#8212; c &hellip;"), "a — b — c …");
+        assert_eq!(text("&lt;tag&gt; &quot;q&quot; x&nbsp;y"), "<tag> \"q\" x\u{a0}y");
+        // A literal `&amp;` is one `&`, and what follows it isn't decoded again.
+        assert_eq!(text("&amp;copy; &amp;amp;"), "&copy; &amp;");
+        assert_eq!(text("&unknown; & alone"), "&unknown; & alone");
+    }
+
+    #[test]
+    fn test_parser_entities_decode_in_html_summaries() {
+        let md = "<details><summary>Q &amp; A &mdash; &#x2192; &lt;b&gt;</summary>\n\nbody\n\n\
+                  </details>";
+        match &parse_html_render(md)[0] {
+            RenderedBlock::Details { summary, .. } => assert_eq!(summary[0].text, "Q & A — → <b>"),
+            _ => panic!("expected Details block"),
+        }
+        assert_eq!(decode_entities("&amp;lt; &bogus; &#0; &#xZZ; &"), "&lt; &bogus; &#0; &#xZZ; &");
+    }
+
+    fn task_summary() -> ParseOptions {
+        ParseOptions { task_summary: true, ..ParseOptions::default() }
+    }
+
+    #[test]
+    fn test_parser_task_summary_annotates_heading() {
+        let md = "## Roadmap\n\n- [x] parse\n- [ ] render\n  - [X] nested\n- plain item\n";
+        let blocks = parse_with_options(md, h(), &task_summary());
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(&blocks[1], RenderedBlock::List { items, .. } if items.len() == 3));
+        let RenderedBlock::Heading { content, .. } = &blocks[0] else {
+            panic!("expected Heading block");
+        };
+        assert_eq!(content.last().unwrap().text, " (2/3)");
+        assert!(content.last().unwrap().style.add_modifier.contains(Modifier::DIM));
+    }
+
+    #[test]
+    fn test_parser_task_summary_needs_list_right_after_heading() {
+        let cases = [
+            "# Title\n\nIntro.\n\n- [x] done\n",
+            "# Title\n\n- plain\n- list\n",
+            "# Title\n",
+        ];
+        for md in cases {
+            let blocks = parse_with_options(md, h(), &task_summary());
+            assert_eq!(span_texts(&blocks[0]), ["Title"], "{md:?}");
+        }
+    }
+
+    #[test]
+    fn test_parser_task_summary_off_by_default() {
+        let blocks = parse("# Todo\n\n- [x] one\n", h());
+        assert_eq!(span_texts(&blocks[0]), ["Todo"]);
+    }
+
+    #[test]
+    fn test_parser_raw_ansi_console_blocks() {
+        let md = "```console\n\x1b[31mfail\x1b[0m\n```\n\n```text\n\x1b[31mx\n```\n";
+        let options = ParseOptions { raw_ansi: true, ..ParseOptions::default() };
+        let blocks = parse_with_options(md, h(), &options);
+        assert_eq!(code_block(&blocks[0]), ("console", "fail".to_string()));
+        // Other languages are highlighted as usual, escapes and all.
+        assert_eq!(code_block(&blocks[1]).1, "\x1b[31mx");
+        // Without the flag, console blocks are untouched too.
+        let blocks = parse(md, h());
+        assert_eq!(code_block(&blocks[0]).1, "\x1b[31mfail\x1b[0m");
+    }
+
+    fn math() -> ParseOptions {
+        ParseOptions { math: true, ..ParseOptions::default() }
+    }
+
+    #[test]
+    fn test_parser_math_inline_converts_to_unicode() {
+        let blocks = parse_with_options(r"Area is $\pi r^2$ and $\weird$.", h(), &math());
+        assert_eq!(span_texts(&blocks[0]).concat(), r"Area is π r² and $\weird$.");
+    }
+
+    #[test]
+    fn test_parser_math_display_splits_paragraph() {
+        let md = "Before\n$$\n\\sum_{i=1}^n i\n$$\nafter";
+        let blocks = parse_with_options(md, h(), &math());
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(span_texts(&blocks[0]), ["Before"]);
+        match &blocks[1] {
+            RenderedBlock::MathBlock { lines } => assert_eq!(lines, &["∑ᵢ₌₁ⁿ i"]),
+            _ => panic!("expected MathBlock"),
+        }
+        assert_eq!(span_texts(&blocks[2]), ["after"]);
+    }
+
+    #[test]
+    fn test_parser_math_display_keeps_unsupported_latex() {
+        let blocks = parse_with_options("$$\\begin{pmatrix} a \\end{pmatrix}$$", h(), &math());
+        assert_eq!(blocks.len(), 1);
+        match &blocks[0] {
+            RenderedBlock::MathBlock { lines } => {
+                assert_eq!(lines, &[r"\begin{pmatrix} a \end{pmatrix}"]);
+            }
+            _ => panic!("expected MathBlock"),
+        }
+    }
+
+    #[test]
+    fn test_parser_math_off_by_default() {
+        let blocks = parse(r"costs $5 and $\alpha$", h());
+        assert_eq!(span_texts(&blocks[0]).concat(), r"costs $5 and $\alpha$");
+    }
+
+    fn repo() -> ParseOptions {
+        ParseOptions {
+            repo_url: Some("https://github.com/o/r".to_string()),
+            ..ParseOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_parser_references_off_by_default() {
+        let blocks = parse("Fixed in #12 by 4f2a9c1.", h());
+        assert_eq!(span_texts(&blocks[0]), ["Fixed in #12 by 4f2a9c1."]);
+    }
+
+    #[test]
+    fn test_parser_references_split_into_link_spans() {
+        let blocks = parse_with_options("Fixed in #12 by 4f2a9c1.", h(), &repo());
+        assert_eq!(span_texts(&blocks[0]), ["Fixed in ", "#12", " by ", "4f2a9c1", "."]);
+        match &blocks[0] {
+            RenderedBlock::Paragraph { content } => {
+                assert_eq!(content[1].style, default_link_style());
+                assert_eq!(content[3].style, default_link_style());
+                let issue = "https://github.com/o/r/issues/12";
+                assert_eq!(content[1].reference.as_deref(), Some(issue));
+                let commit = "https://github.com/o/r/commit/4f2a9c1";
+                assert_eq!(content[3].reference.as_deref(), Some(commit));
+                assert_eq!(content[0].reference, None);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parser_references_require_word_boundaries() {
+        assert!(find_references("a#12 #12b x/#3 see#4").is_empty());
+        assert!(find_references("4f2a9c1.png user@4f2a9c1 v1.4f2a9c1 4f2a9c1-dirty").is_empty());
+        assert_eq!(find_references("(#7), 4f2a9c1: done"), vec![1..3, 6..13]);
+    }
+
+    #[test]
+    fn test_parser_reference_hashes_need_plausible_shape() {
+        // Too short, all letters, all digits, uppercase, too long.
+        assert!(find_references("abc123 deadbeef 12345678 4F2A9C1").is_empty());
+        assert!(find_references(&"a1".repeat(21)).is_empty());
+        assert_eq!(find_references(&"a1".repeat(20)), vec![0..40]);
+        assert!(find_references("#1234567890 #").is_empty());
+    }
+
+    #[test]
+    fn test_parser_references_not_linked_inside_urls_or_links() {
+        let blocks = parse_with_options("https://x.com/#12 [#3](u)", h(), &repo());
+        assert_eq!(span_texts(&blocks[0]), ["https://x.com/#12", " ", "#3"]);
+    }
+
+    #[test]
+    fn test_parser_reference_url() {
+        let repo = "https://github.com/o/r/";
+        assert_eq!(reference_url(repo, "#12").as_deref(), Some("https://github.com/o/r/issues/12"));
+        assert_eq!(
+            reference_url(repo, "4f2a9c1").as_deref(),
+            Some("https://github.com/o/r/commit/4f2a9c1")
+        );
+        assert_eq!(reference_url(repo, "hello"), None);
+    }
+
+    #[test]
+    fn test_parser_parse_as_source_keeps_markdown_verbatim() {
+        let source = "# Title\n\n```rust\nlet x = 1;\n```\n*em*";
+        let blocks = parse_as_source(source, h(), &ParseOptions::default());
+        assert_eq!(blocks.len(), 1);
+        let RenderedBlock::CodeBlock { language, highlighted_lines, .. } = &blocks[0] else {
+            panic!("expected CodeBlock");
+        };
+        assert_eq!(language, "markdown");
+        let lines: Vec<String> = highlighted_lines
+            .iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        assert_eq!(lines, source.lines().collect::<Vec<_>>());
+    }
+
+    fn list_items(block: &RenderedBlock) -> (Option<u64>, &Vec<ListItem>) {
+        match block {
+            RenderedBlock::List { start, items } => (*start, items),
+            _ => panic!("expected List"),
+        }
+    }
+
+    #[test]
+    fn test_parser_tight_list_items_become_paragraphs() {
+        let blocks = parse("- one\n- *two*\n  - nested\n", h());
+        assert_eq!(blocks.len(), 1);
+        let (start, items) = list_items(&blocks[0]);
+        assert_eq!(start, None);
+        assert_eq!(items.len(), 2);
+        assert_eq!(span_texts(&items[0].blocks[0]), ["one"]);
+        // Text before a nested list ends at the list.
+        assert_eq!(span_texts(&items[1].blocks[0]), ["two"]);
+        let (_, nested) = list_items(&items[1].blocks[1]);
+        assert_eq!(span_texts(&nested[0].blocks[0]), ["nested"]);
+    }
+
+    #[test]
+    fn test_parser_list_item_keeps_continuation_paragraphs_and_code() {
+        let md = "3. first\n\n   second paragraph\n\n   ```rust\n   let x = 1;\n   ```\n4. next\n";
+        let blocks = parse(md, h());
+        assert_eq!(blocks.len(), 1);
+        let (start, items) = list_items(&blocks[0]);
+        assert_eq!(start, Some(3));
+        assert_eq!(items[0].blocks.len(), 3);
+        assert_eq!(span_texts(&items[0].blocks[0]), ["first"]);
+        assert_eq!(span_texts(&items[0].blocks[1]), ["second paragraph"]);
+        // Code inside list items is highlighted like any other.
+        assert_eq!(code_block(&items[0].blocks[2]), ("rust", "let x = 1;".to_string()));
+        assert_eq!(span_texts(&items[1].blocks[0]), ["next"]);
+    }
+
+    #[test]
+    fn test_parser_list_then_paragraph_stays_separate() {
+        let blocks = parse("- item\n\nAfter.\n", h());
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(span_texts(&blocks[1]), ["After."]);
+    }
+
+    #[test]
+    fn test_parser_ordered_task_list_keeps_number_and_checkbox() {
+        let blocks = parse("1. [ ] todo
+2. [x] done
+
+   more
+3. plain
+", h());
+        let (start, items) = list_items(&blocks[0]);
+        assert_eq!(start, Some(1));
+        let tasks: Vec<Option<bool>> = items.iter().map(|item| item.task).collect();
+        assert_eq!(tasks, [Some(false), Some(true), None]);
+        assert_eq!(span_texts(&items[0].blocks[0]), ["todo"]);
+        // In a loose list the marker sits inside the item's paragraph.
+        assert_eq!(span_texts(&items[1].blocks[0]), ["done"]);
+        assert_eq!(span_texts(&items[1].blocks[1]), ["more"]);
+    }
+
+    fn collapse_badges() -> ParseOptions {
+        ParseOptions { collapse_badges: true, ..ParseOptions::default() }
+    }
+
+    #[test]
+    fn test_parser_collapse_badges_shrinks_badge_row() {
+        let md = "[![build](https://ci/b.svg)](https://ci) ![cov 90%](c.svg)\n\
+                  [![docs](d.svg)](https://docs)\n\nText.";
+        let blocks = parse_with_options(md, h(), &collapse_badges());
+        assert_eq!(span_texts(&blocks[0]), ["build · cov 90% · docs"]);
+        let RenderedBlock::Paragraph { content } = &blocks[0] else {
+            panic!("expected Paragraph block");
+        };
+        assert!(content[0].style.add_modifier.contains(Modifier::DIM));
+        assert_eq!(span_texts(&blocks[1]), ["Text."]);
+        // Off by default: the alt texts and paths stay.
+        let blocks = parse(md, h());
+        assert!(span_texts(&blocks[0]).contains(&" (c.svg)".to_string()));
+    }
+
+    #[test]
+    fn test_parser_collapse_badges_needs_only_images_and_links() {
+        let cases = [
+            "See ![build](b.svg) here",
+            "![build](b.svg) `code`",
+            "[just a link](https://x) [another](https://y)",
+        ];
+        for md in cases {
+            let collapsed = parse_with_options(md, h(), &collapse_badges());
+            assert_eq!(span_texts(&collapsed[0]), span_texts(&parse(md, h())[0]), "{md:?}");
+        }
+    }
+
+    #[test]
+    fn test_parser_expand_tabs_to_next_stop() {
+        assert_eq!(expand_tabs("a\tb", 4), "a   b");
+        assert_eq!(expand_tabs("abcd\tb", 4), "abcd    b");
+        assert_eq!(expand_tabs("\tx\n日本\ty", 4), "    x\n日本    y");
+        assert_eq!(expand_tabs("no tabs", 8), "no tabs");
+    }
+
+    #[test]
+    fn test_parser_tab_width_expands_prose_and_code() {
+        let options = ParseOptions { tab_width: Some(4), ..ParseOptions::default() };
+        let md = "name:\tvalue *em*\tx\n\n```\n\tindented\nab\tc\n```\n";
+        let blocks = parse_with_options(md, h(), &options);
+        // A tab mid-sentence stops relative to its own span's start.
+        assert_eq!(span_texts(&blocks[0]), ["name:   value ", "em", "    x"]);
+        assert_eq!(code_block(&blocks[1]).1, "    indented\nab  c");
+        let blocks = parse(md, h());
+        assert_eq!(span_texts(&blocks[0])[0], "name:\tvalue ");
+    }
+
+    #[test]
+    fn test_parser_mixed_indent_code_blocks() {
+        let md = "Intro\n\n```go\nfunc f() {\n\tx := 1\n    y := 2\n}\n```\n\n\
+                  ```rust,no_run\nfn f() {\n    g();\n}\n```\n\n\
+                  - item\n\n  ```\n  \t mixed on one line\n  ```\n";
+        let found = mixed_indent_code_blocks(md);
+        assert_eq!(found, [(3, "go".to_string()), (18, String::new())]);
+        assert!(mixed_indent_code_blocks("\tindented code\n").is_empty());
+    }
+
+    #[test]
+    fn test_parser_latest_entry_spans_the_first_level_two_section() {
+        let md = "# Changelog\n\nIntro.\n\n## 2.0\n\n### Added\n\n- x\n\n## 1.0\n\nOld.\n";
+        let blocks = parse(md, h());
+        let entry = latest_entry(&blocks);
+        assert_eq!(entry, 2..5);
+        assert!(matches!(&blocks[2], RenderedBlock::Heading { level: 2, .. }));
+        assert!(matches!(&blocks[5], RenderedBlock::Heading { level: 2, .. }));
+
+        let blocks = parse("## Only\n\nText.\n", h());
+        assert_eq!(latest_entry(&blocks), 0..2);
+        let blocks = parse("# Title\n\nText.\n", h());
+        assert_eq!(latest_entry(&blocks), 0..2);
+    }
+
+    fn quote(block: &RenderedBlock) -> (&Vec<RenderedBlock>, Option<String>) {
+        match block {
+            RenderedBlock::BlockQuote { body, attribution } => {
+                (body, attribution.as_ref().map(|spans| spans.iter().map(|s| &*s.text).collect()))
+            }
+            _ => panic!("expected BlockQuote"),
+        }
+    }
+
+    #[test]
+    fn test_parser_block_quote_splits_final_dash_line_as_attribution() {
+        let blocks = parse("> Stay hungry — stay foolish.\n> — *Whole Earth* Catalog\n", h());
+        assert_eq!(blocks.len(), 1);
+        let (body, attribution) = quote(&blocks[0]);
+        // The em dash mid-quote stays; the soft break before the line goes.
+        assert_eq!(span_texts(&body[0]), ["Stay hungry — stay foolish."]);
+        assert_eq!(attribution.as_deref(), Some("— Whole Earth Catalog"));
+
+        let blocks = parse("> One.\n>\n> -- Anon\n", h());
+        let (body, attribution) = quote(&blocks[0]);
+        assert_eq!(body.len(), 1, "the attribution paragraph is removed");
+        assert_eq!(attribution.as_deref(), Some("-- Anon"));
+    }
+
+    #[test]
+    fn test_parser_block_quote_attribution_only_on_last_line() {
+        let blocks = parse("> — not last\n> but this is\n", h());
+        assert_eq!(quote(&blocks[0]).1, None);
+        let blocks = parse("> — Author\n>\n> ```\n> code\n> ```\n", h());
+        let (body, attribution) = quote(&blocks[0]);
+        assert_eq!(attribution, None);
+        assert_eq!(code_block(&body[1]).1, "code");
+        // Quotes nest, and text before them isn't lost.
+        let blocks = parse("> outer\n>> inner\n", h());
+        let (body, _) = quote(&blocks[0]);
+        assert_eq!(span_texts(&body[0]), ["outer"]);
+        assert_eq!(span_texts(&quote(&body[1]).0[0]), ["inner"]);
+    }
+
+    #[test]
+    fn test_parser_ragged_tables_are_skipped_without_losing_what_follows() {
+        // Tables aren't rendered yet; rows with missing or extra cells must
+        // not trip the parser or swallow the blocks after them.
+        let source = "| a | b | c |\n|---|---|---|\n| 1 |\n| 1 | 2 | 3 | 4 |\n\nafter\n\n\
+                      - item\n\n  | x |\n  |---|\n  | 1 | 2 |\n\n# End\n";
+        let blocks = parse(source, h());
+        assert!(matches!(
+            &blocks[0],
+            RenderedBlock::Paragraph { content } if content[0].text == "after"
+        ));
+        assert!(matches!(blocks.last(), Some(RenderedBlock::Heading { level: 1, .. })));
+        let layout = crate::layout::flatten(&blocks, 20);
+        assert!(layout.lines.iter().any(|line| line.plain_text() == "End"));
+    }
+
+    /// All text of `blocks` and the blocks nested in them, one block per line.
+    fn all_text(blocks: &[RenderedBlock]) -> String {
+        let mut out = String::new();
+        for block in blocks {
+            match block {
+                RenderedBlock::Heading { content, .. } | RenderedBlock::Paragraph { content } => {
+                    out.extend(content.iter().map(|s| s.text.as_str()));
+                }
+                RenderedBlock::Details { summary, body } => {
+                    out.extend(summary.iter().map(|s| s.text.as_str()));
+                    out.push('\n');
+                    out.push_str(&all_text(body));
+                }
+                RenderedBlock::List { items, .. } => {
+                    for item in items {
+                        out.push_str(&all_text(&item.blocks));
+                    }
+                }
+                RenderedBlock::BlockQuote { body, attribution } => {
+                    out.push_str(&all_text(body));
+                    out.extend(attribution.iter().flatten().map(|s| s.text.as_str()));
+                }
+                _ => {}
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn test_parser_adversarial_nesting_keeps_the_rest_of_the_document() {
+        // Fragments that open, close and interleave containers, inline
+        // styles and HTML; none can legitimately swallow what follows a
+        // blank line (no unclosed fences or comments).
+        const FRAGMENTS: &[&str] = &[
+            "<details>",
+            "</details>",
+            "<summary>*s*</summary>",
+            "<div>\n*a*",
+            "</div>",
+            "<b>**x</b>**",
+            "- item",
+            "  - nested",
+            "1. one",
+            "> quote",
+            "> > deeper",
+            "- > quoted item",
+            "> - listed quote",
+            "- [ ] task",
+            "# heading",
+            "![alt [link](u) *em*](i.png)",
+            "[a ![b](c) **d](e)",
+            "*a **b* c**",
+            "~~s *t~~ u*",
+            "$$x$$",
+            "```\ncode\n```",
+            "    indented",
+            "---",
+            "<!-- c -->",
+            "[^1]: note",
+            "| a | b |\n|---|---|\n| c |",
+        ];
+        let options = [
+            ParseOptions::default(),
+            ParseOptions {
+                html: HtmlMode::Render,
+                math: true,
+                show_images: false,
+                task_summary: true,
+                ..ParseOptions::default()
+            },
+        ];
+        let mut seed: u32 = 0x2545_f491;
+        for _ in 0..300 {
+            let mut md = String::new();
+            for _ in 0..8 {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                md.push_str(FRAGMENTS[(seed >> 16) as usize % FRAGMENTS.len()]);
+                md.push_str(if seed & 1 == 0 { "\n" } else { "\n\n" });
+            }
+            md.push_str("\nend of document\n");
+            for options in &options {
+                let blocks = parse_with_options(&md, h(), options);
+                let text = all_text(&blocks);
+                assert!(text.contains("end of document"), "lost the end of {md:?}: {text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_strip_front_matter_keeps_the_body_unchanged() {
+        let md = "---\ntitle: Notes\ntags: [a]\n---\n\n# Notes\n\n*Body*  \n---\n";
+        assert_eq!(strip_front_matter(md), "\n# Notes\n\n*Body*  \n---\n");
+        assert_eq!(strip_front_matter("---\na: 1\n...\nText\n"), "Text\n");
+        let plain = "# Title\n\n---\n\ntitle: not front matter\n---\n";
+        assert_eq!(strip_front_matter(plain), plain);
+        assert_eq!(strip_front_matter(""), "");
+    }
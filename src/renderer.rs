@@ -11,94 +11,383 @@ use ratatui::Frame;
 use ratatui::layout::Rect;
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::text::{Line, Span};
-use ratatui::widgets::Paragraph;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph};
 use unicode_width::UnicodeWidthStr;
 
-use crate::app::App;
-use crate::layout::DocumentLine;
+use crate::app::{App, COLUMN_GAP, InputMode, column_width};
+use crate::export::{self, ColorMode};
+use crate::keymap;
+use crate::layout::{DocumentLine, tile_to_width};
 
 /// Draws the current view of the document and status bar to the frame.
 ///
 /// The content area occupies all rows except the last, which is reserved
-/// for the status bar. For extremely small terminals (height < 2), only
-/// the status bar is rendered.
+/// for the status bar unless it is hidden, minus the outline sidebar on the
+/// left when it is open and fits. For extremely small terminals (height < 2),
+/// only the status bar is rendered.
 pub fn draw(frame: &mut Frame, app: &App) {
     let area = frame.area();
 
-    // Reserve the bottom row for the status bar.
-    let content_height = area.height.saturating_sub(1) as usize;
-    let content_area = Rect {
-        x: area.x,
-        y: area.y,
-        width: area.width,
-        height: content_height as u16,
-    };
+    let content_area = content_rect(app, area);
+    let content_height = content_area.height as usize;
 
-    // Draw visible document lines.
+    let sidebar_width = app.sidebar_width(area.width);
+    if sidebar_width > 0 && content_height > 0 {
+        let sidebar_area = Rect {
+            x: area.x,
+            width: sidebar_width,
+            ..content_area
+        };
+        draw_outline(frame, app, sidebar_area);
+    }
+
+    // Draw visible document lines, down each column in turn.
     if content_height > 0 {
         let range = app.visible_range();
-        for (i, line_idx) in range.enumerate() {
-            if i >= content_height {
-                break;
+        let columns = column_rects(app, content_area);
+        for (column, start) in columns.iter().zip(range.clone().step_by(content_height)) {
+            let end = (start + content_height).min(range.end);
+            for (row, line_idx) in (start..end).enumerate() {
+                let Some(line) = app.document.lines.get(line_idx) else {
+                    break;
+                };
+                // saturating_add prevents u16 overflow if area.y is non-zero.
+                let line_area = Rect {
+                    y: column.y.saturating_add(row as u16),
+                    height: 1,
+                    ..*column
+                };
+                draw_line(frame, app, line, line_idx, line_area);
+            }
+        }
+    }
+
+    // Without colors, clear them from everything drawn so far; bold,
+    // italic and the like stay.
+    if app.color == ColorMode::None {
+        for cell in &mut frame.buffer_mut().content {
+            cell.set_fg(Color::Reset).set_bg(Color::Reset);
+        }
+    }
+
+    match &app.mode {
+        InputMode::Help => draw_help(frame, content_area),
+        InputMode::ThemePicker { selected, .. } => {
+            draw_theme_picker(frame, app, *selected, content_area);
+        }
+        InputMode::Normal
+        | InputMode::Search { .. }
+        | InputMode::Goto { .. }
+        | InputMode::Command { .. } => {}
+    }
+
+    // Draw status bar at the bottom row.
+    if app.status_bar_visible() {
+        draw_status_bar(frame, app, area);
+    }
+}
+
+/// Returns the document content area: everything but the status bar row
+/// (when drawn), the outline sidebar and the left margin.
+fn content_rect(app: &App, area: Rect) -> Rect {
+    let width = app.content_width(area.width);
+    Rect {
+        x: area.x + (area.width - width),
+        y: area.y,
+        width,
+        height: app.content_rows(area.height),
+    }
+}
+
+/// Splits the content area into `app.columns` side-by-side column rects of
+/// `App::column_width()` cells, `COLUMN_GAP` apart.
+fn column_rects(app: &App, content_area: Rect) -> Vec<Rect> {
+    let width = column_width(content_area.width, app.columns);
+    (0..app.columns.max(1))
+        .map(|i| Rect {
+            x: content_area.x.saturating_add(i * (width + COLUMN_GAP)),
+            width,
+            ..content_area
+        })
+        .collect()
+}
+
+/// Draws one document line into `line_area`, a single row of a column.
+fn draw_line(frame: &mut Frame, app: &App, line: &DocumentLine, line_idx: usize, line_area: Rect) {
+    match line {
+        DocumentLine::Text(line) => {
+            let paragraph = Paragraph::new(line.clone());
+            frame.render_widget(paragraph, line_area);
+        }
+        DocumentLine::Code(line) => {
+            let bg = Style { bg: export::code_background(line, app.code_bg), ..Style::default() };
+            // Override background on every span and add left padding.
+            let mut spans = vec![Span::styled(" ".repeat(app.code_padding), bg)];
+            for span in &line.spans {
+                let style = Style { bg: bg.bg, ..span.style };
+                spans.push(Span::styled(span.content.to_string(), style));
+            }
+            // The paragraph's background covers the whole row, so it reaches
+            // the edge however wide the text turns out (ZWJ emoji sequences
+            // and combining marks are where width tables and terminals
+            // disagree); no padding from computed widths.
+            let code_line = Line::from(spans);
+            let paragraph = Paragraph::new(code_line).style(bg);
+            frame.render_widget(paragraph, line_area);
+        }
+        DocumentLine::Empty => {
+            // Nothing to render — blank line.
+        }
+        DocumentLine::ImageStart { height, alt_text, .. } => {
+            // Fully visible images are drawn over this blank area by the
+            // event loop (see `image_placements`). Partially scrolled-off
+            // images can't be clipped, so show the alt text instead.
+            if !image_fully_visible(app, line_idx, *height) {
+                let alt_line = Line::from(Span::styled(
+                    format!("[image: {alt_text}]"),
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+                frame.render_widget(Paragraph::new(alt_line), line_area);
+            }
+        }
+        DocumentLine::ImageContinuation => {
+            // Covered by the image above — nothing to render.
+        }
+        DocumentLine::Rule => {
+            let rule = tile_to_width(&app.rule, line_area.width as usize);
+            let rule_line =
+                Line::from(Span::styled(rule, Style::default().add_modifier(Modifier::DIM)));
+            let paragraph = Paragraph::new(rule_line);
+            frame.render_widget(paragraph, line_area);
+        }
+    }
+}
+
+/// Draws the heading tree into the sidebar, indented by level.
+///
+/// The heading nearest the viewport top is bold; the outline cursor is
+/// reversed. The list scrolls to keep the cursor in view.
+fn draw_outline(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::RIGHT)
+        .border_style(Style::default().add_modifier(Modifier::DIM));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let headings = &app.document.headings;
+    let selected = app.outline.map_or(0, |o| o.selected);
+    let current = app.current_heading();
+    let height = inner.height as usize;
+    let first = selected
+        .saturating_sub(height / 2)
+        .min(headings.len().saturating_sub(height));
+
+    let lines: Vec<Line> = headings
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .map(|(i, heading)| {
+            let indent = "  ".repeat(heading.level.saturating_sub(1) as usize);
+            let mut style = Style::default();
+            if Some(i) == current {
+                style = style.add_modifier(Modifier::BOLD).fg(Color::Cyan);
             }
-            if line_idx >= app.document.lines.len() {
-                break;
+            if i == selected {
+                style = style.add_modifier(Modifier::REVERSED);
             }
+            Line::from(Span::styled(format!("{indent}{}", heading.text), style))
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), inner);
+}
 
-            // saturating_add prevents u16 overflow if area.y is non-zero and i is large.
-            let y = content_area.y.saturating_add(i as u16);
-            let line_area = Rect {
-                x: content_area.x,
-                y,
-                width: content_area.width,
-                height: 1,
+/// Draws the key help in a bordered box centered over `area`, clipped to
+/// it on small terminals.
+fn draw_help(frame: &mut Frame, area: Rect) {
+    let rows = keymap::help_rows();
+    let keys_width = rows.iter().map(|(keys, _)| keys.width()).max().unwrap_or(0);
+    let lines: Vec<Line> = rows
+        .iter()
+        .map(|(keys, action)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{keys:<keys_width$}  "),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+    let inner_width = lines.iter().map(Line::width).max().unwrap_or(0) as u16;
+    let width = (inner_width + 4).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let help_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Keys (any key closes) ");
+    let inner = block.inner(help_area);
+    frame.render_widget(Clear, help_area);
+    frame.render_widget(block, help_area);
+    let inner = Rect {
+        x: inner.x.saturating_add(1),
+        width: inner.width.saturating_sub(1),
+        ..inner
+    };
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+/// Draws the theme list in a bordered box at the top right of `area`,
+/// leaving the rest of the document visible as a live preview. The list
+/// scrolls to keep `selected` in view.
+fn draw_theme_picker(frame: &mut Frame, app: &App, selected: usize, area: Rect) {
+    let names_width = app.themes.iter().map(|t| t.width()).max().unwrap_or(0) as u16;
+    let width = (names_width + 4).min(area.width);
+    let height = (app.themes.len() as u16 + 2).min(area.height);
+    let picker_area = Rect {
+        x: area.x + (area.width - width),
+        width,
+        height,
+        ..area
+    };
+    let block = Block::default().borders(Borders::ALL).title(" Theme ");
+    let inner = block.inner(picker_area);
+    frame.render_widget(Clear, picker_area);
+    frame.render_widget(block, picker_area);
+
+    let rows = inner.height as usize;
+    let first = selected
+        .saturating_sub(rows / 2)
+        .min(app.themes.len().saturating_sub(rows));
+    let lines: Vec<Line> = app
+        .themes
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(rows)
+        .map(|(i, theme)| {
+            let style = if i == selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
             };
+            Line::from(Span::styled(format!(" {theme} "), style))
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+/// Where to draw one image, in terminal cell coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImagePlacement {
+    pub protocol_index: usize,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
 
-            match &app.document.lines[line_idx] {
-                DocumentLine::Text(line) => {
-                    let paragraph = Paragraph::new(line.clone());
-                    frame.render_widget(paragraph, line_area);
-                }
-                DocumentLine::Code(line) => {
-                    let code_bg = Color::Indexed(235);
-                    // Override background on every span and add left padding.
-                    let mut spans = vec![Span::styled(" ", Style::default().bg(code_bg))];
-                    for span in &line.spans {
-                        let mut style = span.style;
-                        style.bg = Some(code_bg);
-                        spans.push(Span::styled(span.content.to_string(), style));
-                    }
-                    // Fill remaining width with background.
-                    // Use display width (columns), not byte length, to handle multi-byte
-                    // characters correctly (e.g. Unicode operators, CJK, arrows).
-                    let used: usize = spans.iter().map(|s| s.content.width()).sum();
-                    let remaining = (content_area.width as usize).saturating_sub(used);
-                    if remaining > 0 {
-                        spans.push(Span::styled(
-                            " ".repeat(remaining),
-                            Style::default().bg(code_bg),
-                        ));
-                    }
-                    let code_line = Line::from(spans);
-                    let paragraph = Paragraph::new(code_line);
-                    frame.render_widget(paragraph, line_area);
-                }
-                DocumentLine::Empty => {
-                    // Nothing to render — blank line.
-                }
-                DocumentLine::Rule => {
-                    let rule_char = "─".repeat(content_area.width as usize);
-                    let rule_line =
-                        Line::from(Span::styled(rule_char, Style::default().add_modifier(Modifier::DIM)));
-                    let paragraph = Paragraph::new(rule_line);
-                    frame.render_widget(paragraph, line_area);
-                }
+/// Returns the images to draw for the current frame.
+///
+/// Graphics escapes bypass ratatui's cell buffer, so the event loop writes
+/// them after `draw()` using these placements. Only images whose full
+/// height is inside the viewport are included; `draw()` shows alt text
+/// for the rest.
+pub fn image_placements(app: &App, area: Rect) -> Vec<ImagePlacement> {
+    let area = content_rect(app, area);
+    let height = area.height as usize;
+    // Overlays would be drawn over.
+    if height == 0 || app.overlay_open() {
+        return Vec::new();
+    }
+    let columns = column_rects(app, area);
+    app.visible_range()
+        .enumerate()
+        .filter_map(|(i, line_idx)| match app.document.lines.get(line_idx)? {
+            DocumentLine::ImageStart {
+                protocol_index,
+                width,
+                height: image_height,
+                ..
+            } if image_fully_visible(app, line_idx, *image_height) => {
+                let column = columns.get(i / height)?;
+                Some(ImagePlacement {
+                    protocol_index: *protocol_index,
+                    x: column.x,
+                    y: column.y.saturating_add((i % height) as u16),
+                    width: (*width).min(column.width),
+                    height: *image_height,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where to write one issue or commit reference link, in terminal cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPlacement {
+    pub x: u16,
+    pub y: u16,
+    pub text: String,
+    pub style: Style,
+    pub url: String,
+}
+
+/// Returns the reference links (`--repo-url`) visible in the current frame.
+///
+/// Like images, OSC 8 hyperlinks can't go through ratatui's cell buffer:
+/// the event loop rewrites these spans with the hyperlink escapes after
+/// `draw()`. Spans cut off at the column edge are left unlinked.
+pub fn link_placements(app: &App, area: Rect) -> Vec<LinkPlacement> {
+    let area = content_rect(app, area);
+    let height = area.height as usize;
+    if height == 0 || app.document.references.is_empty() || app.overlay_open() {
+        return Vec::new();
+    }
+    let columns = column_rects(app, area);
+    let mut placements = Vec::new();
+    for (i, line_idx) in app.visible_range().enumerate() {
+        let (Some(DocumentLine::Text(line)), Some(column)) =
+            (app.document.lines.get(line_idx), columns.get(i / height))
+        else {
+            continue;
+        };
+        let mut x = column.x as usize;
+        for (j, span) in line.spans.iter().enumerate() {
+            let width = span.content.width();
+            if let Some(url) = app.document.reference(line_idx, j)
+                && x + width <= column.right() as usize
+            {
+                placements.push(LinkPlacement {
+                    x: x as u16,
+                    y: column.y.saturating_add((i % height) as u16),
+                    text: span.content.to_string(),
+                    style: line.style.patch(span.style),
+                    url: url.to_string(),
+                });
             }
+            x += width;
         }
     }
+    placements
+}
 
-    // Draw status bar at the bottom row.
-    draw_status_bar(frame, app, area);
+/// Returns true if an image starting at `line_idx` fits entirely in the
+/// viewport, within a single column.
+fn image_fully_visible(app: &App, line_idx: usize, height: u16) -> bool {
+    let range = app.visible_range();
+    let end = line_idx + height as usize;
+    if line_idx < range.start || end > range.end {
+        return false;
+    }
+    let column_of = |line: usize| (line - range.start) / app.viewport_height.max(1);
+    height == 0 || column_of(line_idx) == column_of(end - 1)
 }
 
 /// Renders the status bar at the bottom row of the given area.
@@ -112,22 +401,42 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     };
 
     let percent = app.scroll_percent();
-    let total_lines = app.document.total_height;
-    let current_line = if total_lines == 0 {
-        0
-    } else {
-        app.scroll_offset + 1
-    };
 
-    let status_text = format!(
-        " {} | {}% | {}/{} ",
-        app.filename, percent, current_line, total_lines
+    let modified = format!(
+        "{}{}",
+        if app.stale { " [modified]" } else { "" },
+        if app.raw { " [source]" } else { "" }
     );
+    let status_text = match (&app.mode, &app.status_message) {
+        // The query being typed replaces the whole bar, as in less.
+        (InputMode::Search { buffer }, _) => format!("/{buffer}"),
+        (InputMode::Goto { buffer }, _) => format!(" Go to line: {buffer}"),
+        (InputMode::Command { buffer }, _) => format!(":{buffer}"),
+        (InputMode::ThemePicker { .. }, _) => format!(
+            " Theme: {} | ↑↓ preview, Enter keep, Esc revert",
+            app.theme
+        ),
+        (InputMode::Normal | InputMode::Help, Some(message)) => {
+            format!(" {}{} | {} ", app.current_file(), modified, message)
+        }
+        // Like less, say so once the bottom is reached: further presses
+        // of `j` don't move.
+        (InputMode::Normal | InputMode::Help, None) => format!(
+            " {}{} | {}% | {}{} ",
+            app.current_file(),
+            modified,
+            percent,
+            app.position_label(),
+            if app.at_end() { " (END)" } else { "" }
+        ),
+    };
 
-    let status_style = Style::default()
-        .fg(Color::Black)
-        .bg(Color::White)
-        .add_modifier(Modifier::BOLD);
+    let status_style = match app.color {
+        ColorMode::Full if app.light => Style::default().fg(Color::White).bg(Color::Black),
+        ColorMode::Full => Style::default().fg(Color::Black).bg(Color::White),
+        ColorMode::None => Style::default().add_modifier(Modifier::REVERSED),
+    }
+    .add_modifier(Modifier::BOLD);
 
     // Pad the status text to fill the entire width.
     let padded = format!("{:<width$}", status_text, width = area.width as usize);
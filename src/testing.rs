@@ -0,0 +1,107 @@
+//! Headless driving of the viewer, for integration tests.
+//!
+//! `drive()` runs a document through the same pipeline and key handling as
+//! the `mdink` binary, minus the terminal: keys go straight to
+//! `App::handle_key()` and the result is read off the `App` instead of the
+//! screen. This lets keybindings (search, goto, `:` commands, scrolling) be
+//! tested end to end without crossterm.
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::app::App;
+use crate::highlight::Highlighter;
+use crate::layout::{self, DocumentLine, LayoutOptions};
+use crate::parser::{self, ParseOptions, RenderedBlock};
+
+/// What the viewer shows after `drive()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Index of the top visible document line.
+    pub scroll_offset: usize,
+    /// Plain text of the visible document lines, top to bottom (across
+    /// every column with `App::columns`).
+    pub lines: Vec<String>,
+    /// The status bar message left by the last key, if any.
+    pub status_message: Option<String>,
+}
+
+/// Opens `source` in a viewer of `size` (columns, rows, the status bar
+/// included), presses `keys` in order and returns what is shown.
+///
+/// Like the event loop, this re-lays out the document when a key changes
+/// the text width, the wrap mode or the outline, and re-parses for theme
+/// switches and the raw source view. Editing, reloading and copying need a
+/// terminal or a file and are ignored. Keys after a quit are not pressed.
+pub fn drive(source: &str, size: (u16, u16), keys: &[KeyEvent]) -> Snapshot {
+    let (cols, rows) = size;
+    let highlighter = Highlighter::new();
+    let mut options = ParseOptions::default();
+    let mut layout = LayoutOptions::default();
+    let parse = |options: &ParseOptions, raw: bool| -> Vec<RenderedBlock> {
+        if raw {
+            parser::parse_as_source(source, &highlighter, options)
+        } else {
+            parser::parse_with_options(source, &highlighter, options)
+        }
+    };
+
+    let mut blocks = parse(&options, false);
+    let mut app = App::new(layout::flatten(&blocks, cols), "test.md".to_string());
+    app.viewport_height = rows.saturating_sub(1) as usize;
+    app.terminal_width = cols;
+    app.themes = highlighter.theme_names();
+    app.document = layout::flatten_with_options(&blocks, app.column_width(cols), &layout);
+
+    for &key in keys {
+        if app.quit {
+            break;
+        }
+        let had_outline = app.outline.is_some();
+        let width = app.column_width(cols);
+        let raw = app.raw;
+        app.handle_key(key);
+        let mut reparse = app.raw != raw;
+        if let Some(theme) = app.theme_request.take() {
+            options.theme = theme;
+            reparse = true;
+        }
+        if reparse {
+            blocks = parse(&options, app.raw);
+        }
+        if reparse
+            || app.outline.is_some() != had_outline
+            || app.column_width(cols) != width
+            || app.wrap != layout.wrap
+        {
+            layout.wrap = app.wrap;
+            app.document = layout::flatten_with_options(&blocks, app.column_width(cols), &layout);
+            app.clamp_scroll();
+        }
+    }
+
+    Snapshot {
+        scroll_offset: app.scroll_offset,
+        lines: app.document.lines[app.visible_range()]
+            .iter()
+            .map(DocumentLine::plain_text)
+            .collect(),
+        status_message: app.status_message,
+    }
+}
+
+/// Returns the key presses that type `text`: `\n` is Enter, `\x1b` is Esc
+/// and every other character is itself, e.g. `keys("/needle\n")`.
+pub fn keys(text: &str) -> Vec<KeyEvent> {
+    text.chars()
+        .map(|c| match c {
+            '\n' => KeyCode::Enter,
+            '\x1b' => KeyCode::Esc,
+            c => KeyCode::Char(c),
+        })
+        .map(|code| KeyEvent::new(code, KeyModifiers::empty()))
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "testing_tests.rs"]
+mod tests;
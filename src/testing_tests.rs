@@ -0,0 +1,46 @@
+    use super::*;
+
+    fn numbered(count: usize) -> String {
+        (1..=count).map(|n| format!("line {n}\n\n")).collect()
+    }
+
+    #[test]
+    fn test_testing_scroll_keys_move_viewport() {
+        // 10 rows: 9 document lines and the status bar.
+        let snapshot = drive(&numbered(20), (40, 10), &keys("jjj"));
+        assert_eq!(snapshot.scroll_offset, 3);
+        assert_eq!(snapshot.lines.len(), 9);
+        assert_eq!(snapshot.lines[1], "line 3");
+        let snapshot = drive(&numbered(20), (40, 10), &keys("G"));
+        assert_eq!(snapshot.scroll_offset, 39 - 9);
+        assert_eq!(snapshot.lines.last().unwrap(), "line 20");
+    }
+
+    #[test]
+    fn test_testing_search_and_goto_end_to_end() {
+        // Targets land below two lines of context (the scroll margin).
+        let snapshot = drive(&numbered(20), (40, 10), &keys("/line 12\n"));
+        assert_eq!(snapshot.lines[2], "line 12");
+        // Goto takes 1-based display lines.
+        let snapshot = drive(&numbered(20), (40, 10), &keys("5g"));
+        assert_eq!(snapshot.scroll_offset, 2);
+        let snapshot = drive(&numbered(20), (40, 10), &keys(":goto 7\n"));
+        assert_eq!(snapshot.scroll_offset, 4);
+    }
+
+    #[test]
+    fn test_testing_width_command_relayouts() {
+        let source = "one two three four five six";
+        let snapshot = drive(source, (40, 10), &keys(""));
+        assert_eq!(snapshot.lines, ["one two three four five six"]);
+        let snapshot = drive(source, (40, 10), &keys(":set width 20\n"));
+        assert_eq!(snapshot.lines, ["one two three four", "five six"]);
+        let snapshot = drive(source, (40, 10), &keys(":theme nope\n"));
+        assert_eq!(snapshot.status_message.as_deref(), Some("unknown theme: nope"));
+    }
+
+    #[test]
+    fn test_testing_keys_after_quit_are_ignored() {
+        let snapshot = drive(&numbered(20), (40, 10), &keys("jqjj"));
+        assert_eq!(snapshot.scroll_offset, 1);
+    }